@@ -1,29 +1,124 @@
-use std::{fmt::Display};
+use std::{fmt::Display, rc::Rc, sync::Arc};
 
-use crate::{lexer::Location, environment::Environment};
+use crate::{lexer::Location, environment::Environment, interner::Symbol, task::Channel};
 
+///A node of the syntax tree. Each node owns its children, so the type checker and optimizer change a tree in place or take it apart
 #[derive(Debug, Clone, PartialEq)]
 pub enum Exp {
     BinOpExp(Box<Exp>, Operator, Box<Exp>, Location),
     UnOpExp(Operator, Box<Exp>, Location),
     LiteralExp(Literal, Location),
-    VarExp(String, Location),
-    WhileExp(Box<Exp>, Box<Exp>, Location),
+    VarExp(Symbol, Location),
 
-    //let exp, comparison, increment, body
-    ForExp(Box<Exp>, Box<Exp>, Box<Exp>, Box<Exp>, Location),
+    //condition, body, label
+    WhileExp(Box<Exp>, Box<Exp>, Option<String>, Location),
+
+    //let exp, comparison, increment, body, label
+    ForExp(Box<Exp>, Box<Exp>, Box<Exp>, Box<Exp>, Option<String>, Location),
+
+    //Variable, collection, body, label
+    ForInExp(Symbol, Box<Exp>, Box<Exp>, Option<String>, Location),
+
+    //Optional label of the loop to break out of or continue
+    BreakExp(Option<String>, Location),
+    ContinueExp(Option<String>, Location),
 
     //Id, exp
-    LetExp(String, Box<Exp>, Location),
+    LetExp(Symbol, Box<Exp>, Location),
 
     ///Condition, if true, else
     IfElseExp(Box<Exp>, Box<Exp>, Option<Box<Exp>>, Location),
 
-    BlockExp(Vec<Exp>, Vec<(String, Box<Function>)>, Location),
-    FunCallExp(String, Vec<Exp>, Location),
-    FunDeclExp(String, Location)
+    BlockExp(Vec<Exp>, Vec<(Symbol, Box<Function>)>, Location),
+    FunCallExp(Symbol, Vec<Exp>, Location),
+    FunDeclExp(Symbol, Location),
+
+    ///some(exp) or none
+    OptionExp(Option<Box<Exp>>, Location),
+
+    TupleExp(Vec<Exp>, Location),
+
+    //Name, field names and types
+    StructDeclExp(String, Vec<(String, Type)>, Location),
+
+    //Name, field names and values
+    StructExp(String, Vec<(String, Exp)>, Location),
+
+    //Struct, field name
+    FieldExp(Box<Exp>, String, Location),
+
+    ///let with a tuple or struct pattern on the left side
+    DestructExp(Pattern, Box<Exp>, Location),
+
+    //Name, method names, parameter types after self and return types
+    InterfaceDeclExp(String, Vec<(String, Vec<Type>, Type)>, Location),
+
+    //Interface, struct, method names. The methods are functions named 'Struct.method'
+    ImplExp(String, String, Vec<String>, Location),
+
+    //Receiver, method name, arguments after the receiver
+    MethodCallExp(Box<Exp>, Symbol, Vec<Exp>, Location),
+
+    ArrayExp(Vec<Exp>, Location),
+
+    ///'...exp' in an array literal or the arguments of a call
+    SpreadExp(Box<Exp>, Location),
+
+    //Array, index
+    IndexExp(Box<Exp>, Box<Exp>, Location),
+
+    ///'operator SYMBOL left|right LEVEL = FUNCTION'
+    OperatorDeclExp(CustomOperator, Location)
+}
+
+///A binary operator declared in the source. Using it calls the function with the two sides
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomOperator {
+    pub symbol: String,
+    pub fun: Symbol,
+
+    ///Level of the builtin operators it binds like, counting from 1 for the tightest
+    pub level: usize,
+    pub associativity: Associativity,
+}
+
+///Declaration of a named type
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeDecl {
+    //Field names and types, interfaces it implements
+    Struct(Vec<(String, Type)>, Vec<String>),
+
+    //Method names, parameter types after self and return types
+    Interface(Vec<(String, Vec<Type>, Type)>)
 }
 
+///Left side of a destructuring let
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Id(Symbol),
+    Tuple(Vec<Pattern>),
+
+    //Struct name, field names and the patterns they are bound to
+    Struct(String, Vec<(String, Pattern)>)
+}
+
+impl Pattern {
+    ///Adds the variables the pattern declares
+    pub fn ids(&self, ids: &mut Vec<Symbol>) {
+        match self {
+            Pattern::Id(id) => ids.push(*id),
+            Pattern::Tuple(patterns) => for pattern in patterns {
+                pattern.ids(ids);
+            },
+            Pattern::Struct(_, fields) => for (_, pattern) in fields {
+                pattern.ids(ids);
+            }
+        }
+    }
+}
+
+///A runtime value. Strings, tuples, structs, arrays and sets are shared between copies, and only copied when one of them is changed.
+///This makes copies cheap, and a value can never end up containing itself
 #[derive(Clone, Debug, PartialEq)]
 pub enum Literal {
     //Function(&'a Function),
@@ -31,20 +126,46 @@ pub enum Literal {
     Float(f64),
     Bool(bool),
     Char(char),
-    Str(String),
+    Str(Rc<String>),
+    Optional(Option<Box<Literal>>),
+    Tuple(Rc<Vec<Literal>>),
+    Struct(Rc<StructValue>),
+    Array(Rc<Vec<Literal>>),
+
+    ///Unique values in insertion order
+    Set(Rc<Vec<Literal>>),
+
+    ///A named function used as a value
+    Fun(Box<Closure<Literal>>),
+
+    ///Id of a task started by spawn
+    Task(u64),
+
+    ///Shared by all tasks that have a copy of it
+    Channel(Arc<Channel>),
     Unit,
 }
 
+//Values are moved and copied all the time, so every variant holds at most a pointer or a number
+const _: () = assert!(std::mem::size_of::<Literal>() <= 16);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructValue {
+    pub name: String,
+    pub fields: Vec<(String, Literal)>,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Function {
+    pub name: Symbol,
     pub loc: Location,
     pub ret_type: Type,
     pub param_types: Vec<Type>,
-    pub params: Vec<String>,
+    pub params: Vec<Symbol>,
     pub exp: Box<Exp>
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Type {
     Int,
     Float,
@@ -52,11 +173,75 @@ pub enum Type {
     Unit,
     Char,
     Str,
+    Optional(Box<Type>),
+    Tuple(Vec<Type>),
+    Struct(String),
+    Array(Box<Type>),
+    Set(Box<Type>),
+
+    //Parameter types, return type
+    Fun(Vec<Type>, Box<Type>),
 
-    //Before type check
+    ///A spawned task, which gives the inner type when joined
+    Task(Box<Type>),
+    Channel(Box<Type>),
+
+    //Before type check. Also the inner type of 'none' and '[]'
     Any
 }
 
+impl Type {
+    ///Whether a value of this type can be used where 'other' is expected. 'Any' matches everything
+    pub fn matches(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Optional(left), Type::Optional(right)) => left.matches(right),
+            (Type::Array(left), Type::Array(right))
+            | (Type::Set(left), Type::Set(right))
+            | (Type::Task(left), Type::Task(right))
+            | (Type::Channel(left), Type::Channel(right)) => left.matches(right),
+            (Type::Fun(left_params, left), Type::Fun(right_params, right)) => left.matches(right)
+                && left_params.len() == right_params.len()
+                && left_params.iter().zip(right_params).all(|(left, right)| left.matches(right)),
+            (Type::Tuple(left), Type::Tuple(right)) => left.len() == right.len()
+                && left.iter().zip(right).all(|(left, right)| left.matches(right)),
+            (left, right) => left == right
+        }
+    }
+
+    ///The type both fit, where an 'any' in one of them is taken from the other. None if they do not match
+    pub fn join(&self, other: &Type) -> Option<Type> {
+        match (self, other) {
+            (Type::Any, typ) | (typ, Type::Any) => Some(typ.clone()),
+            (Type::Optional(left), Type::Optional(right)) => Some(Type::Optional(Box::new(left.join(right)?))),
+            (Type::Array(left), Type::Array(right)) => Some(Type::Array(Box::new(left.join(right)?))),
+            (Type::Set(left), Type::Set(right)) => Some(Type::Set(Box::new(left.join(right)?))),
+            (Type::Task(left), Type::Task(right)) => Some(Type::Task(Box::new(left.join(right)?))),
+            (Type::Channel(left), Type::Channel(right)) => Some(Type::Channel(Box::new(left.join(right)?))),
+            (Type::Fun(left_params, left), Type::Fun(right_params, right)) if left_params.len() == right_params.len() => {
+                let params = left_params.iter().zip(right_params).map(|(left, right)| left.join(right)).collect::<Option<Vec<Type>>>()?;
+                Some(Type::Fun(params, Box::new(left.join(right)?)))
+            },
+            (Type::Tuple(left), Type::Tuple(right)) if left.len() == right.len() => {
+                Some(Type::Tuple(left.iter().zip(right).map(|(left, right)| left.join(right)).collect::<Option<Vec<Type>>>()?))
+            },
+            (left, right) if left == right => Some(left.clone()),
+            _ => None
+        }
+    }
+
+    ///Whether an 'any' is left in the type, like in the type of 'none' or '[]'
+    pub fn is_open(&self) -> bool {
+        match self {
+            Type::Any => true,
+            Type::Optional(typ) | Type::Array(typ) | Type::Set(typ) | Type::Task(typ) | Type::Channel(typ) => typ.is_open(),
+            Type::Fun(params, ret_type) => params.iter().any(Type::is_open) || ret_type.is_open(),
+            Type::Tuple(types) => types.iter().any(Type::is_open),
+            _ => false
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Closure<T> {
     pub declared: bool,
@@ -64,6 +249,13 @@ pub struct Closure<T> {
     pub envir: Environment<T>
 }
 
+///Closures are equal when they are the same function
+impl<T> PartialEq for Closure<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.fun == other.fun
+    }
+}
+
 impl<T> Closure<T> {
     pub fn new(fun: Box<Function>, envir: Environment<T>) -> Self {
         Self { fun, envir, declared: false }
@@ -92,7 +284,64 @@ pub enum Operator {
     MinusAssign,
     And,
     Or,
-    NotEquals
+    NotEquals,
+    Coalesce,
+
+    ///'from..to', the ints from and up to, but not including, to
+    Range
+}
+
+///Which side binary operators of the same level group from. 'a - b - c' is '(a - b) - c' as '-' is left associative
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Associativity {
+    Left,
+    Right
+}
+
+impl Display for Associativity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Associativity::Left => write!(f, "left"),
+            Associativity::Right => write!(f, "right")
+        }
+    }
+}
+
+impl Literal {
+    ///The value as it is printed. Unlike Display, a string or char is shown without quotes
+    pub fn render(&self) -> String {
+        match self {
+            Literal::Str(s) => s.to_string(),
+            Literal::Char(c) => c.to_string(),
+            lit => lit.to_string()
+        }
+    }
+
+    ///The type of the value. Empty arrays and sets, and 'none', have 'any' inside
+    pub fn get_type(&self) -> Type {
+        match self {
+            Literal::Int(_) => Type::Int,
+            Literal::Float(_) => Type::Float,
+            Literal::Bool(_) => Type::Bool,
+            Literal::Char(_) => Type::Char,
+            Literal::Str(_) => Type::Str,
+            Literal::Unit => Type::Unit,
+            Literal::Optional(lit) => Type::Optional(Box::new(lit.as_ref().map_or(Type::Any, |lit| lit.get_type()))),
+            Literal::Tuple(lits) => Type::Tuple(lits.iter().map(|lit| lit.get_type()).collect()),
+            Literal::Struct(value) => Type::Struct(value.name.clone()),
+            Literal::Array(lits) | Literal::Set(lits) => {
+                //An element can be a 'none' or '[]' where a later one tells the type
+                let elem = lits.iter().fold(Type::Any, |elem, lit| elem.join(&lit.get_type()).unwrap_or(elem));
+                match self {
+                    Literal::Array(_) => Type::Array(Box::new(elem)),
+                    _ => Type::Set(Box::new(elem))
+                }
+            },
+            Literal::Fun(closure) => Type::Fun(closure.fun.param_types.clone(), Box::new(closure.fun.ret_type.clone())),
+            Literal::Task(_) => Type::Task(Box::new(Type::Any)),
+            Literal::Channel(_) => Type::Channel(Box::new(Type::Any)),
+        }
+    }
 }
 
 impl Display for Literal {
@@ -104,7 +353,16 @@ impl Display for Literal {
                 Literal::Bool(b) => b.to_string(),
                 Literal::Char(c) => format!("'{}'", c),
                 Literal::Str(s) => format!("\"{}\"", s),
-                Literal::Unit => format!("Unit"),
+                Literal::Optional(Some(lit)) => format!("some({lit})"),
+                Literal::Optional(None) => String::from("none"),
+                Literal::Tuple(lits) => format!("({})", lits.iter().map(|lit| lit.to_string()).collect::<Vec<String>>().join(", ")),
+                Literal::Struct(value) => format!("{} {{ {} }}", value.name, value.fields.iter().map(|(id, lit)| format!("{id}: {lit}")).collect::<Vec<String>>().join(", ")),
+                Literal::Array(lits) => format!("[{}]", lits.iter().map(|lit| lit.to_string()).collect::<Vec<String>>().join(", ")),
+                Literal::Set(lits) => format!("{{{}}}", lits.iter().map(|lit| lit.to_string()).collect::<Vec<String>>().join(", ")),
+                Literal::Fun(_) => String::from("fun"),
+                Literal::Task(id) => format!("task {id}"),
+                Literal::Channel(_) => String::from("channel"),
+                Literal::Unit => String::from("Unit"),
             }
         )
     }
@@ -114,46 +372,139 @@ impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}",
             match self {
-                Type::Int => "int",
-                Type::Float => "float",
-                Type::Bool => "bool",
-                Type::Char => "char",
-                Type::Str => "string",
-                Type::Unit => "unit",
-                Type::Any => "any",
+                Type::Int => String::from("int"),
+                Type::Float => String::from("float"),
+                Type::Bool => String::from("bool"),
+                Type::Char => String::from("char"),
+                Type::Str => String::from("string"),
+                Type::Unit => String::from("unit"),
+                Type::Optional(typ) => format!("option<{typ}>"),
+                Type::Tuple(types) => format!("({})", types.iter().map(|typ| typ.to_string()).collect::<Vec<String>>().join(", ")),
+                Type::Struct(name) => name.clone(),
+                Type::Array(typ) => format!("[{typ}]"),
+                Type::Set(typ) => format!("set<{typ}>"),
+                Type::Task(typ) => format!("task<{typ}>"),
+                Type::Channel(typ) => format!("chan<{typ}>"),
+                Type::Fun(params, ret_type) => format!("fun({}): {ret_type}", params.iter().map(|typ| typ.to_string()).collect::<Vec<String>>().join(", ")),
+                Type::Any => String::from("any"),
             }
         )
     }
 }
 
+impl Exp {
+    ///Where the expression starts in the source
+    pub fn loc(&self) -> Location {
+        match self {
+            Exp::BinOpExp(_, _, _, loc) | Exp::UnOpExp(_, _, loc) | Exp::LiteralExp(_, loc) | Exp::VarExp(_, loc)
+            | Exp::WhileExp(_, _, _, loc) | Exp::ForExp(_, _, _, _, _, loc) | Exp::ForInExp(_, _, _, _, loc)
+            | Exp::BreakExp(_, loc) | Exp::ContinueExp(_, loc) | Exp::LetExp(_, _, loc) | Exp::IfElseExp(_, _, _, loc)
+            | Exp::BlockExp(_, _, loc) | Exp::FunCallExp(_, _, loc) | Exp::FunDeclExp(_, loc) | Exp::OptionExp(_, loc)
+            | Exp::TupleExp(_, loc) | Exp::StructDeclExp(_, _, loc) | Exp::StructExp(_, _, loc) | Exp::FieldExp(_, _, loc)
+            | Exp::DestructExp(_, _, loc) | Exp::InterfaceDeclExp(_, _, loc) | Exp::ImplExp(_, _, _, loc)
+            | Exp::MethodCallExp(_, _, _, loc) | Exp::ArrayExp(_, loc) | Exp::SpreadExp(_, loc) | Exp::IndexExp(_, _, loc)
+            | Exp::OperatorDeclExp(_, loc) => *loc
+        }
+    }
+
+    ///The direct subexpressions, including the bodies of functions declared in a block
+    pub fn children(&self) -> Vec<&Exp> {
+        match self {
+            Exp::BinOpExp(left, _, right, _) | Exp::IndexExp(left, right, _) => vec![left, right],
+            Exp::UnOpExp(_, exp, _) | Exp::LetExp(_, exp, _) | Exp::FieldExp(exp, _, _) | Exp::DestructExp(_, exp, _)
+            | Exp::SpreadExp(exp, _) => vec![exp],
+            Exp::WhileExp(cond, body, _, _) => vec![cond, body],
+            Exp::ForExp(init, cond, incr, body, _, _) => vec![init, cond, incr, body],
+            Exp::ForInExp(_, exp, body, _, _) => vec![exp, body],
+            Exp::IfElseExp(cond, pos, neg, _) => [cond, pos].into_iter().chain(neg).map(|exp| exp.as_ref()).collect(),
+            Exp::BlockExp(exps, funs, _) => exps.iter().chain(funs.iter().map(|(_, fun)| fun.exp.as_ref())).collect(),
+            Exp::FunCallExp(_, exps, _) | Exp::TupleExp(exps, _) | Exp::ArrayExp(exps, _) => exps.iter().collect(),
+            Exp::MethodCallExp(receiver, _, args, _) => std::iter::once(receiver.as_ref()).chain(args).collect(),
+            Exp::OptionExp(exp, _) => exp.iter().map(|exp| exp.as_ref()).collect(),
+            Exp::StructExp(_, fields, _) => fields.iter().map(|(_, exp)| exp).collect(),
+            Exp::LiteralExp(_, _) | Exp::VarExp(_, _) | Exp::BreakExp(_, _) | Exp::ContinueExp(_, _) | Exp::FunDeclExp(_, _)
+            | Exp::StructDeclExp(_, _, _) | Exp::InterfaceDeclExp(_, _, _) | Exp::ImplExp(_, _, _, _) | Exp::OperatorDeclExp(_, _) => vec![]
+        }
+    }
+
+    pub fn loc_mut(&mut self) -> &mut Location {
+        match self {
+            Exp::BinOpExp(_, _, _, loc) | Exp::UnOpExp(_, _, loc) | Exp::LiteralExp(_, loc) | Exp::VarExp(_, loc)
+            | Exp::WhileExp(_, _, _, loc) | Exp::ForExp(_, _, _, _, _, loc) | Exp::ForInExp(_, _, _, _, loc)
+            | Exp::BreakExp(_, loc) | Exp::ContinueExp(_, loc) | Exp::LetExp(_, _, loc) | Exp::IfElseExp(_, _, _, loc)
+            | Exp::BlockExp(_, _, loc) | Exp::FunCallExp(_, _, loc) | Exp::FunDeclExp(_, loc) | Exp::OptionExp(_, loc)
+            | Exp::TupleExp(_, loc) | Exp::StructDeclExp(_, _, loc) | Exp::StructExp(_, _, loc) | Exp::FieldExp(_, _, loc)
+            | Exp::DestructExp(_, _, loc) | Exp::InterfaceDeclExp(_, _, loc) | Exp::ImplExp(_, _, _, loc)
+            | Exp::MethodCallExp(_, _, _, loc) | Exp::ArrayExp(_, loc) | Exp::SpreadExp(_, loc) | Exp::IndexExp(_, _, loc)
+            | Exp::OperatorDeclExp(_, loc) => loc
+        }
+    }
+
+    ///The direct subexpressions in the same order as children, to change them
+    pub fn children_mut(&mut self) -> Vec<&mut Exp> {
+        match self {
+            Exp::BinOpExp(left, _, right, _) | Exp::IndexExp(left, right, _) => vec![left, right],
+            Exp::UnOpExp(_, exp, _) | Exp::LetExp(_, exp, _) | Exp::FieldExp(exp, _, _) | Exp::DestructExp(_, exp, _)
+            | Exp::SpreadExp(exp, _) => vec![exp],
+            Exp::WhileExp(cond, body, _, _) => vec![cond, body],
+            Exp::ForExp(init, cond, incr, body, _, _) => vec![init, cond, incr, body],
+            Exp::ForInExp(_, exp, body, _, _) => vec![exp, body],
+            Exp::IfElseExp(cond, pos, neg, _) => [cond, pos].into_iter().chain(neg).map(|exp| exp.as_mut()).collect(),
+            Exp::BlockExp(exps, funs, _) => exps.iter_mut().chain(funs.iter_mut().map(|(_, fun)| fun.exp.as_mut())).collect(),
+            Exp::FunCallExp(_, exps, _) | Exp::TupleExp(exps, _) | Exp::ArrayExp(exps, _) => exps.iter_mut().collect(),
+            Exp::MethodCallExp(receiver, _, args, _) => std::iter::once(receiver.as_mut()).chain(args).collect(),
+            Exp::OptionExp(exp, _) => exp.iter_mut().map(|exp| exp.as_mut()).collect(),
+            Exp::StructExp(_, fields, _) => fields.iter_mut().map(|(_, exp)| exp).collect(),
+            Exp::LiteralExp(_, _) | Exp::VarExp(_, _) | Exp::BreakExp(_, _) | Exp::ContinueExp(_, _) | Exp::FunDeclExp(_, _)
+            | Exp::StructDeclExp(_, _, _) | Exp::InterfaceDeclExp(_, _, _) | Exp::ImplExp(_, _, _, _) | Exp::OperatorDeclExp(_, _) => vec![]
+        }
+    }
+}
+
+impl Function {
+    ///Names the body refers to, which are the variables a closure of it keeps from where it is declared.
+    ///Names it declares itself are included, as it can use the outer variable before shadowing it
+    pub fn used_names(&self) -> Vec<Symbol> {
+        let mut names = Vec::new();
+        self.exp.used_names(&mut names);
+        names
+    }
+}
+
+impl Exp {
+    ///Whether the expression refers to any of the names
+    pub fn uses_any(&self, ids: &[Symbol]) -> bool {
+        let mut names = Vec::new();
+        self.used_names(&mut names);
+        names.iter().any(|name| ids.contains(name))
+    }
+
+    fn used_names(&self, names: &mut Vec<Symbol>) {
+        let name = match self {
+            Exp::VarExp(id, _) | Exp::FunCallExp(id, _, _) | Exp::MethodCallExp(_, id, _, _) => Some(*id),
+            //A for-in loop calls 'next' on what it loops over, which can be a function value
+            Exp::ForInExp(_, _, _, _, _) => Some(Symbol::new("next")),
+            _ => None
+        };
+        if let Some(name) = name.filter(|name| !names.contains(name)) {
+            names.push(name);
+        }
+        for child in self.children() {
+            child.used_names(names);
+        }
+    }
+}
+
+///The expression as source, which parses back to the same tree
 impl Display for Exp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}",
-            match self {
-                Exp::BinOpExp(left, op, right, _) => format!("({left} {op} {right})"),
-                Exp::UnOpExp(op, exp, _) => format!("({op}{exp})"),
-                Exp::LiteralExp(lit, _) => format!("{lit}"),
-                Exp::VarExp(id, _) => format!("{id}"),
-                Exp::BlockExp(exps, _, _) => {
-                    //Should also output fun defs
-                    let mut res = format!("{{");
-                    for exp in exps {
-                        res = format!("{res}\n {exp}")
-                    }
-                    res = format!("{res}\n}}");
-                    res
-                },
-                Exp::IfElseExp(cond, pos, neg, _) => match neg {
-                    Some(neg) => format!("if({cond}) {pos} else {neg}"),
-                    None => format!("if({cond}) {pos}"),
-                },
-                Exp::LetExp(id, exp, _) =>  format!("let {id} = {exp};"),
-                Exp::WhileExp(cond, exp, _) => format!("while({cond})  {exp}"),
-                Exp::FunCallExp(_, _, _) => format!("FunCall"),
-                Exp::FunDeclExp(_, _) => format!("FunDecl"),
-                Exp::ForExp(_, _, _, _, _) => format!("For"),
-            }
-        )
+        write!(f, "{}", self.to_source())
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_source())
     }
 }
 
@@ -178,7 +529,18 @@ impl Display for Operator {
                 Operator::And => "&&",
                 Operator::Or => "||",
                 Operator::NotEquals => "!=",
+                Operator::Coalesce => "??",
+                Operator::Range => "..",
             }
         )
     }
-}
\ No newline at end of file
+}
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Id(id) => write!(f, "{id}"),
+            Pattern::Tuple(patterns) => write!(f, "({})", patterns.iter().map(|pat| pat.to_string()).collect::<Vec<String>>().join(", ")),
+            Pattern::Struct(name, fields) => write!(f, "{name} {{ {} }}", fields.iter().map(|(id, pat)| format!("{id}: {pat}")).collect::<Vec<String>>().join(", ")),
+        }
+    }
+}
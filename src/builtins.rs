@@ -0,0 +1,1526 @@
+use std::{cmp::Ordering, fs, io::Write, path::Path, rc::Rc, sync::Arc, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use lazy_static::lazy_static;
+
+use super::*;
+use eval::{Signal, EvalResult, call_closure, int_result, type_error};
+use Literal::*;
+use options::{Hooks, Output};
+use interner::Symbol;
+
+///A function implemented by the interpreter. User functions with the same name take precedence
+pub struct Builtin {
+    pub name: &'static str,
+
+    ///Gives the return type for the argument types, or what is wrong with them
+    pub check: fn(&[Type]) -> Result<Type, String>,
+
+    ///Runs on arguments that passed the check
+    pub eval: fn(&mut [Literal], Location) -> EvalResult,
+
+    ///Whether it changes its first argument, which must then be a variable
+    pub mutates: bool
+}
+
+///Waits for the duration, or fails with why it cannot
+pub type SleepHandler = Box<dyn FnMut(Duration) -> Result<(), String> + Send>;
+
+lazy_static!(
+    ///What clock measures from, set by its first call
+    static ref START: Instant = Instant::now();
+
+    ///All builtin functions
+    pub static ref BUILTINS: Vec<Builtin> = vec![
+        //Output
+        Builtin { name: "print", check: check_print, eval: eval_print, mutates: false },
+        Builtin { name: "println", check: check_print, eval: eval_println, mutates: false },
+        Builtin { name: "eprint", check: check_print, eval: eval_eprint, mutates: false },
+        Builtin { name: "eprintln", check: check_print, eval: eval_eprintln, mutates: false },
+
+        //Input
+        Builtin { name: "read_line", check: check_read_line, eval: eval_read_line, mutates: false },
+        Builtin { name: "read_int", check: check_read_int, eval: eval_read_int, mutates: false },
+
+        //Environment
+        Builtin { name: "env", check: check_env, eval: eval_env, mutates: false },
+        Builtin { name: "args", check: check_program_args, eval: eval_program_args, mutates: false },
+        Builtin { name: "exit", check: check_exit, eval: eval_exit, mutates: false },
+
+        //Time
+        Builtin { name: "now", check: check_now, eval: eval_now, mutates: false },
+        Builtin { name: "now_ms", check: check_now_ms, eval: eval_now_ms, mutates: false },
+        Builtin { name: "clock", check: check_now, eval: eval_clock, mutates: false },
+        Builtin { name: "sleep_ms", check: check_sleep_ms, eval: eval_sleep_ms, mutates: false },
+
+        //Files
+        Builtin { name: "read_file", check: check_read_file, eval: eval_read_file, mutates: false },
+        Builtin { name: "write_file", check: check_write_file, eval: eval_write_file, mutates: false },
+        Builtin { name: "append_file", check: check_write_file, eval: eval_append_file, mutates: false },
+        Builtin { name: "file_exists", check: check_file_exists, eval: eval_file_exists, mutates: false },
+
+        //Math
+        Builtin { name: "sqrt", check: check_float_fn, eval: eval_sqrt, mutates: false },
+        Builtin { name: "pow", check: check_pow, eval: eval_pow, mutates: false },
+        Builtin { name: "abs", check: check_abs, eval: eval_abs, mutates: false },
+        Builtin { name: "floor", check: check_rounding, eval: eval_floor, mutates: false },
+        Builtin { name: "ceil", check: check_rounding, eval: eval_ceil, mutates: false },
+        Builtin { name: "round", check: check_rounding, eval: eval_round, mutates: false },
+        Builtin { name: "sin", check: check_float_fn, eval: eval_sin, mutates: false },
+        Builtin { name: "cos", check: check_float_fn, eval: eval_cos, mutates: false },
+        Builtin { name: "tan", check: check_float_fn, eval: eval_tan, mutates: false },
+        Builtin { name: "log", check: check_float_fn, eval: eval_log, mutates: false },
+        Builtin { name: "exp", check: check_float_fn, eval: eval_exp, mutates: false },
+        Builtin { name: "min", check: check_numbers, eval: eval_min, mutates: false },
+        Builtin { name: "max", check: check_numbers, eval: eval_max, mutates: false },
+        Builtin { name: "clamp", check: check_clamp, eval: eval_clamp, mutates: false },
+
+        //Values
+        Builtin { name: "typeof", check: check_typeof, eval: eval_typeof, mutates: false },
+        Builtin { name: "format", check: check_format, eval: eval_format, mutates: false },
+        Builtin { name: "to_string", check: check_to_string, eval: eval_to_string, mutates: false },
+        Builtin { name: "hash", check: check_hash, eval: eval_hash, mutates: false },
+
+        //Assertions
+        Builtin { name: "assert_eq", check: check_assert, eval: eval_assert_eq, mutates: false },
+        Builtin { name: "assert_ne", check: check_assert, eval: eval_assert_ne, mutates: false },
+
+        //Strings, arrays and sets
+        Builtin { name: "len", check: check_len, eval: eval_len, mutates: false },
+        Builtin { name: "contains", check: check_contains, eval: eval_contains, mutates: false },
+
+        //Arrays and sets
+        Builtin { name: "next", check: check_next, eval: eval_next, mutates: false },
+
+        //Strings
+        Builtin { name: "substring", check: check_substring, eval: eval_substring, mutates: false },
+        Builtin { name: "split", check: check_split, eval: eval_split, mutates: false },
+        Builtin { name: "trim", check: check_str_to_str, eval: eval_trim, mutates: false },
+        Builtin { name: "to_upper", check: check_case, eval: eval_to_upper, mutates: false },
+        Builtin { name: "to_lower", check: check_case, eval: eval_to_lower, mutates: false },
+        Builtin { name: "replace", check: check_replace, eval: eval_replace, mutates: false },
+        Builtin { name: "starts_with", check: check_starts_with, eval: eval_starts_with, mutates: false },
+
+        //Chars
+        Builtin { name: "is_digit", check: check_char_class, eval: eval_is_digit, mutates: false },
+        Builtin { name: "is_alpha", check: check_char_class, eval: eval_is_alpha, mutates: false },
+        Builtin { name: "is_whitespace", check: check_char_class, eval: eval_is_whitespace, mutates: false },
+
+        //Arrays
+        Builtin { name: "push", check: check_push, eval: eval_push, mutates: true },
+        Builtin { name: "pop", check: check_pop, eval: eval_pop, mutates: true },
+        Builtin { name: "insert", check: check_insert, eval: eval_insert, mutates: true },
+        Builtin { name: "remove", check: check_remove, eval: eval_remove, mutates: true },
+        Builtin { name: "map", check: check_map, eval: eval_map, mutates: false },
+        Builtin { name: "filter", check: check_filter, eval: eval_filter, mutates: false },
+        Builtin { name: "reduce", check: check_reduce, eval: eval_reduce, mutates: false },
+        Builtin { name: "sort", check: check_sort, eval: eval_sort, mutates: false },
+        Builtin { name: "sort_by", check: check_sort_by, eval: eval_sort_by, mutates: false },
+
+        //Sets. 'remove' is shared with arrays
+        Builtin { name: "to_set", check: check_to_set, eval: eval_to_set, mutates: false },
+        Builtin { name: "add", check: check_add, eval: eval_add, mutates: true },
+        Builtin { name: "union", check: check_set_operation, eval: eval_union, mutates: false },
+        Builtin { name: "intersect", check: check_set_operation, eval: eval_intersect, mutates: false },
+
+        //Tasks
+        Builtin { name: "spawn", check: check_spawn, eval: eval_spawn, mutates: false },
+        Builtin { name: "join", check: check_join, eval: eval_join, mutates: false },
+        Builtin { name: "channel", check: check_channel, eval: eval_channel, mutates: false },
+        Builtin { name: "send", check: check_send, eval: eval_send, mutates: false },
+        Builtin { name: "recv", check: check_recv, eval: eval_recv, mutates: false },
+
+        //Events, whose callbacks are called after the program by the event loop
+        Builtin { name: "on", check: check_on, eval: eval_on, mutates: false },
+        Builtin { name: "after", check: check_after, eval: eval_after, mutates: false },
+        Builtin { name: "emit", check: check_emit, eval: eval_emit, mutates: false },
+
+        //Regular expressions
+        #[cfg(feature = "regex")]
+        Builtin { name: "regex_match", check: check_regex_match, eval: eval_regex_match, mutates: false },
+        #[cfg(feature = "regex")]
+        Builtin { name: "regex_find", check: check_regex_find, eval: eval_regex_find, mutates: false },
+        #[cfg(feature = "regex")]
+        Builtin { name: "regex_replace", check: check_replace, eval: eval_regex_replace, mutates: false },
+
+        //HTTP
+        #[cfg(feature = "http")]
+        Builtin { name: "http_get", check: check_http_get, eval: eval_http_get, mutates: false },
+
+        //Random numbers
+        #[cfg(feature = "rand")]
+        Builtin { name: "random", check: check_random, eval: eval_random, mutates: false },
+        #[cfg(feature = "rand")]
+        Builtin { name: "random_int", check: check_random_int, eval: eval_random_int, mutates: false },
+        #[cfg(feature = "rand")]
+        Builtin { name: "shuffle", check: check_shuffle, eval: eval_shuffle, mutates: false },
+    ];
+);
+
+pub fn lookup_builtin(id: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == id)
+}
+
+lazy_static! {
+    ///The builtins by the index of the symbol of their name, so a call finds its builtin without comparing names
+    static ref BUILTIN_SYMBOLS: Vec<Option<&'static Builtin>> = {
+        let mut table = Vec::new();
+        for builtin in BUILTINS.iter() {
+            let index = Symbol::new(builtin.name).index();
+            if table.len() <= index {
+                table.resize(index + 1, None);
+            }
+            table[index] = Some(builtin);
+        }
+        table
+    };
+}
+
+///The builtin with the name of the symbol, like lookup_builtin but without searching
+pub fn lookup_builtin_symbol(id: &Symbol) -> Option<&'static Builtin> {
+    BUILTIN_SYMBOLS.get(id.index()).copied().flatten()
+}
+
+///Named values that are always available. Variables and functions with the same name take precedence
+pub fn lookup_constant(id: &str) -> Option<Literal> {
+    match id {
+        "PI" => Some(Float(std::f64::consts::PI)),
+        "E" => Some(Float(std::f64::consts::E)),
+        _ => None
+    }
+}
+
+///Checks the arguments against a fixed list of parameter types
+fn signature(args: &[Type], params: &[Type], ret_type: Type) -> Result<Type, String> {
+    if args.len() != params.len() {
+        return Err(format!("takes {} arguments, got {}", params.len(), args.len()))
+    }
+    for (arg, param) in args.iter().zip(params) {
+        if !arg.matches(param) {
+            return Err(format!("expects '{param}', got '{arg}'"))
+        }
+    }
+    Ok(ret_type)
+}
+
+fn check_len(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Array(_)] | [Type::Set(_)] => Ok(Type::Int),
+        _ => signature(args, &[Type::Str], Type::Int)
+    }
+}
+
+fn eval_len(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s)] => Ok(Int(s.chars().count() as i64)),
+        [Array(lits)] | [Set(lits)] => Ok(Int(lits.len() as i64)),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_substring(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Int, Type::Int], Type::Str)
+}
+
+///Chars from start up to, but not including, end
+fn eval_substring(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s), Int(start), Int(end)] => {
+            let count = s.chars().count() as i64;
+            if *start < 0 || start > end || *end > count {
+                return Err(Signal::Error(format!("Cannot take substring {start} to {end} of a string of length {count}"), loc))
+            }
+            Ok(Str(s.chars().skip(*start as usize).take((*end - *start) as usize).collect::<String>().into()))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_split(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Str], Type::Array(Box::new(Type::Str)))
+}
+
+fn eval_split(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(_), Str(sep)] if sep.is_empty() => Err(Signal::Error(String::from("Cannot split on an empty string"), loc)),
+        [Str(s), Str(sep)] => Ok(Array(Rc::new(s.split(&sep[..]).map(|part| Str(Rc::new(part.to_string()))).collect()))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_str_to_str(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str], Type::Str)
+}
+
+fn eval_trim(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s)] => Ok(Str(Rc::new(s.trim().to_string()))),
+        _ => Err(type_error(loc))
+    }
+}
+
+///to_upper and to_lower work on a string or a char
+fn check_case(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Char] => Ok(Type::Char),
+        _ => signature(args, &[Type::Str], Type::Str)
+    }
+}
+
+///The char a char changes case to. It is left as it is when it would become more than one char, as 'ß' does
+fn change_case(c: char, mut changed: impl Iterator<Item = char>) -> char {
+    match (changed.next(), changed.next()) {
+        (Some(changed), None) => changed,
+        _ => c
+    }
+}
+
+fn eval_to_upper(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s)] => Ok(Str(s.to_uppercase().into())),
+        [Char(c)] => Ok(Char(change_case(*c, c.to_uppercase()))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn eval_to_lower(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s)] => Ok(Str(s.to_lowercase().into())),
+        [Char(c)] => Ok(Char(change_case(*c, c.to_lowercase()))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_char_class(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Char], Type::Bool)
+}
+
+///'0' to '9'
+fn eval_is_digit(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Char(c)] => Ok(Bool(c.is_ascii_digit())),
+        _ => Err(type_error(loc))
+    }
+}
+
+///Letters in any alphabet
+fn eval_is_alpha(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Char(c)] => Ok(Bool(c.is_alphabetic())),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn eval_is_whitespace(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Char(c)] => Ok(Bool(c.is_whitespace())),
+        _ => Err(type_error(loc))
+    }
+}
+
+///A part of a string can be a string or a char
+fn check_contains(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Str, Type::Char] => Ok(Type::Bool),
+        [Type::Array(elem), value] | [Type::Set(elem), value] => if value.matches(elem) {
+            Ok(Type::Bool)
+        } else {
+            Err(format!("cannot look for '{value}' in '{}'", args[0]))
+        },
+        _ => signature(args, &[Type::Str, Type::Str], Type::Bool)
+    }
+}
+
+fn eval_contains(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s), Str(part)] => Ok(Bool(s.contains(&part[..]))),
+        [Str(s), Char(c)] => Ok(Bool(s.contains(*c))),
+        [Array(lits), lit] | [Set(lits), lit] => Ok(Bool(lits.contains(lit))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_replace(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Str, Type::Str], Type::Str)
+}
+
+fn eval_replace(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(_), Str(from), Str(_)] if from.is_empty() => Err(Signal::Error(String::from("Cannot replace an empty string"), loc)),
+        [Str(s), Str(from), Str(to)] => Ok(Str(s.replace(&from[..], to).into())),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_starts_with(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Str], Type::Bool)
+}
+
+fn eval_starts_with(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s), Str(prefix)] => Ok(Bool(s.starts_with(&prefix[..]))),
+        _ => Err(type_error(loc))
+    }
+}
+
+///print(value) or print(format: string, values...)
+fn check_print(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [] | [_] | [Type::Str, ..] => Ok(Type::Unit),
+        [typ, ..] => Err(format!("expects a format string before the values, got '{typ}'"))
+    }
+}
+
+///A single value is printed as it is. With more, the first is a format string
+fn format_output(args: &[Literal], loc: Location) -> Result<String, Signal> {
+    match args {
+        [] => Ok(String::new()),
+        [lit] => Ok(lit.render()),
+        [Str(format), values @ ..] => format_values(format, values, loc),
+        _ => Err(type_error(loc))
+    }
+}
+
+///Replaces each '{}' in the format string by the next value. '{{' and '}}' become '{' and '}'
+fn format_values(format: &str, values: &[Literal], loc: Location) -> Result<String, Signal> {
+    let mut res = String::new();
+    let mut rest = values.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                res.push(c);
+            },
+            ('{', Some('}')) => {
+                chars.next();
+                match rest.next() {
+                    Some(lit) => res.push_str(&lit.render()),
+                    None => return Err(Signal::Error(format!("Format string has more '{{}}' than the {} values given", values.len()), loc))
+                }
+            },
+            _ => res.push(c)
+        }
+    }
+    if rest.next().is_some() {
+        return Err(Signal::Error(format!("Format string has fewer '{{}}' than the {} values given", values.len()), loc))
+    }
+    Ok(res)
+}
+
+///format(format: string, values...)
+fn check_format(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Str, ..] => Ok(Type::Str),
+        [typ, ..] => Err(format!("expects a format string, got '{typ}'")),
+        [] => Err(String::from("expects a format string"))
+    }
+}
+
+fn eval_format(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(format), values @ ..] => Ok(Str(format_values(format, values, loc)?.into())),
+        _ => Err(type_error(loc))
+    }
+}
+
+///Reads the hooks of the run on this thread
+fn with_hooks<R>(f: impl FnOnce(&Hooks) -> R) -> R {
+    eval::with_options(|options| f(&options.hooks))
+}
+
+fn write_to(output: Output, text: String, loc: Location) -> EvalResult {
+    let mut output = output.lock().unwrap();
+    output.write_all(text.as_bytes())
+        .map(|_| Unit)
+        .map_err(|e| Signal::Error(format!("Could not write output: {e}"), loc))
+}
+
+///Shows what was printed without a newline, before waiting
+fn flush_output() {
+    with_hooks(|hooks| hooks.output.clone()).lock().unwrap().flush().ok();
+}
+
+fn eval_print(args: &mut [Literal], loc: Location) -> EvalResult {
+    write_to(with_hooks(|hooks| hooks.output.clone()), format_output(args, loc)?, loc)
+}
+
+fn eval_println(args: &mut [Literal], loc: Location) -> EvalResult {
+    write_to(with_hooks(|hooks| hooks.output.clone()), format!("{}\n", format_output(args, loc)?), loc)
+}
+
+fn eval_eprint(args: &mut [Literal], loc: Location) -> EvalResult {
+    write_to(with_hooks(|hooks| hooks.error_output.clone()), format_output(args, loc)?, loc)
+}
+
+fn eval_eprintln(args: &mut [Literal], loc: Location) -> EvalResult {
+    write_to(with_hooks(|hooks| hooks.error_output.clone()), format!("{}\n", format_output(args, loc)?), loc)
+}
+
+///The next line of input without the line ending
+fn next_line(loc: Location) -> Result<String, Signal> {
+    flush_output();
+
+    let mut line = String::new();
+    let input = with_hooks(|hooks| hooks.input.clone());
+    let read = input.lock().unwrap().read_line(&mut line);
+    match read {
+        Ok(0) => Err(Signal::Error(String::from("No more input to read"), loc)),
+        Ok(_) => Ok(line.trim_end_matches(['\n', '\r']).to_string()),
+        Err(e) => Err(Signal::Error(format!("Could not read input: {e}"), loc))
+    }
+}
+
+fn check_read_line(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[], Type::Str)
+}
+
+fn eval_read_line(_: &mut [Literal], loc: Location) -> EvalResult {
+    Ok(Str(next_line(loc)?.into()))
+}
+
+fn check_read_int(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[], Type::Int)
+}
+
+fn eval_read_int(_: &mut [Literal], loc: Location) -> EvalResult {
+    let line = next_line(loc)?;
+    match line.trim().parse::<i64>() {
+        Ok(i) => Ok(Int(i)),
+        Err(_) => Err(Signal::Error(format!("Expected an int as input, got '{line}'"), loc))
+    }
+}
+
+///Fails if the options of the run do not allow the group of builtins
+fn check_allowed(allowed: fn(&ExecutionOptions) -> bool, what: &str, loc: Location) -> Result<(), Signal> {
+    if eval::with_options(allowed) {
+        Ok(())
+    } else {
+        Err(Signal::Error(format!("{what} is not allowed"), loc))
+    }
+}
+
+fn check_read_file(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str], Type::Str)
+}
+
+fn eval_read_file(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.io, "File access", loc)?;
+    match args {
+        [Str(path)] => fs::read_to_string(&path[..])
+            .map(|content| Str(content.into()))
+            .map_err(|e| Signal::Error(format!("Could not read '{path}': {e}"), loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_write_file(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Str], Type::Unit)
+}
+
+///Creates the file, or replaces what is in it
+fn eval_write_file(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.io, "File access", loc)?;
+    match args {
+        [Str(path), Str(content)] => fs::write(&path[..], &content[..])
+            .map(|_| Unit)
+            .map_err(|e| Signal::Error(format!("Could not write '{path}': {e}"), loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+///Creates the file, or adds to the end of it
+fn eval_append_file(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.io, "File access", loc)?;
+    match args {
+        [Str(path), Str(content)] => fs::OpenOptions::new().append(true).create(true).open(&path[..])
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map(|_| Unit)
+            .map_err(|e| Signal::Error(format!("Could not write '{path}': {e}"), loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_file_exists(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str], Type::Bool)
+}
+
+fn eval_file_exists(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.io, "File access", loc)?;
+    match args {
+        [Str(path)] => Ok(Bool(Path::new(&path[..]).is_file())),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_env(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str], Type::Optional(Box::new(Type::Str)))
+}
+
+///The value of an environment variable, none if it is not set or not unicode
+fn eval_env(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.io, "Environment variable access", loc)?;
+    match args {
+        [Str(name)] => Ok(Optional(std::env::var(&name[..]).ok().map(|value| Box::new(Str(value.into()))))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_program_args(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[], Type::Array(Box::new(Type::Str)))
+}
+
+fn eval_program_args(_: &mut [Literal], _: Location) -> EvalResult {
+    Ok(Array(Rc::new(with_hooks(|hooks| hooks.args.iter().map(|arg| Str(Rc::new(arg.clone()))).collect()))))
+}
+
+fn check_exit(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Int], Type::Unit)
+}
+
+///Ends the program with the status code
+fn eval_exit(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Int(code)] => match i32::try_from(*code) {
+            Ok(code) => Err(Signal::Exit(code)),
+            Err(_) => Err(Signal::Error(format!("Exit status must fit in 32 bits, got {code}"), loc))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_now(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[], Type::Float)
+}
+
+///Seconds since 1970-01-01 UTC
+fn eval_now(_: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.time, "Reading the clock", loc)?;
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(Float(since_epoch.as_secs_f64()))
+}
+
+fn check_now_ms(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[], Type::Int)
+}
+
+///Milliseconds since 1970-01-01 UTC
+fn eval_now_ms(_: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.time, "Reading the clock", loc)?;
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(Int(since_epoch.as_millis() as i64))
+}
+
+///Seconds on a clock that never goes back, for measuring time between two calls
+fn eval_clock(_: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.time, "Reading the clock", loc)?;
+    Ok(Float(START.elapsed().as_secs_f64()))
+}
+
+fn check_typeof(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Any], Type::Str)
+}
+
+///The name of the type of the value
+fn eval_typeof(args: &mut [Literal], _: Location) -> EvalResult {
+    Ok(Str(args[0].get_type().to_string().into()))
+}
+
+fn check_sleep_ms(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Int], Type::Unit)
+}
+
+fn eval_sleep_ms(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.time, "Sleeping", loc)?;
+    match args {
+        [Int(ms)] => {
+            let ms = u64::try_from(*ms)
+                .map_err(|_| Signal::Error(format!("Cannot sleep for a negative time, got {ms}"), loc))?;
+            flush_output();
+            let sleep = with_hooks(|hooks| hooks.sleep.clone());
+            let slept = (sleep.lock().unwrap())(Duration::from_millis(ms));
+            slept
+                .map(|_| Unit)
+                .map_err(|msg| Signal::Error(msg, loc))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_to_string(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Any], Type::Str)
+}
+
+///The value as println prints it
+fn eval_to_string(args: &mut [Literal], _: Location) -> EvalResult {
+    Ok(Str(args[0].render().into()))
+}
+
+fn check_hash(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Any], Type::Int)
+}
+
+///Equal values give the same hash, also between runs and builds
+fn eval_hash(args: &mut [Literal], _: Location) -> EvalResult {
+    let mut hash = FNV_OFFSET;
+    hash_literal(&args[0], &mut hash);
+    Ok(Int(hash as i64))
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+///Adds bytes to an FNV-1a hash
+fn hash_bytes(bytes: &[u8], hash: &mut u64) {
+    for byte in bytes {
+        *hash ^= *byte as u64;
+        *hash = hash.wrapping_mul(FNV_PRIME);
+    }
+}
+
+///Adds a value to the hash. Each kind of value starts with its own tag, and lengths are included so nested values cannot collide by shifting
+fn hash_literal(lit: &Literal, hash: &mut u64) {
+    match lit {
+        Int(i) => {
+            hash_bytes(&[0], hash);
+            hash_bytes(&i.to_le_bytes(), hash);
+        },
+        Float(f) => {
+            //0.0 and -0.0 are equal
+            let f = if *f == 0.0 { 0.0 } else { *f };
+            hash_bytes(&[1], hash);
+            hash_bytes(&f.to_bits().to_le_bytes(), hash);
+        },
+        Bool(b) => hash_bytes(&[2, *b as u8], hash),
+        Char(c) => {
+            hash_bytes(&[3], hash);
+            hash_bytes(&(*c as u32).to_le_bytes(), hash);
+        },
+        Str(s) => {
+            hash_bytes(&[4], hash);
+            hash_bytes(&s.len().to_le_bytes(), hash);
+            hash_bytes(s.as_bytes(), hash);
+        },
+        Optional(None) => hash_bytes(&[5], hash),
+        Optional(Some(lit)) => {
+            hash_bytes(&[6], hash);
+            hash_literal(lit, hash);
+        },
+        Tuple(lits) => hash_sequence(7, lits.iter(), hash),
+        Array(lits) => hash_sequence(8, lits.iter(), hash),
+        Set(lits) => hash_sequence(9, lits.iter(), hash),
+        Struct(value) => {
+            hash_bytes(&[10], hash);
+            hash_bytes(&value.name.len().to_le_bytes(), hash);
+            hash_bytes(value.name.as_bytes(), hash);
+            hash_sequence(10, value.fields.iter().map(|(_, lit)| lit), hash);
+        },
+        Fun(closure) => {
+            //Functions are equal when they are the same declaration
+            hash_bytes(&[11], hash);
+            hash_bytes(&closure.fun.loc.line.to_le_bytes(), hash);
+            hash_bytes(&closure.fun.loc.col.to_le_bytes(), hash);
+        },
+        Unit => hash_bytes(&[12], hash),
+        Task(id) => {
+            hash_bytes(&[13], hash);
+            hash_bytes(&id.to_le_bytes(), hash);
+        },
+        Channel(channel) => {
+            //Channels are equal when they are the same channel
+            hash_bytes(&[14], hash);
+            hash_bytes(&(Arc::as_ptr(channel) as usize).to_le_bytes(), hash);
+        },
+    }
+}
+
+fn hash_sequence<'a>(tag: u8, lits: impl ExactSizeIterator<Item = &'a Literal>, hash: &mut u64) {
+    hash_bytes(&[tag], hash);
+    hash_bytes(&lits.len().to_le_bytes(), hash);
+    for lit in lits {
+        hash_literal(lit, hash);
+    }
+}
+
+///Both values must have the same type
+fn check_assert(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [left, right] if !left.matches(right) => Err(format!("cannot compare '{left}' with '{right}'")),
+        [left, _] => signature(args, &[left.clone(), left.clone()], Type::Unit),
+        _ => Err(format!("takes 2 arguments, got {}", args.len()))
+    }
+}
+
+fn eval_assert_eq(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [left, right] if left == right => Ok(Unit),
+        [left, right] => Err(Signal::Error(format!("Assertion failed, {left} is not equal to {right}"), loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn eval_assert_ne(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [left, right] if left != right => Ok(Unit),
+        [left, _] => Err(Signal::Error(format!("Assertion failed, both values are {left}"), loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+///A number as a float
+fn to_float(lit: &Literal, loc: Location) -> Result<f64, Signal> {
+    match lit {
+        Int(i) => Ok(*i as f64),
+        Float(f) => Ok(*f),
+        _ => Err(type_error(loc))
+    }
+}
+
+///Functions from an int or float to a float
+fn check_float_fn(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Int] => Ok(Type::Float),
+        _ => signature(args, &[Type::Float], Type::Float)
+    }
+}
+
+fn eval_sqrt(args: &mut [Literal], loc: Location) -> EvalResult {
+    Ok(Float(to_float(&args[0], loc)?.sqrt()))
+}
+
+fn eval_sin(args: &mut [Literal], loc: Location) -> EvalResult {
+    Ok(Float(to_float(&args[0], loc)?.sin()))
+}
+
+fn eval_cos(args: &mut [Literal], loc: Location) -> EvalResult {
+    Ok(Float(to_float(&args[0], loc)?.cos()))
+}
+
+fn eval_tan(args: &mut [Literal], loc: Location) -> EvalResult {
+    Ok(Float(to_float(&args[0], loc)?.tan()))
+}
+
+///The natural logarithm
+fn eval_log(args: &mut [Literal], loc: Location) -> EvalResult {
+    Ok(Float(to_float(&args[0], loc)?.ln()))
+}
+
+fn eval_exp(args: &mut [Literal], loc: Location) -> EvalResult {
+    Ok(Float(to_float(&args[0], loc)?.exp()))
+}
+
+///A number of ints gives an int. With a float among them they are all used as floats
+fn number_signature(args: &[Type], count: usize) -> Result<Type, String> {
+    if args.len() != count {
+        return Err(format!("takes {count} arguments, got {}", args.len()))
+    }
+    if let Some(arg) = args.iter().find(|arg| !matches!(arg, Type::Int | Type::Float)) {
+        return Err(format!("expects 'int' or 'float', got '{arg}'"))
+    }
+    if args.iter().all(|arg| *arg == Type::Int) {
+        Ok(Type::Int)
+    } else {
+        Ok(Type::Float)
+    }
+}
+
+fn check_numbers(args: &[Type]) -> Result<Type, String> {
+    number_signature(args, 2)
+}
+
+fn eval_min(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Int(left), Int(right)] => Ok(Int(*left.min(right))),
+        [left, right] => Ok(Float(to_float(left, loc)?.min(to_float(right, loc)?))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn eval_max(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Int(left), Int(right)] => Ok(Int(*left.max(right))),
+        [left, right] => Ok(Float(to_float(left, loc)?.max(to_float(right, loc)?))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_clamp(args: &[Type]) -> Result<Type, String> {
+    number_signature(args, 3)
+}
+
+///The value limited to be from low to high
+fn eval_clamp(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Int(_), Int(low), Int(high)] if *low > *high => Err(Signal::Error(format!("Cannot clamp with {low} above {high}"), loc)),
+        [Int(value), Int(low), Int(high)] => Ok(Int(*value.clamp(low, high))),
+        [value, low, high] => {
+            let (value, low, high) = (to_float(value, loc)?, to_float(low, loc)?, to_float(high, loc)?);
+            if low > high || low.is_nan() || high.is_nan() {
+                Err(Signal::Error(format!("Cannot clamp with {low} above {high}"), loc))
+            } else {
+                Ok(Float(value.clamp(low, high)))
+            }
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///An int to an int power is an int, anything else is a float
+fn check_pow(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Int, Type::Int] => Ok(Type::Int),
+        [Type::Int | Type::Float, Type::Int | Type::Float] => Ok(Type::Float),
+        _ => signature(args, &[Type::Float, Type::Float], Type::Float)
+    }
+}
+
+fn eval_pow(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Int(base), Int(exp)] => {
+            let exp = u32::try_from(*exp)
+                .map_err(|_| Signal::Error(format!("Int power must be between 0 and {}, got {exp}", u32::MAX), loc))?;
+            int_result(base.checked_pow(exp), base.wrapping_pow(exp), base.saturating_pow(exp), || format!("pow({base}, {exp})"), loc)
+        },
+        [base, exp] => Ok(Float(to_float(base, loc)?.powf(to_float(exp, loc)?))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_abs(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Int] => Ok(Type::Int),
+        _ => signature(args, &[Type::Float], Type::Float)
+    }
+}
+
+fn eval_abs(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Int(i)] => int_result(i.checked_abs(), i.wrapping_abs(), i.saturating_abs(), || format!("abs({i})"), loc),
+        [Float(f)] => Ok(Float(f.abs())),
+        _ => Err(type_error(loc))
+    }
+}
+
+///floor, ceil and round go from a float to an int
+fn check_rounding(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Int] => Ok(Type::Int),
+        _ => signature(args, &[Type::Float], Type::Int)
+    }
+}
+
+///Rounds with the given function, failing when the result does not fit in an int
+fn round_with(args: &[Literal], round: fn(f64) -> f64, loc: Location) -> EvalResult {
+    let f = match &args[0] {
+        Int(i) => return Ok(Int(*i)),
+        lit => round(to_float(lit, loc)?)
+    };
+    if f.is_finite() && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+        Ok(Int(f as i64))
+    } else {
+        Err(Signal::Error(format!("{f} does not fit in an int"), loc))
+    }
+}
+
+fn eval_floor(args: &mut [Literal], loc: Location) -> EvalResult {
+    round_with(args, f64::floor, loc)
+}
+
+fn eval_ceil(args: &mut [Literal], loc: Location) -> EvalResult {
+    round_with(args, f64::ceil, loc)
+}
+
+fn eval_round(args: &mut [Literal], loc: Location) -> EvalResult {
+    round_with(args, f64::round, loc)
+}
+
+///The element type of the array that is the first argument
+fn element_type(args: &[Type]) -> Result<Type, String> {
+    match args.first() {
+        Some(Type::Array(elem)) => Ok(*elem.clone()),
+        Some(typ) => Err(format!("expects an array, got '{typ}'")),
+        None => Err(String::from("expects an array"))
+    }
+}
+
+fn check_push(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    signature(args, &[Type::Array(Box::new(elem.clone())), elem], Type::Unit)
+}
+
+fn eval_push(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits), lit] => Rc::make_mut(lits).push(lit.clone()),
+        _ => return Err(type_error(loc))
+    }
+    Ok(Unit)
+}
+
+fn check_pop(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    signature(args, &[Type::Array(Box::new(elem.clone()))], Type::Optional(Box::new(elem)))
+}
+
+///Removes the last element. Gives none if the array is empty
+fn eval_pop(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits)] => Ok(Optional(Rc::make_mut(lits).pop().map(Box::new))),
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_insert(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    signature(args, &[Type::Array(Box::new(elem.clone())), Type::Int, elem], Type::Unit)
+}
+
+///Inserts before the index. The index can be the length of the array, to insert at the end
+fn eval_insert(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits), Int(index), lit] => {
+            if *index < 0 || *index as usize > lits.len() {
+                return Err(Signal::Error(format!("Cannot insert at index {index} of an array of length {}", lits.len()), loc))
+            }
+            Rc::make_mut(lits).insert(*index as usize, lit.clone());
+            Ok(Unit)
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///remove(array: [T], index: int): T or remove(set: set<T>, value: T): bool
+fn check_remove(args: &[Type]) -> Result<Type, String> {
+    if let Some(Type::Set(elem)) = args.first() {
+        return signature(args, &[args[0].clone(), *elem.clone()], Type::Bool)
+    }
+    let elem = element_type(args)?;
+    signature(args, &[Type::Array(Box::new(elem.clone())), Type::Int], elem)
+}
+
+///Removes the element at the index of an array and gives it.
+///For sets it removes the value, and tells whether it was there
+fn eval_remove(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Set(lits), lit] => match lits.iter().position(|elem| elem == lit) {
+            Some(index) => {
+                Rc::make_mut(lits).remove(index);
+                Ok(Bool(true))
+            },
+            None => Ok(Bool(false))
+        },
+        [Array(lits), Int(index)] => {
+            if *index < 0 || *index as usize >= lits.len() {
+                return Err(Signal::Error(format!("Cannot remove index {index} of an array of length {}", lits.len()), loc))
+            }
+            Ok(Rc::make_mut(lits).remove(*index as usize))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///Parameter types and return type of the function argument at the index
+fn function_type(args: &[Type], index: usize) -> Result<(Vec<Type>, Type), String> {
+    match args.get(index) {
+        Some(Type::Fun(params, ret_type)) => Ok((params.clone(), *ret_type.clone())),
+        Some(typ) => Err(format!("expects a function as argument {}, got '{typ}'", index + 1)),
+        None => Err(format!("expects a function as argument {}", index + 1))
+    }
+}
+
+///map(array: [T], f: fun(T): U): [U]
+fn check_map(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    let (_, ret_type) = function_type(args, 1)?;
+    let fun = Type::Fun(vec![elem.clone()], Box::new(ret_type.clone()));
+    signature(args, &[Type::Array(Box::new(elem)), fun.clone()], Type::Array(Box::new(ret_type)))
+        .map_err(|_| format!("expects '{fun}' to map '{}'", args[0]))
+}
+
+fn eval_map(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits), Fun(closure)] => {
+            let mut res = Vec::new();
+            for lit in lits.iter() {
+                res.push(call_closure(closure, vec![lit.clone()], loc)?);
+            }
+            Ok(Array(res.into()))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///filter(array: [T], keep: fun(T): bool): [T]
+fn check_filter(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    let fun = Type::Fun(vec![elem.clone()], Box::new(Type::Bool));
+    signature(args, &[Type::Array(Box::new(elem)), fun.clone()], args[0].clone())
+        .map_err(|_| format!("expects '{fun}' to filter '{}'", args[0]))
+}
+
+fn eval_filter(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits), Fun(closure)] => {
+            let mut res = Vec::new();
+            for lit in lits.iter() {
+                if call_closure(closure, vec![lit.clone()], loc)? == Bool(true) {
+                    res.push(lit.clone());
+                }
+            }
+            Ok(Array(res.into()))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///reduce(array: [T], initial: U, f: fun(U, T): U): U
+fn check_reduce(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    let acc = match args.get(1) {
+        Some(acc) => acc.clone(),
+        None => return Err(format!("takes 3 arguments, got {}", args.len()))
+    };
+    //The initial value can be a 'none' or '[]' that the function gives a type
+    let acc = match function_type(args, 2) {
+        Ok((_, ret_type)) => acc.join(&ret_type).unwrap_or(acc),
+        Err(_) => acc
+    };
+    let fun = Type::Fun(vec![acc.clone(), elem.clone()], Box::new(acc.clone()));
+    signature(args, &[Type::Array(Box::new(elem)), acc.clone(), fun.clone()], acc)
+        .map_err(|msg| if args.len() == 3 { format!("expects '{fun}' to reduce '{}'", args[0]) } else { msg })
+}
+
+fn eval_reduce(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits), acc, Fun(closure)] => {
+            let mut acc = acc.clone();
+            for lit in lits.iter() {
+                acc = call_closure(closure, vec![acc, lit.clone()], loc)?;
+            }
+            Ok(acc)
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///sort(array: [T]): [T] where T can be compared with '<'
+fn check_sort(args: &[Type]) -> Result<Type, String> {
+    match element_type(args)? {
+        Type::Int | Type::Float | Type::Char | Type::Str | Type::Bool | Type::Any => signature(args, &[args[0].clone()], args[0].clone()),
+        elem => Err(format!("cannot compare '{elem}' values, use 'sort_by'"))
+    }
+}
+
+///None for values that cannot be compared
+fn compare(left: &Literal, right: &Literal) -> Option<Ordering> {
+    Some(match (left, right) {
+        (Int(left), Int(right)) => left.cmp(right),
+        //NaN goes after every other float, so the rest are still in order
+        (Float(left), Float(right)) => left.partial_cmp(right).unwrap_or_else(|| left.is_nan().cmp(&right.is_nan())),
+        (Char(left), Char(right)) => left.cmp(right),
+        (Str(left), Str(right)) => left.cmp(right),
+        (Bool(left), Bool(right)) => left.cmp(right),
+        _ => return None
+    })
+}
+
+///Gives a sorted copy. Equal elements keep their order
+fn eval_sort(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits)] => {
+            let mut lits = lits.to_vec();
+            let mut comparable = true;
+            lits.sort_by(|left, right| compare(left, right).unwrap_or_else(|| {
+                comparable = false;
+                Ordering::Equal
+            }));
+            if !comparable {
+                return Err(type_error(loc))
+            }
+            Ok(Array(lits.into()))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///sort_by(array: [T], cmp: fun(T, T): int): [T]
+fn check_sort_by(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    let fun = Type::Fun(vec![elem.clone(), elem], Box::new(Type::Int));
+    signature(args, &[args[0].clone(), fun.clone()], args[0].clone())
+        .map_err(|_| format!("expects '{fun}' to sort '{}'", args[0]))
+}
+
+///The comparator gives a negative number if the first element goes first, and a positive number if it goes last.
+///Equal elements keep their order
+fn eval_sort_by(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits), Fun(closure)] => {
+            let mut lits = lits.to_vec();
+            //The first signal from the comparator stops the sorting
+            let mut signal = None;
+            lits.sort_by(|left, right| {
+                if signal.is_some() {
+                    return Ordering::Equal
+                }
+                match call_closure(closure, vec![left.clone(), right.clone()], loc) {
+                    Ok(Int(order)) => order.cmp(&0),
+                    Ok(_) => {
+                        signal = Some(type_error(loc));
+                        Ordering::Equal
+                    },
+                    Err(sig) => {
+                        signal = Some(sig);
+                        Ordering::Equal
+                    }
+                }
+            });
+            match signal {
+                Some(signal) => Err(signal),
+                None => Ok(Array(lits.into()))
+            }
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_to_set(args: &[Type]) -> Result<Type, String> {
+    let elem = element_type(args)?;
+    signature(args, &[args[0].clone()], Type::Set(Box::new(elem)))
+}
+
+///The values of the array without duplicates, in the order they first appear
+fn eval_to_set(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits)] => {
+            let mut set = Vec::new();
+            for lit in lits.iter() {
+                if !set.contains(lit) {
+                    set.push(lit.clone());
+                }
+            }
+            Ok(Set(set.into()))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///next(a: [T]): option<(T, [T])>, the iterator protocol of for-in loops
+fn check_next(args: &[Type]) -> Result<Type, String> {
+    let elem = match args.first() {
+        Some(Type::Set(elem)) => *elem.clone(),
+        _ => element_type(args)?
+    };
+    signature(args, &[args[0].clone()], Type::Optional(Box::new(Type::Tuple(vec![elem, args[0].clone()]))))
+}
+
+///The first element and the rest
+fn eval_next(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Array(lits)] | [Set(lits)] if lits.is_empty() => Ok(Optional(None)),
+        [Array(lits)] => Ok(Optional(Some(Box::new(Tuple(Rc::new(vec![lits[0].clone(), Array(Rc::new(lits[1..].to_vec()))])))))),
+        [Set(lits)] => Ok(Optional(Some(Box::new(Tuple(Rc::new(vec![lits[0].clone(), Set(Rc::new(lits[1..].to_vec()))])))))),
+        _ => Err(type_error(loc))
+    }
+}
+
+///The element type of the set that is the first argument
+fn set_element_type(args: &[Type]) -> Result<Type, String> {
+    match args.first() {
+        Some(Type::Set(elem)) => Ok(*elem.clone()),
+        Some(typ) => Err(format!("expects a set, got '{typ}'")),
+        None => Err(String::from("expects a set"))
+    }
+}
+
+fn check_add(args: &[Type]) -> Result<Type, String> {
+    let elem = set_element_type(args)?;
+    signature(args, &[args[0].clone(), elem], Type::Bool)
+}
+
+///Adds the value if it is not in the set already, and tells whether it was added
+fn eval_add(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Set(lits), lit] => if lits.contains(lit) {
+            Ok(Bool(false))
+        } else {
+            Rc::make_mut(lits).push(lit.clone());
+            Ok(Bool(true))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_set_operation(args: &[Type]) -> Result<Type, String> {
+    set_element_type(args)?;
+    let set = args.get(1).and_then(|other| args[0].join(other)).unwrap_or_else(|| args[0].clone());
+    signature(args, &[set.clone(), set.clone()], set)
+}
+
+fn eval_union(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Set(left), Set(right)] => {
+            let mut set = left.clone();
+            for lit in right.iter() {
+                if !set.contains(lit) {
+                    Rc::make_mut(&mut set).push(lit.clone());
+                }
+            }
+            Ok(Set(set))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+fn eval_intersect(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Set(left), Set(right)] => Ok(Set(Rc::new(left.iter().filter(|lit| right.contains(lit)).cloned().collect()))),
+        _ => Err(type_error(loc))
+    }
+}
+
+///spawn(f: fun(): T): task<T>
+fn check_spawn(args: &[Type]) -> Result<Type, String> {
+    let (params, ret_type) = function_type(args, 0)?;
+    if !params.is_empty() {
+        return Err(format!("expects a function without parameters, got '{}'", args[0]))
+    }
+    signature(args, &[args[0].clone()], Type::Task(Box::new(ret_type)))
+}
+
+///Calls the function on another thread, with a copy of the values it can see
+fn eval_spawn(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Fun(closure)] => task::spawn(closure, loc).map(Task).map_err(|msg| Signal::Error(msg, loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+///join(task: task<T>): T
+fn check_join(args: &[Type]) -> Result<Type, String> {
+    match args {
+        [Type::Task(ret_type)] => Ok(*ret_type.clone()),
+        [typ] => Err(format!("expects a task, got '{typ}'")),
+        _ => Err(format!("takes 1 argument, got {}", args.len()))
+    }
+}
+
+///Waits for the task to end
+fn eval_join(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Task(id)] => task::join(*id, loc),
+        _ => Err(type_error(loc))
+    }
+}
+
+///channel(): chan<T>. T is the type of the first value sent
+fn check_channel(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[], Type::Channel(Box::new(Type::Any)))
+}
+
+fn eval_channel(_: &mut [Literal], _: Location) -> EvalResult {
+    Ok(Channel(Arc::new(task::Channel::new())))
+}
+
+///The element type of the channel argument
+fn channel_type(args: &[Type]) -> Result<Type, String> {
+    match args.first() {
+        Some(Type::Channel(elem)) => Ok(*elem.clone()),
+        Some(typ) => Err(format!("expects a channel, got '{typ}'")),
+        None => Err(String::from("expects a channel"))
+    }
+}
+
+///send(ch: chan<T>, value: T): unit
+fn check_send(args: &[Type]) -> Result<Type, String> {
+    let elem = match (channel_type(args)?, args.get(1)) {
+        (Type::Any, Some(value)) => value.clone(),
+        (elem, _) => elem
+    };
+    signature(args, &[Type::Channel(Box::new(elem.clone())), elem], Type::Unit)
+}
+
+///Sends a copy of the value
+fn eval_send(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Channel(channel), value] => channel.send(value).map(|_| Unit).map_err(|msg| Signal::Error(msg, loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+///recv(ch: chan<T>): T
+fn check_recv(args: &[Type]) -> Result<Type, String> {
+    let elem = channel_type(args)?;
+    signature(args, &[Type::Channel(Box::new(elem.clone()))], elem)
+}
+
+///Waits until a value is sent, and takes it
+fn eval_recv(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Channel(channel)] => channel.recv(loc),
+        _ => Err(type_error(loc))
+    }
+}
+
+///on(event: string, f: fun(string): T): unit
+fn check_on(args: &[Type]) -> Result<Type, String> {
+    let (_, ret_type) = function_type(args, 1)?;
+    signature(args, &[Type::Str, Type::Fun(vec![Type::Str], Box::new(ret_type))], Type::Unit)
+}
+
+///Calls the function with the payload each time the event is emitted
+fn eval_on(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(event), Fun(closure)] => {
+            events::on(event, *closure.clone());
+            Ok(Unit)
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+///after(ms: int, f: fun(): T): unit
+fn check_after(args: &[Type]) -> Result<Type, String> {
+    let (_, ret_type) = function_type(args, 1)?;
+    signature(args, &[Type::Int, Type::Fun(Vec::new(), Box::new(ret_type))], Type::Unit)
+}
+
+///Calls the function once the time has passed
+fn eval_after(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.time, "Setting timers", loc)?;
+    match args {
+        [Int(ms), Fun(closure)] => {
+            let ms = u64::try_from(*ms)
+                .map_err(|_| Signal::Error(format!("Cannot wait for a negative time, got {ms}"), loc))?;
+            events::after(Duration::from_millis(ms), *closure.clone());
+            Ok(Unit)
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+fn check_emit(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Str], Type::Unit)
+}
+
+///Queues the event, so its callbacks are called by the event loop
+fn eval_emit(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(event), Str(payload)] => {
+            events::emit(event, payload);
+            Ok(Unit)
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+#[cfg(feature = "regex")]
+fn regex(pattern: &str, loc: Location) -> Result<regex::Regex, Signal> {
+    regex::Regex::new(pattern).map_err(|err| Signal::Error(format!("Invalid regex '{pattern}': {err}"), loc))
+}
+
+#[cfg(feature = "regex")]
+fn check_regex_match(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Str], Type::Bool)
+}
+
+///Whether the pattern matches anywhere in the string
+#[cfg(feature = "regex")]
+fn eval_regex_match(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s), Str(pattern)] => Ok(Bool(regex(pattern, loc)?.is_match(s))),
+        _ => Err(type_error(loc))
+    }
+}
+
+#[cfg(feature = "regex")]
+fn check_regex_find(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str, Type::Str], Type::Optional(Box::new(Type::Str)))
+}
+
+///The first part of the string the pattern matches
+#[cfg(feature = "regex")]
+fn eval_regex_find(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s), Str(pattern)] => Ok(Optional(regex(pattern, loc)?.find(s).map(|found| Box::new(Str(Rc::new(found.as_str().to_string())))))),
+        _ => Err(type_error(loc))
+    }
+}
+
+///Replaces every match. The replacement can refer to groups with $1, $2 and so on
+#[cfg(feature = "regex")]
+fn eval_regex_replace(args: &mut [Literal], loc: Location) -> EvalResult {
+    match args {
+        [Str(s), Str(pattern), Str(to)] => Ok(Str(regex(pattern, loc)?.replace_all(s, &to[..]).to_string().into())),
+        _ => Err(type_error(loc))
+    }
+}
+
+#[cfg(feature = "http")]
+fn check_http_get(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Str], Type::Str)
+}
+
+///The body of the response. An error status is a runtime error
+#[cfg(feature = "http")]
+fn eval_http_get(args: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.io, "Network access", loc)?;
+    match args {
+        [Str(url)] => match ureq::get(url).call() {
+            Ok(response) => response.into_string()
+                .map(|body| Str(body.into()))
+                .map_err(|e| Signal::Error(format!("Could not read the response from '{url}': {e}"), loc)),
+            Err(ureq::Error::Status(status, _)) => Err(Signal::Error(format!("'{url}' responded with status {status}"), loc)),
+            Err(e) => Err(Signal::Error(format!("Request failed: {e}"), loc))
+        },
+        _ => Err(type_error(loc))
+    }
+}
+
+#[cfg(feature = "rand")]
+fn check_random(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[], Type::Float)
+}
+
+///A float from 0 up to, but not including, 1
+#[cfg(feature = "rand")]
+fn eval_random(_: &mut [Literal], loc: Location) -> EvalResult {
+    check_allowed(|options| options.random, "Randomness", loc)?;
+    Ok(Float(rand::random::<f64>()))
+}
+
+#[cfg(feature = "rand")]
+fn check_random_int(args: &[Type]) -> Result<Type, String> {
+    signature(args, &[Type::Int, Type::Int], Type::Int)
+}
+
+///An int from low up to, but not including, high
+#[cfg(feature = "rand")]
+fn eval_random_int(args: &mut [Literal], loc: Location) -> EvalResult {
+    use rand::Rng;
+    check_allowed(|options| options.random, "Randomness", loc)?;
+    match args {
+        [Int(low), Int(high)] if *low < *high => Ok(Int(rand::thread_rng().gen_range(*low..*high))),
+        [Int(low), Int(high)] => Err(Signal::Error(format!("Empty range for random_int, {low} is not below {high}"), loc)),
+        _ => Err(type_error(loc))
+    }
+}
+
+#[cfg(feature = "rand")]
+fn check_shuffle(args: &[Type]) -> Result<Type, String> {
+    element_type(args)?;
+    signature(args, &[args[0].clone()], args[0].clone())
+}
+
+///A copy of the array in random order
+#[cfg(feature = "rand")]
+fn eval_shuffle(args: &mut [Literal], loc: Location) -> EvalResult {
+    use rand::seq::SliceRandom;
+    check_allowed(|options| options.random, "Randomness", loc)?;
+    match args {
+        [Array(lits)] => {
+            let mut lits = lits.to_vec();
+            lits.shuffle(&mut rand::thread_rng());
+            Ok(Array(lits.into()))
+        },
+        _ => Err(type_error(loc))
+    }
+}
@@ -0,0 +1,227 @@
+use std::rc::Rc;
+
+use super::*;
+use Literal::*;
+
+impl From<i64> for Literal {
+    fn from(i: i64) -> Self {
+        Int(i)
+    }
+}
+
+impl From<i32> for Literal {
+    fn from(i: i32) -> Self {
+        Int(i as i64)
+    }
+}
+
+impl From<f64> for Literal {
+    fn from(f: f64) -> Self {
+        Float(f)
+    }
+}
+
+impl From<bool> for Literal {
+    fn from(b: bool) -> Self {
+        Bool(b)
+    }
+}
+
+impl From<char> for Literal {
+    fn from(c: char) -> Self {
+        Char(c)
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(s: &str) -> Self {
+        Str(Rc::new(s.to_string()))
+    }
+}
+
+impl From<String> for Literal {
+    fn from(s: String) -> Self {
+        Str(Rc::new(s))
+    }
+}
+
+impl From<()> for Literal {
+    fn from(_: ()) -> Self {
+        Unit
+    }
+}
+
+impl<T: Into<Literal>> From<Option<T>> for Literal {
+    fn from(value: Option<T>) -> Self {
+        Optional(value.map(|value| Box::new(value.into())))
+    }
+}
+
+impl<T: Into<Literal>> From<Vec<T>> for Literal {
+    fn from(values: Vec<T>) -> Self {
+        Array(Rc::new(values.into_iter().map(Into::into).collect()))
+    }
+}
+
+impl<A: Into<Literal>, B: Into<Literal>> From<(A, B)> for Literal {
+    fn from((a, b): (A, B)) -> Self {
+        Tuple(Rc::new(vec![a.into(), b.into()]))
+    }
+}
+
+impl<A: Into<Literal>, B: Into<Literal>, C: Into<Literal>> From<(A, B, C)> for Literal {
+    fn from((a, b, c): (A, B, C)) -> Self {
+        Tuple(Rc::new(vec![a.into(), b.into(), c.into()]))
+    }
+}
+
+fn expected(typ: &str, value: &Literal) -> String {
+    format!("Expected '{typ}', got '{}'", value.get_type())
+}
+
+impl TryFrom<Literal> for i64 {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Int(i) => Ok(i),
+            value => Err(expected("int", &value))
+        }
+    }
+}
+
+impl TryFrom<Literal> for f64 {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Float(f) => Ok(f),
+            value => Err(expected("float", &value))
+        }
+    }
+}
+
+impl TryFrom<Literal> for bool {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Bool(b) => Ok(b),
+            value => Err(expected("bool", &value))
+        }
+    }
+}
+
+impl TryFrom<Literal> for char {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Char(c) => Ok(c),
+            value => Err(expected("char", &value))
+        }
+    }
+}
+
+impl TryFrom<Literal> for String {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Str(s) => Ok(s.to_string()),
+            value => Err(expected("string", &value))
+        }
+    }
+}
+
+impl TryFrom<Literal> for () {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Unit => Ok(()),
+            value => Err(expected("unit", &value))
+        }
+    }
+}
+
+impl<T: TryFrom<Literal, Error = String>> TryFrom<Literal> for Option<T> {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Optional(value) => value.map(|value| T::try_from(*value)).transpose(),
+            value => Err(expected("option", &value))
+        }
+    }
+}
+
+///Both arrays and sets convert to a vector
+impl<T: TryFrom<Literal, Error = String>> TryFrom<Literal> for Vec<T> {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match value {
+            Array(values) | Set(values) => values.iter().cloned().map(T::try_from).collect(),
+            value => Err(expected("array", &value))
+        }
+    }
+}
+
+impl<A: TryFrom<Literal, Error = String>, B: TryFrom<Literal, Error = String>> TryFrom<Literal> for (A, B) {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match &value {
+            Tuple(values) if values.len() == 2 => Ok((A::try_from(values[0].clone())?, B::try_from(values[1].clone())?)),
+            _ => Err(expected("tuple of 2", &value))
+        }
+    }
+}
+
+impl<A: TryFrom<Literal, Error = String>, B: TryFrom<Literal, Error = String>, C: TryFrom<Literal, Error = String>> TryFrom<Literal> for (A, B, C) {
+    type Error = String;
+
+    fn try_from(value: Literal) -> Result<Self, Self::Error> {
+        match &value {
+            Tuple(values) if values.len() == 3 => {
+                Ok((A::try_from(values[0].clone())?, B::try_from(values[1].clone())?, C::try_from(values[2].clone())?))
+            },
+            _ => Err(expected("tuple of 3", &value))
+        }
+    }
+}
+
+impl StructValue {
+    ///The field converted to a Rust type
+    pub fn field<T: TryFrom<Literal, Error = String>>(&self, name: &str) -> Result<T, String> {
+        match self.fields.iter().find(|(field, _)| field == name) {
+            Some((_, value)) => T::try_from(value.clone()).map_err(|msg| format!("{msg} in field '{name}' of '{}'", self.name)),
+            None => Err(format!("'{}' has no field '{name}'", self.name))
+        }
+    }
+}
+
+///A Rust struct that converts to and from a struct of the source with the same fields.
+///The methods only go through the fields one by one, like a derive would
+pub trait HostStruct: Sized {
+    ///The name of the struct in the source
+    const NAME: &'static str;
+
+    ///The fields with their values, in the order they are declared in the source
+    fn to_fields(&self) -> Vec<(String, Literal)>;
+
+    ///Reads the fields, usually with StructValue::field
+    fn from_fields(value: &StructValue) -> Result<Self, String>;
+
+    fn to_value(&self) -> Literal {
+        Struct(Rc::new(StructValue { name: Self::NAME.to_string(), fields: self.to_fields() }))
+    }
+
+    fn from_value(value: &Literal) -> Result<Self, String> {
+        match value {
+            Struct(value) if value.name == Self::NAME => Self::from_fields(value),
+            value => Err(expected(Self::NAME, value))
+        }
+    }
+}
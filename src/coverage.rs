@@ -0,0 +1,133 @@
+use std::{cell::{Cell, RefCell}, collections::BTreeMap, fmt::Display};
+
+use super::*;
+use visit::{Visitor, walk_exp};
+
+///How many times each branch of an if was taken
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Branch {
+    pub when_true: u64,
+    pub when_false: u64,
+}
+
+///What a run covered of a program, keyed by where the statements and ifs start
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    ///How many times each statement in a block ran. Statements that never ran are 0
+    pub statements: BTreeMap<Location, u64>,
+    pub branches: BTreeMap<Location, Branch>,
+}
+
+///Adds the statements and ifs of the expressions it visits as not run
+impl Visitor for Coverage {
+    fn visit_exp(&mut self, exp: &Exp) {
+        match exp {
+            Exp::BlockExp(exps, _, _) => for exp in exps {
+                self.statements.insert(exp.loc(), 0);
+            },
+            Exp::IfElseExp(_, _, _, loc) => {
+                self.branches.insert(*loc, Branch::default());
+            },
+            _ => ()
+        }
+        walk_exp(self, exp);
+    }
+}
+
+impl Coverage {
+
+    ///How many of the statements ran at least once
+    pub fn statements_run(&self) -> usize {
+        self.statements.values().filter(|count| **count > 0).count()
+    }
+
+    ///How many branches were taken at least once. Each if has two
+    pub fn branches_taken(&self) -> usize {
+        self.branches.values().map(|branch| (branch.when_true > 0) as usize + (branch.when_false > 0) as usize).sum()
+    }
+}
+
+thread_local! {
+    ///Whether what runs on this thread is recorded now
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+
+    ///What the last measured run on this thread covered
+    static COVERAGE: RefCell<Option<Coverage>> = const { RefCell::new(None) };
+}
+
+///Starts measuring the coverage of the runs on this thread from scratch, or stops it. Gives whether it was recorded before
+pub fn set_coverage(enabled: bool) -> bool {
+    COVERAGE.with(|coverage| *coverage.borrow_mut() = enabled.then(Coverage::default));
+    ENABLED.with(|cell| cell.replace(enabled))
+}
+
+///Stops or goes on recording, keeping what was recorded, like when a measured run ends. Gives whether it was recorded before
+pub fn set_recording(enabled: bool) -> bool {
+    ENABLED.with(|cell| cell.replace(enabled))
+}
+
+///Changes the coverage, if it is being recorded
+fn record(f: impl FnOnce(&mut Coverage)) {
+    if ENABLED.with(|enabled| enabled.get()) {
+        COVERAGE.with(|coverage| {
+            if let Some(coverage) = coverage.borrow_mut().as_mut() {
+                f(coverage)
+            }
+        })
+    }
+}
+
+///Adds the statements and ifs of a program about to run, so those that never run are in the coverage
+pub fn register(program: &Exp) {
+    record(|coverage| coverage.visit_exp(program))
+}
+
+///Records that the statement starting here is about to run
+pub fn statement(loc: Location) {
+    record(|coverage| *coverage.statements.entry(loc).or_insert(0) += 1)
+}
+
+///Records which branch the if starting here took
+pub fn branch(loc: Location, taken: bool) {
+    record(|coverage| {
+        let branch = coverage.branches.entry(loc).or_default();
+        match taken {
+            true => branch.when_true += 1,
+            false => branch.when_false += 1,
+        }
+    })
+}
+
+///The coverage of the last run on this thread, if it was measured
+pub fn take_coverage() -> Option<Coverage> {
+    COVERAGE.with(|coverage| coverage.borrow_mut().take())
+}
+
+///The share of the total as a percentage, where nothing of nothing is everything
+fn percentage(part: usize, total: usize) -> f64 {
+    match total {
+        0 => 100.0,
+        _ => part as f64 * 100.0 / total as f64
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (run, statements) = (self.statements_run(), self.statements.len());
+        let (taken, branches) = (self.branches_taken(), self.branches.len() * 2);
+        write!(f, "Statements run: {run} of {statements} ({:.1}%)", percentage(run, statements))?;
+        write!(f, "\nBranches taken: {taken} of {branches} ({:.1}%)", percentage(taken, branches))?;
+        for (loc, _) in self.statements.iter().filter(|(_, count)| **count == 0) {
+            write!(f, "\nNot run: statement at {loc}")?;
+        }
+        for (loc, branch) in &self.branches {
+            if branch.when_true == 0 {
+                write!(f, "\nNot taken: if at {loc} was never true")?;
+            }
+            if branch.when_false == 0 {
+                write!(f, "\nNot taken: if at {loc} was never false")?;
+            }
+        }
+        Ok(())
+    }
+}
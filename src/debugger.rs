@@ -0,0 +1,227 @@
+use std::{cell::RefCell, collections::HashSet, io::{self, BufRead, Write}};
+
+use super::*;
+use eval::call_depth;
+use interner::Symbol;
+
+///What the program does when the debugger lets it go on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resume {
+    ///Runs until it reaches a breakpoint
+    Continue,
+
+    ///Pauses at the next statement, also when it is inside a called function
+    StepInto,
+
+    ///Pauses at the next statement that is not inside a function called from here
+    StepOver,
+}
+
+///Where a program is paused, and what it can see there
+pub struct Pause<'a> {
+    ///The statement that is about to run, or the variable that was just changed
+    pub exp: &'a Exp,
+
+    ///How many calls are running
+    pub depth: usize,
+    envir: &'a Environment<Literal>,
+}
+
+impl Pause<'_> {
+    pub fn loc(&self) -> Location {
+        self.exp.loc()
+    }
+
+    ///The variables that can be used here, innermost first. Those the parser makes, like the counter of 'for (n)', start with '.' and are left out
+    pub fn variables(&self) -> Vec<(Symbol, Literal)> {
+        self.envir.variables().into_iter().filter(|(id, _)| !id.as_str().starts_with('.')).collect()
+    }
+
+    pub fn variable(&self, name: &str) -> Option<Literal> {
+        self.envir.lookup_var(&Symbol::new(name)).ok()
+    }
+}
+
+///A frontend, like an IDE, that decides what the program does when it pauses.
+///The program waits until it returns, so it can wait for the user
+pub trait Debugger {
+    fn paused(&mut self, pause: &Pause) -> Resume;
+
+    ///Called after a watched variable changed. It pauses the program by default
+    fn changed(&mut self, _change: &Change, pause: &Pause) -> Resume {
+        self.paused(pause)
+    }
+}
+
+///A change of a watched variable
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub name: Symbol,
+
+    ///The value before the change, which is none when the variable was declared
+    pub old: Option<Literal>,
+    pub new: Literal,
+
+    ///Where the variable was assigned or declared
+    pub loc: Location,
+}
+
+enum Mode {
+    Run,
+    StepInto,
+
+    ///Pauses when no more than this many calls are running
+    StepOver(usize),
+}
+
+impl Mode {
+    ///How the program goes on when the debugger resumes it this many calls deep
+    fn after(resume: Resume, depth: usize) -> Mode {
+        match resume {
+            Resume::Continue => Mode::Run,
+            Resume::StepInto => Mode::StepInto,
+            Resume::StepOver => Mode::StepOver(depth),
+        }
+    }
+}
+
+struct Session {
+    debugger: Box<dyn Debugger>,
+    mode: Mode,
+}
+
+thread_local! {
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+
+    ///Lines that pause the program when a statement on them is about to run
+    static BREAKPOINTS: RefCell<HashSet<u32>> = RefCell::new(HashSet::new());
+
+    ///Variables that the debugger is told about when they change
+    static WATCHES: RefCell<HashSet<Symbol>> = RefCell::new(HashSet::new());
+}
+
+///Debugs the programs run by the tree-walking interpreter on this thread. The VM cannot be debugged
+pub fn attach(debugger: Box<dyn Debugger>) {
+    SESSION.with(|session| *session.borrow_mut() = Some(Session { debugger, mode: Mode::Run }));
+}
+
+///Stops debugging, and gives back the debugger
+#[allow(dead_code)]
+pub fn detach() -> Option<Box<dyn Debugger>> {
+    SESSION.with(|session| session.borrow_mut().take()).map(|session| session.debugger)
+}
+
+///Pauses at the next statement
+pub fn pause() {
+    SESSION.with(|session| if let Some(session) = session.borrow_mut().as_mut() {
+        session.mode = Mode::StepInto;
+    })
+}
+
+pub fn set_breakpoint(line: u32) {
+    BREAKPOINTS.with(|breakpoints| breakpoints.borrow_mut().insert(line));
+}
+
+pub fn remove_breakpoint(line: u32) {
+    BREAKPOINTS.with(|breakpoints| breakpoints.borrow_mut().remove(&line));
+}
+
+///Tells the debugger whenever a variable with the name is declared or assigned
+pub fn watch(name: Symbol) {
+    WATCHES.with(|watches| watches.borrow_mut().insert(name));
+}
+
+pub fn unwatch(name: Symbol) {
+    WATCHES.with(|watches| watches.borrow_mut().remove(&name));
+}
+
+///Whether the debugger should be told about changes to the variable
+pub fn is_watched(name: &Symbol) -> bool {
+    WATCHES.with(|watches| !watches.borrow().is_empty() && watches.borrow().contains(name))
+        && SESSION.with(|session| session.borrow().is_some())
+}
+
+///Called after a watched variable changed. The debugger decides how the program goes on
+pub fn changed(change: Change, exp: &Exp, envir: &Environment<Literal>) {
+    let mut session = match SESSION.with(|session| session.borrow_mut().take()) {
+        Some(session) => session,
+        None => return
+    };
+    let depth = call_depth();
+    let resume = session.debugger.changed(&change, &Pause { exp, depth, envir });
+    session.mode = Mode::after(resume, depth);
+    SESSION.with(|current| *current.borrow_mut() = Some(session));
+}
+
+///Called before each statement, which pauses the program if the debugger should see it
+pub fn before_statement(exp: &Exp, envir: &Environment<Literal>) {
+    let depth = call_depth();
+    let pauses = SESSION.with(|session| match &*session.borrow() {
+        Some(session) => match session.mode {
+            Mode::Run => BREAKPOINTS.with(|breakpoints| breakpoints.borrow().contains(&exp.loc().line)),
+            Mode::StepInto => true,
+            Mode::StepOver(max) => depth <= max,
+        },
+        None => false
+    });
+    if !pauses {
+        return
+    }
+
+    //The session is taken out while the debugger runs, so it can set breakpoints
+    let mut session = SESSION.with(|session| session.borrow_mut().take()).unwrap();
+    let resume = session.debugger.paused(&Pause { exp, depth, envir });
+    session.mode = Mode::after(resume, depth);
+    SESSION.with(|current| *current.borrow_mut() = Some(session));
+}
+
+///Debugs from the terminal. It reads commands from stdin when the program pauses
+pub struct ConsoleDebugger;
+
+impl Debugger for ConsoleDebugger {
+    fn changed(&mut self, change: &Change, pause: &Pause) -> Resume {
+        match &change.old {
+            Some(old) => println!("'{}' changed from {old} to {} at {}", change.name, change.new, change.loc),
+            None => println!("'{}' declared as {} at {}", change.name, change.new, change.loc),
+        }
+        self.paused(pause)
+    }
+
+    fn paused(&mut self, pause: &Pause) -> Resume {
+        let text = pause.exp.to_string();
+        println!("Paused at {}, call depth {}: {}", pause.loc(), pause.depth, text.lines().next().unwrap_or(""));
+        let mut lines = io::stdin().lock().lines();
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => return Resume::Continue
+            };
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("c" | "continue"), None) => return Resume::Continue,
+                (Some("s" | "step"), None) => return Resume::StepInto,
+                (Some("n" | "next"), None) => return Resume::StepOver,
+                (Some("p" | "print"), Some(name)) => match pause.variable(name) {
+                    Some(value) => println!("{name} = {value}"),
+                    None => println!("No variable '{name}' here")
+                },
+                (Some("v" | "vars"), None) => for (name, value) in pause.variables() {
+                    println!("{name} = {value}");
+                },
+                (Some("b" | "break"), Some(line)) => match line.parse() {
+                    Ok(line) => set_breakpoint(line),
+                    Err(_) => println!("Invalid line '{line}'")
+                },
+                (Some("d" | "delete"), Some(line)) => match line.parse() {
+                    Ok(line) => remove_breakpoint(line),
+                    Err(_) => println!("Invalid line '{line}'")
+                },
+                (Some("w" | "watch"), Some(name)) => watch(Symbol::new(name)),
+                (Some("u" | "unwatch"), Some(name)) => unwatch(Symbol::new(name)),
+                _ => println!("Commands: c(ontinue), s(tep) into calls, n(ext) statement, p(rint) NAME, v(ars), b(reak) LINE, d(elete) LINE, w(atch) NAME, u(nwatch) NAME"),
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt::Display;
+
+use super::*;
+
+///Codes of errors with the patterns of their messages, where '*' is any text. The first code with a matching pattern is used,
+///and the code ending in 00 is for errors of that kind no other pattern matches. A code is never given to another error
+const CODES: &[(&str, &str)] = &[
+    //Lexer errors
+    ("E0001", "Invalid char *"),
+    ("E0002", "Unterminated string literal *"),
+    ("E0003", "Expected '"),
+    ("E0003", "Expected char"),
+    ("E0003", "Char literal * has * chars, *"),
+    ("E0004", "Int * is too large"),
+    ("E0004", "Invalid float *"),
+    ("E0005", "Unterminated comment *"),
+
+    //Parse errors
+    ("E0101", "Unexpected end of input*"),
+    ("E0102", "Unexpected operator *"),
+    ("E0103", "Unexpected *"),
+    ("E0104", "Not a unary operator *"),
+    ("E0104", "Not a binary operator *"),
+    ("E0104", "Unknown operator: *"),
+    ("E0106", "Expected a statement"),
+    ("E0106", "Expected a term*"),
+    ("E0106", "Expected an expression*"),
+    ("E0106", "Expected a literal*"),
+    ("E0107", "Expected a type*"),
+    ("E0107", "Unknown type"),
+    ("E0108", "Expected an identifier*"),
+    ("E0109", "Expected 'self' as the first parameter of method *"),
+    ("E0110", "Method * in interface * needs a return type annotation"),
+    ("E0111", "Only loops can be labeled, *"),
+    ("E0112", "From in for must be *"),
+    ("E0112", "To in for must be *"),
+    ("E0113", "* is already an operator"),
+    ("E0113", "Precedence must be from *"),
+    ("E0113", "Operators can only be declared *"),
+    ("E0105", "Expected *"),
+
+    //Type errors
+    ("E0201", "Invalid operation * for * and *"),
+    ("E0201", "Unary operator * is not valid for *"),
+    ("E0202", "Variable * does not exist here*"),
+    ("E0203", "Function * does not exist here*"),
+    ("E0204", "Type * does not exist here"),
+    ("E0204", "Struct * does not exist here"),
+    ("E0204", "Interface * does not exist here"),
+    ("E0205", "* already exist in this scope"),
+    ("E0206", "Condition for * must be boolean, got *"),
+    ("E0207", "If and else branch must have same type, *"),
+    ("E0208", "* takes * argument*, got *"),
+    ("E0209", "Invalid argument for *"),
+    ("E0209", "* expects *"),
+    ("E0210", "Return type does not match annotation, *"),
+    ("E0211", "Cannot call * here. * needs a type annotation *"),
+    ("E0212", "Cannot assign *"),
+    ("E0212", "Cannot destructure * in assignment"),
+    ("E0212", "Left side of assignment must be a variable name"),
+    ("E0212", "Left side of * must be a variable"),
+    ("E0213", "Cannot destructure * with pattern *"),
+    ("E0213", "Pattern * has * elements, *"),
+    ("E0214", "Cannot * outside of a loop"),
+    ("E0214", "Cannot * *, no enclosing loop has that label"),
+    ("E0214", "Label * is already used by an enclosing loop"),
+    ("E0215", "Cannot access field *"),
+    ("E0215", "Struct * has no field *"),
+    ("E0215", "Missing field *"),
+    ("E0215", "Field *"),
+    ("E0216", "Cannot index into *"),
+    ("E0216", "Index must be 'int', got *"),
+    ("E0217", "Elements of an array must have the same type, *"),
+    ("E0218", "'...' can only be used *"),
+    ("E0218", "Arrays can* be spread *"),
+    ("E0218", "Only arrays * can be spread*"),
+    ("E0219", "* is not a method of *"),
+    ("E0219", "* must return * as declared in *"),
+    ("E0219", "Interface * has no method *"),
+    ("E0219", "Missing method * of * for *"),
+    ("E0219", "Parameters of * do not match the interface *"),
+    ("E0219", "* already implements *"),
+    ("E0219", "Implementations must be at the top level *"),
+    ("E0220", "* has no method *, and no function * exists here"),
+    ("E0221", "Cannot iterate over *"),
+    ("E0221", "'next' of * must give *"),
+    ("E0222", "* changes its first argument, so it must be a variable"),
+    ("E0223", "Left side of * must be an option, got *"),
+    ("E0223", "Right side of * must be *, got *"),
+    ("E0224", "Count in for must be *"),
+    ("E0224", "Step in for must be *"),
+    ("E0225", "* is used while it is *, so it cannot become * later"),
+    ("E0225", "* holds a channel with no element type yet*"),
+
+    //Runtime errors
+    ("E0301", "Division by zero"),
+    ("E0301", "Modulo by zero"),
+    ("E0302", "Overflow in *"),
+    ("E0303", "Index out of bounds"),
+    ("E0303", "Cannot insert at index *"),
+    ("E0303", "Cannot remove index *"),
+    ("E0303", "Cannot take substring *"),
+    ("E0304", "Maximum recursion depth exceeded*"),
+    ("E0305", "Out of memory*"),
+    ("E0306", "Execution budget exceeded"),
+    ("E0307", "Timed out"),
+    ("E0308", "Assertion failed*"),
+    ("E0309", "* is not allowed"),
+    ("E0310", "Could not *"),
+    ("E0310", "No more input to read"),
+    ("E0310", "Expected an int as input*"),
+    ("E0310", "Request failed*"),
+    ("E0310", "* responded with status *"),
+    ("E0311", "The task *"),
+    ("E0311", "Function * cannot be sent between tasks"),
+    ("E0312", "* returned *"),
+    ("E0313", "* expects *"),
+    ("E0313", "* takes * argument*, got *"),
+    ("E0313", "* cannot *"),
+    ("E0313", "Cannot *"),
+    ("E0313", "Empty range for *"),
+    ("E0313", "Exit status must fit *"),
+    ("E0313", "Format string has *"),
+    ("E0313", "Int power must be *"),
+    ("E0313", "Invalid regex *"),
+    ("E0313", "* does not fit in an int"),
+
+    //Warnings
+    ("W0001", "Variable * is never read"),
+    ("W0002", "Function * is never called"),
+    ("W0003", "Unreachable statement*"),
+    ("W0004", "Condition is always *"),
+];
+
+///An error or warning with where it is and notes about it, which can be shown with the line of the source it is on
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    ///What kind of error it is, like "Type Error", or "Warning"
+    pub kind: String,
+
+    ///A code for the error that stays the same when its message changes, like "E0201"
+    pub code: &'static str,
+    pub message: String,
+
+    ///None when it is not at a place in the source, like an exit or a call from the host
+    pub loc: Option<Location>,
+
+    ///Lines shown under the source, like the calls a runtime error went through
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(kind: &str, message: String, loc: Location) -> Self {
+        Self { kind: kind.to_string(), code: code(kind, &message), message, loc: Some(loc).filter(|loc| loc.line != 0), notes: Vec::new() }
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    ///A runtime error, with the calls it went through as notes
+    pub fn from_signal(signal: &Signal) -> Self {
+        let message = signal.message();
+        Self {
+            kind: String::from("Runtime Error"),
+            code: code("Runtime Error", &message),
+            message,
+            loc: signal.loc().filter(|loc| loc.line != 0),
+            notes: signal.trace()
+        }
+    }
+
+    ///The message, then the line of the source it is on with its span underlined, then the notes
+    pub fn render(&self, source: &str) -> String {
+        let mut res = format!("{}[{}]: {}", self.kind, self.code, self.message);
+        let mut gutter = String::new();
+        if let Some(loc) = self.loc {
+            match source.lines().nth(loc.line as usize - 1) {
+                Some(line) => {
+                    let number = loc.line.to_string();
+                    gutter = " ".repeat(number.len());
+                    res = format!("{res}\n{gutter}--> {loc}\n{gutter} |\n{number} | {line}\n{gutter} | {}", underline(line, loc));
+                },
+                None => res = format!("{res}. At {loc}")
+            }
+        }
+        for note in &self.notes {
+            res = format!("{res}\n{gutter} = {note}");
+        }
+        res
+    }
+
+    ///The diagnostic as a JSON object on one line. The location is null when it is not in the source
+    pub fn to_json(&self) -> String {
+        let loc = match self.loc {
+            Some(loc) => format!("{{\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{}}}", loc.line, loc.col, loc.end_line, loc.end_col),
+            None => String::from("null")
+        };
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        format!("{{\"code\":\"{}\",\"kind\":{},\"message\":{},\"location\":{loc},\"notes\":[{}]}}",
+            self.code, json_string(&self.kind), json_string(&self.message), notes.join(","))
+    }
+}
+
+///The diagnostic on one line, as it is shown without the source
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.kind, self.code, self.message)?;
+        if let Some(loc) = self.loc {
+            write!(f, ". At {loc}")?;
+        }
+        for note in &self.notes {
+            write!(f, "\n    {note}")?;
+        }
+        Ok(())
+    }
+}
+
+///Carets under the span on the line. A span that goes on to the next lines is underlined to the end of this one
+fn underline(line: &str, loc: Location) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let start = loc.col.saturating_sub(1).min(chars.len());
+    let end = match loc.end_line > loc.line {
+        true => chars.len(),
+        false => loc.end_col.saturating_sub(1).min(chars.len())
+    };
+    //Tabs are kept, so the carets line up however wide a tab is shown
+    let indent: String = chars[..start].iter().map(|c| if *c == '\t' { '\t' } else { ' ' }).collect();
+    format!("{indent}{}", "^".repeat(end.saturating_sub(start).max(1)))
+}
+
+///The code of an error of the kind with the message
+fn code(kind: &str, message: &str) -> &'static str {
+    let (prefix, other) = match kind {
+        "Lexer Error" => ("E00", "E0000"),
+        "Parse Error" => ("E01", "E0100"),
+        "Type Error" => ("E02", "E0200"),
+        "Warning" => ("W0", "W0000"),
+        _ => ("E03", "E0300")
+    };
+    CODES.iter()
+        .find(|(code, pattern)| code.starts_with(prefix) && glob(pattern, message))
+        .map_or(other, |(code, _)| code)
+}
+
+///Whether the text matches the pattern, where '*' is any text
+fn glob(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((start, rest)) => match text.strip_prefix(start) {
+            Some(text) => text.char_indices().map(|(i, _)| i).chain([text.len()]).any(|i| glob(rest, &text[i..])),
+            None => false
+        }
+    }
+}
+
+///The text as a JSON string, in quotes and escaped
+pub fn json_string(text: &str) -> String {
+    let mut res = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if c.is_control() => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c)
+        }
+    }
+    res.push('"');
+    res
+}
@@ -0,0 +1,183 @@
+use std::fmt::Display;
+
+use super::*;
+use diagnostics::json_string;
+use Exp::*;
+
+///An expression as plain data, the kind of each node with its fields in order, to show exactly what the parser made.
+///Exp::to_json and Exp::to_sexp write it, and with the serde feature an Exp serializes as it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+
+    ///A string value, which is quoted in S-expressions
+    Str(String),
+
+    ///A name, operator or type, which is not
+    Name(String),
+    List(Vec<Node>),
+
+    ///Kind, fields, and where it is in the source. Parts of a node that are not expressions, like parameters, have no location
+    Tree(&'static str, Vec<(&'static str, Node)>, Option<Location>),
+}
+
+impl Exp {
+    pub fn to_node(&self) -> Node {
+        let exp = |exp: &Exp| exp.to_node();
+        let exps = |exps: &[Exp]| Node::List(exps.iter().map(Exp::to_node).collect());
+        let name = |name: &dyn Display| Node::Name(name.to_string());
+        let label = |label: &Option<String>| label.as_ref().map_or(Node::Null, |label| Node::Name(label.clone()));
+        let (kind, fields) = match self {
+            BinOpExp(left, op, right, _) => ("BinOp", vec![("op", name(op)), ("left", exp(left)), ("right", exp(right))]),
+            UnOpExp(op, operand, _) => ("UnOp", vec![("op", name(op)), ("exp", exp(operand))]),
+            LiteralExp(lit, _) => ("Literal", vec![("type", name(&lit.get_type())), ("value", literal(lit))]),
+            VarExp(id, _) => ("Var", vec![("name", name(id))]),
+            WhileExp(cond, body, loop_label, _) => ("While", vec![("cond", exp(cond)), ("body", exp(body)), ("label", label(loop_label))]),
+            ForExp(init, cond, incr, body, loop_label, _) => ("For", vec![("init", exp(init)), ("cond", exp(cond)), ("incr", exp(incr)),
+                ("body", exp(body)), ("label", label(loop_label))]),
+            ForInExp(id, collection, body, loop_label, _) => ("ForIn", vec![("var", name(id)), ("collection", exp(collection)),
+                ("body", exp(body)), ("label", label(loop_label))]),
+            BreakExp(loop_label, _) => ("Break", vec![("label", label(loop_label))]),
+            ContinueExp(loop_label, _) => ("Continue", vec![("label", label(loop_label))]),
+            LetExp(id, value, _) => ("Let", vec![("name", name(id)), ("value", exp(value))]),
+            IfElseExp(cond, pos, neg, _) => ("IfElse", vec![("cond", exp(cond)), ("then", exp(pos)),
+                ("else", neg.as_ref().map_or(Node::Null, |neg| exp(neg)))]),
+            BlockExp(statements, funs, _) => ("Block", vec![("statements", exps(statements)),
+                ("functions", Node::List(funs.iter().map(|(_, fun)| fun.to_node()).collect()))]),
+            FunCallExp(id, args, _) => ("FunCall", vec![("name", name(id)), ("args", exps(args))]),
+            FunDeclExp(id, _) => ("FunDecl", vec![("name", name(id))]),
+            OptionExp(value, _) => ("Option", vec![("value", value.as_ref().map_or(Node::Null, |value| exp(value)))]),
+            TupleExp(elements, _) => ("Tuple", vec![("elements", exps(elements))]),
+            StructDeclExp(id, fields, _) => ("StructDecl", vec![("name", name(id)), ("fields", Node::List(fields.iter().map(|(field, typ)| {
+                Node::Tree("Field", vec![("name", name(field)), ("type", name(typ))], None)
+            }).collect()))]),
+            StructExp(id, fields, _) => ("Struct", vec![("name", name(id)), ("fields", Node::List(fields.iter().map(|(field, value)| {
+                Node::Tree("Field", vec![("name", name(field)), ("value", exp(value))], None)
+            }).collect()))]),
+            FieldExp(value, field, _) => ("Field", vec![("exp", exp(value)), ("field", name(field))]),
+            DestructExp(pattern, value, _) => ("Destruct", vec![("pattern", pattern.to_node()), ("value", exp(value))]),
+            InterfaceDeclExp(id, methods, _) => ("InterfaceDecl", vec![("name", name(id)), ("methods", Node::List(methods.iter().map(|(method, p_types, ret_type)| {
+                Node::Tree("Method", vec![("name", name(method)), ("param_types", Node::List(p_types.iter().map(|typ| name(typ)).collect())),
+                    ("ret_type", name(ret_type))], None)
+            }).collect()))]),
+            ImplExp(interface, id, methods, _) => ("Impl", vec![("interface", name(interface)), ("struct", name(id)),
+                ("methods", Node::List(methods.iter().map(|method| name(method)).collect()))]),
+            MethodCallExp(receiver, method, args, _) => ("MethodCall", vec![("receiver", exp(receiver)), ("method", name(method)), ("args", exps(args))]),
+            ArrayExp(elements, _) => ("Array", vec![("elements", exps(elements))]),
+            SpreadExp(value, _) => ("Spread", vec![("exp", exp(value))]),
+            IndexExp(value, index, _) => ("Index", vec![("exp", exp(value)), ("index", exp(index))]),
+            OperatorDeclExp(custom, _) => ("OperatorDecl", vec![("symbol", name(&custom.symbol)), ("associativity", name(&custom.associativity)),
+                ("level", Node::Int(custom.level as i64)), ("function", name(&custom.fun))]),
+        };
+        Node::Tree(kind, fields, Some(self.loc()))
+    }
+
+    ///The expression as JSON on one line. Each node is an object with its kind, its fields and its location
+    pub fn to_json(&self) -> String {
+        self.to_node().to_json()
+    }
+
+    ///The expression as an S-expression, '(Kind field ...)' for each node, with a field on each line when it is long
+    pub fn to_sexp(&self) -> String {
+        self.to_node().to_sexp()
+    }
+}
+
+impl Function {
+    pub fn to_node(&self) -> Node {
+        let params = self.params.iter().zip(&self.param_types).map(|(param, typ)| {
+            Node::Tree("Param", vec![("name", Node::Name(param.to_string())), ("type", Node::Name(typ.to_string()))], None)
+        }).collect();
+        Node::Tree("Function", vec![("name", Node::Name(self.name.to_string())), ("params", Node::List(params)),
+            ("ret_type", Node::Name(self.ret_type.to_string())), ("body", self.exp.to_node())], Some(self.loc))
+    }
+}
+
+impl Pattern {
+    pub fn to_node(&self) -> Node {
+        match self {
+            Pattern::Id(id) => Node::Tree("IdPattern", vec![("name", Node::Name(id.to_string()))], None),
+            Pattern::Tuple(patterns) => Node::Tree("TuplePattern", vec![("patterns", Node::List(patterns.iter().map(Pattern::to_node).collect()))], None),
+            Pattern::Struct(id, fields) => Node::Tree("StructPattern", vec![("name", Node::Name(id.clone())), ("fields", Node::List(fields.iter().map(|(field, pattern)| {
+                Node::Tree("Field", vec![("name", Node::Name(field.clone())), ("pattern", pattern.to_node())], None)
+            }).collect()))], None)
+        }
+    }
+}
+
+///The value of a literal. Those only the optimizer makes, like arrays, are written as they are shown
+fn literal(lit: &Literal) -> Node {
+    match lit {
+        Literal::Int(i) => Node::Int(*i),
+        Literal::Float(f) => Node::Float(*f),
+        Literal::Bool(b) => Node::Bool(*b),
+        Literal::Char(c) => Node::Str(c.to_string()),
+        Literal::Str(s) => Node::Str(s.to_string()),
+        Literal::Unit => Node::Null,
+        lit => Node::Name(lit.to_string())
+    }
+}
+
+impl Node {
+    pub fn to_json(&self) -> String {
+        match self {
+            Node::Null => String::from("null"),
+            Node::Bool(b) => b.to_string(),
+            Node::Int(i) => i.to_string(),
+            Node::Float(f) if f.is_finite() => format!("{f:?}"),
+            Node::Float(_) => String::from("null"),
+            Node::Str(s) | Node::Name(s) => json_string(s),
+            Node::List(nodes) => format!("[{}]", nodes.iter().map(Node::to_json).collect::<Vec<String>>().join(",")),
+            Node::Tree(kind, fields, loc) => {
+                let mut res = format!("{{\"kind\":\"{kind}\"");
+                for (field, node) in fields {
+                    res = format!("{res},\"{field}\":{}", node.to_json());
+                }
+                if let Some(loc) = loc {
+                    res = format!("{res},\"location\":{{\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{}}}", loc.line, loc.col, loc.end_line, loc.end_col);
+                }
+                format!("{res}}}")
+            }
+        }
+    }
+
+    pub fn to_sexp(&self) -> String {
+        self.sexp(0)
+    }
+
+    ///The node on one line when it fits in 100 columns after the indentation, or else with each field on its own line
+    fn sexp(&self, indent: usize) -> String {
+        let flat = self.flat_sexp();
+        if indent + flat.len() <= 100 {
+            return flat
+        }
+        let pad = " ".repeat(indent + 2);
+        match self {
+            Node::List(nodes) => format!("({})", nodes.iter().map(|node| node.sexp(indent + 1)).collect::<Vec<String>>().join(&format!("\n{}", " ".repeat(indent + 1)))),
+            Node::Tree(kind, fields, _) => {
+                let fields: Vec<String> = fields.iter().map(|(_, node)| format!("\n{pad}{}", node.sexp(indent + 2))).collect();
+                format!("({kind}{})", fields.concat())
+            },
+            _ => flat
+        }
+    }
+
+    fn flat_sexp(&self) -> String {
+        match self {
+            Node::Null => String::from("nil"),
+            Node::Bool(b) => b.to_string(),
+            Node::Int(i) => i.to_string(),
+            Node::Float(f) => format!("{f:?}"),
+            Node::Str(s) => json_string(s),
+            Node::Name(s) => s.clone(),
+            Node::List(nodes) => format!("({})", nodes.iter().map(Node::flat_sexp).collect::<Vec<String>>().join(" ")),
+            Node::Tree(kind, fields, _) => {
+                let fields: Vec<String> = fields.iter().map(|(_, node)| format!(" {}", node.flat_sexp())).collect();
+                format!("({kind}{})", fields.concat())
+            }
+        }
+    }
+}
@@ -1,33 +1,73 @@
 use core::panic;
 use std::{rc::Rc, cell::RefCell};
 use super::*;
+use interner::Symbol;
 
+///A variable, function or type, and the ones declared before it. Names are found by walking the list from the innermost scope out
 #[derive(Debug)]
 pub struct EnvNode<T> {
     scope_depth: u32,
-    id: String,
-    value: T,
+
+    id: Symbol,
+    value: Binding<T>,
     next: Option<Rc<RefCell<EnvNode<T>>>>,
+
+    ///Whether the variable was read while its type still held 'any'. Only the type checker uses it
+    read_open: bool,
+}
+
+///What a node holds. A closure keeps the variables it uses as nodes of their own that point to where they were declared,
+///so an assignment on either side is seen by the other
+#[derive(Debug)]
+enum Binding<T> {
+    Value(T),
+    Captured(Rc<RefCell<EnvNode<T>>>),
 }
 
 impl<T: Clone> EnvNode<T> {
-    pub fn new(id: String, value: T, next: Option<Rc<RefCell<EnvNode<T>>>>, scope_depth: u32) -> Self {
-        Self { id, value, next, scope_depth }
+    pub fn new(id: Symbol, value: T, next: Option<Rc<RefCell<EnvNode<T>>>>, scope_depth: u32) -> Self {
+        Self { id, value: Binding::Value(value), next, scope_depth, read_open: false }
     }
 
-    pub fn lookup(&self, id: &String) -> Result<T, String> {
+    fn get(&self) -> T {
+        match &self.value {
+            Binding::Value(value) => value.clone(),
+            Binding::Captured(node) => node.borrow().get(),
+        }
+    }
+
+    fn set(&mut self, value: T) {
+        match &mut self.value {
+            Binding::Value(old) => *old = value,
+            Binding::Captured(node) => node.borrow_mut().set(value),
+        }
+    }
+
+    ///The value itself. Only variables are captured, so functions and types always hold theirs
+    fn own_value(&mut self) -> &mut T {
+        match &mut self.value {
+            Binding::Value(value) => value,
+            Binding::Captured(_) => unreachable!("Only variables are captured")
+        }
+    }
+
+    pub fn lookup(&self, id: &Symbol) -> Result<T, String> {
+        self.find(id).ok_or_else(|| format!("Id '{id}' not found"))
+    }
+
+    ///Like lookup, without making an error message when it is not found
+    pub fn find(&self, id: &Symbol) -> Option<T> {
         if self.id == *id {
-            return Ok(self.value.clone())
+            return Some(self.get())
         }
 
-        if let Some(next) = &self.next {
-            next.borrow().lookup(id)
-        } else {
-            Err(format!("Id '{id}' not found"))
+        match &self.next {
+            Some(next) => next.borrow().find(id),
+            None => None
         }
     }
 
-    pub fn id_exist_in_scope(&self, id: &String, scope: u32) -> bool {
+    pub fn id_exist_in_scope(&self, id: &Symbol, scope: u32) -> bool {
         if self.scope_depth != scope {
             false
         } else if self.id == *id {
@@ -39,9 +79,34 @@ impl<T: Clone> EnvNode<T> {
         }
     }
 
-    pub fn mutate(&mut self, id: &String, value: T) {
+    ///Marks the variable as read while its type was open
+    pub fn mark_read(&mut self, id: &Symbol) {
         if self.id == *id {
-            self.value = value;
+            match &self.value {
+                Binding::Value(_) => self.read_open = true,
+                Binding::Captured(node) => node.borrow_mut().mark_read(id),
+            }
+        } else if let Some(next) = &self.next {
+            next.borrow_mut().mark_read(id)
+        }
+    }
+
+    pub fn was_read(&self, id: &Symbol) -> bool {
+        if self.id == *id {
+            match &self.value {
+                Binding::Value(_) => self.read_open,
+                Binding::Captured(node) => node.borrow().was_read(id),
+            }
+        } else if let Some(next) = &self.next {
+            next.borrow().was_read(id)
+        } else {
+            false
+        }
+    }
+
+    pub fn mutate(&mut self, id: &Symbol, value: T) {
+        if self.id == *id {
+            self.set(value);
             return;
         }
 
@@ -67,15 +132,17 @@ impl<T: Clone> EnvNode<T> {
 }
 
 impl<T: Clone> EnvNode<Closure<T>> {
-    pub fn declare_fun(&mut self, id: &String, new_head: Option<Rc<RefCell<EnvNode<T>>>>) {
+    ///Lets the function use the variables it needs from the list
+    pub fn declare_fun(&mut self, id: &Symbol, var_head: &Option<Rc<RefCell<EnvNode<T>>>>) {
         if self.id == *id {
-            self.value.envir.set_var_head(new_head);
-            self.value.declared = true;
+            let closure = self.own_value();
+            closure.envir.set_var_head(captured(var_head, &closure.fun.used_names()));
+            closure.declared = true;
             return;
         }
 
         if let Some(next) = &mut self.next {
-            next.borrow_mut().declare_fun(id, new_head)
+            next.borrow_mut().declare_fun(id, var_head)
         } else {
             panic!("Function did not exist. Should never happen after correct type check")
         }
@@ -83,24 +150,28 @@ impl<T: Clone> EnvNode<Closure<T>> {
 
     pub fn update_fun_envir(&mut self, scope: u32, new_head: Rc<RefCell<EnvNode<Closure<T>>>>) {
         if self.scope_depth == scope {
-            self.value.envir.set_fun_head(Some(new_head.clone()));
+            self.own_value().envir.set_fun_head(Some(new_head.clone()));
             if let Some(next) = &self.next {
                 next.borrow_mut().update_fun_envir(scope, new_head)
             }
-        } else {
-            return;
         }
     }
 
-    pub fn update_return_type(&mut self, id: &String, ret_type: Type) {
+    pub fn update_fun(&mut self, id: &Symbol, fun: Box<Function>) {
         if self.id == *id {
-            self.value.fun.ret_type = ret_type;
+            self.own_value().fun = fun;
+        } else if let Some(next) = &self.next {
+            next.borrow_mut().update_fun(id, fun)
+        }
+    }
+
+    pub fn update_return_type(&mut self, id: &Symbol, ret_type: Type) {
+        if self.id == *id {
+            self.own_value().fun.ret_type = ret_type;
+        } else if let Some(next) = &self.next {
+            next.borrow_mut().update_return_type(id, ret_type)
         } else {
-            if let Some(next) = &self.next {
-                next.borrow_mut().update_return_type(id, ret_type)
-            } else {
-                panic!("Will only be called on existing functions")
-            }
+            panic!("Will only be called on existing functions")
         }
     }
 }
@@ -110,6 +181,12 @@ pub struct Environment<T> {
     pub scope_depth: u32,
     var_head: Option<Rc<RefCell<EnvNode<T>>>>,
     fun_head: Option<Rc<RefCell<EnvNode<Closure<T>>>>>,
+
+    ///Declared structs and interfaces
+    type_head: Option<Rc<RefCell<EnvNode<TypeDecl>>>>,
+
+    ///Labels of the loops currently being checked. None for unlabeled loops
+    loops: Vec<Option<String>>,
 }
 
 impl<T: Clone> Environment<T> {
@@ -118,6 +195,8 @@ impl<T: Clone> Environment<T> {
             scope_depth: 0,
             var_head: None,
             fun_head: None,
+            type_head: None,
+            loops: Vec::new(),
         }
     }
 
@@ -127,35 +206,27 @@ impl<T: Clone> Environment<T> {
 
     pub fn leave_scope(&mut self) {
         self.scope_depth -= 1;
-        self.var_head = match &self.var_head {
-            Some(head) => if head.borrow().scope_depth > self.scope_depth {
-                head.borrow().get_scope(self.scope_depth).clone()
-            } else {
-                Some(head.clone())
-            },
-            None => None,
-        };
-        self.fun_head = match &self.fun_head {
-            Some(head) => if head.borrow().scope_depth > self.scope_depth {
-                head.borrow().get_scope(self.scope_depth).clone()
-            } else {
-                Some(head.clone())
-            },
-            None => None,
-        };
+        self.var_head = scope_head(&self.var_head, self.scope_depth);
+        self.fun_head = scope_head(&self.fun_head, self.scope_depth);
+        self.type_head = scope_head(&self.type_head, self.scope_depth);
     }
 
-    pub fn push_variable(&mut self, id: String, value: T) {
-        let new_var = EnvNode::new(id, value, self.var_head.take(), self.scope_depth);
+    pub fn push_variable(&mut self, id: &Symbol, value: T) {
+        let new_var = EnvNode::new(*id, value, self.var_head.take(), self.scope_depth);
         self.var_head = Some(Rc::new(RefCell::new(new_var)));
     }
 
-    pub fn push_function(&mut self, id: String, fun: Box<Function>) {
-        let new_fun = EnvNode::new(id, Closure::new(fun.clone(), self.clone()), self.fun_head.clone(), self.scope_depth);
+    pub fn push_function(&mut self, id: &Symbol, fun: Box<Function>) {
+        let new_fun = EnvNode::new(*id, Closure::new(fun.clone(), self.clone()), self.fun_head.clone(), self.scope_depth);
         self.fun_head = Some(Rc::new(RefCell::new(new_fun)));
     }
     
-    pub fn lookup_var(&self, id: &String) -> Result<T, String> {
+    pub fn push_type(&mut self, id: &str, decl: TypeDecl) {
+        let new_type = EnvNode::new(Symbol::new(id), decl, self.type_head.take(), self.scope_depth);
+        self.type_head = Some(Rc::new(RefCell::new(new_type)));
+    }
+
+    pub fn lookup_var(&self, id: &Symbol) -> Result<T, String> {
         match &self.var_head {
             Some(head) => {
                 head.borrow().lookup(id)
@@ -164,7 +235,40 @@ impl<T: Clone> Environment<T> {
         }
     }
 
-    pub fn lookup_fun(&self, id: &String) -> Result<Closure<T>, String> {
+    ///The value of the variable, if there is one with the name. Cheaper than lookup_var when it is often missing
+    pub fn find_var(&self, id: &Symbol) -> Option<T> {
+        self.var_head.as_ref().and_then(|head| head.borrow().find(id))
+    }
+
+    ///The variables that can be used here, innermost first. Shadowed variables are left out
+    pub fn variables(&self) -> Vec<(Symbol, T)> {
+        let mut vars: Vec<(Symbol, T)> = Vec::new();
+        let mut node = self.var_head.clone();
+        while let Some(current) = node {
+            let current = current.borrow();
+            if !vars.iter().any(|(id, _)| *id == current.id) {
+                vars.push((current.id, current.get()));
+            }
+            node = current.next.clone();
+        }
+        vars
+    }
+
+    ///The functions that can be called here, innermost first. Shadowed functions are left out
+    pub fn functions(&self) -> Vec<(Symbol, Box<Function>)> {
+        let mut funs: Vec<(Symbol, Box<Function>)> = Vec::new();
+        let mut node = self.fun_head.clone();
+        while let Some(current) = node {
+            let current = current.borrow();
+            if !funs.iter().any(|(id, _)| *id == current.id) {
+                funs.push((current.id, current.get().fun));
+            }
+            node = current.next.clone();
+        }
+        funs
+    }
+
+    pub fn lookup_fun(&self, id: &Symbol) -> Result<Closure<T>, String> {
         match &self.fun_head {
             Some(head) => {
                 head.borrow().lookup(id)
@@ -173,7 +277,52 @@ impl<T: Clone> Environment<T> {
         }
     }
 
-    pub fn var_exist_in_scope(&self, id: &String) -> bool {
+    ///The function, if there is one with the name. Cheaper than lookup_fun when it is often missing
+    pub fn find_fun(&self, id: &Symbol) -> Option<Closure<T>> {
+        self.fun_head.as_ref().and_then(|head| head.borrow().find(id))
+    }
+
+    pub fn lookup_type(&self, id: &str) -> Result<TypeDecl, String> {
+        match &self.type_head {
+            Some(head) => {
+                head.borrow().lookup(&Symbol::new(id))
+            },
+            None => Err(format!("Type '{id}' not found")),
+        }
+    }
+
+    ///Fields of the struct
+    pub fn lookup_struct(&self, id: &str) -> Result<Vec<(String, Type)>, String> {
+        match self.lookup_type(id)? {
+            TypeDecl::Struct(fields, _) => Ok(fields),
+            TypeDecl::Interface(_) => Err(format!("'{id}' is an interface, not a struct")),
+        }
+    }
+
+    ///Method signatures of the interface
+    pub fn lookup_interface(&self, id: &str) -> Result<Vec<(String, Vec<Type>, Type)>, String> {
+        match self.lookup_type(id)? {
+            TypeDecl::Interface(methods) => Ok(methods),
+            TypeDecl::Struct(_, _) => Err(format!("'{id}' is a struct, not an interface")),
+        }
+    }
+
+    pub fn mutate_type(&mut self, id: &str, decl: TypeDecl) {
+        if let Some(head) = &self.type_head {
+            head.as_ref().borrow_mut().mutate(&Symbol::new(id), decl);
+        }
+    }
+
+    pub fn type_exist_in_scope(&self, id: &str) -> bool {
+        match &self.type_head {
+            Some(head) => {
+                head.borrow().id_exist_in_scope(&Symbol::new(id), self.scope_depth)
+            },
+            None => false,
+        }
+    }
+
+    pub fn var_exist_in_scope(&self, id: &Symbol) -> bool {
         match &self.var_head {
             Some(head) => {
                 head.borrow().id_exist_in_scope(id, self.scope_depth)
@@ -182,7 +331,7 @@ impl<T: Clone> Environment<T> {
         }
     }
 
-    pub fn fun_exist_in_scope(&self, id: &String) -> bool {
+    pub fn fun_exist_in_scope(&self, id: &Symbol) -> bool {
         match &self.fun_head {
             Some(head) => {
                 head.borrow().id_exist_in_scope(id, self.scope_depth)
@@ -191,7 +340,18 @@ impl<T: Clone> Environment<T> {
         }
     }
 
-    pub fn mutate(&mut self, id: &String, value: T) {
+    ///Marks that the variable was read while its type held 'any', so it can no longer get a type from a value put into it
+    pub fn mark_read(&self, id: &Symbol) {
+        if let Some(head) = &self.var_head {
+            head.borrow_mut().mark_read(id);
+        }
+    }
+
+    pub fn was_read(&self, id: &Symbol) -> bool {
+        self.var_head.as_ref().is_some_and(|head| head.borrow().was_read(id))
+    }
+
+    pub fn mutate(&mut self, id: &Symbol, value: T) {
         if let Some(head) = &self.var_head {
             head.as_ref().borrow_mut().mutate(id, value);
         }
@@ -205,10 +365,10 @@ impl<T: Clone> Environment<T> {
     }
 
     ///Prepares the function for calls
-    pub fn declare_fun(&mut self, id: &String) {
+    pub fn declare_fun(&mut self, id: &Symbol) {
         //Find the function closure and update the variable closure for declaration point
         if let Some(head) = &self.fun_head {
-            head.borrow_mut().declare_fun(&id, self.var_head.clone())
+            head.borrow_mut().declare_fun(id, &self.var_head)
         }
     }
 
@@ -220,37 +380,116 @@ impl<T: Clone> Environment<T> {
         self.fun_head = new_head
     }
 
-    pub fn update_return_type(&mut self, id: &String, ret_type: Type) {
+    ///Keeps the function as it was type checked, as checking puts the fields of struct literals in order
+    pub fn update_fun(&mut self, id: &Symbol, fun: Box<Function>) {
+        if let Some(head) = &self.fun_head {
+            head.borrow_mut().update_fun(id, fun)
+        }
+    }
+
+    pub fn update_return_type(&mut self, id: &Symbol, ret_type: Type) {
         if let Some(head) = &self.fun_head {
-            head.borrow_mut().update_return_type(&id, ret_type)
+            head.borrow_mut().update_return_type(id, ret_type)
         } else {
             panic!("Should not be empty here!")
         }
     }
 
+    ///The environment a closure of the function keeps. Of the variables, it only has those the function uses
+    pub fn capture(&self, fun: &Function) -> Self {
+        let mut envir = self.clone();
+        envir.var_head = captured(&self.var_head, &fun.used_names());
+        envir
+    }
+
     pub fn get_scope(&mut self, scope: u32) -> Self {
-        let var_head = match &self.var_head {
-            Some(head) => if head.borrow().scope_depth > scope {
-                head.borrow().get_scope(scope).clone()
-            } else {
-                Some(head.clone())
-            },
-            None => None,
-        };
-        let fun_head = match &self.fun_head {
-            Some(head) => if head.borrow().scope_depth > scope {
-                head.borrow().get_scope(scope).clone()
-            } else {
-                Some(head.clone())
-            },
-            None => None,
-        };
         Self { 
             scope_depth: scope,
-            var_head,
-            fun_head
+            var_head: scope_head(&self.var_head, scope),
+            fun_head: scope_head(&self.fun_head, scope),
+            type_head: scope_head(&self.type_head, scope),
+            loops: Vec::new(),
+        }
+    }
+
+    pub fn enter_loop(&mut self, label: Option<String>) {
+        self.loops.push(label)
+    }
+
+    pub fn leave_loop(&mut self) {
+        self.loops.pop();
+    }
+
+    ///Whether a break or continue with the given label has a loop to target
+    pub fn loop_exist(&self, label: &Option<String>) -> bool {
+        match label {
+            Some(_) => self.loops.contains(label),
+            None => !self.loops.is_empty(),
         }
     }
+
+    ///Removes all enclosing loops, as they can not be targeted from inside a function
+    pub fn take_loops(&mut self) -> Vec<Option<String>> {
+        std::mem::take(&mut self.loops)
+    }
+
+    pub fn restore_loops(&mut self, loops: Vec<Option<String>>) {
+        self.loops = loops
+    }
+
+    ///The scope and loops, to go back to after a statement with an error
+    pub fn checkpoint(&self) -> (u32, Vec<Option<String>>) {
+        (self.scope_depth, self.loops.clone())
+    }
+
+    ///Leaves the scopes entered since the checkpoint, and restores its loops
+    pub fn restore(&mut self, (scope_depth, loops): (u32, Vec<Option<String>>)) {
+        while self.scope_depth > scope_depth {
+            self.leave_scope();
+        }
+        self.loops = loops;
+    }
+}
+
+///The head of a list, without the nodes deeper than 'scope'
+fn scope_head<T: Clone>(head: &Option<Rc<RefCell<EnvNode<T>>>>, scope: u32) -> Option<Rc<RefCell<EnvNode<T>>>> {
+    match head {
+        Some(head) => if head.borrow().scope_depth > scope {
+            head.borrow().get_scope(scope).clone()
+        } else {
+            Some(head.clone())
+        },
+        None => None,
+    }
+}
+
+///A list of nodes pointing to the variables with the names, in the order they are in the list. Shadowed variables are left out
+fn captured<T: Clone>(head: &Option<Rc<RefCell<EnvNode<T>>>>, names: &[Symbol]) -> Option<Rc<RefCell<EnvNode<T>>>> {
+    let mut found: Vec<Rc<RefCell<EnvNode<T>>>> = Vec::new();
+    let mut node = head.clone();
+    while let Some(current) = node {
+        if found.len() == names.len() {
+            break
+        }
+        let id = current.borrow().id;
+        if names.contains(&id) && !found.iter().any(|other| other.borrow().id == id) {
+            found.push(current.clone());
+        }
+        node = current.borrow().next.clone();
+    }
+
+    let mut captured = None;
+    for node in found.into_iter().rev() {
+        let (id, scope_depth) = (node.borrow().id, node.borrow().scope_depth);
+        captured = Some(Rc::new(RefCell::new(EnvNode { scope_depth, id, value: Binding::Captured(node), next: captured, read_open: false })));
+    }
+    captured
+}
+
+impl<T: Clone> Default for Environment<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> Clone for Environment<T> {
@@ -259,16 +498,8 @@ impl<T> Clone for Environment<T> {
             scope_depth: self.scope_depth, 
             var_head: self.var_head.clone(), 
             fun_head: self.fun_head.clone(), 
+            type_head: self.type_head.clone(),
+            loops: self.loops.clone(),
         }
     }
-}
-
-mod environment_tests {
-    //use super::*;
-
-    #[test]
-    fn test1() {
-        //let mut envir = 
-        //assert_eq!(x + y, 15);
-    }
 }
\ No newline at end of file
@@ -1,232 +1,1014 @@
+use std::{fmt::Display, cell::{Cell, RefCell}, collections::HashMap, rc::Rc, sync::{Arc, atomic::{AtomicU64, Ordering}}, time::Instant};
+
+use lazy_static::lazy_static;
+
 use super::*;
+use options::{ExecutionOptions, Overflow};
+use interner::Symbol;
+use snapshot::Snapshot;
 use Literal::*;
 use Operator::*;
 use Exp::*;
 
-impl<'a> Exp {
-    pub fn evaluate(&'a self, envir: &'a mut Environment<Literal>) -> Literal {
+///Interrupts the normal evaluation and travels up the tree until it is handled
+#[derive(Debug, Clone)]
+pub enum Signal {
+    ///Optional label of the targeted loop
+    Break(Option<String>, Location),
+    Continue(Option<String>, Location),
+
+    ///Function and prepared environment of a call in tail position
+    TailCall(Box<Function>, Environment<Literal>, Location),
+
+    ///Runtime error, which ends the program
+    Error(String, Location),
+
+    ///Ends the program with a status code
+    Exit(i32),
+
+    ///The program used all of its fuel
+    BudgetExceeded(Location),
+
+    ///The program ran for longer than its maximum duration
+    Timeout(Location),
+
+    ///An error and the calls it went through on its way out, innermost first. Each call is the function and where it was called
+    Traced(Box<Signal>, Vec<(Symbol, Location)>),
+}
+
+///A prepared call. Builtins have already run, as they cannot be in tail position
+enum Call {
+    Closure(Box<Function>, Environment<Literal>, Location),
+    Done(Literal)
+}
+
+impl Call {
+    fn run(self) -> EvalResult {
         match self {
-            BinOpExp(left, op, right, _) => match op {
-                Plus => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Int(left + right),
-                    (Int(left), Float(right)) => Float(left as f64 + right),
-                    (Float(left), Int(right)) => Float(left + right  as f64),
-                    (Float(left), Float(right)) => Float(left + right),
-
-                    (Str(left), Str(right)) => Str(format!("{}{}", left, right)),
-                    (Str(left), Char(right)) => Str(format!("{}{}", left, right)),
-                    (Char(left), Str(right)) => Str(format!("{}{}", left, right)),
-                    (Str(left), Int(right)) => Str(format!("{}{}", left, right)),
-                    (Int(left), Str(right)) => Str(format!("{}{}", left, right)),
-                    (Str(left), Float(right)) => Str(format!("{}{}", left, right)),
-                    (Float(left), Str(right)) => Str(format!("{}{}", left, right)),
-                    (Str(left), Bool(right)) => Str(format!("{}{}", left, right)),
-                    (Bool(left), Str(right)) => Str(format!("{}{}", left, right)),
-                    (Char(left), Char(right)) => Str(format!("{}{}", left, right)),
-
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                Minus => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Int(left - right),
-                    (Int(left), Float(right)) => Float(left as f64 - right),
-                    (Float(left), Int(right)) => Float(left - right  as f64),
-                    (Float(left), Float(right)) => Float(left - right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                Multiply => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Int(left * right),
-                    (Int(left), Float(right)) => Float(left as f64 * right),
-                    (Float(left), Int(right)) => Float(left * right  as f64),
-                    (Float(left), Float(right)) => Float(left * right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                Divide => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Int(left / right),
-                    (Int(left), Float(right)) => Float(left as f64 / right),
-                    (Float(left), Int(right)) => Float(left / right  as f64),
-                    (Float(left), Float(right)) => Float(left / right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                Modulo => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Int(left % right),
-                    (Int(left), Float(right)) => Float(left as f64 % right),
-                    (Float(left), Int(right)) => Float(left % right  as f64),
-                    (Float(left), Float(right)) => Float(left % right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                LessThan => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Bool(left < right),
-                    (Int(left), Float(right)) => Bool((left as f64) < right),
-                    (Float(left), Int(right)) => Bool(left < right  as f64),
-                    (Float(left), Float(right)) => Bool(left < right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                GreaterThan => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Bool(left > right),
-                    (Int(left), Float(right)) => Bool(left as f64 > right),
-                    (Float(left), Int(right)) => Bool(left > right  as f64),
-                    (Float(left), Float(right)) => Bool(left > right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                Equals => Bool(left.evaluate(envir) == right.evaluate(envir)),
-                NotEquals => Bool(left.evaluate(envir) != right.evaluate(envir)),
-                LessOrEquals => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Bool(left <= right),
-                    (Int(left), Float(right)) => Bool(left as f64 <= right),
-                    (Float(left), Int(right)) => Bool(left <= right  as f64),
-                    (Float(left), Float(right)) => Bool(left <= right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                GreaterOrEquals => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Int(left), Int(right)) => Bool(left >= right),
-                    (Int(left), Float(right)) => Bool(left as f64 >= right),
-                    (Float(left), Int(right)) => Bool(left >= right  as f64),
-                    (Float(left), Float(right)) => Bool(left >= right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                And => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Bool(left), Bool(right)) => Bool(left && right),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                Or => match (left.evaluate(envir), right.evaluate(envir)) {
-                    (Bool(left), Bool(right)) => Bool(left || right),
-                    _ => unreachable!("Runtime type-error should not happen"),
+            Call::Closure(fun, call_envir, loc) => run_call(fun, call_envir, loc),
+            Call::Done(lit) => Ok(lit)
+        }
+    }
+}
+
+pub type EvalResult = Result<Literal, Signal>;
+
+///Runs a type checked program with the tree-walking interpreter
+pub fn run(program: &Exp, options: &ExecutionOptions) -> EvalResult {
+    let _run = start_run(options);
+    coverage::register(program);
+    program.evaluate(&mut Environment::new())
+}
+
+///Runs a program like run, with top-level variables from before it, and also gives the values its top-level variables had when it ended
+pub fn run_with_variables(program: &Exp, variables: Vec<(Symbol, Literal)>, options: &ExecutionOptions) -> (EvalResult, Vec<(Symbol, Literal)>) {
+    let (exps, funs) = match program {
+        BlockExp(exps, funs, _) => (exps, funs),
+        _ => return (run(program, options), Vec::new())
+    };
+    let _run = start_run(options);
+    coverage::register(program);
+    let mut envir = Environment::new();
+    envir.enter_scope();
+    for (id, value) in variables {
+        envir.push_variable(&id, value);
+    }
+    let res = Exp::evaluate_statements(exps, funs, false, &mut envir);
+    (res, envir.variables())
+}
+
+///Runs a program, or resumes it from a saved snapshot. After each top-level statement, a snapshot of the program is given to save
+pub fn run_resumable(program: &Exp, from: Option<&String>, options: &ExecutionOptions, mut save: impl FnMut(Snapshot)) -> EvalResult {
+    let (exps, funs, loc) = match program {
+        BlockExp(exps, funs, loc) => (exps, funs, *loc),
+        _ => return run(program, options)
+    };
+    let _run = start_run(options);
+    coverage::register(program);
+    let mut envir = Environment::new();
+    envir.enter_scope();
+    for fun in funs {
+        envir.push_function(&fun.0, fun.1.clone())
+    }
+    envir.update_fun_envirs();
+
+    let start = match from {
+        Some(text) => {
+            let snapshot = snapshot::read(text, |id| envir.lookup_fun(&id).is_ok().then(|| fun_value(&id, &mut envir)))
+                .map_err(|msg| Signal::Error(msg, loc))?;
+            for (id, value) in snapshot.variables {
+                envir.push_variable(&id, value);
+            }
+            //Declarations are not in the snapshot, so they are made again
+            for exp in exps.iter().take(snapshot.next) {
+                if matches!(exp, FunDeclExp(_, _) | StructDeclExp(_, _, _) | InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _)) {
+                    exp.evaluate(&mut envir)?;
+                }
+            }
+            snapshot.next
+        },
+        None => 0
+    };
+    let mut returned = Ok(Unit);
+    for (i, exp) in exps.iter().enumerate().skip(start) {
+        returned = Ok(exp.evaluate_statement(false, &mut envir)?);
+        let mut variables = envir.variables();
+        variables.reverse();
+        save(Snapshot { next: i + 1, variables });
+    }
+    returned
+}
+
+///Default for how many calls can be running at once. Calls in tail position do not count
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+///The options of a run, and how much of its limits it has used. The tasks it spawns share its fuel and deadline
+#[derive(Debug, Clone)]
+pub struct RunState {
+    options: ExecutionOptions,
+
+    ///Steps left, when the fuel is limited
+    fuel: Arc<AtomicU64>,
+
+    deadline: Option<Instant>,
+}
+
+impl RunState {
+    ///The fuel and the time start counting now
+    fn new(options: &ExecutionOptions) -> Self {
+        Self {
+            options: options.clone(),
+            fuel: Arc::new(AtomicU64::new(options.fuel.unwrap_or(0))),
+            deadline: options.max_duration.map(|max_duration| Instant::now() + max_duration),
+        }
+    }
+}
+
+thread_local! {
+    ///The run evaluating on this thread. Outside of a run everything is allowed
+    static RUN: RefCell<Rc<RunState>> = RefCell::new(Rc::new(RunState::new(&ExecutionOptions::default())));
+
+    ///Calls that have not returned yet on this thread
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+///Puts back the run that was on the thread before when it is dropped
+pub struct RunGuard {
+    previous: Rc<RunState>,
+
+    ///Whether profiling and coverage were recorded before, to go back to
+    recording: (bool, bool),
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.clone();
+        RUN.with(|run| *run.borrow_mut() = previous);
+        profiler::set_recording(self.recording.0);
+        coverage::set_recording(self.recording.1);
+    }
+}
+
+///Starts a run with the options on this thread, which lasts until the guard is dropped. Each run has its own options and limits,
+///so interpreters with different options can run at the same time
+pub fn start_run(options: &ExecutionOptions) -> RunGuard {
+    let recording = (profiler::set_profiling(options.profile), coverage::set_coverage(options.coverage));
+    let previous = RUN.with(|run| run.replace(Rc::new(RunState::new(options))));
+    RunGuard { previous, recording }
+}
+
+///Goes on with a run on this thread until the guard is dropped, like for a callback or task it started.
+///Its calls are profiled and covered if the run is
+pub fn enter_run(state: Rc<RunState>) -> RunGuard {
+    let recording = (profiler::set_recording(state.options.profile), coverage::set_recording(state.options.coverage));
+    RunGuard { previous: RUN.with(|run| run.replace(state)), recording }
+}
+
+///The run on this thread
+pub fn current_run() -> Rc<RunState> {
+    RUN.with(|run| run.borrow().clone())
+}
+
+///The run on this thread, for a task that takes part in it on another thread. Tasks are not traced
+pub fn task_run() -> RunState {
+    let mut state = RunState::clone(&current_run());
+    state.options.trace = None;
+    state
+}
+
+///Reads the options of the run on this thread
+pub fn with_options<R>(f: impl FnOnce(&ExecutionOptions) -> R) -> R {
+    RUN.with(|run| f(&run.borrow().options))
+}
+
+///How many calls are running on this thread
+pub fn call_depth() -> usize {
+    CALL_DEPTH.with(|depth| depth.get())
+}
+
+pub fn max_call_depth() -> usize {
+    with_options(|options| options.max_call_depth)
+}
+
+///Stack that is kept free for the calls of builtins and the host, when a program recurses
+const STACK_RESERVE: usize = 256 * 1_024;
+
+///Counts a call, or gives an error at the call if it goes too deep, or the thread is about to run out of stack
+fn enter_call(loc: Location) -> Result<(), Signal> {
+    let max = max_call_depth();
+    CALL_DEPTH.with(|depth| {
+        if depth.get() >= max {
+            return Err(Signal::Error(format!("Maximum recursion depth exceeded, calls can only go {max} deep"), loc))
+        }
+        if stacker::remaining_stack().is_some_and(|left| left < STACK_RESERVE) {
+            return Err(Signal::Error(format!("Maximum recursion depth exceeded, the stack ran out after {} calls", depth.get()), loc))
+        }
+        depth.set(depth.get() + 1);
+        Ok(())
+    })
+}
+
+fn leave_call() {
+    CALL_DEPTH.with(|depth| depth.set(depth.get() - 1));
+}
+
+///Called with each statement that is evaluated and its value
+pub type TraceSink = fn(&Exp, &Literal);
+
+fn trace(exp: &Exp, value: &Literal) {
+    if let Some(sink) = with_options(|options| options.trace) {
+        sink(exp, value)
+    }
+}
+
+///Uses fuel for the steps, or stops the program at the location if it is used up
+fn use_fuel(run: &RunState, steps: u64, loc: Location) -> Result<(), Signal> {
+    if run.options.fuel.is_none() {
+        return Ok(())
+    }
+    //Tasks use the same fuel, so it is taken in one step
+    match run.fuel.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |fuel| fuel.checked_sub(steps)) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Signal::BudgetExceeded(loc))
+    }
+}
+
+///Stops the program at the location if the run is out of time
+fn check_time(run: &RunState, loc: Location) -> Result<(), Signal> {
+    match run.deadline {
+        Some(deadline) if Instant::now() > deadline => Err(Signal::Timeout(loc)),
+        _ => Ok(())
+    }
+}
+
+///Stops the program at the location if the run on this thread is out of time, like while it waits for a task
+pub fn check_deadline(loc: Location) -> Result<(), Signal> {
+    RUN.with(|run| check_time(&run.borrow(), loc))
+}
+
+///Takes a step of the program. Every function call and loop iteration is a step.
+///A long builtin like 'sleep_ms' can go over the deadline, as it is only checked at steps
+pub fn step(loc: Location) -> Result<(), Signal> {
+    RUN.with(|run| {
+        let run = run.borrow();
+        use_fuel(&run, 1, loc)?;
+        check_time(&run, loc)?;
+        memory::check_memory(run.options.max_memory, loc)
+    })
+}
+
+///The ints from 'left' up to 'right'. Each of them is a step, and they must fit in the memory that is left,
+///so a huge range stops the program rather than the host
+fn range(left: i64, right: i64, loc: Location) -> EvalResult {
+    let len = (right as i128 - left as i128).max(0);
+    let too_big = || Signal::Error(format!("Out of memory, the range {left}..{right} is too big"), loc);
+    let len = usize::try_from(len).map_err(|_| too_big())?;
+    RUN.with(|run| {
+        let run = run.borrow();
+        use_fuel(&run, len as u64, loc)?;
+        memory::check_allocation(run.options.max_memory, len.saturating_mul(std::mem::size_of::<Literal>()), loc)
+    })?;
+    let mut elems = Vec::new();
+    elems.try_reserve_exact(len).map_err(|_| too_big())?;
+    elems.extend((left..right).map(Int));
+    Ok(Array(Rc::new(elems)))
+}
+
+///A value that does not have the type it was checked to have. Programs cannot make one, and values from the host are checked,
+///so this only keeps a value that got past the checks from panicking
+pub fn type_error(loc: Location) -> Signal {
+    Signal::Error(String::from("Runtime type error"), loc)
+}
+
+impl Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.loc() {
+            Some(loc) => write!(f, "{}. At {loc}", self.message())?,
+            None => write!(f, "{}", self.message())?
+        }
+        for line in self.trace() {
+            write!(f, "\n    {line}")?;
+        }
+        Ok(())
+    }
+}
+
+///How many different calls of a trace are shown
+const MAX_SHOWN_CALLS: usize = 20;
+
+impl Signal {
+    ///Records that the signal went out of a call. Only errors and stopped programs keep track of the calls
+    pub fn traced(self, name: Symbol, loc: Location) -> Signal {
+        match self {
+            Signal::Error(_, _) | Signal::BudgetExceeded(_) | Signal::Timeout(_) => Signal::Traced(Box::new(self), vec![(name, loc)]),
+            Signal::Traced(signal, mut calls) => {
+                calls.push((name, loc));
+                Signal::Traced(signal, calls)
+            },
+            signal => signal
+        }
+    }
+
+    ///What the signal is, without where it happened
+    pub fn message(&self) -> String {
+        match self {
+            Signal::Break(_, _) => String::from("Unhandled break"),
+            Signal::Continue(_, _) => String::from("Unhandled continue"),
+            Signal::TailCall(_, _, _) => String::from("Unhandled tail call"),
+            Signal::Error(msg, _) => msg.clone(),
+            Signal::Exit(code) => format!("Exited with status {code}"),
+            Signal::BudgetExceeded(_) => String::from("Execution budget exceeded"),
+            Signal::Timeout(_) => String::from("Timed out"),
+            Signal::Traced(signal, _) => signal.message(),
+        }
+    }
+
+    ///Where the signal happened, if it is shown
+    pub fn loc(&self) -> Option<Location> {
+        match self {
+            Signal::Break(_, loc) | Signal::Continue(_, loc) | Signal::Error(_, loc)
+            | Signal::BudgetExceeded(loc) | Signal::Timeout(loc) => Some(*loc),
+            Signal::TailCall(_, _, _) | Signal::Exit(_) => None,
+            Signal::Traced(signal, _) => signal.loc(),
+        }
+    }
+
+    ///The calls the signal went out of, one line each. Calls repeated in a row are shown once
+    pub fn trace(&self) -> Vec<String> {
+        let calls = match self {
+            Signal::Traced(_, calls) => calls,
+            _ => return Vec::new()
+        };
+        let mut lines = Vec::new();
+        let mut shown = 0;
+        let mut i = 0;
+        while i < calls.len() {
+            if shown == MAX_SHOWN_CALLS {
+                lines.push(format!("... {} more calls", calls.len() - i));
+                break
+            }
+            let (name, loc) = calls[i];
+            lines.push(format!("in '{name}', called at {loc}"));
+            shown += 1;
+            //Deep recursion gives the same call many times in a row
+            let repeated = calls[i + 1..].iter().take_while(|call| **call == calls[i]).count();
+            if repeated > 0 {
+                lines.push(format!("... the same call {repeated} more times"));
+            }
+            i += repeated + 1;
+        }
+        lines
+    }
+
+    ///Whether the signal targets a loop with this label. Unlabeled signals target the innermost loop
+    fn targets(label: &Option<String>, loop_label: &Option<String>) -> bool {
+        label.is_none() || label == loop_label
+    }
+}
+
+impl<'a> Exp {
+    pub fn evaluate(&'a self, envir: &'a mut Environment<Literal>) -> EvalResult {
+        Ok(match self {
+            BinOpExp(left, op, right, loc) => match op {
+                Coalesce => match left.evaluate(envir)? {
+                    Optional(Some(lit)) => *lit,
+                    Optional(None) => right.evaluate(envir)?,
+                    _ => return Err(type_error(*loc)),
                 },
-                Assign => match (left.as_ref(), right.evaluate(envir)) {
-                    (VarExp(id, _), value) => {
-                        envir.mutate(id, value);
-                        Unit
-                    },
-                    _ => unreachable!("Not a variable id")
+                Assign => {
+                    let value = right.evaluate(envir)?;
+                    assign(left, value, envir)?;
+                    Unit
                 },
                 PlusAssign | MinusAssign => match left.as_ref() {
                     VarExp(id, loc) => {
-                        let other = Box::new(Exp::LiteralExp(right.evaluate(envir), *loc));
-                        let vexp = Box::new(Exp::VarExp(id.clone(), *loc));
-                        let op = match op {
-                            PlusAssign => Plus,
-                            MinusAssign => Minus,
-                            _ => unreachable!()
-                        };
-                        let new_value = Exp::BinOpExp(vexp, op, other, *loc).evaluate(envir);
-                        envir.mutate(id, new_value);
+                        let other = Box::new(Exp::LiteralExp(right.evaluate(envir)?, *loc));
+                        let vexp = Box::new(Exp::VarExp(*id, *loc));
+                        let op = if *op == PlusAssign { Plus } else { Minus };
+                        let new_value = Exp::BinOpExp(vexp, op, other, *loc).evaluate(envir)?;
+                        assign(left, new_value, envir)?;
                         Unit
                     },
-                    _ => unreachable!("Not a variable id")
+                    _ => return Err(type_error(*loc))
                 },
-                _ => unreachable!("Not a binary operator: '{op}'")
-            },
-            UnOpExp(op, exp, _) => match op {
-                Minus => match exp.evaluate(envir) {
-                    Int(i) => Int(-i),
-                    Float(i) => Float(-i as f64),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                Not => match exp.evaluate(envir) {
-                    Bool(i) => Bool(!i),
-                    _ => unreachable!("Runtime type-error should not happen"),
-                },
-                _ => unreachable!("Not a unary operator")
+                _ => binary_op(op, left.evaluate(envir)?, right.evaluate(envir)?, *loc)?
             },
+            UnOpExp(op, exp, loc) => unary_op(op, exp.evaluate(envir)?, *loc)?,
             LiteralExp(lit, _) => lit.clone(),
-            BlockExp(exps, funs, _) => {
-                envir.enter_scope();
-                for fun in funs {
-                    envir.push_function(fun.0.clone(), fun.1.clone())
-                }
-
-                envir.update_fun_envirs();
-
-                let mut returned = Unit;
-                for exp in exps {
-                    returned = exp.evaluate(envir);
-                }
-                envir.leave_scope();
-
-                returned
+            BlockExp(exps, funs, _) => Exp::evaluate_block(exps, funs, false, envir)?,
+            VarExp(id, loc) => match envir.find_var(id) {
+                Some(lit) => lit,
+                None if envir.find_fun(id).is_some() => fun_value(id, envir),
+                None => lookup_constant(id.as_str()).ok_or_else(|| Signal::Error(format!("Variable '{id}' does not exist"), *loc))?
             },
-            VarExp(id, _) => envir.lookup_var(&id).unwrap(),
             LetExp(id, exp, _) => {
-                let value = exp.evaluate(envir);
-                envir.push_variable(id.clone(), value); 
+                let value = exp.evaluate(envir)?;
+                envir.push_variable(id, value); 
+                if debugger::is_watched(id) {
+                    report_change(id, None, self, envir);
+                }
                 Unit
             },
-            IfElseExp(cond, pos, neg, _) => {
-                let cond = cond.evaluate(envir);
-                let res = match cond {
-                    Bool(true) => pos.evaluate(envir), //This should return unit if neg is none. This should not be a problem after type check
+            IfElseExp(cond, pos, neg, loc) => {
+                let cond = cond.evaluate(envir)?;
+                if let Bool(taken) = cond {
+                    coverage::branch(*loc, taken);
+                }
+                match cond {
+                    Bool(true) => pos.evaluate(envir)?, //This should return unit if neg is none. This should not be a problem after type check
                     Bool(false) => if let Some(exp) = neg {
-                        exp.evaluate(envir)
+                        exp.evaluate(envir)?
                     } else {
                         Unit
                     },
-                    _ => unreachable!("Condition must be a bool. Shouldn't happen at runtime")
-                };
-
-                res
+                    _ => return Err(type_error(*loc))
+                }
             },
-            WhileExp(cond, exp, _) => {
+            WhileExp(cond, exp, label, loc) => {
                 loop {
-                    let res = cond.evaluate(envir);
+                    let res = cond.evaluate(envir)?;
                     match res {
-                        Bool(true) => exp.evaluate(envir),
+                        Bool(true) => match exp.evaluate(envir) {
+                            Err(Signal::Break(target, _)) if Signal::targets(&target, label) => break,
+                            Err(Signal::Continue(target, _)) if Signal::targets(&target, label) => {},
+                            res => { res?; }
+                        },
                         Bool(false) => break,
-                        _ => panic!("Condition must be a bool")
+                        _ => return Err(type_error(*loc))
                     };
+                    step(*loc)?;
                 }
 
                 Unit
-            }
-            FunCallExp(id, args, _) => {
+            },
+            BreakExp(label, loc) => return Err(Signal::Break(label.clone(), *loc)),
+            ContinueExp(label, loc) => return Err(Signal::Continue(label.clone(), *loc)),
+            FunCallExp(_, _, _) | MethodCallExp(_, _, _, _) => self.prepare_call(envir)?.run()?,
+            InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _) | OperatorDeclExp(_, _) => Unit,
+            FunDeclExp(id, _) => {
+                envir.declare_fun(id);
+                Unit
+            },
+            TupleExp(exps, _) => {
                 let mut lits = Vec::new();
-                for i in 0..args.len() {
-                    lits.push(args[i].evaluate(envir));
+                for exp in exps {
+                    lits.push(exp.evaluate(envir)?);
                 }
-
-                let mut closure = envir.lookup_fun(id).unwrap();
-                //If it is not declared, it takes the most recent scope from decl scope
-                let res: Literal = if closure.declared {
-                    closure.envir.enter_scope();
-                    for i in 0..args.len() {
-                        closure.envir.push_variable(closure.fun.params[i].clone(), lits[i].clone());
-                    }
-                    let res = closure.fun.exp.evaluate(&mut closure.envir);
-                    closure.envir.leave_scope();
-                    res
-                } else {
-                    let mut envir = envir.get_scope(closure.decl_scope());
-                    for i in 0..args.len() {
-                        envir.push_variable(closure.fun.params[i].clone(), lits[i].clone());
-                    }
-                    closure.fun.exp.evaluate(&mut envir)
-                };
-
-                if closure.fun.ret_type == ast::Type::Unit {
-                    Unit
-                } else {
-                    res
+                Tuple(lits.into())
+            },
+            ArrayExp(exps, _) => Array(Rc::new(evaluate_elements(exps, envir)?)),
+            SpreadExp(_, loc) => return Err(type_error(*loc)),
+            IndexExp(exp, index, loc) => {
+                let value = exp.evaluate(envir)?;
+                index_op(value, index.evaluate(envir)?, *loc)?
+            },
+            StructDeclExp(_, _, _) => Unit,
+            StructExp(id, fields, _) => {
+                let mut lits = Vec::new();
+                for (field, exp) in fields {
+                    lits.push((field.clone(), exp.evaluate(envir)?));
                 }
+                Struct(Rc::new(StructValue { name: id.clone(), fields: lits }))
             },
-            FunDeclExp(id, _) => {
-                envir.declare_fun(&id);
+            FieldExp(exp, field, loc) => match exp.evaluate(envir)? {
+                Struct(value) => match value.fields.iter().find(|(id, _)| id == field) {
+                    Some((_, lit)) => lit.clone(),
+                    None => return Err(type_error(*loc)),
+                },
+                _ => return Err(type_error(*loc)),
+            },
+            DestructExp(pattern, exp, _) => {
+                let value = exp.evaluate(envir)?;
+                bind_pattern(pattern, value, self, envir)?;
+                Unit
+            },
+            OptionExp(exp, _) => match exp {
+                Some(exp) => Optional(Some(Box::new(exp.evaluate(envir)?))),
+                None => Optional(None),
+            },
+            ForInExp(id, exp, body, label, loc) => {
+                let mut iterator = exp.evaluate(envir)?;
+                let mut index = 0;
+                loop {
+                    //Arrays and sets are looped over directly, to avoid copying the rest in every 'next'
+                    let lit = match &iterator {
+                        Array(lits) | Set(lits) => match lits.get(index) {
+                            Some(lit) => {
+                                index += 1;
+                                lit.clone()
+                            },
+                            None => break
+                        },
+                        _ => match call_next(iterator, *loc, envir)? {
+                            Some((lit, rest)) => {
+                                iterator = rest;
+                                lit
+                            },
+                            None => break
+                        }
+                    };
+                    envir.enter_scope();
+                    envir.push_variable(id, lit);
+                    let res = body.evaluate(envir);
+                    envir.leave_scope();
+                    match res {
+                        Err(Signal::Break(target, _)) if Signal::targets(&target, label) => break,
+                        Err(Signal::Continue(target, _)) if Signal::targets(&target, label) => {},
+                        res => { res?; }
+                    };
+                    step(*loc)?;
+                }
                 Unit
             },
-            ForExp(let_exp, cond, increment, body, _) => {
+            ForExp(let_exp, cond, increment, body, label, loc) => {
                 envir.enter_scope();
-                let_exp.evaluate(envir);
+                let res = Exp::run_for(let_exp, cond, increment, body, label, *loc, envir);
+                envir.leave_scope();
 
-                loop {
-                    if let Literal::Bool(false) = cond.evaluate(envir) {
-                        break;
+                res?
+            },
+        })
+    }
+
+    ///Like evaluate, but a function call in tail position is returned as a signal instead of being evaluated
+    fn evaluate_tail(&'a self, envir: &'a mut Environment<Literal>) -> EvalResult {
+        match self {
+            FunCallExp(_, _, _) | MethodCallExp(_, _, _, _) => match self.prepare_call(envir)? {
+                Call::Closure(fun, call_envir, loc) => Err(Signal::TailCall(fun, call_envir, loc)),
+                Call::Done(lit) => Ok(lit)
+            },
+            IfElseExp(cond, pos, neg, loc) => match cond.evaluate(envir)? {
+                Bool(true) => {
+                    coverage::branch(*loc, true);
+                    pos.evaluate_tail(envir)
+                },
+                Bool(false) => {
+                    coverage::branch(*loc, false);
+                    match neg {
+                        Some(neg) => neg.evaluate_tail(envir),
+                        None => Ok(Unit)
                     }
-                    body.evaluate(envir);
-                    increment.evaluate(envir);
-                }
+                },
+                _ => Err(type_error(*loc))
+            },
+            BlockExp(exps, funs, _) => Exp::evaluate_block(exps, funs, true, envir),
+            _ => self.evaluate(envir)
+        }
+    }
 
-                envir.leave_scope();
+    fn evaluate_block(exps: &[Exp], funs: &[(Symbol, Box<Function>)], tail: bool, envir: &mut Environment<Literal>) -> EvalResult {
+        envir.enter_scope();
+        //The scope must be left even when a signal interrupts the block
+        let returned = Exp::evaluate_statements(exps, funs, tail, envir);
+        envir.leave_scope();
 
-                Unit
+        returned
+    }
+
+    ///Evaluates the statements of a block in the current scope, after declaring its functions
+    fn evaluate_statements(exps: &[Exp], funs: &[(Symbol, Box<Function>)], tail: bool, envir: &mut Environment<Literal>) -> EvalResult {
+        for fun in funs {
+            envir.push_function(&fun.0, fun.1.clone())
+        }
+
+        envir.update_fun_envirs();
+
+        let mut returned = Ok(Unit);
+        for (i, exp) in exps.iter().enumerate() {
+            returned = exp.evaluate_statement(tail && i == exps.len() - 1, envir);
+            if returned.is_err() {
+                break
+            }
+        }
+        returned
+    }
+
+    ///Evaluates a statement of a block, and lets coverage, the debugger and the trace see it
+    fn evaluate_statement(&'a self, tail: bool, envir: &'a mut Environment<Literal>) -> EvalResult {
+        coverage::statement(self.loc());
+        debugger::before_statement(self, envir);
+        let returned = match tail {
+            true => self.evaluate_tail(envir),
+            false => self.evaluate(envir)
+        };
+        if let Ok(value) = &returned {
+            trace(self, value);
+        }
+        returned
+    }
+
+    ///Evaluates the arguments of a function or method call, and creates the environment the function body runs in
+    fn prepare_call(&'a self, envir: &'a mut Environment<Literal>) -> Result<Call, Signal> {
+        let mut lits = Vec::new();
+        let (id, first, args, loc) = match self {
+            FunCallExp(id, args, loc) => (*id, args.first(), args, loc),
+            MethodCallExp(exp, method, args, loc) => {
+                let receiver = exp.evaluate(envir)?;
+                let id = method_id(&receiver, method, envir);
+                lits.push(receiver);
+                (id, Some(exp.as_ref()), args, loc)
             },
+            _ => return Err(type_error(self.loc()))
+        };
+        lits.extend(evaluate_elements(args, envir)?);
+        prepare_named_call(&id, first, lits, *loc, envir)
+    }
+
+    fn run_for(let_exp: &Exp, cond: &Exp, increment: &Exp, body: &Exp, label: &Option<String>, loc: Location, envir: &mut Environment<Literal>) -> EvalResult {
+        let_exp.evaluate(envir)?;
+
+        loop {
+            if let Literal::Bool(false) = cond.evaluate(envir)? {
+                break;
+            }
+            match body.evaluate(envir) {
+                Err(Signal::Break(target, _)) if Signal::targets(&target, label) => break,
+                Err(Signal::Continue(target, _)) if Signal::targets(&target, label) => {},
+                res => { res?; }
+            }
+            increment.evaluate(envir)?;
+            step(loc)?;
+        }
+
+        Ok(Unit)
+    }
+}
+
+///Applies a binary operator to two values. Dividing an int by zero is a runtime error at the location of the operation
+pub fn binary_op(op: &Operator, left: Literal, right: Literal, loc: Location) -> EvalResult {
+    Ok(match op {
+        Plus => match (left, right) {
+            (Int(l), Int(r)) => int_result(l.checked_add(r), l.wrapping_add(r), l.saturating_add(r), || format!("{l} + {r}"), loc)?,
+            (Int(left), Float(right)) => Float(left as f64 + right),
+            (Float(left), Int(right)) => Float(left + right  as f64),
+            (Float(left), Float(right)) => Float(left + right),
+
+            (Str(left), Str(right)) => Str(format!("{}{}", left, right).into()),
+            (Str(left), Char(right)) => Str(format!("{}{}", left, right).into()),
+            (Char(left), Str(right)) => Str(format!("{}{}", left, right).into()),
+            (Str(left), Int(right)) => Str(format!("{}{}", left, right).into()),
+            (Int(left), Str(right)) => Str(format!("{}{}", left, right).into()),
+            (Str(left), Float(right)) => Str(format!("{}{}", left, right).into()),
+            (Float(left), Str(right)) => Str(format!("{}{}", left, right).into()),
+            (Str(left), Bool(right)) => Str(format!("{}{}", left, right).into()),
+            (Bool(left), Str(right)) => Str(format!("{}{}", left, right).into()),
+            (Char(left), Char(right)) => Str(format!("{}{}", left, right).into()),
+
+            _ => return Err(type_error(loc)),
+        },
+        Minus => match (left, right) {
+            (Int(l), Int(r)) => int_result(l.checked_sub(r), l.wrapping_sub(r), l.saturating_sub(r), || format!("{l} - {r}"), loc)?,
+            (Int(left), Float(right)) => Float(left as f64 - right),
+            (Float(left), Int(right)) => Float(left - right  as f64),
+            (Float(left), Float(right)) => Float(left - right),
+            _ => return Err(type_error(loc)),
+        },
+        Multiply => match (left, right) {
+            (Int(l), Int(r)) => int_result(l.checked_mul(r), l.wrapping_mul(r), l.saturating_mul(r), || format!("{l} * {r}"), loc)?,
+            (Int(left), Float(right)) => Float(left as f64 * right),
+            (Float(left), Int(right)) => Float(left * right  as f64),
+            (Float(left), Float(right)) => Float(left * right),
+            _ => return Err(type_error(loc)),
+        },
+        Divide => match (left, right) {
+            (Int(_), Int(0)) => return Err(Signal::Error(String::from("Division by zero"), loc)),
+            (Int(l), Int(r)) => int_result(l.checked_div(r), l.wrapping_div(r), l.saturating_div(r), || format!("{l} / {r}"), loc)?,
+            (Int(left), Float(right)) => Float(left as f64 / right),
+            (Float(left), Int(right)) => Float(left / right  as f64),
+            (Float(left), Float(right)) => Float(left / right),
+            _ => return Err(type_error(loc)),
+        },
+        Modulo => match (left, right) {
+            (Int(_), Int(0)) => return Err(Signal::Error(String::from("Modulo by zero"), loc)),
+            //The remainder of the min int by -1 is 0, but it still overflows when computed
+            (Int(l), Int(r)) => int_result(l.checked_rem(r), l.wrapping_rem(r), l.wrapping_rem(r), || format!("{l} % {r}"), loc)?,
+            (Int(left), Float(right)) => Float(left as f64 % right),
+            (Float(left), Int(right)) => Float(left % right  as f64),
+            (Float(left), Float(right)) => Float(left % right),
+            _ => return Err(type_error(loc)),
+        },
+        LessThan => match (left, right) {
+            (Int(left), Int(right)) => Bool(left < right),
+            (Int(left), Float(right)) => Bool((left as f64) < right),
+            (Float(left), Int(right)) => Bool(left < right  as f64),
+            (Float(left), Float(right)) => Bool(left < right),
+            _ => return Err(type_error(loc)),
+        },
+        GreaterThan => match (left, right) {
+            (Int(left), Int(right)) => Bool(left > right),
+            (Int(left), Float(right)) => Bool(left as f64 > right),
+            (Float(left), Int(right)) => Bool(left > right  as f64),
+            (Float(left), Float(right)) => Bool(left > right),
+            _ => return Err(type_error(loc)),
+        },
+        Equals => Bool(left == right),
+        NotEquals => Bool(left != right),
+        LessOrEquals => match (left, right) {
+            (Int(left), Int(right)) => Bool(left <= right),
+            (Int(left), Float(right)) => Bool(left as f64 <= right),
+            (Float(left), Int(right)) => Bool(left <= right  as f64),
+            (Float(left), Float(right)) => Bool(left <= right),
+            _ => return Err(type_error(loc)),
+        },
+        GreaterOrEquals => match (left, right) {
+            (Int(left), Int(right)) => Bool(left >= right),
+            (Int(left), Float(right)) => Bool(left as f64 >= right),
+            (Float(left), Int(right)) => Bool(left >= right  as f64),
+            (Float(left), Float(right)) => Bool(left >= right),
+            _ => return Err(type_error(loc)),
+        },
+        Range => match (left, right) {
+            (Int(left), Int(right)) => range(left, right, loc)?,
+            _ => return Err(type_error(loc)),
+        },
+        And => match (left, right) {
+            (Bool(left), Bool(right)) => Bool(left && right),
+            _ => return Err(type_error(loc)),
+        },
+        Or => match (left, right) {
+            (Bool(left), Bool(right)) => Bool(left || right),
+            _ => return Err(type_error(loc)),
+        },
+        _ => return Err(type_error(loc))
+    })
+}
+
+pub fn unary_op(op: &Operator, value: Literal, loc: Location) -> EvalResult {
+    Ok(match op {
+        Minus => match value {
+            Int(i) => int_result(i.checked_neg(), i.wrapping_neg(), i.saturating_neg(), || format!("-({i})"), loc)?,
+            Float(i) => Float(-i),
+            _ => return Err(type_error(loc)),
+        },
+        Not => match value {
+            Bool(i) => Bool(!i),
+            _ => return Err(type_error(loc)),
+        },
+        _ => return Err(type_error(loc))
+    })
+}
+
+///The element of an array at the index, or the char of a string, which is counted in chars rather than bytes
+pub fn index_op(value: Literal, index: Literal, loc: Location) -> EvalResult {
+    let i = match index {
+        Int(i) => usize::try_from(i).ok(),
+        _ => return Err(type_error(loc))
+    };
+    let elem = match value {
+        Array(lits) => i.and_then(|i| lits.get(i).cloned()),
+        Str(s) => i.and_then(|i| s.chars().nth(i)).map(Char),
+        _ => return Err(type_error(loc))
+    };
+    elem.ok_or_else(|| Signal::Error(String::from("Index out of bounds"), loc))
+}
+
+pub fn overflow() -> Overflow {
+    with_options(|options| options.overflow)
+}
+
+///The result of int arithmetic, given as it is when it fits and else as the overflow setting says.
+///'what' describes the operation for the error
+pub fn int_result(checked: Option<i64>, wrapped: i64, saturated: i64, what: impl FnOnce() -> String, loc: Location) -> EvalResult {
+    match checked {
+        Some(i) => Ok(Int(i)),
+        None => match overflow() {
+            Overflow::Error => Err(Signal::Error(format!("Overflow in {}", what()), loc)),
+            Overflow::Wrap => Ok(Int(wrapped)),
+            Overflow::Saturate => Ok(Int(saturated)),
+        }
+    }
+}
+
+///Evaluates the elements of an array literal or the arguments of a call, where spreads give all their elements
+fn evaluate_elements(exps: &Vec<Exp>, envir: &mut Environment<Literal>) -> Result<Vec<Literal>, Signal> {
+    let mut lits = Vec::new();
+    for exp in exps {
+        match exp {
+            SpreadExp(exp, loc) => match exp.evaluate(envir)? {
+                Array(elems) => lits.extend(elems.iter().cloned()),
+                Tuple(elems) => lits.extend(elems.iter().cloned()),
+                _ => return Err(type_error(*loc)),
+            },
+            exp => lits.push(exp.evaluate(envir)?)
+        }
+    }
+    Ok(lits)
+}
+
+thread_local! {
+    ///The names 'Struct.method' by method and struct, so a method call only makes the name the first time
+    static METHODS: RefCell<HashMap<Symbol, HashMap<String, Symbol>>> = RefCell::new(HashMap::new());
+}
+
+lazy_static! {
+    static ref NEXT: Symbol = Symbol::new("next");
+}
+
+///Methods are functions named 'Struct.method'. Otherwise 'x.f(a)' is 'f(x, a)'
+fn method_id(receiver: &Literal, method: &Symbol, envir: &Environment<Literal>) -> Symbol {
+    if let Struct(value) = receiver {
+        let id = METHODS.with(|methods| {
+            let mut methods = methods.borrow_mut();
+            let structs = methods.entry(*method).or_default();
+            match structs.get(&value.name) {
+                Some(id) => *id,
+                None => *structs.entry(value.name.clone()).or_insert_with(|| Symbol::new(&format!("{}.{method}", value.name)))
+            }
+        });
+        if envir.find_fun(&id).is_some() {
+            return id
+        }
+    }
+    *method
+}
+
+///Finds the function, function value, host function or builtin with the name, and prepares the call with the evaluated arguments.
+///The first argument is needed for builtins that change it
+fn prepare_named_call(id: &Symbol, first: Option<&Exp>, lits: Vec<Literal>, loc: Location, envir: &mut Environment<Literal>) -> Result<Call, Signal> {
+    let mut lits = lits;
+    //A function value is only looked for when there is no function with the name
+    let closure = match envir.find_fun(id) {
+        Some(closure) => closure,
+        None => match envir.find_var(id) {
+            Some(Fun(closure)) => *closure,
+            _ => return match (host::lookup_host_function(id), lookup_builtin_symbol(id)) {
+                (Some(host), _) => Ok(Call::Done(host.call(id, &lits, loc)?)),
+                (None, Some(builtin)) => {
+                    //The type checker ensures that the first argument is a variable.
+                    //It lets go of its value during the call, so the builtin can change it without copying it
+                    let (var, old) = match first {
+                        Some(first @ VarExp(var, _)) if builtin.mutates => {
+                            let old = debugger::is_watched(var).then(|| lits[0].clone());
+                            envir.mutate(var, Unit);
+                            (Some((var, first)), old)
+                        },
+                        _ => (None, None)
+                    };
+                    let res = (builtin.eval)(&mut lits, loc);
+                    if let Some((var, first)) = var {
+                        envir.mutate(var, lits.swap_remove(0));
+                        if old.is_some() {
+                            report_change(var, old, first, envir);
+                        }
+                    }
+                    Ok(Call::Done(res?))
+                },
+                (None, None) => Err(Signal::Error(format!("Function '{id}' does not exist"), loc))
+            }
+        }
+    };
+    //Only spread arrays can give the wrong number of arguments after the type check
+    if lits.len() != closure.fun.params.len() {
+        return Err(Signal::Error(format!("'{id}' takes {} arguments, got {}", closure.fun.params.len(), lits.len()), loc))
+    }
+    //If it is not declared, it takes the most recent scope from decl scope
+    let mut call_envir = if closure.declared {
+        let mut call_envir = closure.envir;
+        call_envir.enter_scope();
+        call_envir
+    } else {
+        envir.get_scope(closure.decl_scope()).capture(&closure.fun)
+    };
+    for (param, lit) in closure.fun.params.iter().zip(lits) {
+        call_envir.push_variable(param, lit);
+    }
+
+    Ok(Call::Closure(closure.fun, call_envir, loc))
+}
+
+///Calls 'next' on an iterator, giving the next element and the rest of the iteration
+fn call_next(iterator: Literal, loc: Location, envir: &mut Environment<Literal>) -> Result<Option<(Literal, Literal)>, Signal> {
+    let id = method_id(&iterator, &NEXT, envir);
+    match prepare_named_call(&id, None, vec![iterator], loc, envir)?.run()? {
+        Optional(Some(next)) => match *next {
+            Tuple(pair) => Ok(Some((pair[0].clone(), pair[1].clone()))),
+            _ => Err(type_error(loc)),
+        },
+        Optional(None) => Ok(None),
+        _ => Err(type_error(loc)),
+    }
+}
+
+///Runs a prepared call. Calls in tail position come back as signals, and run here without growing the stack
+fn run_call(fun: Box<Function>, call_envir: Environment<Literal>, loc: Location) -> EvalResult {
+    step(loc)?;
+    enter_call(loc)?;
+    profiler::enter(fun.name);
+    let mut fun = fun;
+    let mut call_envir = call_envir;
+    let mut loc = loc;
+    let res = loop {
+        match fun.exp.evaluate_tail(&mut call_envir) {
+            Err(Signal::TailCall(next_fun, next_envir, next_loc)) => {
+                profiler::leave();
+                profiler::enter(next_fun.name);
+                fun = next_fun;
+                call_envir = next_envir;
+                loc = next_loc;
+                if let Err(signal) = step(loc) {
+                    break Err(signal)
+                }
+            },
+            res => break if fun.ret_type == ast::Type::Unit {
+                res.map(|_| Unit)
+            } else {
+                res
+            }
         }
+    };
+    profiler::leave();
+    leave_call();
+    res.map_err(|signal| signal.traced(fun.name, loc))
+}
+
+///Calls a function value with evaluated arguments
+pub fn call_closure(closure: &Closure<Literal>, args: Vec<Literal>, loc: Location) -> EvalResult {
+    let mut call_envir = closure.envir.clone();
+    call_envir.enter_scope();
+    for (param, lit) in closure.fun.params.iter().zip(args) {
+        call_envir.push_variable(param, lit);
+    }
+    run_call(closure.fun.clone(), call_envir, loc)
+}
+
+///A named function as a value. It keeps the environment it would be called in here
+pub fn fun_value(id: &Symbol, envir: &mut Environment<Literal>) -> Literal {
+    let mut closure = envir.lookup_fun(id).unwrap();
+    if !closure.declared {
+        closure.envir = envir.get_scope(closure.decl_scope()).capture(&closure.fun);
+        closure.declared = true;
+    }
+    Fun(Box::new(closure))
+}
+
+///Assigns to a variable, or to a tuple of variables
+fn assign(target: &Exp, value: Literal, envir: &mut Environment<Literal>) -> Result<(), Signal> {
+    match (target, value) {
+        (VarExp(id, _), value) if debugger::is_watched(id) => {
+            let old = envir.lookup_var(id).ok();
+            envir.mutate(id, value);
+            report_change(id, old, target, envir);
+        },
+        (VarExp(id, _), value) => envir.mutate(id, value),
+        (TupleExp(targets, _), Tuple(lits)) => {
+            for (target, lit) in targets.iter().zip(lits.iter()) {
+                assign(target, lit.clone(), envir)?;
+            }
+        },
+        _ => return Err(type_error(target.loc()))
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+///Declares the variables of the pattern in the declaration. The type checker ensures that the value fits
+fn bind_pattern(pattern: &Pattern, value: Literal, declaration: &Exp, envir: &mut Environment<Literal>) -> Result<(), Signal> {
+    match (pattern, value) {
+        (Pattern::Id(id), value) => {
+            envir.push_variable(id, value);
+            if debugger::is_watched(id) {
+                report_change(id, None, declaration, envir);
+            }
+        },
+        (Pattern::Tuple(patterns), Tuple(lits)) => {
+            for (pattern, lit) in patterns.iter().zip(lits.iter()) {
+                bind_pattern(pattern, lit.clone(), declaration, envir)?;
+            }
+        },
+        (Pattern::Struct(_, fields), Struct(value)) => {
+            for (field, pattern) in fields {
+                match value.fields.iter().find(|(id, _)| id == field) {
+                    Some((_, lit)) => bind_pattern(pattern, lit.clone(), declaration, envir)?,
+                    None => return Err(type_error(declaration.loc())),
+                }
+            }
+        },
+        _ => return Err(type_error(declaration.loc())),
+    }
+    Ok(())
+}
+
+///Tells the debugger that a variable it watches was changed by the expression
+fn report_change(id: &Symbol, old: Option<Literal>, exp: &Exp, envir: &Environment<Literal>) {
+    let new = envir.lookup_var(id).unwrap();
+    debugger::changed(debugger::Change { name: *id, old, new, loc: exp.loc() }, exp, envir);
+}
@@ -0,0 +1,143 @@
+use std::{cell::RefCell, collections::{HashMap, VecDeque}, rc::Rc, sync::mpsc::{self, Receiver, RecvTimeoutError, Sender}, time::{Duration, Instant}};
+
+use super::*;
+use eval::{Signal, RunState, call_closure};
+
+///Sends events to the event loop of a thread from anywhere, like a thread that reads input
+#[derive(Debug, Clone)]
+pub struct EventSender(Sender<(String, String)>);
+
+impl EventSender {
+    ///Queues the event. It fails when the event loop is gone
+    pub fn send(&self, event: &str, payload: &str) -> Result<(), String> {
+        self.0.send((event.to_string(), payload.to_string())).map_err(|_| String::from("The event loop has stopped"))
+    }
+}
+
+///A function to call once a time has passed
+struct Timer {
+    due: Instant,
+    callback: Callback,
+}
+
+///A function of the program, called with the options of the run that registered it
+type Callback = (Closure<Literal>, Rc<RunState>);
+
+///Callbacks registered by the program, and what they are waiting for
+struct EventLoop {
+    handlers: HashMap<String, Vec<Callback>>,
+
+    ///Earliest first
+    timers: Vec<Timer>,
+
+    ///Events emitted by the program itself
+    queue: VecDeque<(String, String)>,
+
+    ///Events from hosts. The sender is dropped when the events run, so it is known when the senders of the hosts are all gone
+    sender: Option<Sender<(String, String)>>,
+    receiver: Receiver<(String, String)>,
+}
+
+impl EventLoop {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { handlers: HashMap::new(), timers: Vec::new(), queue: VecDeque::new(), sender: Some(sender), receiver }
+    }
+}
+
+thread_local! {
+    static EVENTS: RefCell<EventLoop> = RefCell::new(EventLoop::new());
+}
+
+///Calls the function each time the event happens, with its payload
+pub fn on(event: &str, callback: Closure<Literal>) {
+    EVENTS.with(|events| events.borrow_mut().handlers.entry(event.to_string()).or_default().push((callback, eval::current_run())))
+}
+
+///Calls the function once, after the duration
+pub fn after(duration: Duration, callback: Closure<Literal>) {
+    let due = Instant::now() + duration;
+    EVENTS.with(|events| {
+        let timers = &mut events.borrow_mut().timers;
+        let index = timers.partition_point(|timer| timer.due <= due);
+        timers.insert(index, Timer { due, callback: (callback, eval::current_run()) });
+    })
+}
+
+///Queues an event on this thread
+pub fn emit(event: &str, payload: &str) {
+    EVENTS.with(|events| events.borrow_mut().queue.push_back((event.to_string(), payload.to_string())))
+}
+
+///Gives a sender that hosts can queue events on this thread with, from any thread
+pub fn sender() -> EventSender {
+    EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        match &events.sender {
+            Some(sender) => EventSender(sender.clone()),
+            None => {
+                //The senders of the last wait are all gone, so events from the new ones need a new channel
+                let (sender, receiver) = mpsc::channel();
+                events.receiver = receiver;
+                events.sender = Some(sender.clone());
+                EventSender(sender)
+            }
+        }
+    })
+}
+
+///The next timer that is due, or else the handlers of the next event with its payload
+fn next_callbacks() -> Option<(Vec<Callback>, Vec<Literal>)> {
+    EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        if events.timers.first().is_some_and(|timer| timer.due <= Instant::now()) {
+            return Some((vec![events.timers.remove(0).callback], Vec::new()))
+        }
+        let (event, payload) = events.queue.pop_front().or_else(|| events.receiver.try_recv().ok())?;
+        let handlers = events.handlers.get(&event).cloned().unwrap_or_default();
+        Some((handlers, vec![Literal::Str(Rc::new(payload))]))
+    })
+}
+
+///Calls the callbacks of the queued events and the timers that are due, including those they add, and gives how many were called.
+///Nothing is borrowed while a callback runs, so it can register callbacks and emit events
+pub fn run_pending() -> Result<usize, Signal> {
+    let mut called = 0;
+    while let Some((callbacks, args)) = next_callbacks() {
+        for (callback, run) in &callbacks {
+            let _run = eval::enter_run(run.clone());
+            call_closure(callback, args.clone(), callback.fun.loc)?;
+        }
+        called += callbacks.len();
+    }
+    Ok(called)
+}
+
+///Runs callbacks until there are no more timers or queued events, and the senders of the hosts are gone
+pub fn run_events() -> Result<(), Signal> {
+    EVENTS.with(|events| events.borrow_mut().sender = None);
+    loop {
+        run_pending()?;
+        let next_timer = EVENTS.with(|events| events.borrow().timers.first().map(|timer| timer.due));
+        let event = match next_timer {
+            Some(due) => {
+                let timeout = due.saturating_duration_since(Instant::now());
+                match EVENTS.with(|events| events.borrow().receiver.recv_timeout(timeout)) {
+                    Ok(event) => Some(event),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        std::thread::sleep(timeout);
+                        None
+                    }
+                }
+            },
+            None => match EVENTS.with(|events| events.borrow().receiver.recv()) {
+                Ok(event) => Some(event),
+                Err(_) => return Ok(())
+            }
+        };
+        if let Some((event, payload)) = event {
+            emit(&event, &payload);
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::VecDeque;
+
+use super::*;
+use interner::Symbol;
+use diagnostics::Diagnostic;
+use Exp::*;
+
+///Formats the program as canonical nebulang: four spaces of indentation, a space around binary operators and after commas,
+///the '{' of a block on the line it belongs to, and a ';' after every statement but the last of a block and those ending with a block.
+///Parentheses are only kept where they are needed. Comments are kept, on their own line before the statement after them,
+///or at the end of the line when there was code before them on their line. A blank line between statements is kept
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let lexed = lex(source).map_err(|(msg, loc)| vec![Diagnostic::new("Lexer Error", msg, loc)])?;
+    let program = parse(&mut lexed.iter()).map_err(|errors| {
+        errors.into_iter().map(|(msg, loc)| Diagnostic::new("Parse Error", msg, loc)).collect::<Vec<_>>()
+    })?;
+    let (exps, funs) = match program {
+        BlockExp(exps, funs, _) => (exps, funs),
+        exp => (vec![exp], Vec::new())
+    };
+
+    let mut formatter = Formatter {
+        lines: source.lines().collect(),
+        tokens: lexed.tokens(),
+        comments: lexed.comments().iter().cloned().collect(),
+        operators: exps.iter().filter_map(|exp| match exp {
+            OperatorDeclExp(custom, _) => Some(custom.clone()),
+            _ => None
+        }).collect()
+    };
+    let mut res = String::new();
+    formatter.statements(&mut res, &exps, &funs, 0, None);
+    Ok(format!("{}\n", res.trim_start_matches('\n')))
+}
+
+///The parsed program as source that parses back to the same tree, apart from locations. It is formatted like by format,
+///with its statements on lines of their own, but there are no comments or blank lines to keep, and a call of a declared operator
+///is written as a call of its function
+pub fn program_source(program: &Exp) -> String {
+    let mut res = String::new();
+    match program {
+        BlockExp(exps, funs, _) => Formatter::default().statements(&mut res, exps, funs, 0, None),
+        exp => res = exp.to_source()
+    }
+    format!("{}\n", res.trim_start_matches('\n'))
+}
+
+impl Exp {
+    ///The expression as source that parses back to the same tree, like program_source. A block is written with its braces
+    pub fn to_source(&self) -> String {
+        Formatter::default().exp(self, BINARY_OP_PRECEDENCE.len(), 0)
+    }
+}
+
+impl Function {
+    ///The declaration of the function, 'fun name(params): type = body'. A method is written as it is in its impl
+    pub fn to_source(&self) -> String {
+        Formatter::default().function(self, 0)
+    }
+}
+
+#[derive(Default)]
+struct Formatter<'a> {
+    lines: Vec<&'a str>,
+    tokens: &'a [(LexToken, Location)],
+
+    ///The comments not written yet, in order
+    comments: VecDeque<(String, Location)>,
+
+    ///Operators the program declares, as calls of their functions are written with them
+    operators: Vec<CustomOperator>,
+}
+
+impl Formatter<'_> {
+    ///Writes the statements on lines of their own, with the comments before the end, or all that are left
+    fn statements(&mut self, res: &mut String, exps: &[Exp], funs: &[(Symbol, Box<Function>)], indent: usize, end: Option<Location>) {
+        let pad = "    ".repeat(indent);
+        //Methods are written in their impl
+        let methods: Vec<String> = exps.iter().flat_map(|exp| match exp {
+            ImplExp(_, name, methods, _) => methods.iter().map(|method| format!("{name}.{method}")).collect(),
+            _ => Vec::new()
+        }).collect();
+        let shown: Vec<&Exp> = exps.iter().filter(|exp| !matches!(exp, FunDeclExp(id, _) if methods.iter().any(|method| method == id.as_str()))).collect();
+        for (i, exp) in shown.iter().enumerate() {
+            let start = start(exp);
+            self.comments_before(res, Some(start), &pad);
+            self.blank_line(res, start.line);
+
+            let (text, block) = match exp {
+                ImplExp(interface, name, methods, loc) => {
+                    let ids: Vec<String> = methods.iter().map(|method| format!("{name}.{method}")).collect();
+                    let methods: Vec<Exp> = exps.iter()
+                        .filter(|exp| matches!(exp, FunDeclExp(id, _) if ids.iter().any(|method| method == id.as_str())))
+                        .cloned()
+                        .collect();
+                    let mut text = format!("impl {interface} for {name} {{");
+                    let start = self.tokens.partition_point(|(_, token_loc)| token_loc < loc);
+                    let open = self.tokens[start..].iter().position(|(token, _)| *token == LexToken::Paren('{')).map_or(start, |i| start + i);
+                    let end = self.block_end(open);
+                    self.statements(&mut text, &methods, funs, indent + 1, Some(end));
+                    let text = match text.ends_with('{') {
+                        true => format!("{text}}}"),
+                        false => format!("{text}\n{pad}}}")
+                    };
+                    (text, true)
+                },
+                FunDeclExp(id, loc) => match funs.iter().find(|(fun, decl)| fun == id && decl.loc == *loc) {
+                    Some((_, fun)) => (self.function(fun, indent), ends_with_block(&fun.exp)),
+                    None => (format!("fun {id}"), false)
+                },
+                StructDeclExp(..) | InterfaceDeclExp(..) => (self.exp(exp, BINARY_OP_PRECEDENCE.len(), indent), true),
+                exp => (self.exp(exp, BINARY_OP_PRECEDENCE.len(), indent), ends_with_block(exp))
+            };
+            let semi_colon = if i + 1 < shown.len() && !block { ";" } else { "" };
+            *res = format!("{res}\n{pad}{text}{semi_colon}");
+        }
+        self.comments_before(res, end, &pad);
+    }
+
+    ///Writes the comments before the location. One with code before it on its line is put at the end of the last line
+    fn comments_before(&mut self, res: &mut String, before: Option<Location>, pad: &str) {
+        while let Some((text, loc)) = self.comments.front().filter(|(_, loc)| before.is_none_or(|before| *loc < before)).cloned() {
+            self.comments.pop_front();
+            let code_before = self.tokens.iter().take_while(|(_, token_loc)| *token_loc < loc).last()
+                .is_some_and(|(_, token_loc)| token_loc.end_line == loc.line);
+            if code_before && !res.is_empty() {
+                *res = format!("{res} {text}");
+            } else {
+                self.blank_line(res, loc.line);
+                *res = format!("{res}\n{pad}{text}");
+            }
+        }
+    }
+
+    ///Adds a blank line when there is one before the line in the source, unless it would be the first line of a block
+    fn blank_line(&self, res: &mut String, line: u32) {
+        let blank = (line as usize).checked_sub(2).and_then(|i| self.lines.get(i)).is_some_and(|line| line.trim().is_empty());
+        if blank && !res.is_empty() && !res.ends_with('{') && !res.ends_with('\n') {
+            res.push('\n');
+        }
+    }
+
+    ///Where the '}' is that closes the '{' of the token at the index
+    fn block_end(&self, open: usize) -> Location {
+        let mut depth = 0;
+        for (token, token_loc) in &self.tokens[open..] {
+            match token {
+                LexToken::Paren('(' | '[' | '{') => depth += 1,
+                LexToken::Paren(')' | ']' | '}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return *token_loc
+                    }
+                },
+                _ => {}
+            }
+        }
+        self.tokens.last().map_or(Location::point(0, 0), |(_, loc)| *loc)
+    }
+
+    ///The expression, in parentheses when it has a binary operator of the level or looser
+    fn exp(&mut self, exp: &Exp, level: usize, indent: usize) -> String {
+        let any = BINARY_OP_PRECEDENCE.len();
+        match exp {
+            BinOpExp(left, op, right, _) => {
+                let op_level = BINARY_OP_PRECEDENCE.iter().position(|(_, ops)| ops.contains(op)).unwrap_or(0);
+                self.binary(left, &op.to_string(), right, (op_level, BINARY_OP_PRECEDENCE[op_level].0), level, indent)
+            },
+            FunCallExp(fun, args, loc) => match self.custom_operator(*fun, args, *loc) {
+                Some(custom) => self.binary(&args[0], &custom.symbol, &args[1], (custom.level - 1, custom.associativity), level, indent),
+                None => format!("{fun}({})", self.list(args, indent))
+            },
+            UnOpExp(op, exp, _) => format!("{op}{}", self.operand(exp, 0, indent)),
+            LiteralExp(lit, _) => literal(lit),
+            VarExp(id, _) => format!("{id}"),
+            LetExp(id, exp, _) => format!("let {id} = {}", self.exp(exp, any, indent)),
+            DestructExp(pattern_exp, exp, _) => format!("let {} = {}", pattern(pattern_exp), self.exp(exp, any, indent)),
+            IfElseExp(cond, pos, neg, _) => {
+                let res = format!("if ({}) {}", self.exp(cond, any, indent), self.exp(pos, any, indent));
+                match neg {
+                    Some(neg) => format!("{res} else {}", self.exp(neg, any, indent)),
+                    None => res
+                }
+            },
+            WhileExp(cond, body, label, _) => format!("{}while ({}) {}", label_prefix(label), self.exp(cond, any, indent), self.exp(body, any, indent)),
+            ForExp(init, cond, incr, body, label, _) => {
+                let header = self.for_header(init, cond, incr, indent);
+                format!("{}for ({header}) {}", label_prefix(label), self.exp(body, any, indent))
+            },
+            ForInExp(id, collection, body, label, _) => format!("{}for ({id} in {}) {}", label_prefix(label),
+                self.exp(collection, any, indent), self.exp(body, any, indent)),
+            BreakExp(label, _) => format!("break{}", label.as_ref().map_or(String::new(), |label| format!(" {label}"))),
+            ContinueExp(label, _) => format!("continue{}", label.as_ref().map_or(String::new(), |label| format!(" {label}"))),
+            BlockExp(exps, _, _) if is_comprehension(exps) => self.comprehension(exps, indent),
+            BlockExp(exps, funs, loc) => {
+                let mut res = String::from("{");
+                //A block is at its first statement, or its '}' when it is empty, so the '{' is the token before
+                let open = self.tokens.partition_point(|(_, token_loc)| token_loc < loc).saturating_sub(1);
+                let end = self.block_end(open);
+                self.statements(&mut res, exps, funs, indent + 1, Some(end));
+                match res.as_str() {
+                    "{" => String::from("{}"),
+                    _ => format!("{res}\n{}}}", "    ".repeat(indent))
+                }
+            },
+            OptionExp(Some(exp), _) => format!("some({})", self.exp(exp, any, indent)),
+            OptionExp(None, _) => String::from("none"),
+            TupleExp(exps, _) if exps.len() == 1 => format!("({},)", self.exp(&exps[0], any, indent)),
+            TupleExp(exps, _) => format!("({})", self.list(exps, indent)),
+            ArrayExp(exps, _) => format!("[{}]", self.list(exps, indent)),
+            SpreadExp(exp, _) => format!("...{}", self.exp(exp, any, indent)),
+            StructExp(name, fields, _) if fields.is_empty() => format!("{name} {{}}"),
+            StructExp(name, fields, _) => {
+                let mut values = Vec::new();
+                for (field, exp) in fields {
+                    values.push(format!("{field}: {}", self.exp(exp, any, indent)));
+                }
+                format!("{name} {{ {} }}", values.join(", "))
+            },
+            FieldExp(exp, field, _) => format!("{}.{field}", self.receiver(exp, indent)),
+            MethodCallExp(exp, method, args, _) => format!("{}.{method}({})", self.receiver(exp, indent), self.list(args, indent)),
+            IndexExp(exp, index, _) => format!("{}[{}]", self.receiver(exp, indent), self.exp(index, any, indent)),
+            StructDeclExp(name, fields, _) => {
+                let pad = "    ".repeat(indent + 1);
+                let fields: Vec<String> = fields.iter().map(|(field, typ)| format!("\n{pad}{field}: {typ}")).collect();
+                match fields.is_empty() {
+                    true => format!("struct {name} {{}}"),
+                    false => format!("struct {name} {{{}\n{}}}", fields.join(","), "    ".repeat(indent))
+                }
+            },
+            InterfaceDeclExp(name, methods, loc) => {
+                let pad = "    ".repeat(indent + 1);
+                let names = self.interface_params(*loc);
+                let methods: Vec<String> = methods.iter().enumerate().map(|(i, (method, p_types, ret_type))| {
+                    let params: Vec<String> = p_types.iter().enumerate().map(|(j, typ)| {
+                        let param = names.get(i).and_then(|names| names.get(j)).map_or("_", String::as_str);
+                        format!(", {param}: {typ}")
+                    }).collect();
+                    format!("\n{pad}fun {method}(self{}): {ret_type}", params.concat())
+                }).collect();
+                match methods.is_empty() {
+                    true => format!("interface {name} {{}}"),
+                    false => format!("interface {name} {{{}\n{}}}", methods.join(";"), "    ".repeat(indent))
+                }
+            },
+            OperatorDeclExp(custom, _) => format!("operator {} {} {} = {}", custom.symbol, custom.associativity, custom.level, custom.fun),
+            //Declarations are written by statements, with what they declare
+            FunDeclExp(id, _) => format!("fun {id}"),
+            ImplExp(interface, name, _, _) => format!("impl {interface} for {name} {{}}"),
+        }
+    }
+
+    ///The operator with its operands, where the precedence is its level in BINARY_OP_PRECEDENCE and its associativity
+    fn binary(&mut self, left: &Exp, op: &str, right: &Exp, (op_level, associativity): (usize, Associativity), level: usize, indent: usize) -> String {
+        let (left_level, right_level) = match associativity {
+            Associativity::Left => (op_level + 1, op_level),
+            Associativity::Right => (op_level, op_level + 1)
+        };
+        let res = format!("{} {op} {}", self.operand(left, left_level, indent), self.operand(right, right_level, indent));
+        match op_level >= level {
+            true => format!("({res})"),
+            false => res
+        }
+    }
+
+    ///An operand of an operator. An if is put in parentheses, as its last branch would take the rest of the expression
+    fn operand(&mut self, exp: &Exp, level: usize, indent: usize) -> String {
+        match exp {
+            IfElseExp(..) => format!("({})", self.exp(exp, BINARY_OP_PRECEDENCE.len(), indent)),
+            exp => self.exp(exp, level, indent)
+        }
+    }
+
+    ///What a field, method or index is taken from, which must be a term
+    fn receiver(&mut self, exp: &Exp, indent: usize) -> String {
+        match exp {
+            UnOpExp(..) => format!("({})", self.exp(exp, BINARY_OP_PRECEDENCE.len(), indent)),
+            exp => self.operand(exp, 0, indent)
+        }
+    }
+
+    fn list(&mut self, exps: &[Exp], indent: usize) -> String {
+        let mut res = Vec::new();
+        for exp in exps {
+            res.push(self.exp(exp, BINARY_OP_PRECEDENCE.len(), indent));
+        }
+        res.join(", ")
+    }
+
+    fn function(&mut self, fun: &Function, indent: usize) -> String {
+        //A method is named 'Struct.method', and its first parameter is 'self'
+        let (name, method) = match fun.name.as_str().split_once('.') {
+            Some((_, method)) => (method.to_string(), true),
+            None => (fun.name.to_string(), false)
+        };
+        let params: Vec<String> = fun.params.iter().zip(&fun.param_types).enumerate().map(|(i, (param, typ))| match method && i == 0 {
+            true => format!("{param}"),
+            false => format!("{param}: {typ}")
+        }).collect();
+        let ret_type = match fun.ret_type {
+            Type::Any => String::new(),
+            ref typ => format!(": {typ}")
+        };
+        format!("fun {name}({}){ret_type} = {}", params.join(", "), self.exp(&fun.exp, BINARY_OP_PRECEDENCE.len(), indent))
+    }
+
+    ///What is between the parentheses of a for loop, from the variable, condition and increment it is made of
+    fn for_header(&mut self, init: &Exp, cond: &Exp, incr: &Exp, indent: usize) -> String {
+        let any = BINARY_OP_PRECEDENCE.len();
+        match (init, cond, incr) {
+            //'for (n)', which runs the body n times
+            (LetExp(id, _, _), BinOpExp(_, _, times, _), _) if id.as_str() == ".for" => self.exp(times, any, indent),
+            (LetExp(id, from, _), BinOpExp(_, _, to, _), BinOpExp(_, _, step, _)) => {
+                let step = match step.as_ref() {
+                    LiteralExp(Literal::Int(1), _) => String::new(),
+                    step => format!(", {}", self.exp(step, any, indent))
+                };
+                format!("{id}, {}, {}{step}", self.exp(from, any, indent), self.exp(to, any, indent))
+            },
+            (init, cond, incr) => format!("{init}; {cond}; {incr}")
+        }
+    }
+
+    ///'[exp for id in collection if cond]', from the block it is parsed to
+    fn comprehension(&mut self, exps: &[Exp], indent: usize) -> String {
+        let any = BINARY_OP_PRECEDENCE.len();
+        let (id, collection, body) = match &exps[1] {
+            ForInExp(id, collection, body, _, _) => (id, collection, body),
+            exp => return self.exp(exp, any, indent)
+        };
+        let (push, cond) = match body.as_ref() {
+            IfElseExp(cond, push, None, _) => (push.as_ref(), Some(cond)),
+            push => (push, None)
+        };
+        let element = match push {
+            FunCallExp(_, args, _) if args.len() == 2 => self.operand(&args[1], any, indent),
+            exp => self.exp(exp, any, indent)
+        };
+        let cond = match cond {
+            Some(cond) => format!(" if {}", self.operand(cond, any, indent)),
+            None => String::new()
+        };
+        format!("[{element} for {id} in {}{cond}]", self.operand(collection, any, indent))
+    }
+
+    ///The declared operator a call is written with, when it is at an operator in the source rather than the name of the function
+    fn custom_operator(&self, fun: Symbol, args: &[Exp], loc: Location) -> Option<CustomOperator> {
+        if args.len() != 2 {
+            return None
+        }
+        let line = self.lines.get((loc.line as usize).checked_sub(1)?)?;
+        let rest: String = line.chars().skip(loc.col.saturating_sub(1)).collect();
+        self.operators.iter().rev().find(|custom| custom.fun == fun && rest.starts_with(&custom.symbol)).cloned()
+    }
+
+    ///The names of the parameters after 'self' of each method of the interface at the location, which are only in the source
+    fn interface_params(&self, loc: Location) -> Vec<Vec<String>> {
+        let start = self.tokens.partition_point(|(_, token_loc)| *token_loc < loc);
+        let mut tokens = self.tokens[start..].iter().peekable();
+        let mut methods: Vec<Vec<String>> = Vec::new();
+        let mut depth = 0;
+        while let Some((token, _)) = tokens.next() {
+            match token {
+                LexToken::Keyword("fun") if depth == 1 => methods.push(Vec::new()),
+                LexToken::Paren('(' | '[' | '{') => depth += 1,
+                LexToken::Paren(')' | ']' | '}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break
+                    }
+                },
+                LexToken::Id(name) if depth == 2 && matches!(tokens.peek(), Some((LexToken::Colon, _))) => {
+                    if let Some(params) = methods.last_mut() {
+                        params.push(name.clone());
+                    }
+                },
+                _ => {}
+            }
+        }
+        methods
+    }
+}
+
+///Where the expression starts in the source, which is before its location when that is the operator or the '.' after a term
+fn start(exp: &Exp) -> Location {
+    exp.children().first().map_or(exp.loc(), |child| start(child).min(exp.loc()))
+}
+
+///Whether the statement ends with a block, so it needs no ';' after it
+fn ends_with_block(exp: &Exp) -> bool {
+    match exp {
+        BlockExp(exps, _, _) => !is_comprehension(exps),
+        IfElseExp(_, pos, neg, _) => ends_with_block(neg.as_ref().unwrap_or(pos)),
+        WhileExp(_, body, _, _) | ForExp(_, _, _, body, _, _) | ForInExp(_, _, body, _, _) => ends_with_block(body),
+        _ => false
+    }
+}
+
+///Whether the statements of a block are those an array comprehension is parsed to
+fn is_comprehension(exps: &[Exp]) -> bool {
+    matches!(exps, [LetExp(id, _, _), ForInExp(..), VarExp(_, _)] if id.as_str() == ".comprehension")
+}
+
+fn label_prefix(label: &Option<String>) -> String {
+    label.as_ref().map_or(String::new(), |label| format!("{label}: "))
+}
+
+///The literal as it is written in the source
+fn literal(lit: &Literal) -> String {
+    match lit {
+        Literal::Float(f) => format!("{f:?}"),
+        Literal::Str(s) => format!("\"{}\"", s.replace('"', "\\\"")),
+        Literal::Unit => String::from("()"),
+        lit => lit.to_string()
+    }
+}
+
+///The pattern as it is written in the source, with 'field' for 'field: field'
+fn pattern(pattern_exp: &Pattern) -> String {
+    match pattern_exp {
+        Pattern::Id(id) => format!("{id}"),
+        Pattern::Tuple(patterns) => format!("({})", patterns.iter().map(pattern).collect::<Vec<String>>().join(", ")),
+        Pattern::Struct(name, fields) => {
+            let fields: Vec<String> = fields.iter().map(|(field, field_pattern)| match field_pattern {
+                Pattern::Id(id) if id.as_str() == field => field.clone(),
+                field_pattern => format!("{field}: {}", pattern(field_pattern))
+            }).collect();
+            match fields.is_empty() {
+                true => format!("{name} {{}}"),
+                false => format!("{name} {{ {} }}", fields.join(", "))
+            }
+        }
+    }
+}
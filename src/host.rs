@@ -0,0 +1,99 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc};
+
+use super::*;
+use eval::{EvalResult, Signal};
+use interner::Symbol;
+
+///Gives the return value for the arguments, or an error message
+pub type HostFn = dyn Fn(&[Literal]) -> Result<Literal, String>;
+
+///A function of the host program that programs can call like a builtin, with the types it was declared with
+#[derive(Clone)]
+pub struct HostFunction {
+    pub params: Vec<Type>,
+    pub ret_type: Type,
+    pub function: Rc<HostFn>,
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HostFunction({:?}, {:?})", self.params, self.ret_type)
+    }
+}
+
+impl HostFunction {
+    ///Calls the function on arguments that passed the type check. What it returns is checked against its return type
+    pub fn call(&self, id: &Symbol, args: &[Literal], loc: Location) -> EvalResult {
+        let value = (self.function)(args).map_err(|msg| Signal::Error(msg, loc))?;
+        STRUCTS.with(|structs| check_value(&value, &structs.borrow()))
+            .map_err(|msg| Signal::Error(format!("'{id}' returned an invalid value: {msg}"), loc))?;
+        let typ = value.get_type();
+        if !typ.matches(&self.ret_type) {
+            return Err(Signal::Error(format!("'{id}' returned '{typ}', but is declared to return '{}'", self.ret_type), loc))
+        }
+        Ok(value)
+    }
+}
+
+///Checks that a value made by the host could have been made by a program, so the type of an array or set is the type of each element,
+///and a struct has the fields of its declaration in the same order, with their types
+pub fn check_value(value: &Literal, structs: &Structs) -> Result<(), String> {
+    match value {
+        Literal::Array(lits) | Literal::Set(lits) => {
+            if let Some(first) = lits.first() {
+                let typ = first.get_type();
+                if let Some(other) = lits.iter().map(Literal::get_type).find(|other| !other.matches(&typ)) {
+                    return Err(format!("Elements of an array or set must have the same type, got '{typ}' and '{other}'"))
+                }
+            }
+            lits.iter().try_for_each(|lit| check_value(lit, structs))
+        },
+        Literal::Tuple(lits) => lits.iter().try_for_each(|lit| check_value(lit, structs)),
+        Literal::Optional(Some(lit)) => check_value(lit, structs),
+        Literal::Struct(value) => {
+            let name = &value.name;
+            let fields = structs.get(name).ok_or_else(|| format!("Struct '{name}' does not exist"))?;
+            if value.fields.len() != fields.len() || value.fields.iter().zip(fields).any(|((id, _), (field, _))| id != field) {
+                let names: Vec<&str> = fields.iter().map(|(field, _)| field.as_str()).collect();
+                return Err(format!("'{name}' must have the fields {}, in that order", names.join(", ")))
+            }
+            for ((id, lit), (_, typ)) in value.fields.iter().zip(fields) {
+                let actual = lit.get_type();
+                if !actual.matches(typ) {
+                    return Err(format!("Field '{id}' of '{name}' must be '{typ}', got '{actual}'"))
+                }
+                check_value(lit, structs)?;
+            }
+            Ok(())
+        },
+        _ => Ok(())
+    }
+}
+
+pub type HostFunctions = HashMap<Symbol, HostFunction>;
+
+///The fields of each struct with their types, by the name of the struct
+pub type Structs = HashMap<String, Vec<(String, Type)>>;
+
+thread_local! {
+    ///The host functions that programs on this thread can call
+    static HOST_FUNCTIONS: RefCell<HostFunctions> = RefCell::new(HashMap::new());
+
+    ///The structs of the program running on this thread, to check the values host functions give
+    static STRUCTS: RefCell<Structs> = RefCell::new(HashMap::new());
+}
+
+///Makes these the structs that values from host functions are checked against, and gives the ones before
+pub fn set_structs(structs: Structs) -> Structs {
+    STRUCTS.with(|current| current.replace(structs))
+}
+
+///Makes these the host functions programs on this thread can call, and gives the ones before
+pub fn set_host_functions(functions: HostFunctions) -> HostFunctions {
+    HOST_FUNCTIONS.with(|host| host.replace(functions))
+}
+
+///The host function with the name. Functions and function values with the same name take precedence, while it takes precedence over builtins
+pub fn lookup_host_function(id: &Symbol) -> Option<HostFunction> {
+    HOST_FUNCTIONS.with(|host| host.borrow().get(id).cloned())
+}
@@ -0,0 +1,203 @@
+use super::*;
+use parser::{join_top_level, parse_top_level, TopLevel};
+
+///A parsed program that keeps where each top-level statement is in the source, so an edit only lexes and parses again
+///the statements it touches, for an editor that parses on every keystroke
+#[derive(Debug, Clone)]
+pub struct Document {
+    source: String,
+
+    ///The top-level statements in order with the location of their first token. Each goes on to where the next starts
+    statements: Vec<(Location, TopLevel)>,
+
+    ///Lexing stops at an error, so then there are no statements, and an edit parses everything again
+    lex_error: Option<(String, Location)>,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Self {
+        let mut document = Self { source: source.to_string(), statements: Vec::new(), lex_error: None };
+        document.parse_all();
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    ///The program as parse gives it for the source, or its errors. A lexer error is given on its own
+    pub fn program(&self) -> Result<Exp, Vec<(String, Location)>> {
+        if let Some(err) = &self.lex_error {
+            return Err(vec![err.clone()])
+        }
+        let loc = match self.statements.first() {
+            Some((loc, _)) => *loc,
+            None => return Err(vec![(String::from("Unexpected end of input"), Location::point(1, 1))])
+        };
+        join_top_level(loc, self.statements.iter().map(|(_, statement)| statement.clone()))
+    }
+
+    pub fn lex_error(&self) -> Option<&(String, Location)> {
+        self.lex_error.as_ref()
+    }
+
+    ///Replaces the text from the start of the range to its end with the text. Only the statements the range touches are parsed again,
+    ///with the one before and the one after them, and more after them when a statement now goes on past them
+    pub fn edit(&mut self, range: Location, text: &str) {
+        let from = offset(&self.source, range.line, range.col);
+        let to = offset(&self.source, range.end_line, range.end_col).max(from);
+        self.source.replace_range(from..to, text);
+        if self.lex_error.is_some() || self.statements.is_empty() {
+            return self.parse_all()
+        }
+
+        //Where the end of the range is now
+        let end = match text.rfind('\n') {
+            Some(i) => (range.line + text.matches('\n').count() as u32, text[i + 1..].chars().count() + 1),
+            None => (range.line, range.col + text.chars().count())
+        };
+        let moved = |(line, col): (u32, usize)| {
+            if (line, col) < (range.end_line, range.end_col) {
+                (line, col)
+            } else if line == range.end_line {
+                (end.0, col + end.1 - range.end_col)
+            } else {
+                (line + end.0 - range.end_line, col)
+            }
+        };
+
+        let len = self.statements.len();
+        let touched = |i: usize| {
+            let start = if i == 0 { (1, 1) } else { position(self.statements[i].0) };
+            let next = self.statements.get(i + 1).map_or((u32::MAX, usize::MAX), |(loc, _)| position(*loc));
+            start <= (range.end_line, range.end_col) && (range.line, range.col) <= next
+        };
+        let first_touched = (0..len).find(|i| touched(*i)).unwrap_or(len - 1);
+        let last_touched = (first_touched..len).take_while(|i| touched(*i)).last().unwrap_or(first_touched);
+
+        //The token before a statement decides where it ends, so the one before is parsed too
+        let first = first_touched.saturating_sub(1);
+        for (loc, statement) in &mut self.statements[last_touched + 1..] {
+            *loc = shift(*loc, &moved);
+            shift_statement(statement, &moved);
+        }
+
+        //The last statement parsed is one the edit did not touch. When it is parsed the same, the statements after it are too,
+        //unless operators are declared or no longer declared, as they change how the statements after them are parsed
+        let mut stop = (last_touched + 2).min(len);
+        if !operators(&self.statements[first..stop]).is_empty() {
+            stop = len;
+        }
+        let before = operators(&self.statements[..first]);
+        loop {
+            let start = if first == 0 { Location::point(1, 1) } else { self.statements[first].0 };
+            let from = offset(&self.source, start.line, start.col);
+            let to = match self.statements.get(stop) {
+                Some((loc, _)) => offset(&self.source, loc.line, loc.col),
+                None => self.source.len()
+            };
+            match parse_part(&self.source[from..to], start, &before) {
+                Ok(statements) if stop == len || statements.last() == self.statements.get(stop - 1) && operators(&statements).is_empty() => {
+                    self.statements.splice(first..stop, statements);
+                    return
+                },
+                Ok(statements) if !operators(&statements).is_empty() => stop = len,
+                Err(err) if stop == len => {
+                    self.statements.clear();
+                    self.lex_error = Some(err);
+                    return
+                },
+                //A statement goes on past the part, so a part twice as long is tried
+                _ => stop = (2 * stop - first).min(len)
+            }
+        }
+    }
+
+    fn parse_all(&mut self) {
+        match parse_part(&self.source, Location::point(1, 1), &[]) {
+            Ok(statements) => {
+                self.statements = statements;
+                self.lex_error = None;
+            },
+            Err(err) => {
+                self.statements.clear();
+                self.lex_error = Some(err);
+            }
+        }
+    }
+}
+
+///The top-level statements in a part of the source that starts at the location, with the location of the first token of each
+fn parse_part(text: &str, start: Location, operators: &[CustomOperator]) -> Result<Vec<(Location, TopLevel)>, (String, Location)> {
+    let lexed = lex_at(text.as_bytes(), start)?;
+    let statements = parse_top_level(&mut lexed.iter(), operators);
+    let mut tokens = lexed.tokens().iter();
+    Ok(statements.into_iter().map(|statement| {
+        let loc = tokens.clone().next().map_or(start, |(_, loc)| *loc);
+        tokens.nth(statement.tokens.saturating_sub(1));
+        (loc, statement)
+    }).collect())
+}
+
+///The operators the statements declare
+fn operators(statements: &[(Location, TopLevel)]) -> Vec<CustomOperator> {
+    statements.iter()
+        .flat_map(|(_, statement)| &statement.exps)
+        .filter_map(|exp| match exp {
+            Exp::OperatorDeclExp(custom, _) => Some(custom.clone()),
+            _ => None
+        })
+        .collect()
+}
+
+///Moves every location in the statement, as when lines are added before it
+fn shift_statement(statement: &mut TopLevel, moved: &impl Fn((u32, usize)) -> (u32, usize)) {
+    for exp in &mut statement.exps {
+        shift_exp(exp, moved);
+    }
+    for (_, fun) in &mut statement.funs {
+        fun.loc = shift(fun.loc, moved);
+        shift_exp(&mut fun.exp, moved);
+    }
+    for (_, loc) in &mut statement.errors {
+        *loc = shift(*loc, moved);
+    }
+}
+
+fn shift_exp(exp: &mut Exp, moved: &impl Fn((u32, usize)) -> (u32, usize)) {
+    *exp.loc_mut() = shift(exp.loc(), moved);
+    //The functions of a block are not expressions, but their bodies are children of it
+    if let Exp::BlockExp(_, funs, _) = exp {
+        for (_, fun) in funs {
+            fun.loc = shift(fun.loc, moved);
+        }
+    }
+    for child in exp.children_mut() {
+        shift_exp(child, moved);
+    }
+}
+
+fn shift(loc: Location, moved: &impl Fn((u32, usize)) -> (u32, usize)) -> Location {
+    let (line, col) = moved((loc.line, loc.col));
+    let (end_line, end_col) = moved((loc.end_line, loc.end_col));
+    Location { line, col, end_line, end_col }
+}
+
+fn position(loc: Location) -> (u32, usize) {
+    (loc.line, loc.col)
+}
+
+///Where the line and column are in the source, in bytes. A column past the end of its line is the end of the line,
+///and a line past the end of the source is the end of the source
+fn offset(source: &str, line: u32, col: usize) -> usize {
+    let mut start = 0;
+    for _ in 1..line {
+        match source[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return source.len()
+        }
+    }
+    let rest = &source[start..];
+    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+    start + line.char_indices().nth(col.saturating_sub(1)).map_or(line.len(), |(i, _)| i)
+}
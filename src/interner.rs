@@ -0,0 +1,131 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}, fmt::{Debug, Display}, rc::Rc, sync::{Mutex, OnceLock}};
+
+use lazy_static::lazy_static;
+
+///Keeps a single shared copy of each string it is given
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Rc<String>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///The shared copy of the string. It is only allocated the first time
+    pub fn intern(&mut self, s: &String) -> Rc<String> {
+        match self.strings.get(s) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned = Rc::new(s.clone());
+                self.strings.insert(interned.clone());
+                interned
+            }
+        }
+    }
+
+    ///Lets go of the strings nothing else holds anymore
+    pub fn release_unused(&mut self) {
+        self.strings.retain(|s| Rc::strong_count(s) > 1);
+    }
+
+    ///How many different strings it holds
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+thread_local! {
+    ///Holds string literals and the names of variables, functions and types
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+pub fn intern(s: &String) -> Rc<String> {
+    INTERNER.with(|interner| interner.borrow_mut().intern(s))
+}
+
+///Lets go of the strings of programs that are gone, so the interner of a thread that parses again and again does not keep growing
+pub fn release_unused() {
+    INTERNER.with(|interner| interner.borrow_mut().release_unused())
+}
+
+///Gives access to the interner of this thread
+#[allow(dead_code)]
+pub fn with_interner<R>(f: impl FnOnce(&mut Interner) -> R) -> R {
+    INTERNER.with(|interner| f(&mut interner.borrow_mut()))
+}
+
+///An interned name of a variable or function. Symbols with the same name are equal, so comparing them is an integer comparison
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+///How many names the first chunk of the table holds. Each chunk after it holds twice as many as the one before
+const FIRST_CHUNK: usize = 1_024;
+
+///Enough chunks for every symbol a u32 can number
+const CHUNKS: usize = 22;
+
+///Names of symbols, each set once
+type Chunk = Box<[OnceLock<Box<str>>]>;
+
+///The names of the symbols, by index. A chunk is never moved once it is made, so a name can be read without locking
+static NAMES: [OnceLock<Chunk>; CHUNKS] = [const { OnceLock::new() }; CHUNKS];
+
+lazy_static! {
+    ///The symbols by name. Only making a symbol or finding one by name locks it
+    static ref SYMBOLS: Mutex<HashMap<&'static str, Symbol>> = Mutex::new(HashMap::new());
+}
+
+///The chunk of the table the name of the symbol with the index is in, and where in it
+fn slot(index: usize) -> (usize, usize) {
+    let chunk = (index / FIRST_CHUNK + 1).ilog2() as usize;
+    (chunk, index - FIRST_CHUNK * ((1 << chunk) - 1))
+}
+
+impl Symbol {
+    ///The symbol of the name. The same name always gives the same symbol
+    pub fn new(name: &str) -> Self {
+        let mut symbols = SYMBOLS.lock().unwrap();
+        if let Some(symbol) = symbols.get(name) {
+            return *symbol
+        }
+        let index = symbols.len();
+        let (chunk, offset) = slot(index);
+        let names = NAMES[chunk].get_or_init(|| (0..FIRST_CHUNK << chunk).map(|_| OnceLock::new()).collect());
+        let name: &'static str = names[offset].get_or_init(|| name.into());
+        let symbol = Symbol(index as u32);
+        symbols.insert(name, symbol);
+        symbol
+    }
+
+    ///The symbol of the name if there is one, without making it
+    pub fn find(name: &str) -> Option<Self> {
+        SYMBOLS.lock().unwrap().get(name).copied()
+    }
+
+    ///Symbols are numbered from 0 in the order they were made, so they can index a table
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        let (chunk, offset) = slot(self.index());
+        NAMES[chunk].get().and_then(|names| names[offset].get()).expect("The name of a symbol is set before it is made")
+    }
+}
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{fmt::Display, rc::Rc};
+
+use super::*;
+use interner::Symbol;
+use host::{HostFunction, HostFunctions, Structs};
+use diagnostics::Diagnostic;
+use Exp::*;
+
+///A runtime value, as given to and by the interpreter
+pub type Value = Literal;
+
+///Why a piece of source could not be run
+#[derive(Debug, Clone)]
+pub enum Error {
+    Lex(String, Location),
+    Parse(String, Location),
+    Type(String, Location),
+
+    ///The program was type checked, but stopped with an error or exited
+    Runtime(Signal),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Lex(msg, loc) => write!(f, "Lexer Error: {msg}. At {loc}"),
+            Error::Parse(msg, loc) => write!(f, "Parse Error: {msg}. At {loc}"),
+            Error::Type(msg, loc) => write!(f, "Type Error: {msg}. At {loc}"),
+            Error::Runtime(signal) => write!(f, "Runtime Error: {signal}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    ///The error as a diagnostic, to show with the source it is in
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            Error::Lex(msg, loc) => Diagnostic::new("Lexer Error", msg.clone(), *loc),
+            Error::Parse(msg, loc) => Diagnostic::new("Parse Error", msg.clone(), *loc),
+            Error::Type(msg, loc) => Diagnostic::new("Type Error", msg.clone(), *loc),
+            Error::Runtime(signal) => Diagnostic::from_signal(signal),
+        }
+    }
+}
+
+///A top-level variable with its type and value
+type Variable = (Symbol, Type, Literal);
+
+///Functions declared in a block, as the parser gives them
+type Functions = Vec<(Symbol, Box<Function>)>;
+
+///Runs programs from a host program, one piece of source at a time. The functions, types and top-level variables
+///of a piece can be used by the pieces after it, like in a REPL. Declaring one of them again replaces it
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    options: ExecutionOptions,
+
+    ///Top-level declarations of functions, structs, interfaces and implementations, and the functions they declare
+    declarations: Vec<Exp>,
+    funs: Vec<(Symbol, Box<Function>)>,
+
+    ///Top-level variables with their types, in the order they were declared. The type is kept, as a value like 'none' does not give all of it
+    variables: Vec<Variable>,
+
+    ///Functions of the host that the source can call
+    host_functions: HostFunctions,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///An interpreter that runs everything with the options
+    pub fn with_options(options: ExecutionOptions) -> Self {
+        Self { options, ..Self::default() }
+    }
+
+    ///Lexes, parses, type checks and runs the source, and gives the value of its last statement. Operators declared by earlier pieces can be used.
+    ///When the source has several errors, the first is given
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let lexed = lex(source).map_err(|(msg, loc)| Error::Lex(msg, loc))?;
+        let operators: Vec<CustomOperator> = self.declarations.iter().filter_map(|exp| match exp {
+            OperatorDeclExp(custom, _) => Some(custom.clone()),
+            _ => None
+        }).collect();
+        let (exps, funs, loc) = match parse_with_operators(&mut lexed.iter(), &operators).map_err(|mut errors| {
+            let (msg, loc) = errors.swap_remove(0);
+            Error::Parse(msg, loc)
+        })? {
+            BlockExp(exps, funs, loc) => (exps, funs, loc),
+            exp => {
+                let loc = exp.loc();
+                (vec![exp], Vec::new(), loc)
+            }
+        };
+        self.run(exps, funs, loc)
+    }
+
+    ///Type checks and runs an expression from parse_expression, with the functions, types and top-level variables so far.
+    ///A parsed expression can be run again and again, like a formula when its inputs change
+    pub fn eval_expression(&mut self, exp: &Exp) -> Result<Value, Error> {
+        self.run(vec![exp.clone()], Vec::new(), exp.loc())
+    }
+
+    ///Calls the top-level function with the arguments, which are type checked like in a call from the source
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, Error> {
+        //A call from the host is not anywhere in the source
+        let loc = Location::point(0, 0);
+        let structs = structs(&self.declarations);
+        for arg in args {
+            host::check_value(arg, &structs).map_err(|msg| Error::Type(format!("Invalid argument for '{name}': {msg}"), loc))?;
+        }
+        //A name that was never made into a symbol cannot be a function, and making it one would keep it forever
+        let id = Symbol::find(name).or_else(|| lookup_builtin(name).map(|builtin| Symbol::new(builtin.name)))
+            .ok_or_else(|| Error::Type(format!("Function '{name}' does not exist here"), loc))?;
+        let args = args.iter().map(|arg| LiteralExp(arg.clone(), loc)).collect();
+        self.run(vec![FunCallExp(id, args, loc)], Vec::new(), loc)
+    }
+
+    ///Lets the source call the Rust function by the name, like a builtin. Its arguments are type checked against the parameter types,
+    ///and what it returns against the return type. An error it gives is a runtime error at the call
+    pub fn register(&mut self, name: &str, params: &[Type], ret_type: Type, function: impl Fn(&[Value]) -> Result<Value, String> + 'static) {
+        let host = HostFunction { params: params.to_vec(), ret_type, function: Rc::new(function) };
+        self.host_functions.insert(Symbol::new(name), host);
+    }
+
+    ///Type checks and runs the statements after the state so far, and keeps the state they leave
+    fn run(&mut self, exps: Vec<Exp>, funs: Vec<(Symbol, Box<Function>)>, loc: Location) -> Result<Value, Error> {
+        let previous = host::set_host_functions(self.host_functions.clone());
+        let res = self.run_with_host_functions(exps, funs, loc);
+        host::set_host_functions(previous);
+        res
+    }
+
+    fn run_with_host_functions(&mut self, exps: Vec<Exp>, funs: Vec<(Symbol, Box<Function>)>, loc: Location) -> Result<Value, Error> {
+        let (variables, mut exps, mut funs) = self.with_state(exps, funs);
+        let mut types = Environment::new();
+        types.enter_scope();
+        for (id, typ, _) in &variables {
+            types.push_variable(id, typ.clone());
+        }
+        type_checker::check_block(&mut exps, &mut funs, loc, &mut types).map_err(|(msg, loc)| Error::Type(msg, loc))?;
+        let types = types.variables();
+
+        let previous = host::set_structs(structs(&exps));
+        let program = BlockExp(exps, funs, loc);
+        let values = variables.into_iter().map(|(id, _, value)| (id, value)).collect();
+        let (res, mut variables) = eval::run_with_variables(&program, values, &self.options);
+        host::set_structs(previous);
+        variables.reverse();
+        self.variables = variables.into_iter().map(|(id, value)| {
+            let typ = types.iter().find(|(other, _)| *other == id).map_or_else(|| value.get_type(), |(_, typ)| typ.clone());
+            (id, typ, value)
+        }).collect();
+        if let BlockExp(exps, funs, _) = program {
+            self.declarations = exps.into_iter().filter(is_declaration).collect();
+            self.funs = funs;
+        }
+        res.map_err(Error::Runtime)
+    }
+
+    ///The top-level variables so far, and the declarations so far followed by the statements. Those the statements declare again are left out
+    fn with_state(&self, exps: Vec<Exp>, funs: Functions) -> (Vec<Variable>, Vec<Exp>, Functions) {
+        let mut variables = Vec::new();
+        let mut types = Vec::new();
+        for exp in &exps {
+            match exp {
+                LetExp(id, _, _) => variables.push(*id),
+                DestructExp(pattern, _, _) => pattern.ids(&mut variables),
+                StructDeclExp(id, _, _) | InterfaceDeclExp(id, _, _) => types.push(id.clone()),
+                _ => ()
+            }
+        }
+        let declared_fun = |id: &Symbol| funs.iter().any(|(fun, _)| fun == id);
+
+        let kept = self.variables.iter().filter(|(id, _, _)| !variables.contains(id)).cloned().collect();
+        let mut all: Vec<Exp> = self.declarations.iter().filter(|exp| match exp {
+            FunDeclExp(id, _) => !declared_fun(id),
+            StructDeclExp(id, _, _) | InterfaceDeclExp(id, _, _) => !types.contains(id),
+            ImplExp(interface, id, _, _) => !types.contains(interface) && !types.contains(id)
+                && !exps.iter().any(|exp| matches!(exp, ImplExp(other_interface, other, _, _) if other_interface == interface && other == id)),
+            OperatorDeclExp(custom, _) => !exps.iter().any(|exp| matches!(exp, OperatorDeclExp(other, _) if other.symbol == custom.symbol)),
+            _ => true
+        }).cloned().collect();
+        all.extend(exps);
+
+        let mut all_funs: Vec<_> = self.funs.iter().filter(|(id, _)| !declared_fun(id)).cloned().collect();
+        all_funs.extend(funs);
+        (kept, all, all_funs)
+    }
+}
+
+///The fields of the structs the statements declare
+fn structs(exps: &[Exp]) -> Structs {
+    exps.iter().filter_map(|exp| match exp {
+        StructDeclExp(id, fields, _) => Some((id.clone(), fields.clone())),
+        _ => None
+    }).collect()
+}
+
+fn is_declaration(exp: &Exp) -> bool {
+    matches!(exp, FunDeclExp(_, _) | StructDeclExp(_, _, _) | InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _) | OperatorDeclExp(_, _))
+}
@@ -0,0 +1,380 @@
+use super::*;
+use interner::Symbol;
+use Literal::*;
+use Exp::*;
+
+///Where a variable is stored
+#[derive(Clone, Copy, Debug)]
+pub enum Var {
+    ///Slot in the frame of the running function
+    Local(usize),
+
+    ///Variables declared at the top level of the program
+    Global(usize)
+}
+
+///A lowered expression. Loops are desugared, variables are resolved to where they are stored and calls to what they call,
+///so running it does not depend on names or scopes
+#[derive(Clone)]
+pub enum Ir {
+    Const(Literal),
+    Load(Var),
+
+    ///Gives unit
+    Store(Var, Box<Ir>),
+    Binary(Operator, Box<Ir>, Box<Ir>, Location),
+    Unary(Operator, Box<Ir>, Location),
+
+    ///The value inside the option on the left, or the right side if it is none
+    Coalesce(Box<Ir>, Box<Ir>),
+
+    ///Condition, if true, else
+    If(Box<Ir>, Box<Ir>, Box<Ir>),
+
+    ///Condition, body, and what runs before the next iteration, also after a continue. Gives unit
+    Loop(Box<Ir>, Box<Ir>, Option<Box<Ir>>, Location),
+
+    ///How many loops out the targeted loop is, 0 being the innermost
+    Break(usize),
+    Continue(usize),
+
+    ///Runs the expressions in order and gives the value of the last one, or unit if there are none
+    Seq(Vec<Ir>),
+
+    ///Function index, arguments
+    Call(usize, Vec<Ir>, Location),
+
+    ///Builtin, arguments, where a changed first argument is stored
+    CallBuiltin(&'static Builtin, Vec<Ir>, Option<Var>, Location),
+    Tuple(Vec<Ir>),
+    Array(Vec<Ir>),
+    Some(Box<Ir>),
+
+    //Array, index
+    Index(Box<Ir>, Box<Ir>, Location),
+}
+
+///A lowered function. Functions returning unit end with an explicit unit
+pub struct IrFunction {
+    pub name: Symbol,
+    pub body: Ir,
+
+    ///Parameters, local variables and temporaries. The parameters are the first slots
+    pub slots: usize,
+}
+
+///A lowered program. The first function is the top level of the program
+pub struct IrProgram {
+    pub functions: Vec<IrFunction>,
+    pub globals: usize,
+}
+
+struct Scope {
+    vars: Vec<(Symbol, Var)>,
+
+    ///Function indexes
+    funs: Vec<(Symbol, usize)>,
+
+    ///Functions of the block that are lowered at their declaration
+    pending: Vec<(Symbol, usize, Box<Function>)>,
+
+    ///Every variable the block declares, also those it has not declared yet
+    declared: Vec<Symbol>,
+}
+
+///The function being lowered
+struct Frame {
+    scopes: Vec<Scope>,
+    slots: usize,
+
+    ///Labels of the loops it is inside
+    loops: Vec<Option<String>>,
+}
+
+struct Lowerer {
+    frames: Vec<Frame>,
+    functions: Vec<Option<IrFunction>>,
+    globals: usize,
+}
+
+///Lowers a type checked program. Gives the name of the first feature the IR does not support, if any
+pub fn lower(program: &Exp) -> Result<IrProgram, String> {
+    let mut lowerer = Lowerer {
+        frames: vec![Frame::new()],
+        functions: vec![None],
+        globals: 0,
+    };
+    let body = lowerer.exp(program)?;
+    let frame = lowerer.frames.pop().unwrap();
+    lowerer.functions[0] = Some(IrFunction { name: Symbol::new("main"), body, slots: frame.slots });
+
+    Ok(IrProgram {
+        functions: lowerer.functions.into_iter().map(|fun| fun.unwrap()).collect(),
+        globals: lowerer.globals,
+    })
+}
+
+impl Frame {
+    fn new() -> Self {
+        Self { scopes: Vec::new(), slots: 0, loops: Vec::new() }
+    }
+}
+
+impl Lowerer {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn exp(&mut self, exp: &Exp) -> Result<Ir, String> {
+        let ir = match exp {
+            LiteralExp(lit, _) => Ir::Const(lit.clone()),
+            VarExp(id, _) => match self.lookup_var(id)? {
+                Some(var) => Ir::Load(var),
+                None if self.lookup_fun(id).is_some() => return Err(String::from("functions used as values")),
+                None => match lookup_constant(id.as_str()) {
+                    Some(lit) => Ir::Const(lit),
+                    None => return Err(format!("the variable '{id}'"))
+                }
+            },
+            BinOpExp(left, op, right, loc) => match op {
+                Operator::Assign => match left.as_ref() {
+                    VarExp(id, _) => {
+                        let value = self.exp(right)?;
+                        Ir::Store(self.assigned_var(id)?, Box::new(value))
+                    },
+                    _ => return Err(String::from("assigning to tuples"))
+                },
+                Operator::PlusAssign | Operator::MinusAssign => match left.as_ref() {
+                    VarExp(id, loc) => {
+                        //The right side is evaluated first, so it is kept in a temporary
+                        let value = self.exp(right)?;
+                        let var = self.assigned_var(id)?;
+                        let temp = self.temporary();
+                        let op = if *op == Operator::PlusAssign { Operator::Plus } else { Operator::Minus };
+                        Ir::Seq(vec![
+                            Ir::Store(temp, Box::new(value)),
+                            Ir::Store(var, Box::new(Ir::Binary(op, Box::new(Ir::Load(var)), Box::new(Ir::Load(temp)), *loc)))
+                        ])
+                    },
+                    _ => unreachable!("Not a variable id")
+                },
+                Operator::Coalesce => Ir::Coalesce(Box::new(self.exp(left)?), Box::new(self.exp(right)?)),
+                op => Ir::Binary(*op, Box::new(self.exp(left)?), Box::new(self.exp(right)?), *loc)
+            },
+            UnOpExp(op, exp, loc) => Ir::Unary(*op, Box::new(self.exp(exp)?), *loc),
+            BlockExp(exps, funs, _) => self.block(exps, funs)?,
+            LetExp(id, exp, _) => {
+                let value = self.exp(exp)?;
+                Ir::Store(self.declare_var(id), Box::new(value))
+            },
+            IfElseExp(cond, pos, neg, _) => {
+                let cond = self.exp(cond)?;
+                let pos = self.exp(pos)?;
+                let neg = match neg {
+                    Some(neg) => self.exp(neg)?,
+                    None => Ir::Const(Unit)
+                };
+                Ir::If(Box::new(cond), Box::new(pos), Box::new(neg))
+            },
+            WhileExp(cond, body, label, loc) => {
+                self.frame().loops.push(label.clone());
+                let cond = self.exp(cond)?;
+                let body = self.exp(body)?;
+                self.frame().loops.pop();
+                Ir::Loop(Box::new(cond), Box::new(body), None, *loc)
+            },
+            //The variable is declared in a scope of its own around the loop
+            ForExp(let_exp, cond, increment, body, label, loc) => {
+                self.enter_scope(std::slice::from_ref(let_exp.as_ref()));
+                let init = self.exp(let_exp)?;
+                self.frame().loops.push(label.clone());
+                let cond = self.exp(cond)?;
+                let body = self.exp(body)?;
+                let increment = self.exp(increment)?;
+                self.frame().loops.pop();
+                self.leave_scope()?;
+                Ir::Seq(vec![init, Ir::Loop(Box::new(cond), Box::new(body), Some(Box::new(increment)), *loc)])
+            },
+            BreakExp(label, _) | ContinueExp(label, _) => {
+                let loops = &self.frame().loops;
+                let target = loops.iter().rposition(|lp| label.is_none() || lp == label).unwrap();
+                let out = loops.len() - 1 - target;
+                if matches!(exp, BreakExp(_, _)) {
+                    Ir::Break(out)
+                } else {
+                    Ir::Continue(out)
+                }
+            },
+            FunCallExp(id, args, loc) => self.call(id, args.iter().collect(), *loc)?,
+            MethodCallExp(receiver, method, args, loc) => {
+                //Without structs, 'x.f(a)' is always 'f(x, a)'
+                self.call(method, [receiver.as_ref()].into_iter().chain(args).collect(), *loc)?
+            },
+            FunDeclExp(id, _) => {
+                let scope = self.frame().scopes.last_mut().unwrap();
+                if let Some(i) = scope.pending.iter().position(|(pending, _, _)| pending == id) {
+                    let (_, index, fun) = scope.pending.remove(i);
+                    self.function(index, &fun)?;
+                }
+                Ir::Const(Unit)
+            },
+            OptionExp(exp, _) => match exp {
+                Some(exp) => Ir::Some(Box::new(self.exp(exp)?)),
+                None => Ir::Const(Optional(None))
+            },
+            TupleExp(exps, _) => Ir::Tuple(self.exps(exps.iter())?),
+            ArrayExp(exps, _) => {
+                if exps.iter().any(|exp| matches!(exp, SpreadExp(_, _))) {
+                    return Err(String::from("spreads"))
+                }
+                Ir::Array(self.exps(exps.iter())?)
+            },
+            IndexExp(exp, index, loc) => Ir::Index(Box::new(self.exp(exp)?), Box::new(self.exp(index)?), *loc),
+            ForInExp(_, _, _, _, _) => return Err(String::from("for-in loops")),
+            SpreadExp(_, _) => return Err(String::from("spreads")),
+            StructDeclExp(_, _, _) | StructExp(_, _, _) | FieldExp(_, _, _) => return Err(String::from("structs")),
+            DestructExp(_, _, _) => return Err(String::from("destructuring")),
+            InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _) => return Err(String::from("interfaces")),
+            //Uses of the operator are calls
+            OperatorDeclExp(_, _) => Ir::Const(Unit),
+        };
+        Ok(ir)
+    }
+
+    fn exps<'a>(&mut self, exps: impl Iterator<Item = &'a Exp>) -> Result<Vec<Ir>, String> {
+        exps.map(|exp| self.exp(exp)).collect()
+    }
+
+    fn block(&mut self, exps: &[Exp], funs: &[(Symbol, Box<Function>)]) -> Result<Ir, String> {
+        self.enter_scope(exps);
+
+        //Functions can be called in the whole block
+        for (id, fun) in funs {
+            let index = self.functions.len();
+            self.functions.push(None);
+            let scope = self.frame().scopes.last_mut().unwrap();
+            scope.funs.push((*id, index));
+            scope.pending.push((*id, index, fun.clone()));
+        }
+
+        let irs = self.exps(exps.iter())?;
+        self.leave_scope()?;
+        Ok(Ir::Seq(irs))
+    }
+
+    fn call(&mut self, id: &Symbol, args: Vec<&Exp>, loc: Location) -> Result<Ir, String> {
+        if args.iter().any(|arg| matches!(arg, SpreadExp(_, _))) {
+            return Err(String::from("spreads"))
+        }
+        //Functions, then function values, then host functions, then builtins
+        if let Some(index) = self.lookup_fun(id) {
+            return Ok(Ir::Call(index, self.exps(args.into_iter())?, loc))
+        }
+        if self.lookup_var(id)?.is_some() {
+            return Err(String::from("calling function values"))
+        }
+        if host::lookup_host_function(id).is_some() {
+            return Err(String::from("host functions"))
+        }
+        match lookup_builtin_symbol(id) {
+            Some(builtin) => {
+                //The type checker ensures that the first argument is a variable
+                let store = match args.first() {
+                    Some(VarExp(var, _)) if builtin.mutates => Some(self.assigned_var(var)?),
+                    _ => None
+                };
+                Ok(Ir::CallBuiltin(builtin, self.exps(args.into_iter())?, store, loc))
+            },
+            None => Err(format!("the function '{id}'"))
+        }
+    }
+
+    ///Lowers a function into its place
+    fn function(&mut self, index: usize, fun: &Function) -> Result<(), String> {
+        self.frames.push(Frame::new());
+        self.enter_scope(&[]);
+        for param in &fun.params {
+            self.declare_var(param);
+        }
+        let mut body = self.exp(&fun.exp)?;
+        if fun.ret_type == Type::Unit {
+            body = Ir::Seq(vec![body, Ir::Const(Unit)]);
+        }
+        self.leave_scope()?;
+        let frame = self.frames.pop().unwrap();
+        self.functions[index] = Some(IrFunction { name: fun.name, body, slots: frame.slots });
+        Ok(())
+    }
+
+    fn enter_scope(&mut self, exps: &[Exp]) {
+        let declared = exps.iter().filter_map(|exp| match exp {
+            LetExp(id, _, _) => Some(*id),
+            _ => None
+        }).collect();
+        self.frame().scopes.push(Scope { vars: Vec::new(), funs: Vec::new(), pending: Vec::new(), declared });
+    }
+
+    ///Functions that were never declared are lowered when their block ends
+    fn leave_scope(&mut self) -> Result<(), String> {
+        let pending = std::mem::take(&mut self.frame().scopes.last_mut().unwrap().pending);
+        for (_, index, fun) in pending {
+            self.function(index, &fun)?;
+        }
+        self.frame().scopes.pop();
+        Ok(())
+    }
+
+    ///Variables declared directly in the top level of the program are globals
+    fn declare_var(&mut self, id: &Symbol) -> Var {
+        let var = if self.frames.len() == 1 && self.frame().scopes.len() == 1 {
+            self.globals += 1;
+            Var::Global(self.globals - 1)
+        } else {
+            self.temporary()
+        };
+        self.frame().scopes.last_mut().unwrap().vars.push((*id, var));
+        var
+    }
+
+    ///A new slot in the frame of the function, which no variable uses
+    fn temporary(&mut self) -> Var {
+        self.frame().slots += 1;
+        Var::Local(self.frame().slots - 1)
+    }
+
+    ///Finds where a variable is. Functions can only use their own variables and globals
+    fn lookup_var(&self, id: &Symbol) -> Result<Option<Var>, String> {
+        let (current, outer) = self.frames.split_last().unwrap();
+        for scope in current.scopes.iter().rev() {
+            if let Some((_, var)) = scope.vars.iter().rev().find(|(name, _)| name == id) {
+                return Ok(Some(*var))
+            }
+        }
+        for (i, frame) in outer.iter().enumerate().rev() {
+            for (j, scope) in frame.scopes.iter().enumerate().rev() {
+                let global = i == 0 && j == 0;
+                match scope.vars.iter().rev().find(|(name, _)| name == id) {
+                    Some((_, var)) if global => return Ok(Some(*var)),
+                    Some(_) => return Err(format!("functions using variables from outside, like '{id}'")),
+                    //A variable declared later in the block might be the one used, depending on when the function is called
+                    None if scope.declared.contains(id) => return Err(format!("functions using variables from outside, like '{id}'")),
+                    None => {}
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn assigned_var(&self, id: &Symbol) -> Result<Var, String> {
+        match self.lookup_var(id)? {
+            Some(var) => Ok(var),
+            None => Err(format!("the variable '{id}'"))
+        }
+    }
+
+    fn lookup_fun(&self, id: &Symbol) -> Option<usize> {
+        self.frames.iter().rev()
+            .flat_map(|frame| frame.scopes.iter().rev())
+            .find_map(|scope| scope.funs.iter().rev().find(|(name, _)| name == id).map(|(_, index)| *index))
+    }
+}
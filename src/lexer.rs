@@ -1,15 +1,16 @@
-use std::{iter::Peekable, fmt::Display};
+use std::{collections::VecDeque, fmt::Display, io::BufRead};
 use lazy_static::*;
 use crate::parser::*;
 
 ///Lexer token
 #[derive(Debug)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 pub enum LexToken {
     Paren(char),
     SemiColon,
     Colon,
     Comma,
+    Dot,
 
     Operator(&'static str),
     Keyword(&'static str),
@@ -23,19 +24,74 @@ pub enum LexToken {
     Char(char),
     Str(String),
 
+    ///Text that is not a token, with why, which only tokenize gives
+    Error(String),
+
+    EndOfInput
+}
+
+///What a token is, as a syntax highlighter colors it
+#[derive(Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Identifier,
+    Operator,
+
+    ///Parens, brackets, braces, ';', ':', ',' and '.'
+    Punctuation,
+    Number,
+    Bool,
+    Char,
+    Str,
+    Error,
     EndOfInput
 }
 
+impl LexToken {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            LexToken::Paren(_) | LexToken::SemiColon | LexToken::Colon | LexToken::Comma | LexToken::Dot => TokenKind::Punctuation,
+            LexToken::Operator(_) => TokenKind::Operator,
+            LexToken::Keyword(_) => TokenKind::Keyword,
+            LexToken::Type(_) => TokenKind::Type,
+            LexToken::Id(_) => TokenKind::Identifier,
+            LexToken::Int(_) | LexToken::Float(_) => TokenKind::Number,
+            LexToken::Bool(_) => TokenKind::Bool,
+            LexToken::Char(_) => TokenKind::Char,
+            LexToken::Str(_) => TokenKind::Str,
+            LexToken::Error(_) => TokenKind::Error,
+            LexToken::EndOfInput => TokenKind::EndOfInput
+        }
+    }
+}
+
 lazy_static! {
     ///First char of every operator
-    static ref OP_FIRST: Vec<char> = OPERATORS.iter().map(|op| op.chars().next().unwrap()).collect();
+    static ref OP_FIRST: Vec<char> = OPERATORS.iter().chain(DECLARABLE_OPERATORS.iter()).map(|op| op.chars().next().unwrap()).collect();
 }
 
+///Where a token starts in the source and where it ends, just after its last char. Lines and columns count from 1
 #[derive(Debug)]
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Location {
     pub line: u32,
-    pub col: usize
+    pub col: usize,
+    pub end_line: u32,
+    pub end_col: usize
+}
+
+impl Location {
+    ///A location that covers no chars, as for the end of input, or line 0 for what is not in the source
+    pub fn point(line: u32, col: usize) -> Self {
+        Self { line, col, end_line: line, end_col: col }
+    }
+
+    ///The location from the start of this one to the end of the other
+    pub fn to(self, other: Location) -> Self {
+        Self { end_line: other.end_line, end_col: other.end_col, ..self }
+    }
 }
 
 impl Display for Location {
@@ -44,22 +100,45 @@ impl Display for Location {
     }
 }
 
+#[derive(Default)]
 pub struct LexedProgram {
     program: Vec<(LexToken, Location)>,
+
+    ///The comments between the tokens, with their '//' or '/* */', and a '#!' line the source starts with
+    comments: Vec<(String, Location)>,
 }
 
 impl LexedProgram {
     pub fn new() -> Self {
-        Self {
-            program: Vec::new()
-        }
+        Self::default()
     }
 
     pub fn push(&mut self, token: LexToken, location: Location) {
         self.program.push((token, location))
     }
 
-    pub fn iter(&self) -> Peekable<std::slice::Iter<(LexToken, Location)>> {
+    fn len(&self) -> usize {
+        self.program.len()
+    }
+
+    ///Sets where the last token ends
+    fn end_last(&mut self, end: Location) {
+        if let Some((_, loc)) = self.program.last_mut() {
+            *loc = loc.to(end);
+        }
+    }
+
+    ///The tokens with the span of each in the source, ending with the end of input
+    pub fn tokens(&self) -> &[(LexToken, Location)] {
+        &self.program
+    }
+
+    ///The comments in the order they are in the source, with their spans. The parser does not see them
+    pub fn comments(&self) -> &[(String, Location)] {
+        &self.comments
+    }
+
+    pub fn iter(&self) -> LexIter<'_> {
         self.program.iter().peekable()
     }
 }
@@ -75,21 +154,125 @@ impl Display for LexedProgram {
     }
 }
 
+///The chars of the input with their index, read a line at a time as they are needed
+struct Chars<R: BufRead> {
+    reader: R,
+    ahead: VecDeque<(usize, char)>,
+
+    ///How many chars have been read
+    read: usize,
+
+    ///Why reading stopped before the end of the input
+    error: Option<std::io::Error>,
+}
+
+impl<R: BufRead> Chars<R> {
+    fn new(reader: R) -> Self {
+        Self { reader, ahead: VecDeque::new(), read: 0, error: None }
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<(usize, char)> {
+        let mut line = String::new();
+        while self.ahead.len() <= n && self.error.is_none() {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => for c in line.chars() {
+                    self.ahead.push_back((self.read, c));
+                    self.read += 1;
+                },
+                Err(err) => self.error = Some(err)
+            }
+        }
+        self.ahead.get(n).copied()
+    }
+
+    fn peek(&mut self) -> Option<(usize, char)> {
+        self.peek_nth(0)
+    }
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        self.peek();
+        self.ahead.pop_front()
+    }
+
+    fn next_if(&mut self, f: impl Fn(&(usize, char)) -> bool) -> Option<(usize, char)> {
+        match self.peek() {
+            Some(c) if f(&c) => self.next(),
+            _ => None
+        }
+    }
+
+    fn starts_with(&mut self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| matches!(self.peek_nth(i), Some((_, next)) if next == c))
+    }
+}
+
 pub fn lex(input: &str) -> Result<LexedProgram, (String, Location)> {
-    let mut program = LexedProgram::new();
+    lex_reader(input.as_bytes())
+}
+
+///Lexes the input as it is read, so it does not have to be in memory all at once, as for a pipe or a generated program
+pub fn lex_reader(reader: impl BufRead) -> Result<LexedProgram, (String, Location)> {
+    lex_at(reader, Location::point(1, 1))
+}
+
+///Lexes a part of a larger source that starts at the location, so the locations of the tokens are in the larger source
+pub fn lex_at(reader: impl BufRead, start: Location) -> Result<LexedProgram, (String, Location)> {
+    lex_tokens(reader, start, false)
+}
+
+///Lexes all of the input, where what is not a token is an Error token with its span, and lexing goes on after it,
+///so an editor can highlight source that is being edited
+pub fn tokenize(input: &str) -> LexedProgram {
+    lex_tokens(input.as_bytes(), Location::point(1, 1), true).expect("Errors are tokens when recovering")
+}
 
-    let mut iter = input.chars().into_iter().enumerate().peekable();
-    let mut col: usize = 0;
-    let mut line = 1;
-    let mut loc = Location {line, col};
+///An error ends lexing, or is a token when recovering from errors
+fn error(program: &mut LexedProgram, recover: bool, msg: String, loc: Location) -> Result<(), (String, Location)> {
+    match recover {
+        true => {
+            program.push(LexToken::Error(msg), loc);
+            Ok(())
+        },
+        false => Err((msg, loc))
+    }
+}
 
-    while let Some(&c) = iter.peek() {
-        loc = Location {
-            line, 
-            col: c.0 - col
-        };
+fn lex_tokens(reader: impl BufRead, start: Location, recover: bool) -> Result<LexedProgram, (String, Location)> {
+    let mut program = LexedProgram::new();
 
-        let rest = &input[c.0..];
+    let mut iter = Chars::new(reader);
+    //A part that starts after column 1 has its first line start before it
+    iter.read = start.col.saturating_sub(1);
+    //Where the current line starts in the input, in chars
+    let mut line_start: usize = 0;
+    let mut line = start.line;
+    //How many tokens have their end, which is where the iteration after the one that pushed them starts
+    let mut ended = 0;
+
+    //A script can start with a byte order mark, and a '#!' line naming what runs it, which is kept as a comment
+    if start == Location::point(1, 1) {
+        if iter.next_if(|(_, c)| *c == '\u{FEFF}').is_some() {
+            line_start = 1;
+        }
+        if iter.starts_with("#!") {
+            let loc = Location::point(line, iter.peek().map_or(1, |c| c.0 - line_start + 1));
+            let mut text = String::new();
+            while let Some((_, c)) = iter.next_if(|(_, c)| *c != '\n') {
+                text.push(c);
+            }
+            let end = iter.peek().map_or(iter.read, |c| c.0);
+            program.comments.push((text, loc.to(Location::point(line, end - line_start + 1))));
+        }
+    }
+
+    while let Some(c) = iter.peek() {
+        let loc = Location::point(line, c.0 - line_start + 1);
+        if program.len() > ended {
+            program.end_last(loc);
+            ended = program.len();
+        }
 
         let char = c.1;
 
@@ -119,29 +302,32 @@ pub fn lex(input: &str) -> Result<LexedProgram, (String, Location)> {
             continue
         }
 
-        //Match operator and comment
-        if OP_FIRST.iter().any(|c| char == *c) {
-            let op = get_operator(&mut iter, rest);
-            //Comments
-            if op == "//" {
-                while let Some(c) = iter.next() {
-                    if c.1 == '\n' {
-                        line += 1;
-                        col = c.0;
-                        break;
+        //Match operator and comment. A single '.' is not an operator
+        if let Some(op) = OP_FIRST.contains(&char).then(|| get_operator(&mut iter)).flatten() {
+            //Comments, the newline after a line comment is not part of it
+            if op == "//" || op == "/*" {
+                let mut text = op.to_string();
+                if op == "//" {
+                    while let Some((_, c)) = iter.next_if(|(_, c)| *c != '\n') {
+                        text.push(c);
                     }
-                }
-            } else if op == "/*" {
-                let mut last = ' ';
-                while let Some(c) = iter.next() {
-                    if c.1 == '\n' {
-                        line += 1;
-                        col = c.0;
-                    } else if last == '*' && c.1 == '/' {
-                        break;
+                } else {
+                    while let Some(c) = iter.next() {
+                        text.push(c.1);
+                        if c.1 == '\n' {
+                            line += 1;
+                            line_start = c.0 + 1;
+                        } else if text.len() > 3 && text.ends_with("*/") {
+                            break;
+                        }
+                    }
+                    if text.len() < 4 || !text.ends_with("*/") {
+                        error(&mut program, recover, format!("Unterminated comment starting at {loc}"), loc)?;
+                        continue
                     }
-                    last = c.1;
                 }
+                let end = iter.peek().map_or(iter.read, |c| c.0);
+                program.comments.push((text, loc.to(Location::point(line, end - line_start + 1))));
             //Normal operator
             } else {
                 program.push(LexToken::Operator(op), loc);
@@ -150,14 +336,17 @@ pub fn lex(input: &str) -> Result<LexedProgram, (String, Location)> {
         }
 
         //Match number
-        if char.is_numeric() {
-            program.push(get_number(&mut iter), loc);
+        if char.is_ascii_digit() {
+            match get_number(&mut iter) {
+                Ok(number) => program.push(number, loc),
+                Err(msg) => error(&mut program, recover, msg, loc)?
+            }
             continue
         }
 
         if char == '\n' {
             line += 1;
-            col = c.0;
+            line_start = c.0 + 1;
         } else if !char.is_whitespace() {
 
             //Match chars
@@ -166,68 +355,119 @@ pub fn lex(input: &str) -> Result<LexedProgram, (String, Location)> {
                 ';' => program.push(LexToken::SemiColon, loc),
                 ':' => program.push(LexToken::Colon, loc),
                 ',' => program.push(LexToken::Comma, loc),
+                '.' => program.push(LexToken::Dot, loc),
                 '\'' => {
                     match get_char(&mut iter) {
                         Ok(c) => program.push(LexToken::Char(c), loc),
-                        Err(_) => {
-                            let next = iter.peek();
-                            if next.is_some() {
-                                loc.col += 1;
-                                return Err((format!("Expected '"), loc))
-                            } else {
-                                return Err((format!("Expected char"), loc))
+                        //When recovering, the error is the whole literal, up to a closing quote on the line
+                        Err((msg, _)) if recover => {
+                            program.push(LexToken::Error(msg), loc);
+                            while let Some((_, c)) = iter.next_if(|(_, c)| *c != '\n') {
+                                if c == '\'' {
+                                    break
+                                }
                             }
+                            continue
                         },
+                        Err((msg, after)) => return Err((msg, Location::point(line, loc.col + after)))
                     }
                 },
                 '"'=> {
-                    match get_string(&mut iter) {
+                    match get_string(&mut iter, &mut line, &mut line_start) {
                         Ok(s) => program.push(LexToken::Str(s), loc),
-                        Err(_) => {
-                            return Err((format!("Illegal string"), loc))
-                        },
+                        Err(_) => error(&mut program, recover, format!("Unterminated string literal starting at {loc}"), loc)?,
                     }
                 },
 
                 ' ' | '\t' => {}
-                _ => return Err((format!("Invalid char: '{char}'"), loc))
+                _ => error(&mut program, recover, invalid_char(char), loc)?
             }
         }
 
         iter.next();
     }
 
-    loc.col += 1;
-    program.push(LexToken::EndOfInput, loc);
+    let end = Location::point(line, iter.read - line_start + 1);
+    if let Some(err) = iter.error {
+        error(&mut program, recover, format!("Could not read the input: {err}"), end)?;
+    }
+    if program.len() > ended {
+        program.end_last(end);
+    }
+    //The end of input is just after the last token
+    let end = match program.program.last() {
+        Some((_, loc)) => Location::point(loc.end_line, loc.end_col),
+        None => Location::point(start.line, start.col)
+    };
+    program.push(LexToken::EndOfInput, end);
 
     Ok(program)
 }
 
-fn get_char<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>) -> Result<char, ()> {
+///Chars that look like valid ones, often pasted from a word processor or typed with another keyboard layout, with what to write instead
+const LOOKALIKES: &[(char, &str)] = &[
+    ('“', "\""), ('”', "\""), ('„', "\""), ('`', "\""), ('‘', "'"), ('’', "'"),
+    ('–', "-"), ('—', "-"), ('−', "-"), ('×', "*"), ('÷', "/"), ('≠', "!="), ('≤', "<="), ('≥', ">="),
+    ('；', ";"), ('：', ":"), ('，', ","), ('（', "("), ('）', ")"), ('＝', "=")
+];
+
+///The error for a char no token starts with, with its code point as it may not be visible, and a hint when there is one
+fn invalid_char(char: char) -> String {
+    let msg = format!("Invalid char '{}' (U+{:04X})", char.escape_debug(), char as u32);
+    if let Some((_, instead)) = LOOKALIKES.iter().find(|(lookalike, _)| *lookalike == char) {
+        return format!("{msg}, did you mean '{instead}'?")
+    }
+    match char {
+        '#' => format!("{msg}, comments start with '//'"),
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}' => format!("{msg}, it is invisible and can be deleted"),
+        _ => msg
+    }
+}
+
+///The char after the quote. The error says what is wrong, and how many columns after the quote
+fn get_char<R: BufRead>(iter: &mut Chars<R>) -> Result<char, (String, usize)> {
     iter.next();
-    if iter.peek().is_some() {
-        let char = iter.next().unwrap().1;
+    if let Some((_, char)) = iter.next() {
         if let Some((_, '\'')) = iter.peek() {
             return Ok(char)
-        }   
+        }
+
+        //More chars before a quote, like a letter and a combining accent, can look like one
+        let mut text = char.to_string();
+        let mut n = 0;
+        while let Some((_, c)) = iter.peek_nth(n).filter(|(_, c)| !c.is_whitespace()) {
+            if c == '\'' {
+                return Err((format!("Char literal '{text}' has {} chars, use a string for more than one", text.chars().count()), 0))
+            }
+            text.push(c);
+            n += 1;
+        }
+    }
+    match iter.peek() {
+        Some(_) => Err((String::from("Expected '"), 1)),
+        None => Err((String::from("Expected char"), 0))
     }
-    Err(())
 }
 
-fn get_string<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>) -> Result<String, ()> {
+///The string after the quote. Newlines in it are counted in the line
+fn get_string<R: BufRead>(iter: &mut Chars<R>, line: &mut u32, line_start: &mut usize) -> Result<String, ()> {
     let mut res = String::new();
     iter.next();
     if let Some((_, c)) = iter.peek() {
-        if *c == '"' { return Ok(res) }
+        if c == '"' { return Ok(res) }
     } else { return Err(()) }
 
-    while let Some((_, c)) = iter.next() {
+    while let Some((i, c)) = iter.next() {
+        if c == '\n' {
+            *line += 1;
+            *line_start = i + 1;
+        }
         if let Some((_, nc)) = iter.peek() {
             // allow \" quotes to avoid ending string
-            if c == '\\' && *nc == '"' {
+            if c == '\\' && nc == '"' {
                 res = format!("{res}{}", nc);
                 iter.next();
-            } else if *nc == '"' {
+            } else if nc == '"' {
                 res = format!("{res}{}", c);
                 return Ok(res)
             } else  {
@@ -240,47 +480,52 @@ fn get_string<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>) -> Resu
     Err(())
 }
 
-fn get_id<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>) -> String {
+fn get_id<R: BufRead>(iter: &mut Chars<R>) -> String {
     let mut res = String::new();
-    while iter.peek().is_some() {
-        let char = iter.peek().unwrap().1;
-        if !(char.is_alphanumeric() || char == '_') {
-            break;
-        }
-        res = format!("{res}{}", iter.next().unwrap().1);
+    while let Some((_, char)) = iter.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+        res = format!("{res}{char}");
     }
-    return res
+    res
 }
 
-fn get_operator<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>, rest: &str) -> &'static str {
-    for op in OPERATORS.iter() {
-        if rest.starts_with(op) {
+///The operator the rest of the input starts with, if any
+fn get_operator<R: BufRead>(iter: &mut Chars<R>) -> Option<&'static str> {
+    for op in OPERATORS.iter().chain(DECLARABLE_OPERATORS.iter()) {
+        if iter.starts_with(op) {
             for _ in 0..op.len() {
                 iter.next();
             }
-            return op
+            return Some(op)
         }
     }
-
-    unreachable!()
+    None
 }
 
-fn get_number<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>) -> LexToken {
+fn get_number<R: BufRead>(iter: &mut Chars<R>) -> Result<LexToken, String> {
     let mut res = String::new();
-    while iter.peek().is_some() && iter.peek().unwrap().1.is_digit(10) {
-        res = format!("{res}{}", iter.next().unwrap().1);
+    while let Some((_, digit)) = iter.next_if(|(_, c)| c.is_ascii_digit()) {
+        res = format!("{res}{digit}");
     }
-    if iter.peek().is_none() || iter.peek().unwrap().1 != '.' {
-        return LexToken::Int(res.parse::<i64>().unwrap())
+    let int = |res: &String| res.parse::<i64>().map(LexToken::Int).map_err(|_| format!("Int '{res}' is too large"));
+    if !matches!(iter.peek(), Some((_, '.'))) {
+        return int(&res)
     }
+
+    //A name after the dot is a method call, as in '5.double()', and another dot is a range, as in '0..10'
+    if let Some((_, c)) = iter.peek_nth(1) {
+        if c.is_alphabetic() || c == '_' || c == '.' {
+            return int(&res)
+        }
+    }
+
     //Floats
     iter.next();
 
     res = format!("{res}.");
 
-    while iter.peek().is_some() && iter.peek().unwrap().1.is_digit(10) {
-        res = format!("{res}{}", iter.next().unwrap().1);
+    while let Some((_, digit)) = iter.next_if(|(_, c)| c.is_ascii_digit()) {
+        res = format!("{res}{digit}");
     }
     
-    LexToken::Float(res.parse::<f64>().unwrap())
+    res.parse::<f64>().map(LexToken::Float).map_err(|_| format!("Invalid float '{res}'"))
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod eval;
+pub mod type_checker;
+pub mod environment;
+pub mod builtins;
+pub mod vm;
+pub mod memory;
+pub mod options;
+pub mod interner;
+pub mod optimize;
+pub mod ir;
+pub mod profiler;
+pub mod debugger;
+pub mod coverage;
+pub mod reload;
+pub mod snapshot;
+pub mod task;
+pub mod events;
+pub mod interpreter;
+pub mod host;
+pub mod convert;
+pub mod diagnostics;
+pub mod lint;
+pub mod incremental;
+pub mod fmt;
+pub mod dump;
+pub mod visit;
+#[cfg(feature = "serde")]
+pub mod serialize;
+
+use lexer::*;
+use parser::*;
+use ast::*;
+use environment::*;
+use builtins::*;
+use options::{ExecutionOptions, ParseOptions};
+use eval::Signal;
+
+pub use interpreter::{Interpreter, Value, Error};
+pub use ast::{Type, StructValue};
+pub use convert::HostStruct;
\ No newline at end of file
@@ -0,0 +1,148 @@
+use super::*;
+use Exp::*;
+use interner::Symbol;
+use visit::{Visitor, walk_exp};
+
+///Finds what type checks but is likely a mistake: variables that are never read, functions that are never called,
+///statements after a break or continue, and conditions that are always true or false. Names starting with '_' are left out
+pub fn lint(program: &Exp) -> Vec<(String, Location)> {
+    let mut linter = Linter::default();
+    linter.visit_exp(program);
+    for (id, loc) in &linter.funs {
+        if !linter.called.contains(id) && !id.as_str().starts_with('_') {
+            linter.warnings.push((format!("Function '{id}' is never called"), *loc));
+        }
+    }
+    linter.warnings.sort_by_key(|(_, loc)| *loc);
+    linter.warnings
+}
+
+#[derive(Default)]
+struct Linter {
+    ///Variables of each scope, innermost last, with where they are declared and whether they have been read
+    scopes: Vec<Vec<(Symbol, Location, bool)>>,
+
+    ///Functions declared in blocks, and the names that are called or used as values
+    funs: Vec<(Symbol, Location)>,
+    called: Vec<Symbol>,
+
+    warnings: Vec<(String, Location)>,
+}
+
+impl Visitor for Linter {
+    fn visit_exp(&mut self, exp: &Exp) {
+        match exp {
+            VarExp(id, _) | FunCallExp(id, _, _) => {
+                self.read(id);
+                self.called.push(*id);
+                walk_exp(self, exp);
+            },
+            MethodCallExp(_, method, _, _) => {
+                self.read(method);
+                self.called.push(*method);
+                walk_exp(self, exp);
+            },
+            LetExp(id, value, loc) => {
+                self.visit_exp(value);
+                self.declare(*id, *loc, false);
+            },
+            DestructExp(pattern, value, loc) => {
+                self.visit_exp(value);
+                let mut ids = Vec::new();
+                pattern.ids(&mut ids);
+                for id in ids {
+                    self.declare(id, *loc, false);
+                }
+            },
+            //Assigning to a variable does not read it
+            BinOpExp(left, Operator::Assign, right, _) if matches!(left.as_ref(), VarExp(_, _)) => self.visit_exp(right),
+            ForInExp(id, collection, body, _, loc) => {
+                self.visit_exp(collection);
+                self.called.push(Symbol::new("next"));
+                self.scopes.push(Vec::new());
+                self.declare(*id, *loc, false);
+                self.visit_exp(body);
+                self.leave_scope();
+            },
+            //The variable of the loop is only in the loop
+            ForExp(_, _, _, _, _, _) => {
+                self.scopes.push(Vec::new());
+                walk_exp(self, exp);
+                self.leave_scope();
+            },
+            WhileExp(cond, _, _, _) => {
+                //'while (true)' is how a loop that ends with a break is written
+                if !matches!(cond.as_ref(), LiteralExp(Literal::Bool(true), _)) {
+                    self.condition(cond);
+                }
+                walk_exp(self, exp);
+            },
+            IfElseExp(cond, _, _, _) => {
+                self.condition(cond);
+                walk_exp(self, exp);
+            },
+            BlockExp(exps, funs, _) => {
+                self.scopes.push(Vec::new());
+                let mut diverged = false;
+                for exp in exps {
+                    let declaration = matches!(exp, FunDeclExp(_, _) | StructDeclExp(_, _, _) | InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _) | OperatorDeclExp(_, _));
+                    if diverged && !declaration {
+                        self.warnings.push((String::from("Unreachable statement, it comes after a break or continue"), exp.loc()));
+                        diverged = false;
+                    }
+                    self.visit_exp(exp);
+                    diverged |= optimize::diverges(exp);
+                }
+
+                //Functions can use every variable of the block, as no other variable in it can have the same name
+                for (id, fun) in funs {
+                    //Methods are named 'Struct.method', and are called through interfaces as well
+                    if !id.as_str().contains('.') {
+                        self.funs.push((*id, fun.loc));
+                    }
+                    self.scopes.push(Vec::new());
+                    for param in &fun.params {
+                        self.declare(*param, fun.loc, true);
+                    }
+                    self.visit_exp(&fun.exp);
+                    self.leave_scope();
+                }
+                self.leave_scope();
+            },
+            _ => walk_exp(self, exp)
+        }
+    }
+
+}
+
+impl Linter {
+    fn declare(&mut self, id: Symbol, loc: Location, read: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((id, loc, read));
+        }
+    }
+
+    ///Marks the innermost variable with the name as read
+    fn read(&mut self, id: &Symbol) {
+        let var = self.scopes.iter_mut().rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|(var, _, _)| var == id));
+        if let Some((_, _, read)) = var {
+            *read = true;
+        }
+    }
+
+    fn leave_scope(&mut self) {
+        for (id, loc, read) in self.scopes.pop().unwrap_or_default() {
+            //Names starting with '.' are made by the parser
+            if !read && !id.as_str().starts_with(['_', '.']) {
+                self.warnings.push((format!("Variable '{id}' is never read"), loc));
+            }
+        }
+    }
+
+    fn condition(&mut self, cond: &Exp) {
+        if let LiteralExp(Literal::Bool(value), _) = optimize::fold_constants(cond.clone()) {
+            self.warnings.push((format!("Condition is always {value}"), cond.loc()));
+        }
+    }
+}
@@ -1,22 +1,94 @@
-mod lexer;
-mod ast;
-mod parser;
-mod eval;
-mod type_checker;
-mod environment;
-
-use std::{fs, time::Instant};
-
-use lexer::*;
-use parser::*;
-use ast::*;
-use simple_process_stats::ProcessStats;
-use environment::*;
-
-#[async_std::main]
-async fn main() {
-    let mut args = std::env::args();
-    args.next();
+use std::{fs, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant, SystemTime}};
+
+use nebulang::{*, lexer::*, parser::*, ast::*, environment::*};
+use options::{ExecutionOptions, Overflow, ParseOptions};
+use eval::Signal;
+use reload::Reloader;
+use diagnostics::Diagnostic;
+
+#[global_allocator]
+static ALLOCATOR: memory::CountingAllocator = memory::CountingAllocator;
+
+///Whether errors are printed as JSON, with --diagnostics=json
+static JSON_DIAGNOSTICS: AtomicBool = AtomicBool::new(false);
+
+///Whether a newline can end a statement, with --newline-ends-statement
+static NEWLINE_ENDS_STATEMENT: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+
+    //Options come before the path
+    let mut use_vm = false;
+    let mut optimized = true;
+    let mut debug = false;
+    let mut watch = false;
+    let mut input_events = false;
+    let mut snapshot = None;
+    let mut format = false;
+    let mut dump = None;
+    let mut options = ExecutionOptions::default();
+    while let Some(option) = args.next_if(|arg| arg.starts_with("--")) {
+        match option.as_str() {
+            "--sandbox" => options = options.sandboxed(),
+            "--vm" => use_vm = true,
+            "--no-optimize" => optimized = false,
+            "--profile" => options.profile = true,
+            "--debug" => debug = true,
+            "--watch" => watch = true,
+            "--events" => input_events = true,
+            "--trace" => options.trace = Some(print_trace),
+            "--coverage" => options.coverage = true,
+            "--fmt" => format = true,
+            "--newline-ends-statement" => NEWLINE_ENDS_STATEMENT.store(true, Ordering::Relaxed),
+            "--diagnostics=json" => JSON_DIAGNOSTICS.store(true, Ordering::Relaxed),
+            _ if option.starts_with("--max-depth=") => match option["--max-depth=".len()..].parse() {
+                Ok(max) => options.max_call_depth = max,
+                Err(_) => {
+                    println!("Invalid maximum depth in '{option}'");
+                    return
+                }
+            },
+            _ if option.starts_with("--fuel=") => match option["--fuel=".len()..].parse() {
+                Ok(fuel) => options.fuel = Some(fuel),
+                Err(_) => {
+                    println!("Invalid fuel in '{option}'");
+                    return
+                }
+            },
+            _ if option.starts_with("--timeout=") => match option["--timeout=".len()..].parse() {
+                Ok(ms) => options.max_duration = Some(Duration::from_millis(ms)),
+                Err(_) => {
+                    println!("Invalid timeout in '{option}'");
+                    return
+                }
+            },
+            _ if option.starts_with("--overflow=") => match &option["--overflow=".len()..] {
+                "error" => options.overflow = Overflow::Error,
+                "wrap" => options.overflow = Overflow::Wrap,
+                "saturate" => options.overflow = Overflow::Saturate,
+                _ => {
+                    println!("Invalid overflow in '{option}', it must be error, wrap or saturate");
+                    return
+                }
+            },
+            "--ast=json" => dump = Some(Exp::to_json as fn(&Exp) -> String),
+            "--ast=sexp" => dump = Some(Exp::to_sexp),
+            _ if option.starts_with("--snapshot=") => snapshot = Some(option["--snapshot=".len()..].to_string()),
+            _ if option.starts_with("--max-memory=") => match option["--max-memory=".len()..].parse::<usize>() {
+                Ok(mb) => options.max_memory = Some(mb * 1_048_576),
+                Err(_) => {
+                    println!("Invalid maximum memory in '{option}'");
+                    return
+                }
+            },
+            _ => {
+                println!("Unknown option '{option}'");
+                return
+            }
+        }
+    }
+
     let path = match args.next() {
         Some(path) => path,
         None => {
@@ -25,40 +97,132 @@ async fn main() {
         }
     };
 
-    let file = fs::read_to_string(path)
+    //The rest are for the program
+    options.hooks.args = args.collect();
+
+    let mut modified = modified_time(&path);
+    let file = fs::read_to_string(&path)
         .expect("Should have been able to read the file");
-    
+    if format {
+        match fmt::format(&file) {
+            Ok(formatted) => print!("{formatted}"),
+            Err(errors) => for error in errors {
+                report(error, &file);
+            }
+        }
+        return
+    }
+    if let Some(dump) = dump {
+        let program = lex(&file).map_err(|(msg, loc)| vec![Diagnostic::new("Lexer Error", msg, loc)]).and_then(|lexed| {
+            parse(&mut lexed.iter()).map_err(|errors| errors.into_iter().map(|(msg, loc)| Diagnostic::new("Parse Error", msg, loc)).collect())
+        });
+        match program {
+            Ok(program) => println!("{}", dump(&program)),
+            Err(errors) => for error in errors {
+                report(error, &file);
+            }
+        }
+        return
+    }
+
+    //Each nebulang call takes several Rust frames, so deep recursion needs a big stack
+    let runner = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            if input_events {
+                read_input_events();
+            }
+            if !watch {
+                return run(file, use_vm, optimized, debug, options, None, snapshot)
+            }
+            let mut reloader = Reloader::new();
+            let mut file = file;
+            loop {
+                run(file, use_vm, optimized, debug, options.clone(), Some(&mut reloader), snapshot.clone());
+                file = wait_for_change(&path, &mut modified);
+                println!("Reloading");
+            }
+        })
+        .expect("Should have been able to start the interpreter thread");
+    runner.join().unwrap();
+}
+
+///Stack size of the thread that runs the program
+const STACK_SIZE: usize = 1 << 30;
+
+fn modified_time(path: &String) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+///Waits until the file is saved again, and reads it
+fn wait_for_change(path: &String, modified: &mut Option<SystemTime>) -> String {
+    loop {
+        std::thread::sleep(Duration::from_millis(200));
+        let now = modified_time(path);
+        if now != *modified {
+            *modified = now;
+            if let Ok(file) = fs::read_to_string(path) {
+                return file
+            }
+        }
+    }
+}
+
+///Lexes, parses, type checks and runs a program. When it is reloaded, its top-level variables keep their values from the last run.
+///With a snapshot file, it resumes from the snapshot there and saves one after each top-level statement, until it ends.
+///The callbacks the program registers are then called until there are no events left
+fn run(file: String, use_vm: bool, optimized: bool, debug: bool, options: ExecutionOptions, reloader: Option<&mut Reloader>, snapshot: Option<String>) {
     let before = Instant::now();
-    let mem_before = ProcessStats::get().await.unwrap().memory_usage_bytes;
+    let mem_before = memory::allocated();
 
     //Lex
     let lexed = match lex(file.as_str()) {
         Ok(lexed) => lexed,
         Err((msg, loc)) => {
-            println!("Lexer Error: {msg}. At {loc}"); 
+            report(Diagnostic::new("Lexer Error", msg, loc), &file);
             return
         }
     };
 
     //Parse
-    let mut program = match parse(&mut lexed.iter()) {
+    let parse_options = ParseOptions { newline_ends_statement: NEWLINE_ENDS_STATEMENT.load(Ordering::Relaxed) };
+    let mut program = match parse_with_options(&mut lexed.iter(), parse_options) {
         Ok(program) => program,
-        Err((msg, loc)) => {
-            println!("Parse Error: {msg}. At {loc}"); 
+        Err(errors) => {
+            for (msg, loc) in errors {
+                report(Diagnostic::new("Parse Error", msg, loc), &file);
+            }
             return
         }
     };
 
     //Type check
-    if let Err((msg, loc)) = program.type_check(&mut Environment::new()) {
-        println!("Type Error: {msg}. At {loc}"); 
+    let kept = reloader.as_ref().map(|reloader| reloader.keep_variables(&program));
+    if let Err(errors) = type_checker::check_program(&mut program, &mut Environment::new()) {
+        for (msg, loc) in errors {
+            report(Diagnostic::new("Type Error", msg, loc), &file);
+        }
         return
     }
+    for (msg, loc) in lint::lint(&program) {
+        report(Diagnostic::new("Warning", msg, loc), &file);
+    }
+    if let Some(mut kept) = kept {
+        match kept.type_check(&mut Environment::new()) {
+            Ok(_) => program = kept,
+            Err((msg, loc)) => println!("The variables are reset, as their values no longer fit: {msg}. At {loc}")
+        }
+    }
+
+    //Optimize. The debugger and coverage show the program as it is written
+    if optimized && !debug && !options.coverage {
+        program = optimize::optimize(program);
+    }
 
     //Run
-    let mem_after = ProcessStats::get().await.unwrap().memory_usage_bytes;
+    let mem_after = memory::allocated();
 
-    let total_mem = (mem_after - mem_before) / 1_028;
+    let total_mem = mem_after.saturating_sub(mem_before) / 1_024;
 
     let elapsed = before.elapsed().as_millis();
 
@@ -68,10 +232,101 @@ async fn main() {
     //println!("------------------------\n");
 
     let before = Instant::now();
-    let res = program.evaluate(&mut Environment::new());
+    if debug {
+        debugger::attach(Box::new(debugger::ConsoleDebugger));
+        debugger::pause();
+    }
+    let use_vm = match use_vm {
+        true if options.trace.is_some() || options.coverage || debug || reloader.is_some() || snapshot.is_some() => {
+            println!("Tracing, coverage, debugging, reloading and snapshots only work without the VM, running without it");
+            false
+        },
+        use_vm => use_vm
+    };
+    let res = match (use_vm, reloader) {
+        (true, _) => match vm::compile(&program) {
+            Ok(mut bytecode) => {
+                if optimized {
+                    let stats = vm::peephole(&mut bytecode);
+                    if options.profile {
+                        println!("{stats}");
+                    }
+                }
+                vm::run(&bytecode, &options)
+            },
+            Err(feature) => {
+                println!("The VM does not support {feature}, running without it");
+                eval::run(&program, &options)
+            }
+        },
+        (false, Some(reloader)) => reloader.run(&program, &options),
+        (false, None) => match &snapshot {
+            Some(path) => {
+                let from = fs::read_to_string(path).ok();
+                if from.is_some() {
+                    println!("Resuming from the snapshot in '{path}'");
+                }
+                let res = eval::run_resumable(&program, from.as_ref(), &options, |snapshot| {
+                    if let Err(err) = fs::write(path, snapshot.to_string()) {
+                        println!("Could not save the snapshot to '{path}': {err}");
+                    }
+                });
+                //A program that ended starts from the beginning next time
+                if matches!(res, Ok(_) | Err(Signal::Exit(_))) {
+                    let _ = fs::remove_file(path);
+                }
+                res
+            },
+            None => eval::run(&program, &options)
+        }
+    };
+    let res = res.and_then(|res| events::run_events().map(|_| res));
+    if let Some(profile) = profiler::take_profile() {
+        println!("{profile}");
+    }
+    if let Some(coverage) = coverage::take_coverage() {
+        println!("{coverage}");
+    }
+    let res = match res {
+        Ok(res) => res,
+        Err(Signal::Exit(code)) => std::process::exit(code),
+        Err(signal) => {
+            report(Diagnostic::from_signal(&signal), &file);
+            return
+        }
+    };
     let elapsed = before.elapsed().as_millis();
 
     //println!("\n------------------------");
     println!("Returned: {res}");
     println!("Time: {elapsed}ms");
+}
+
+///Prints an error with the line of the source it is on, or as one line of JSON to standard error with --diagnostics=json
+fn report(diagnostic: Diagnostic, source: &str) {
+    if JSON_DIAGNOSTICS.load(Ordering::Relaxed) {
+        eprintln!("{}", diagnostic.to_json());
+    } else {
+        println!("{}\n", diagnostic.render(source));
+    }
+}
+
+///Sends each line of the input as a 'line' event, from another thread so timers run while it waits
+fn read_input_events() {
+    let sender = events::sender();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            match line {
+                Ok(line) if sender.send("line", &line).is_ok() => (),
+                _ => return
+            }
+        }
+    });
+}
+
+///Prints a statement that was evaluated, where it is and its value. Only the first line of the statement is shown
+fn print_trace(exp: &Exp, value: &Literal) {
+    let text = exp.to_string();
+    let first_line = text.lines().next().unwrap_or("");
+    eprintln!("[{}] {first_line} => {value}", exp.loc());
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use std::{alloc::{GlobalAlloc, Layout, System}, sync::atomic::{AtomicUsize, Ordering}};
+
+use super::*;
+use eval::Signal;
+
+///Bytes currently allocated on the heap, when the counting allocator is in use
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+///The system allocator, but it keeps count of how much is allocated.
+///Memory limits only work when this is the global allocator
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+///Bytes allocated by the whole interpreter, including the parsed program
+pub fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+///Stops the program with a runtime error when more than the maximum is allocated.
+///It is checked at every step, so a single builtin can go over it
+pub fn check_memory(max: Option<usize>, loc: Location) -> Result<(), Signal> {
+    check_allocation(max, 0, loc)
+}
+
+///Like check_memory, before allocating 'bytes' more
+pub fn check_allocation(max: Option<usize>, bytes: usize, loc: Location) -> Result<(), Signal> {
+    match max {
+        Some(max) if allocated().saturating_add(bytes) > max => Err(Signal::Error(format!("Out of memory, the program used more than {}kB", max / 1_024), loc)),
+        _ => Ok(())
+    }
+}
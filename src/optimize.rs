@@ -0,0 +1,115 @@
+use super::*;
+use Literal::*;
+use Exp::*;
+use Operator::*;
+use visit::Folder;
+
+///Rewrites a type checked program into one that gives the same result with less work
+pub fn optimize(program: Exp) -> Exp {
+    eliminate_dead_code(fold_constants(program))
+}
+
+///Replaces arithmetic and boolean operations on literals with their result, so '2 * 3 + x' becomes '6 + x'.
+///Operations that would fail when run, like dividing by zero or overflowing, are left for the program to fail on
+pub fn fold_constants(exp: Exp) -> Exp {
+    match map_children(exp, fold_constants) {
+        BinOpExp(left, op, right, loc) => match (*left, *right) {
+            (LiteralExp(l, l_loc), LiteralExp(r, r_loc)) if foldable(&op, &l, &r) => match eval::binary_op(&op, l.clone(), r.clone(), loc) {
+                Ok(lit) => LiteralExp(lit, loc),
+                Err(_) => BinOpExp(Box::new(LiteralExp(l, l_loc)), op, Box::new(LiteralExp(r, r_loc)), loc)
+            },
+            (left, right) => BinOpExp(Box::new(left), op, Box::new(right), loc)
+        },
+        UnOpExp(op, exp, loc) => match *exp {
+            LiteralExp(Int(i), _) if op == Minus && i != i64::MIN => LiteralExp(Int(-i), loc),
+            LiteralExp(lit @ (Float(_) | Bool(_)), lit_loc) => match eval::unary_op(&op, lit.clone(), loc) {
+                Ok(lit) => LiteralExp(lit, loc),
+                Err(_) => UnOpExp(op, Box::new(LiteralExp(lit, lit_loc)), loc)
+            },
+            exp => UnOpExp(op, Box::new(exp), loc)
+        },
+        exp => exp
+    }
+}
+
+///Whether an operation on two literals can be done before the program runs
+fn foldable(op: &Operator, left: &Literal, right: &Literal) -> bool {
+    match (op, left, right) {
+        (Plus, Int(l), Int(r)) => l.checked_add(*r).is_some(),
+        (Minus, Int(l), Int(r)) => l.checked_sub(*r).is_some(),
+        (Multiply, Int(l), Int(r)) => l.checked_mul(*r).is_some(),
+        (Divide, Int(l), Int(r)) => l.checked_div(*r).is_some(),
+        (Modulo, Int(l), Int(r)) => l.checked_rem(*r).is_some(),
+        (Plus | Minus | Multiply | Divide | Modulo | LessThan | GreaterThan | LessOrEquals | GreaterOrEquals,
+            Int(_) | Float(_), Int(_) | Float(_)) => true,
+        (Equals | NotEquals, Int(_) | Float(_) | Bool(_), Int(_) | Float(_) | Bool(_)) => true,
+        (And | Or, Bool(_), Bool(_)) => true,
+        _ => false
+    }
+}
+
+///Removes code that can never run or whose result is never used:
+///statements after a break or continue, the branch of an if that is never taken, and pure statements like 'x;' or '1 + 2;'
+pub fn eliminate_dead_code(exp: Exp) -> Exp {
+    match map_children(exp, eliminate_dead_code) {
+        IfElseExp(cond, pos, neg, loc) => match (*cond, neg) {
+            (LiteralExp(Bool(true), _), _) => *pos,
+            (LiteralExp(Bool(false), _), Some(neg)) => *neg,
+            (LiteralExp(Bool(false), _), None) => LiteralExp(Unit, loc),
+            (cond, neg) => IfElseExp(Box::new(cond), pos, neg, loc)
+        },
+        BlockExp(exps, funs, loc) => {
+            let last = exps.len().saturating_sub(1);
+            let mut kept = Vec::new();
+            let mut diverged = false;
+            for (i, exp) in exps.into_iter().enumerate() {
+                //Declarations are kept, as the type checker has already given them a place in the block
+                let declaration = matches!(exp, FunDeclExp(_, _) | StructDeclExp(_, _, _) | InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _) | OperatorDeclExp(_, _));
+                if declaration || !diverged && (i == last || !is_pure(&exp)) {
+                    diverged |= diverges(&exp);
+                    kept.push(exp);
+                }
+            }
+            BlockExp(kept, funs, loc)
+        },
+        exp => exp
+    }
+}
+
+///Whether the expression always jumps out of the code around it with a break or continue
+pub fn diverges(exp: &Exp) -> bool {
+    match exp {
+        BreakExp(_, _) | ContinueExp(_, _) => true,
+        BlockExp(exps, _, _) => exps.iter().any(diverges),
+        IfElseExp(cond, pos, neg, _) => diverges(cond) || neg.as_ref().is_some_and(|neg| diverges(pos) && diverges(neg)),
+        LetExp(_, exp, _) => diverges(exp),
+        _ => false
+    }
+}
+
+///Whether evaluating the expression can have no effect and cannot fail, so it can be removed when its value is not used
+fn is_pure(exp: &Exp) -> bool {
+    match exp {
+        LiteralExp(_, _) | VarExp(_, _) => true,
+        BinOpExp(left, op, right, _) => matches!(op, LessThan | GreaterThan | Equals | NotEquals | LessOrEquals | GreaterOrEquals | And | Or)
+            && is_pure(left) && is_pure(right),
+        UnOpExp(Not, exp, _) => is_pure(exp),
+        OptionExp(exp, _) => exp.as_ref().is_none_or(|exp| is_pure(exp)),
+        TupleExp(exps, _) | ArrayExp(exps, _) => exps.iter().all(is_pure),
+        _ => false
+    }
+}
+
+///Rebuilds the expression with f applied to each of its direct subexpressions, including the bodies of functions declared in a block
+pub fn map_children(exp: Exp, f: impl Fn(Exp) -> Exp) -> Exp {
+    visit::fold_children(&mut Map(f), exp)
+}
+
+///A folder that applies the function to each expression it is given, and leaves going on into the children to it
+struct Map<F>(F);
+
+impl<F: Fn(Exp) -> Exp> Folder for Map<F> {
+    fn fold_exp(&mut self, exp: Exp) -> Exp {
+        (self.0)(exp)
+    }
+}
@@ -0,0 +1,147 @@
+use std::{fmt::Debug, io::{BufRead, BufReader, Write}, sync::{Arc, Mutex}, time::Duration};
+
+use lazy_static::lazy_static;
+
+use super::*;
+use eval::{DEFAULT_MAX_CALL_DEPTH, TraceSink};
+use builtins::SleepHandler;
+
+///What a program is allowed to do, and how far it can run before it is stopped
+#[derive(Debug, Clone)]
+pub struct ExecutionOptions {
+    ///Builtins that reach outside the interpreter, like files, environment variables and network access
+    pub io: bool,
+
+    ///random, random_int and shuffle
+    pub random: bool,
+
+    ///Builtins that read the clock, sleep or set timers
+    pub time: bool,
+
+    ///How many calls can be running at once. Calls in tail position do not count.
+    ///A program also stops with an error before it runs out of the stack of its thread
+    pub max_call_depth: usize,
+
+    ///How many steps the program can take, where every function call, loop iteration and element of a range is a step
+    pub fuel: Option<u64>,
+
+    ///How long the program can run, counted from when it starts
+    pub max_duration: Option<Duration>,
+
+    ///How many bytes the interpreter can have allocated. Only works with memory::CountingAllocator as the global allocator
+    pub max_memory: Option<usize>,
+
+    ///What int arithmetic does when the result does not fit in an int
+    pub overflow: Overflow,
+
+    ///Records the calls and time of each function, given by profiler::take_profile after the run
+    pub profile: bool,
+
+    ///Gets each statement the tree-walking interpreter evaluates, with its value. The VM does not trace
+    pub trace: Option<TraceSink>,
+
+    ///Records which statements and branches the tree-walking interpreter runs, given by coverage::take_coverage after the run
+    pub coverage: bool,
+
+    ///Where the program reads and writes, its arguments and how it sleeps
+    pub hooks: Hooks,
+}
+
+///Where print and println, or eprint and eprintln, write
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+///Where read_line and read_int read from
+pub type Input = Arc<Mutex<dyn BufRead + Send>>;
+
+///What the builtins of a run use to reach the host. Clones of the options share them
+#[derive(Clone)]
+pub struct Hooks {
+    ///Where print and println write, standard output by default. Replacing it captures what the program prints
+    pub output: Output,
+
+    ///Where eprint and eprintln write, standard error by default
+    pub error_output: Output,
+
+    ///Where read_line and read_int read from, standard input by default. Replacing it runs the program with given input
+    pub input: Input,
+
+    ///Arguments given to the program, which args gives
+    pub args: Vec<String>,
+
+    ///What sleep_ms does. It can skip or forbid the waits, and an error is given to the program as a runtime error
+    pub sleep: Arc<Mutex<SleepHandler>>,
+}
+
+lazy_static!(
+    ///Standard input, read through one buffer so no run loses what another read ahead
+    static ref STDIN: Input = Arc::new(Mutex::new(BufReader::new(std::io::stdin())));
+);
+
+impl Default for Hooks {
+    ///The standard streams, no arguments, and sleeping for real
+    fn default() -> Self {
+        Self {
+            output: Arc::new(Mutex::new(std::io::stdout())),
+            error_output: Arc::new(Mutex::new(std::io::stderr())),
+            input: STDIN.clone(),
+            args: Vec::new(),
+            sleep: Arc::new(Mutex::new(Box::new(|duration| {
+                std::thread::sleep(duration);
+                Ok(())
+            }))),
+        }
+    }
+}
+
+impl Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks").field("args", &self.args).finish_non_exhaustive()
+    }
+}
+
+///What happens when int arithmetic overflows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    ///It is a runtime error
+    Error,
+
+    ///It wraps around, so the largest int plus one is the smallest int
+    Wrap,
+
+    ///It stops at the largest or smallest int
+    Saturate,
+}
+
+impl Default for ExecutionOptions {
+    ///Everything is allowed, and only the call depth is limited
+    fn default() -> Self {
+        Self {
+            io: true,
+            random: true,
+            time: true,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel: None,
+            max_duration: None,
+            max_memory: None,
+            overflow: Overflow::Error,
+            profile: false,
+            trace: None,
+            coverage: false,
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+impl ExecutionOptions {
+    ///These options, but builtins that reach outside the interpreter are not allowed
+    pub fn sandboxed(self) -> Self {
+        Self { io: false, ..self }
+    }
+}
+
+///How source is parsed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    ///A newline ends a statement where a ';' could, as parser::newline_semicolons decides
+    pub newline_ends_statement: bool,
+}
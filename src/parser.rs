@@ -1,43 +1,60 @@
-use std::{iter::Peekable, slice::Iter};
+use std::{cell::RefCell, iter::Peekable, slice::Iter};
 
 use lazy_static::lazy_static;
 
 use super::*;
+use interner::{intern, release_unused, Symbol};
 
 use LexToken::*;
 use ast::Operator::*;
 
-type LexIter<'a> = Peekable<Iter<'a, (LexToken, Location)>>;
+///The tokens the parser reads, as given by 'LexedProgram::iter'
+pub type LexIter<'a> = Peekable<Iter<'a, (LexToken, Location)>>;
 type KeepRes = Result<Exp, (String, Location)>;
 type DiscardRes = Result<(), (String, Location)>;
 
+///Name, parameters, parameter types and return type of a function
+type Signature = (String, Vec<Symbol>, Vec<ast::Type>, ast::Type);
+
+///A function declaration, with the name and function it declares
+type FunDecl = (Exp, Symbol, Box<Function>);
+
 lazy_static!(//                                                  for
     ///All legal operators                                   [ comments ]
-    pub static ref OPERATORS: Vec<&'static str> = Vec::from([ "//", "/*" , "+=", "-=", "+", "-", "*", "/", "%", "<=", ">=", "<", ">", "!=", "!", "==", "=", "&&", "||"]);
+    pub static ref OPERATORS: Vec<&'static str> = Vec::from([ "//", "/*" , "+=", "-=", "+", "-", "*", "/", "%", "<=", ">=", "<", ">", "!=", "!", "==", "=", "&&", "||", "??", "...", ".."]);
+
+    ///Chars that are operators only to be part of declared operators, which can also be made of the ones above
+    pub static ref DECLARABLE_OPERATORS: Vec<&'static str> = Vec::from(["&", "|", "^", "~", "@", "$"]);
 
     ///All legal keywords
-    pub static ref KEYWORDS: Vec<&'static str> = Vec::from(["if", "else", "while", "for", "let", "fun"]);
+    pub static ref KEYWORDS: Vec<&'static str> = Vec::from(["if", "else", "while", "for", "let", "fun", "some", "none", "break", "continue", "struct", "interface", "impl", "in", "operator"]);
 
     ///All legal types
-    pub static ref TYPES: Vec<&'static str> = Vec::from(["int", "float", "bool", "char", "string", "unit"]);
+    pub static ref TYPES: Vec<&'static str> = Vec::from(["int", "float", "bool", "char", "string", "unit", "option", "set", "task", "chan"]);
 
     pub static ref UNARY_OPERATORS: Vec<ast::Operator> = vec![
         Minus,
         Not
     ];
 
-    //Precedence of binary operators
-    pub static ref BINARY_OP_PRECEDENCE: Vec<Vec<ast::Operator>> = vec![
+    //Precedence of binary operators, with the side operators of the same level group from,
+    //as in 'a - b - c' and 'a = b = c'
+    pub static ref BINARY_OP_PRECEDENCE: Vec<(Associativity, Vec<ast::Operator>)> = vec![
         //Binary
-        vec![Multiply, Divide, Modulo],  
-        vec![Plus, Minus],
-        vec![LessThan, GreaterThan, LessOrEquals, GreaterOrEquals],
-        vec![Equals, NotEquals],
-        vec![And],
-        vec![Or],
-        vec![Assign, PlusAssign, MinusAssign],
+        (Associativity::Left, vec![Multiply, Divide, Modulo]),
+        (Associativity::Left, vec![Plus, Minus]),
+        (Associativity::Left, vec![Range]),
+        (Associativity::Left, vec![LessThan, GreaterThan, LessOrEquals, GreaterOrEquals]),
+        (Associativity::Left, vec![Equals, NotEquals]),
+        (Associativity::Left, vec![And]),
+        (Associativity::Left, vec![Or]),
+        (Associativity::Right, vec![Coalesce]),
+        (Associativity::Right, vec![Assign, PlusAssign, MinusAssign]),
     ];
 
+    ///What a term can start with, as term decides, for errors
+    pub static ref TERM_STARTS: Vec<&'static str> = vec!["identifier", "literal", "'('", "'['", "'{'", "'if'", "'some'", "'none'", "'unit'"];
+
     pub static ref TERMINATORS: Vec<LexToken> = vec![
         SemiColon,
         Paren(')'),
@@ -49,136 +66,377 @@ lazy_static!(//                                                  for
     ];
 );
 
+thread_local! {
+    ///Errors of the statements that could not be parsed, as parsing goes on after them
+    static ERRORS: RefCell<Vec<(String, Location)>> = const { RefCell::new(Vec::new()) };
+
+    ///Operators declared before the token being parsed, the latest last
+    static DECLARED_OPERATORS: RefCell<Vec<CustomOperator>> = const { RefCell::new(Vec::new()) };
+}
+
 pub fn statement(lexed: &mut LexIter) -> KeepRes {
     if let Some((token, _)) = lexed.peek() {
         return match token {
             //Fun decls are handled in: parse_statements()
             Paren('{') =>        block(lexed),
-            Keyword("while") =>  wwhile(lexed),
-            Keyword("for") =>    ffor(lexed),
+            Keyword("while") =>  wwhile(lexed, None),
+            Keyword("for") =>    ffor(lexed, None),
             Keyword("let") =>    llet(lexed),
             Keyword("if") =>     iif(lexed),
+            Keyword("break")
+            | Keyword("continue") => break_or_continue(lexed),
+            Id(_) if label_follows(lexed) => labeled_loop(lexed),
             _ => expression(lexed)
         }
     }
 
-    unreachable!()
+    Err((String::from("Expected a statement"), curr_loc(lexed)?))
 }
 
 pub fn term(lexed: &mut LexIter) -> KeepRes {
     if let Some((token, _)) = lexed.peek() {
-        return match token {
+        let term = match token {
             Paren('{') =>                   block(lexed),
             Keyword("if") =>                iif(lexed),
-            Paren('(') =>                   parenthesized_exp(lexed),
+            Paren('(') =>                   parenthesized_or_tuple(lexed),
+            Paren('[') =>                   array(lexed),
             Int(_) | Float(_) | Bool(_)
             | Char(_) | Str(_) =>           literal(lexed),
             Id(_) =>                        var_or_fun_call(lexed),
+            Keyword("some")
+            | Keyword("none") =>            option(lexed),
+            Type("unit") =>                 unit(lexed),
+
+            _ => Err(expected(lexed, "a term", &TERM_STARTS))
+        };
+        return postfix(lexed, term?)
+    }
+
+    Err(expected(lexed, "a term", &TERM_STARTS))
+}
 
-            _ => Err((format!("Expected a term"), curr_loc(lexed)?))
+///Any number of '.field', '.method(EXP, ...)' and '[EXP]' after a term
+fn postfix(lexed: &mut LexIter, exp: Exp) -> KeepRes {
+    let mut exp = exp;
+    loop {
+        exp = match lexed.peek() {
+            Some((Dot, loc)) => {
+                lexed.next();
+                let field = id(lexed)?;
+                match lexed.peek() {
+                    Some((Paren('('), _)) => Exp::MethodCallExp(Box::new(exp), Symbol::new(&field), arguments(lexed)?, *loc),
+                    _ => Exp::FieldExp(Box::new(exp), field, *loc)
+                }
+            },
+            Some((Paren('['), loc)) => {
+                lexed.next();
+                let index = expression(lexed)?;
+                parenthesis(lexed, ']')?;
+                Exp::IndexExp(Box::new(exp), Box::new(index), *loc)
+            },
+            _ => return Ok(exp)
         };
     }
+}
+
+///Parses the program. A statement with an error is skipped, so the errors of all statements are given
+pub fn parse(lexed: &mut LexIter) -> Result<Exp, Vec<(String, Location)>> {
+    parse_with_operators(lexed, &[])
+}
+
+///Parses the program with operators declared before it, as in an earlier piece of source
+pub fn parse_with_operators(lexed: &mut LexIter, operators: &[CustomOperator]) -> Result<Exp, Vec<(String, Location)>> {
+    let loc = curr_loc(lexed).map_err(|err| vec![err])?;
+    join_top_level(loc, parse_top_level(lexed, operators))
+}
+
+///Parses the source as one expression, like a formula or a config value, without the statements of a program around it.
+///Anything after the expression is an error. When there are several errors, the first is given
+pub fn parse_expression(source: &str) -> Result<Exp, interpreter::Error> {
+    let lexed = lex(source).map_err(|(msg, loc)| interpreter::Error::Lex(msg, loc))?;
+    let lexed = &mut lexed.iter();
+    ERRORS.with(|errors| errors.borrow_mut().clear());
+    release_unused();
+    DECLARED_OPERATORS.with(|declared| declared.borrow_mut().clear());
+    let exp = expression(lexed).and_then(|exp| match lexed.peek() {
+        Some((EndOfInput, _)) | None => Ok(exp),
+        Some(_) => Err(expected(lexed, "the end of the expression", &[]))
+    });
+    //Errors in the statements of a block in the expression are before where parsing stopped
+    match (ERRORS.with(|errors| errors.take()).into_iter().next(), exp) {
+        (Some((msg, loc)), _) | (None, Err((msg, loc))) => Err(interpreter::Error::Parse(msg, loc)),
+        (None, Ok(exp)) => Ok(exp)
+    }
+}
+
+///Parses the program with the options
+pub fn parse_with_options(lexed: &mut LexIter, options: ParseOptions) -> Result<Exp, Vec<(String, Location)>> {
+    if !options.newline_ends_statement {
+        return parse(lexed)
+    }
+    let tokens = newline_semicolons(lexed);
+    parse(&mut tokens.iter().peekable())
+}
 
-    unreachable!();
+///The tokens with a ';' after each line that ends a statement. A line ends one when its last token can end an expression,
+///which is an identifier, a literal, a type, ')', ']', '}', 'break', 'continue' or 'none', and the next line does not go on with it
+///by starting with an operator, '.', ',', ':', 'else' or a closing bracket. Newlines in '(' and '[' never end a statement,
+///and neither does one after the condition of an if, while or for, so the '{' of a block after anything else must be on the same line
+pub fn newline_semicolons(lexed: &mut LexIter) -> Vec<(LexToken, Location)> {
+    let mut tokens: Vec<(LexToken, Location)> = Vec::new();
+
+    //The brackets the token is in, and whether each is the condition of an if, while or for
+    let mut open: Vec<(char, bool)> = Vec::new();
+    //Whether the last token can end a statement
+    let mut ends = false;
+    for (token, loc) in lexed.by_ref() {
+        if let Some((_, last)) = tokens.last() {
+            let goes_on = matches!(token, Operator(_) | Dot | Comma | Colon | SemiColon | Keyword("else") | Paren(')' | ']' | '}') | EndOfInput);
+            let in_statements = open.last().is_none_or(|(paren, _)| *paren == '{');
+            if ends && last.end_line < loc.line && !goes_on && in_statements {
+                tokens.push((SemiColon, Location::point(last.end_line, last.end_col)));
+            }
+        }
+        ends = match token {
+            Paren(paren @ ('(' | '[' | '{')) => {
+                let condition = *paren == '(' && matches!(tokens.last(), Some((Keyword("if" | "while" | "for"), _)));
+                open.push((*paren, condition));
+                false
+            },
+            Paren(')' | ']' | '}') => open.pop().is_none_or(|(_, condition)| !condition),
+            Id(_) | Int(_) | Float(_) | Bool(_) | Char(_) | Str(_) | Type(_) | Keyword("break" | "continue" | "none") => true,
+            _ => false
+        };
+        tokens.push((token.clone(), *loc));
+    }
+    tokens
 }
 
-pub fn parse(lexed: &mut LexIter) -> KeepRes {
-    parse_statements(lexed)
+///The program made of the top-level statements, or the errors of all of them
+pub fn join_top_level(loc: Location, statements: impl IntoIterator<Item = TopLevel>) -> Result<Exp, Vec<(String, Location)>> {
+    let mut exps = Vec::new();
+    let mut funs = Vec::new();
+    let mut errors = Vec::new();
+    for statement in statements {
+        exps.extend(statement.exps);
+        funs.extend(statement.funs);
+        errors.extend(statement.errors);
+    }
+    match errors.is_empty() {
+        true => Ok(Exp::BlockExp(exps, funs, loc)),
+        false => Err(errors)
+    }
 }
 
-fn parse_statements(lexed: &mut LexIter) -> KeepRes {
+fn parse_statements(lexed: &mut LexIter, in_block: bool) -> KeepRes {
     let loc = curr_loc(lexed)?;
 
     let mut exps: Vec<Exp> = Vec::new();
-    let mut funs: Vec<(String, Box<Function>)> = Vec::new();
+    let mut funs: Vec<(Symbol, Box<Function>)> = Vec::new();
 
     while !terminator(lexed) {
-        match lexed.peek() {
-            Some((Keyword("fun"), _)) => {
-                let decl = fun_decl(lexed)?;
-                exps.push(decl.0);
-                funs.push((decl.1, decl.2));
-            },
-            _ => exps.push(statement(lexed)?)
-        }
-        
-        match semi_colon(lexed) { _ => {} } //Just discard the semicolon if it is present
+        statement_or_decl(lexed, in_block, &mut exps, &mut funs);
     }
 
     Ok(Exp::BlockExp(exps, funs, loc))
 }
 
-#[derive(Clone, Debug)]
-enum Term {
-    ExpTerm(Exp),
-    OpTerm(ast::Operator, Location)
+///Parses a statement or declaration with the ';' after it, adding what it declares. An error is kept, and the statement skipped
+fn statement_or_decl(lexed: &mut LexIter, in_block: bool, exps: &mut Vec<Exp>, funs: &mut Vec<(Symbol, Box<Function>)>) {
+    let res = match lexed.peek() {
+        Some((Keyword("fun"), _)) => fun_decl(lexed).map(|decl| {
+            exps.push(decl.0);
+            funs.push((decl.1, decl.2));
+        }),
+        Some((Keyword("struct"), _)) => struct_decl(lexed).map(|decl| exps.push(decl)),
+        Some((Keyword("interface"), _)) => interface_decl(lexed).map(|decl| exps.push(decl)),
+        //They change how the rest of the source is parsed, so they cannot be in a block
+        Some((Keyword("operator"), loc)) if in_block => Err((String::from("Operators can only be declared at the top level"), *loc)),
+        Some((Keyword("operator"), _)) => operator_decl(lexed).map(|decl| exps.push(decl)),
+        Some((Keyword("impl"), _)) => impl_decl(lexed).map(|(imp, methods)| {
+            exps.push(imp);
+            for decl in methods {
+                exps.push(decl.0);
+                funs.push((decl.1, decl.2));
+            }
+        }),
+        _ => statement(lexed).map(|exp| exps.push(exp))
+    };
+    if let Err(err) = res {
+        ERRORS.with(|errors| errors.borrow_mut().push(err));
+        skip_statement(lexed, in_block);
+    }
+
+    //Just discard the semicolon if it is present
+    let _ = semi_colon(lexed);
 }
 
-fn expression(lexed: &mut LexIter) -> KeepRes {
-    let mut terms: Vec<Term> = Vec::new();
+///A top-level statement parsed on its own, with what it declares, its errors and how many tokens it took
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopLevel {
+    pub exps: Vec<Exp>,
+    pub funs: Vec<(Symbol, Box<Function>)>,
+    pub errors: Vec<(String, Location)>,
+    pub tokens: usize,
+}
 
-    //Collect terms
-    while !terminator(lexed) {
-        if let Some((Operator(_), loc)) = lexed.peek() {
-            terms.push(Term::OpTerm(any_operator(lexed)?, *loc))
+///Parses the program one top-level statement at a time. A token that cannot start a statement, like a '}' with nothing to close, is a statement with an error
+pub fn parse_top_level(lexed: &mut LexIter, operators: &[CustomOperator]) -> Vec<TopLevel> {
+    ERRORS.with(|errors| errors.borrow_mut().clear());
+    release_unused();
+    DECLARED_OPERATORS.with(|declared| *declared.borrow_mut() = operators.to_vec());
+    let mut statements = Vec::new();
+    while let Some((token, loc)) = lexed.peek() {
+        if *token == EndOfInput {
+            break
+        }
+        let before = lexed.len();
+        let mut exps = Vec::new();
+        let mut funs = Vec::new();
+        if terminator(lexed) {
+            ERRORS.with(|errors| errors.borrow_mut().push((format!("Unexpected {}", describe(token)), *loc)));
+            lexed.next();
         } else {
-            if let Some(Term::ExpTerm(_)) = terms.last() {
-                if terms.len() > 0 {
-                    return Err((format!("Expected operator or ';'"), curr_loc(lexed)?))
-                }
-            }
-    
-            terms.push(Term::ExpTerm(term(lexed)?))
+            statement_or_decl(lexed, false, &mut exps, &mut funs);
         }
+        let errors = ERRORS.with(|errors| errors.take());
+        statements.push(TopLevel { exps, funs, errors, tokens: before - lexed.len() });
     }
+    statements
+}
 
-    if terms.len() == 0 {
-        return Err((format!("Expected something"), curr_loc(lexed)?));
+///Skips the rest of a statement with an error, up to the next ';' or the end of a block in it.
+///The '}' of the block the statement is in is left to end that block
+fn skip_statement(lexed: &mut LexIter, in_block: bool) {
+    let mut depth: usize = 0;
+    while let Some((token, _)) = lexed.peek() {
+        match token {
+            EndOfInput => return,
+            SemiColon if depth == 0 => return,
+            Paren('}') if depth == 0 && in_block => return,
+            Paren('(' | '[' | '{') => depth += 1,
+            Paren(')' | ']' | '}') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        let closed = matches!(token, Paren('}')) && depth == 0;
+        lexed.next();
+        if closed && !matches!(lexed.peek(), Some((Keyword("else"), _))) {
+            return
+        }
     }
+}
 
-    //Establish precedence
-    Ok(precedence(terms.as_slice())?)
+///A token as it is written, for errors
+fn describe(token: &LexToken) -> String {
+    match token {
+        Paren(c) | Char(c) => format!("'{c}'"),
+        SemiColon => String::from("';'"),
+        Colon => String::from("':'"),
+        Comma => String::from("','"),
+        Dot => String::from("'.'"),
+        Operator(s) | Keyword(s) | Type(s) => format!("'{s}'"),
+        Id(s) => format!("'{s}'"),
+        Str(s) => format!("'\"{s}\"'"),
+        Int(i) => format!("'{i}'"),
+        Float(f) => format!("'{f}'"),
+        Bool(b) => format!("'{b}'"),
+        Error(_) => String::from("text that is not a token"),
+        EndOfInput => String::from("end of input")
+    }
 }
 
-fn precedence(terms: &[Term]) -> KeepRes {
-    //There should not be an operator last
-    if let Some(Term::OpTerm(op, loc)) = terms.last() {
-        return Err((format!("Unexpected operator '{op}'"), *loc))
+fn expression(lexed: &mut LexIter) -> KeepRes {
+    if terminator(lexed) {
+        let unary: Vec<String> = UNARY_OPERATORS.iter().map(|op| format!("'{op}'")).collect();
+        let starts: Vec<&str> = TERM_STARTS.iter().copied().chain(unary.iter().map(String::as_str)).collect();
+        return Err(expected(lexed, "an expression", &starts));
     }
 
-    if terms.len() == 1 {
-        if let Some(Term::ExpTerm(exp)) = terms.first() {
-            return Ok(exp.clone());
-        }
+    binary(lexed, BINARY_OP_PRECEDENCE.len())
+}
+
+///Level of a binary operator in BINARY_OP_PRECEDENCE, lower levels bind tighter
+fn binary_level(op: &ast::Operator) -> Option<usize> {
+    BINARY_OP_PRECEDENCE.iter().position(|(_, operators)| operators.contains(op))
+}
+
+///The level the right side of an operator of the level is read below
+fn right_level(op_level: usize, associativity: Associativity) -> usize {
+    match associativity {
+        Associativity::Left => op_level,
+        Associativity::Right => op_level + 1
     }
+}
+
+///An expression with binary operators below the level, read in one pass by precedence climbing.
+///Each operator takes only tighter operators on its right, so operators of the same level apply left to right,
+///unless they are right associative
+fn binary(lexed: &mut LexIter, level: usize) -> KeepRes {
+    let mut exp = unary(lexed)?;
 
-    //Binary operators
-    for operators in BINARY_OP_PRECEDENCE.iter().rev() {
-        let mut iter = terms.iter().enumerate();
+    while !terminator(lexed) {
+        match lexed.peek() {
+            Some((Operator(_), loc)) => {
+                let loc = *loc;
+                if let Some((custom, tokens)) = declared_operator(lexed) {
+                    let op_level = custom.level - 1;
+                    if op_level >= level {
+                        return Ok(exp)
+                    }
+                    lexed.nth(tokens - 1);
+                    if terminator(lexed) {
+                        return Err((format!("Unexpected operator '{}'", custom.symbol), loc))
+                    }
+                    let right = binary(lexed, right_level(op_level, custom.associativity))?;
+                    exp = Exp::FunCallExp(custom.fun, vec![exp, right], loc);
+                    continue
+                }
 
-        while let Some(term) = iter.next() {
-            if let (i, Term::OpTerm(op, loc)) = term {
-                if operators.contains(op) && i != 0 {
-                    let split = terms.split_at(i);
-                    return Ok(Exp::BinOpExp(Box::new(precedence(split.0)?), *op, Box::new(precedence(&split.1[1..])?), *loc));
+                //Look at the operator without taking it, as a looser one belongs to an outer level
+                let op = any_operator(&mut lexed.clone())?;
+                let op_level = match binary_level(&op) {
+                    Some(op_level) => op_level,
+                    None => return Err((format!("Not a binary operator '{op}'"), loc))
+                };
+                if op_level >= level {
+                    return Ok(exp)
                 }
+                any_operator(lexed)?;
+                if terminator(lexed) {
+                    return Err((format!("Unexpected operator '{op}'"), loc))
+                }
+                let right = binary(lexed, right_level(op_level, BINARY_OP_PRECEDENCE[op_level].0))?;
+                exp = Exp::BinOpExp(Box::new(exp), op, Box::new(right), loc);
+            },
+            //'for' and 'if' after a term continue an array comprehension
+            Some((Keyword("for") | Keyword("if"), _)) => return Ok(exp),
+            _ => {
+                let ends: Vec<String> = TERMINATORS.iter().map(describe).collect();
+                let options: Vec<&str> = ["operator"].into_iter().chain(ends.iter().map(String::as_str)).collect();
+                return Err(expected(lexed, "an operator or the end of the expression", &options))
             }
         }
     }
 
-    //Unary operators
-    if let Some(Term::OpTerm(op, loc)) = terms.first() {
-        if !UNARY_OPERATORS.contains(op) {
-            return Err((format!("Not a unary operator '{op}'"), *loc))
-        }
-        return Ok(Exp::UnOpExp(*op, Box::new(precedence(&terms[1..])?), *loc));
-    } else if let Some(Term::ExpTerm(_)) = terms.first() {
-        if let Term::OpTerm(op, loc) = terms[1] {
-            return Err((format!("Not a binary operator '{op}'"), loc))
-        }
-    }
+    Ok(exp)
+}
 
-    unreachable!()
+///A term after any number of unary operators
+fn unary(lexed: &mut LexIter) -> KeepRes {
+    match lexed.peek() {
+        Some((Operator(_), loc)) => {
+            let loc = *loc;
+            let op = any_operator(lexed)?;
+            if !UNARY_OPERATORS.contains(&op) {
+                return Err((format!("Not a unary operator '{op}'"), loc))
+            }
+            if terminator(lexed) {
+                return Err((format!("Unexpected operator '{op}'"), loc))
+            }
+            Ok(Exp::UnOpExp(op, Box::new(unary(lexed)?), loc))
+        },
+        _ => term(lexed)
+    }
 }
 
 fn iif(lexed: &mut LexIter) -> KeepRes {
@@ -201,29 +459,113 @@ fn llet(lexed: &mut LexIter) -> KeepRes {
     let loc = curr_loc(lexed)?;
 
     keyword(lexed, "let")?;
-    let id = id(lexed)?;
+    let pattern = pattern(lexed)?;
     operator(lexed, Assign)?;
     let exp = expression(lexed)?;
 
-    Ok(Exp::LetExp(id.clone(), Box::new(exp), loc))
+    match pattern {
+        Pattern::Id(id) => Ok(Exp::LetExp(id, Box::new(exp), loc)),
+        pattern => Ok(Exp::DestructExp(pattern, Box::new(exp), loc))
+    }
+}
+
+fn pattern(lexed: &mut LexIter) -> Result<Pattern, (String, Location)> {
+    if parenthesis(lexed, '(').is_ok() {
+        let mut patterns = Vec::new();
+        loop {
+            patterns.push(pattern(lexed)?);
+            if comma(lexed).is_err() {
+                break
+            }
+        }
+        parenthesis(lexed, ')')?;
+        return Ok(Pattern::Tuple(patterns))
+    }
+
+    let id = id(lexed)?;
+    if parenthesis(lexed, '{').is_err() {
+        return Ok(Pattern::Id(Symbol::new(&id)))
+    }
+
+    //Struct pattern. 'field' is short for 'field: field'
+    let mut fields = Vec::new();
+    while let Ok(field) = self::id(lexed) {
+        let pattern = match colon(lexed) {
+            Ok(_) => pattern(lexed)?,
+            Err(_) => Pattern::Id(Symbol::new(&field))
+        };
+        fields.push((field, pattern));
+
+        if comma(lexed).is_err() {
+            break
+        }
+    }
+    parenthesis(lexed, '}')?;
+    Ok(Pattern::Struct(id, fields))
 }
 
-fn wwhile(lexed: &mut LexIter) -> KeepRes {
+fn wwhile(lexed: &mut LexIter, label: Option<String>) -> KeepRes {
     let loc = curr_loc(lexed)?;
 
     keyword(lexed, "while")?;
     let cond = parenthesized_exp(lexed)?;
     let exp = statement(lexed)?;
 
-    Ok(Exp::WhileExp(Box::new(cond), Box::new(exp), loc))
+    Ok(Exp::WhileExp(Box::new(cond), Box::new(exp), label, loc))
+}
+
+///Checks for 'ID:' without consuming anything
+fn label_follows(lexed: &mut LexIter) -> bool {
+    let mut ahead = lexed.clone();
+    ahead.next();
+    matches!(ahead.peek(), Some((Colon, _)))
+}
+
+fn labeled_loop(lexed: &mut LexIter) -> KeepRes {
+    let label = id(lexed)?;
+    colon(lexed)?;
+
+    match lexed.peek() {
+        Some((Keyword("while"), _)) => wwhile(lexed, Some(label)),
+        Some((Keyword("for"), _)) => ffor(lexed, Some(label)),
+        _ => Err((format!("Only loops can be labeled, '{label}' must be followed by while or for"), curr_loc(lexed)?))
+    }
+}
+
+fn break_or_continue(lexed: &mut LexIter) -> KeepRes {
+    let loc = curr_loc(lexed)?;
+
+    let is_break = keyword(lexed, "break").is_ok();
+    if !is_break {
+        keyword(lexed, "continue")?;
+    }
+
+    let label = match lexed.peek() {
+        Some((Id(_), _)) => Some(id(lexed)?),
+        _ => None
+    };
+
+    if is_break {
+        Ok(Exp::BreakExp(label, loc))
+    } else {
+        Ok(Exp::ContinueExp(label, loc))
+    }
 }
 
-fn ffor(lexed: &mut LexIter) -> KeepRes {
+fn ffor(lexed: &mut LexIter, label: Option<String>) -> KeepRes {
     let loc = curr_loc(lexed)?;
 
     keyword(lexed, "for")?;
     parenthesis(lexed, '(')?;
-    if let Ok(id) = id(lexed) {
+    if let Ok(id) = symbol(lexed) {
+        //For each element of an array or set
+        if keyword(lexed, "in").is_ok() {
+            let collection = expression(lexed)?;
+            parenthesis(lexed, ')')?;
+            let body = statement(lexed)?;
+            return Ok(Exp::ForInExp(id, Box::new(collection), Box::new(body), label, loc))
+        }
+
         //Normal for loop
         comma(lexed)?;
 
@@ -239,10 +581,9 @@ fn ffor(lexed: &mut LexIter) -> KeepRes {
                 from_f = i;
                 from_loc = loc
             },
-            Exp::LiteralExp(lit, loc) => return Err((format!("From in for must be int or float, got '{lit}'"), loc)),
-            _ => unreachable!("Unreachable because it it parsed to a literal")
+            from => return Err((format!("From in for must be int or float, got '{from}'"), from.loc()))
         };
-        let let_exp = Box::new(Exp::LetExp(id.clone(), Box::new(from), from_loc));
+        let let_exp = Box::new(Exp::LetExp(id, Box::new(from), from_loc));
 
         comma(lexed)?;
 
@@ -258,32 +599,26 @@ fn ffor(lexed: &mut LexIter) -> KeepRes {
                 to_f = i;
                 to_loc = loc
             },
-            Exp::LiteralExp(lit, loc) => return Err((format!("To in for must be int or float, got '{lit}'"), loc)),
-            _ => unreachable!("Unreachable because it it parsed to a literal")
+            to => return Err((format!("To in for must be int or float, got '{to}'"), to.loc()))
         };
 
-        let op;
-        let inc;
-        if to_f > from_f {
-            op = ast::Operator::LessThan;
-            inc = 1;
-        } else {
-            op = ast::Operator::GreaterThan;
-            inc = -1;
-        }
-        let cond = Box::new(Exp::BinOpExp(Box::new(Exp::VarExp(id.clone(), from_loc)), op, Box::new(to), to_loc));
+        //Counting down subtracts the step, so the step is kept as it is written
+        let (op, assign) = match to_f > from_f {
+            true => (ast::Operator::LessThan, ast::Operator::PlusAssign),
+            false => (ast::Operator::GreaterThan, ast::Operator::MinusAssign)
+        };
+        let cond = Box::new(Exp::BinOpExp(Box::new(Exp::VarExp(id, from_loc)), op, Box::new(to), to_loc));
 
-        let inc_exp = if let Ok(_) = comma(lexed) {
-            let mut exp = expression(lexed)?;
-            if inc < 0 {
-                exp = Exp::UnOpExp(ast::Operator::Minus, Box::new(exp), loc)
-            }
-            Box::new(exp)
-        } else {
-            Box::new(Exp::LiteralExp(Literal::Int(inc), to_loc))
+        let (step, step_loc) = match comma(lexed) {
+            Ok(_) => {
+                let step = expression(lexed)?;
+                let step_loc = step.loc();
+                (step, step_loc)
+            },
+            Err(_) => (Exp::LiteralExp(Literal::Int(1), to_loc), to_loc)
         };
 
-        let increment = Box::new(Exp::BinOpExp(Box::new(Exp::VarExp(id, to_loc)), ast::Operator::PlusAssign, inc_exp, to_loc));
+        let increment = Box::new(Exp::BinOpExp(Box::new(Exp::VarExp(id, step_loc)), assign, Box::new(step), step_loc));
 
 
         parenthesis(lexed, ')')?;
@@ -294,13 +629,14 @@ fn ffor(lexed: &mut LexIter) -> KeepRes {
             cond,
             increment,
             body,
+            label,
             loc
         ))
     } else {
         //Simple for loop
-        let id = format!(".for");
-        let let_exp = Box::new(Exp::LetExp(id.clone(), Box::new(Exp::LiteralExp(Literal::Int(0), loc)), loc));
-        let cond = Box::new(Exp::BinOpExp(Box::new(Exp::VarExp(id.clone(), loc)), ast::Operator::LessThan, Box::new(expression(lexed)?), loc));
+        let id = Symbol::new(".for");
+        let let_exp = Box::new(Exp::LetExp(id, Box::new(Exp::LiteralExp(Literal::Int(0), loc)), loc));
+        let cond = Box::new(Exp::BinOpExp(Box::new(Exp::VarExp(id, loc)), ast::Operator::LessThan, Box::new(expression(lexed)?), loc));
         let increment = Box::new(Exp::BinOpExp(Box::new(Exp::VarExp(id, loc)), ast::Operator::PlusAssign, Box::new(Exp::LiteralExp(Literal::Int(1), loc)), loc));
         parenthesis(lexed, ')')?;
         let body = Box::new(statement(lexed)?);
@@ -310,6 +646,7 @@ fn ffor(lexed: &mut LexIter) -> KeepRes {
             cond,
             increment,
             body,
+            label,
             loc
         ))
     }
@@ -317,11 +654,95 @@ fn ffor(lexed: &mut LexIter) -> KeepRes {
 
 fn block(lexed: &mut LexIter) -> KeepRes {
     parenthesis(lexed, '{')?;
-    let block = parse_statements(lexed)?;
+    let block = parse_statements(lexed, true)?;
     parenthesis(lexed, '}')?;
     Ok(block)
 }
 
+fn parenthesized_or_tuple(lexed: &mut LexIter) -> KeepRes {
+    let loc = curr_loc(lexed)?;
+
+    parenthesis(lexed, '(')?;
+    if parenthesis(lexed, ')').is_ok() {
+        return Ok(Exp::LiteralExp(Literal::Unit, loc))
+    }
+
+    let exp = expression(lexed)?;
+    if comma(lexed).is_err() {
+        parenthesis(lexed, ')')?;
+        return Ok(exp)
+    }
+
+    let mut exps = vec![exp];
+    while !terminator(lexed) {
+        exps.push(expression(lexed)?);
+        if comma(lexed).is_err() {
+            break
+        }
+    }
+    parenthesis(lexed, ')')?;
+
+    Ok(Exp::TupleExp(exps, loc))
+}
+
+///'[EXP, ...]' or '[EXP for ID in EXP if EXP]'
+fn array(lexed: &mut LexIter) -> KeepRes {
+    let loc = curr_loc(lexed)?;
+    parenthesis(lexed, '[')?;
+
+    if terminator(lexed) {
+        parenthesis(lexed, ']')?;
+        return Ok(Exp::ArrayExp(Vec::new(), loc))
+    }
+    let first = element(lexed)?;
+    if let Some((Keyword("for"), _)) = lexed.peek() {
+        return comprehension(lexed, first, loc)
+    }
+
+    let mut exps = vec![first];
+    while comma(lexed).is_ok() {
+        if terminator(lexed) {
+            break
+        }
+        exps.push(element(lexed)?);
+    }
+    parenthesis(lexed, ']')?;
+
+    Ok(Exp::ArrayExp(exps, loc))
+}
+
+///The rest of '[EXP for ID in EXP if EXP]', where the condition is optional.
+///It becomes '{ let .comprehension = []; for(ID in EXP) if(EXP) push(.comprehension, EXP); .comprehension }'
+fn comprehension(lexed: &mut LexIter, exp: Exp, loc: Location) -> KeepRes {
+    keyword(lexed, "for")?;
+    let id = symbol(lexed)?;
+    keyword(lexed, "in")?;
+    let collection = expression(lexed)?;
+    let cond = match keyword(lexed, "if") {
+        Ok(_) => Some(expression(lexed)?),
+        Err(_) => None
+    };
+    parenthesis(lexed, ']')?;
+
+    let res = Symbol::new(".comprehension");
+    let push = Exp::FunCallExp(Symbol::new("push"), vec![Exp::VarExp(res, loc), exp], loc);
+    let body = match cond {
+        Some(cond) => Exp::IfElseExp(Box::new(cond), Box::new(push), None, loc),
+        None => push
+    };
+    Ok(Exp::BlockExp(vec![
+        Exp::LetExp(res, Box::new(Exp::ArrayExp(Vec::new(), loc)), loc),
+        Exp::ForInExp(id, Box::new(collection), Box::new(body), None, loc),
+        Exp::VarExp(res, loc)
+    ], Vec::new(), loc))
+}
+
+fn unit(lexed: &mut LexIter) -> KeepRes {
+    let loc = curr_loc(lexed)?;
+    lexed.next();
+    Ok(Exp::LiteralExp(Literal::Unit, loc))
+}
+
 fn parenthesized_exp(lexed: &mut LexIter) -> KeepRes {
     parenthesis(lexed, '(')?;
     let exp = expression(lexed)?;
@@ -350,23 +771,21 @@ fn any_operator(lexed: &mut LexIter) -> Result<ast::Operator, (String, Location)
                 "&&" => ast::Operator::And,
                 "||" => ast::Operator::Or,
                 "!=" => ast::Operator::NotEquals,
-                _ => return Err((format!("Unknown operator: '{op}"), *loc))
+                "??" => ast::Operator::Coalesce,
+                ".." => ast::Operator::Range,
+                _ => return Err((format!("Unknown operator: '{op}'"), *loc))
             };
             lexed.next();
             Ok(res)
         },
-        _ => Err((format!("Expected an operator"), curr_loc(lexed)?))
+        _ => Err(expected(lexed, "an operator", &[]))
     }
 }
 
 fn operator(lexed: &mut LexIter, operator: ast::Operator) -> Result<ast::Operator, (String, Location)> {
-    match any_operator(lexed) {
-        Ok(actual) => if actual == operator {
-            Ok(actual)
-        } else {
-            Err((format!("Expected '{operator}', got {actual}"), curr_loc(lexed)?))
-        },
-        Err(_) => Err((format!("Expected '{operator}'"), curr_loc(lexed)?)),
+    match any_operator(&mut lexed.clone()) {
+        Ok(actual) if actual == operator => any_operator(lexed),
+        _ => Err(expected(lexed, &format!("'{operator}'"), &[]))
     }
 }
 
@@ -376,9 +795,9 @@ fn keyword(lexed: &mut LexIter, keyword: &str) -> DiscardRes {
             lexed.next();
             Ok(())
         } else {
-            Err((format!(""), curr_loc(lexed)?))
+            Err(expected(lexed, &format!("'{keyword}'"), &[]))
         },
-        _ => Err((format!("Expected {keyword}"), curr_loc(lexed)?))
+        _ => Err(expected(lexed, &format!("'{keyword}'"), &[]))
     }
 }
 
@@ -388,72 +807,294 @@ fn id(lexed: &mut LexIter) -> Result<String, (String, Location)> {
             lexed.next();
             Ok(id.clone())
         },
-        _ => Err((format!("Expected an identifier"), curr_loc(lexed)?)),
+        _ => Err(expected(lexed, "an identifier", &[])),
     }
 }
 
+///The name of a variable or function
+fn symbol(lexed: &mut LexIter) -> Result<Symbol, (String, Location)> {
+    id(lexed).map(|id| Symbol::new(&id))
+}
+
 fn var_or_fun_call(lexed: &mut LexIter) -> KeepRes {
     let loc = curr_loc(lexed)?;
     let id = id(lexed)?;
 
-    match lexed.peek() {
-        Some((Paren('('), _)) => {
-            parenthesis(lexed, '(')?;
+    //Struct literal
+    if struct_follows(lexed) {
+        parenthesis(lexed, '{')?;
 
-            let mut params = Vec::new();
-            loop {
-                if terminator(lexed) {
-                    break
-                }
-                let param = expression(lexed)?;
-                params.push(param);
-                let _ = comma(lexed);
+        let mut fields = Vec::new();
+        while let Ok(field) = self::id(lexed) {
+            colon(lexed)?;
+            fields.push((field, expression(lexed)?));
+
+            if comma(lexed).is_err() {
+                break
             }
-            parenthesis(lexed, ')')?;
+        }
+        parenthesis(lexed, '}')?;
+
+        return Ok(Exp::StructExp(id, fields, loc))
+    }
+
+    match lexed.peek() {
+        Some((Paren('('), _)) => Ok(Exp::FunCallExp(Symbol::new(&id), arguments(lexed)?, loc)),
+        _ => Ok(Exp::VarExp(Symbol::new(&id), loc)),
+    }
+}
+
+///'(EXP, ...)' of a call
+fn arguments(lexed: &mut LexIter) -> Result<Vec<Exp>, (String, Location)> {
+    parenthesis(lexed, '(')?;
 
-            Ok(Exp::FunCallExp(id, params, loc))
+    let mut params = Vec::new();
+    loop {
+        if terminator(lexed) {
+            break
+        }
+        let param = element(lexed)?;
+        params.push(param);
+        let _ = comma(lexed);
+    }
+    parenthesis(lexed, ')')?;
+
+    Ok(params)
+}
+
+///EXP, or '...EXP' to spread an array or tuple into an array literal or call
+fn element(lexed: &mut LexIter) -> KeepRes {
+    match lexed.peek() {
+        Some((Operator("..."), loc)) => {
+            lexed.next();
+            Ok(Exp::SpreadExp(Box::new(expression(lexed)?), *loc))
         },
-        _ => Ok(Exp::VarExp(id, loc)),
+        _ => expression(lexed)
+    }
+}
+
+///Checks for '{}' or '{ ID:' without consuming anything
+fn struct_follows(lexed: &mut LexIter) -> bool {
+    let mut ahead = lexed.clone();
+    if !matches!(ahead.next(), Some((Paren('{'), _))) {
+        return false
+    }
+    match ahead.next() {
+        Some((Paren('}'), _)) => true,
+        Some((Id(_), _)) => matches!(ahead.next(), Some((Colon, _))),
+        _ => false
+    }
+}
+
+fn struct_decl(lexed: &mut LexIter) -> KeepRes {
+    let loc = curr_loc(lexed)?;
+    keyword(lexed, "struct")?;
+    let name = id(lexed)?;
+    parenthesis(lexed, '{')?;
+
+    let mut fields = Vec::new();
+    while let Ok(field) = id(lexed) {
+        colon(lexed)?;
+        fields.push((field, any_type(lexed)?));
+
+        if comma(lexed).is_err() {
+            break
+        }
     }
+    parenthesis(lexed, '}')?;
+
+    Ok(Exp::StructDeclExp(name, fields, loc))
 }
 
-fn fun_decl(lexed: &mut LexIter) -> Result<(Exp, String, Box<Function>), (String, Location)> {
+fn option(lexed: &mut LexIter) -> KeepRes {
     let loc = curr_loc(lexed)?;
+
+    match keyword(lexed, "none") {
+        Ok(_) => Ok(Exp::OptionExp(None, loc)),
+        Err(_) => {
+            keyword(lexed, "some")?;
+            let exp = parenthesized_exp(lexed)?;
+            Ok(Exp::OptionExp(Some(Box::new(exp)), loc))
+        }
+    }
+}
+
+fn fun_decl(lexed: &mut LexIter) -> Result<FunDecl, (String, Location)> {
+    let loc = curr_loc(lexed)?;
+    let (name, params, p_types, return_type) = fun_signature(lexed, None)?;
+
+    operator(lexed, Assign)?;
+
+    let exp = statement(lexed)?;
+
+    let name = Symbol::new(&name);
+    let func = Function {
+        name,
+        ret_type: return_type,
+        param_types: p_types,
+        params,
+        exp: Box::new(exp),
+        loc
+    };
+
+    Ok((Exp::FunDeclExp(name, loc), name, Box::new(func)))
+}
+
+///Name, parameters, parameter types and return type of 'fun ID(ID: TYPE, ...): TYPE'.
+///With a receiver, the first parameter must be an untyped 'self' of the receiver type
+fn fun_signature(lexed: &mut LexIter, receiver: Option<&String>) -> Result<Signature, (String, Location)> {
     keyword(lexed, "fun")?;
     let name = id(lexed)?;
     parenthesis(lexed, '(')?;
     let mut params = Vec::new();
     let mut p_types = Vec::new();
-    while let Ok(param) = id(lexed) {
+
+    if let Some(receiver) = receiver {
+        match lexed.peek() {
+            Some((Id(id), _)) if id == "self" => {
+                lexed.next();
+                params.push(Symbol::new("self"));
+                p_types.push(ast::Type::Struct(receiver.clone()));
+            },
+            _ => return Err((format!("Expected 'self' as the first parameter of method '{name}'"), curr_loc(lexed)?))
+        }
+        let _ = comma(lexed);
+    }
+
+    while let Ok(param) = symbol(lexed) {
         params.push(param);
         colon(lexed)?;
         p_types.push(any_type(lexed)?);
 
-        if let Err(_) = comma(lexed) {
+        if comma(lexed).is_err() {
             break
         }
     }
     parenthesis(lexed, ')')?;
 
-    let return_type = if let Ok(_) = colon(lexed) {
+    let return_type = if colon(lexed).is_ok() {
         any_type(lexed)?
     } else {
         ast::Type::Any
     };
 
-    operator(lexed, Assign)?;
+    Ok((name, params, p_types, return_type))
+}
 
-    let exp = statement(lexed)?;
+fn interface_decl(lexed: &mut LexIter) -> KeepRes {
+    let loc = curr_loc(lexed)?;
+    keyword(lexed, "interface")?;
+    let name = id(lexed)?;
+    parenthesis(lexed, '{')?;
 
-    let func = Function {
-        ret_type: return_type,
-        param_types: p_types,
-        params,
-        exp: Box::new(exp),
-        loc
+    let mut methods = Vec::new();
+    while let Some((Keyword("fun"), loc)) = lexed.peek() {
+        let (method, _, mut p_types, ret_type) = fun_signature(lexed, Some(&name))?;
+        if ret_type == ast::Type::Any {
+            return Err((format!("Method '{method}' in interface '{name}' needs a return type annotation"), *loc))
+        }
+        p_types.remove(0);
+        methods.push((method, p_types, ret_type));
+
+        let _ = semi_colon(lexed);
+    }
+    parenthesis(lexed, '}')?;
+
+    Ok(Exp::InterfaceDeclExp(name, methods, loc))
+}
+
+///'impl INTERFACE for STRUCT { methods }'. The methods become functions named 'STRUCT.method'
+fn impl_decl(lexed: &mut LexIter) -> Result<(Exp, Vec<FunDecl>), (String, Location)> {
+    let loc = curr_loc(lexed)?;
+    keyword(lexed, "impl")?;
+    let interface = id(lexed)?;
+    keyword(lexed, "for")?;
+    let name = id(lexed)?;
+    parenthesis(lexed, '{')?;
+
+    let mut names = Vec::new();
+    let mut methods = Vec::new();
+    while let Some((Keyword("fun"), _)) = lexed.peek() {
+        let loc = curr_loc(lexed)?;
+        let (method, params, p_types, ret_type) = fun_signature(lexed, Some(&name))?;
+        operator(lexed, Assign)?;
+        let exp = statement(lexed)?;
+
+        let id = Symbol::new(&format!("{name}.{method}"));
+        let func = Function {
+            name: id,
+            ret_type,
+            param_types: p_types,
+            params,
+            exp: Box::new(exp),
+            loc
+        };
+        names.push(method);
+        methods.push((Exp::FunDeclExp(id, loc), id, Box::new(func)));
+
+        let _ = semi_colon(lexed);
+    }
+    parenthesis(lexed, '}')?;
+
+    Ok((Exp::ImplExp(interface, name, names, loc), methods))
+}
+
+///'operator SYMBOL left|right LEVEL = FUNCTION'. The symbol is operators written next to each other, and the level is that of
+///the builtin operators it binds like, from 1 for '*' to 9 for '='
+fn operator_decl(lexed: &mut LexIter) -> KeepRes {
+    let loc = curr_loc(lexed)?;
+    keyword(lexed, "operator")?;
+    let (symbol, tokens) = adjacent_operators(lexed).last().cloned()
+        .ok_or((String::from("Expected an operator"), loc))?;
+    if OPERATORS.contains(&symbol.as_str()) {
+        return Err((format!("'{symbol}' is already an operator"), curr_loc(lexed)?))
+    }
+    lexed.nth(tokens - 1);
+
+    let associativity_loc = curr_loc(lexed)?;
+    let associativity = match id(lexed)?.as_str() {
+        "left" => Associativity::Left,
+        "right" => Associativity::Right,
+        other => return Err((format!("Expected 'left' or 'right', got '{other}'"), associativity_loc))
+    };
+    let level = match lexed.next() {
+        Some((Int(level), _)) if *level >= 1 && *level as usize <= BINARY_OP_PRECEDENCE.len() => *level as usize,
+        Some((_, loc)) => return Err((format!("Precedence must be from 1 to {}", BINARY_OP_PRECEDENCE.len()), *loc)),
+        None => return Err((String::from("Unexpected end of input"), loc))
     };
+    operator(lexed, Assign)?;
+    let fun = Symbol::new(&id(lexed)?);
+
+    let custom = CustomOperator { symbol, fun, level, associativity };
+    DECLARED_OPERATORS.with(|declared| declared.borrow_mut().push(custom.clone()));
+    Ok(Exp::OperatorDeclExp(custom, loc))
+}
+
+///The operator the operator tokens at the start spell, when each is right after the one before, for every number of them
+fn adjacent_operators(lexed: &LexIter) -> Vec<(String, usize)> {
+    let mut symbols: Vec<(String, usize)> = Vec::new();
+    let mut end = None;
+    for (token, loc) in lexed.clone() {
+        match token {
+            Operator(op) if end.is_none_or(|end| end == (loc.line, loc.col)) => {
+                let symbol = symbols.last().map_or(String::new(), |(symbol, _)| symbol.clone());
+                symbols.push((symbol + op, symbols.len() + 1));
+                end = Some((loc.end_line, loc.end_col));
+            },
+            _ => break
+        }
+    }
+    symbols
+}
 
-    Ok((Exp::FunDeclExp(name.clone(), loc), name, Box::new(func)))
+///The latest declared operator the operator tokens at the start spell, the longest if several do, with how many tokens it takes
+fn declared_operator(lexed: &LexIter) -> Option<(CustomOperator, usize)> {
+    if DECLARED_OPERATORS.with(|declared| declared.borrow().is_empty()) {
+        return None
+    }
+    adjacent_operators(lexed).into_iter().rev().find_map(|(symbol, tokens)| DECLARED_OPERATORS.with(|declared| {
+        declared.borrow().iter().rev().find(|custom| custom.symbol == symbol).map(|custom| (custom.clone(), tokens))
+    }))
 }
 
 fn any_type(lexed: &mut LexIter) -> Result<ast::Type, (String, Location)> {
@@ -466,13 +1107,67 @@ fn any_type(lexed: &mut LexIter) -> Result<ast::Type, (String, Location)> {
                 "char" => ast::Type::Char,
                 "string" => ast::Type::Str,
                 "unit" => ast::Type::Unit,
-
-                _ => return Err((format!("Unknown type"), *loc))
+                "option" | "set" | "task" | "chan" => {
+                    lexed.next();
+                    operator(lexed, LessThan)?;
+                    let inner = Box::new(any_type(lexed)?);
+                    operator(lexed, GreaterThan)?;
+                    return Ok(match *typ {
+                        "set" => ast::Type::Set(inner),
+                        "task" => ast::Type::Task(inner),
+                        "chan" => ast::Type::Channel(inner),
+                        _ => ast::Type::Optional(inner)
+                    })
+                },
+
+                _ => return Err((String::from("Unknown type"), *loc))
             };
             lexed.next();
-            return Ok(typ);
-        } 
-        _ => Err((format!("Expected a type"), curr_loc(lexed)?))
+            Ok(typ)
+        },
+        Some((Id(name), _)) => {
+            lexed.next();
+            Ok(ast::Type::Struct(name.clone()))
+        },
+        Some((Paren('('), _)) => {
+            lexed.next();
+            let mut types = Vec::new();
+            loop {
+                types.push(any_type(lexed)?);
+                if comma(lexed).is_err() {
+                    break
+                }
+            }
+            parenthesis(lexed, ')')?;
+            Ok(ast::Type::Tuple(types))
+        },
+        Some((Paren('['), _)) => {
+            lexed.next();
+            let inner = any_type(lexed)?;
+            parenthesis(lexed, ']')?;
+            Ok(ast::Type::Array(Box::new(inner)))
+        },
+        //fun(TYPE, ...): TYPE
+        Some((Keyword("fun"), _)) => {
+            lexed.next();
+            parenthesis(lexed, '(')?;
+            let mut params = Vec::new();
+            while !terminator(lexed) {
+                params.push(any_type(lexed)?);
+                if comma(lexed).is_err() {
+                    break
+                }
+            }
+            parenthesis(lexed, ')')?;
+            colon(lexed)?;
+            let ret_type = any_type(lexed)?;
+            Ok(ast::Type::Fun(params, Box::new(ret_type)))
+        },
+        _ => {
+            let types: Vec<String> = TYPES.iter().map(|typ| format!("'{typ}'")).collect();
+            let options: Vec<&str> = types.iter().map(String::as_str).chain(["identifier", "'('", "'['", "'fun'"]).collect();
+            Err(expected(lexed, "a type", &options))
+        }
     }
 }
 
@@ -484,9 +1179,8 @@ fn literal(lexed: &mut LexIter) -> KeepRes {
             Some((LexToken::Float(f), _)) => Exp::LiteralExp(Literal::Float(*f), loc),
             Some((LexToken::Bool(b), _)) => Exp::LiteralExp(Literal::Bool(*b), loc),
             Some((LexToken::Char(c), _)) => Exp::LiteralExp(Literal::Char(*c), loc),
-            Some((LexToken::Str(s), _)) => Exp::LiteralExp(Literal::Str(s.clone()), loc),
-            Some((_, _))=> return Err((format!("Expected literal"), loc)),
-            _ => unreachable!()
+            Some((LexToken::Str(s), _)) => Exp::LiteralExp(Literal::Str(intern(s)), loc),
+            _ => return Err(expected(lexed, "a literal", &[])),
     };
     lexed.next();
     Ok(lit)
@@ -498,7 +1192,7 @@ fn semi_colon(lexed: &mut LexIter) -> DiscardRes {
             lexed.next();
             Ok(())
         },
-        _ => Err((format!("Expected ';'"), curr_loc(lexed)?)),
+        _ => Err(expected(lexed, "';'", &[])),
     }
 }
 
@@ -508,7 +1202,7 @@ fn colon(lexed: &mut LexIter) -> DiscardRes {
             lexed.next();
             Ok(())
         },
-        _ => Err((format!("Expected ':'"), curr_loc(lexed)?)),
+        _ => Err(expected(lexed, "':'", &[])),
     }
 }
 
@@ -518,31 +1212,43 @@ fn comma(lexed: &mut LexIter) -> DiscardRes {
             lexed.next();
             Ok(())
         },
-        _ => Err((format!("Expected ','"), curr_loc(lexed)?)),
+        _ => Err(expected(lexed, "','", &[])),
     }
 }
 
 fn parenthesis(lexed: &mut LexIter, paren: char) -> Result<(), (String, Location)> {
     match lexed.peek() {
-        Some((Paren(par), loc)) => {
-            if *par != paren {
-                return Err((format!("Expected '{paren}'"), *loc))
-            }
+        Some((Paren(par), _)) if *par == paren => {
             lexed.next();
             Ok(())
         },
-        _ => Err((format!("Expected '{paren}'"), curr_loc(lexed)?))
+        _ => Err(expected(lexed, &format!("'{paren}'"), &[]))
+    }
+}
+
+///The error for the next token when something else was expected, naming what was found.
+///When the parser chose between several options there, they are listed
+fn expected(lexed: &mut LexIter, what: &str, options: &[&str]) -> (String, Location) {
+    let (msg, loc) = match lexed.peek() {
+        Some((EndOfInput, loc)) => (format!("Unexpected end of input, expected {what}"), *loc),
+        Some((token, loc)) => (format!("Expected {what}, got {}", describe(token)), *loc),
+        None => (format!("Unexpected end of input, expected {what}"), Location::point(0, 0))
+    };
+    match options {
+        [] => (msg, loc),
+        options => (format!("{msg}. Expected one of: {}", options.join(", ")), loc)
     }
 }
 
 fn terminator(lexed: &mut LexIter) -> bool {
-    TERMINATORS.contains(&lexed.peek().unwrap().0)
+    lexed.peek().is_none_or(|(token, _)| TERMINATORS.contains(token))
 }
 
 fn curr_loc(lexed: &mut LexIter) -> Result<Location, (String, Location)> {
     match lexed.peek() {
-        Some((EndOfInput, loc)) => Err((format!("Unexpected end of input"), *loc)),
+        Some((EndOfInput, loc)) => Err((String::from("Unexpected end of input"), *loc)),
         Some((_, loc)) => Ok(*loc),
-        None => panic!("Could not get location"),
+        //Only the end of input has no token after it, and its location went with it
+        None => Err((String::from("Unexpected end of input"), Location::point(0, 0))),
     }
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, fmt::Display, time::{Duration, Instant}};
+
+use super::*;
+use interner::Symbol;
+
+///Calls and time spent in a function
+#[derive(Debug, Clone)]
+pub struct FunctionProfile {
+    pub name: Symbol,
+    pub calls: u64,
+
+    ///Time from the function is called until it returns, including the functions it calls.
+    ///Recursive calls are only counted once
+    pub total: Duration,
+
+    ///Time spent in the function itself, not in the functions it calls
+    pub own: Duration,
+}
+
+///What a profiled run spent its time on. Functions that use the most time on their own come first
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub functions: Vec<FunctionProfile>,
+}
+
+///A call that has not returned yet
+struct Running {
+    name: Symbol,
+    start: Instant,
+
+    ///Time spent in the calls it has made
+    children: Duration,
+}
+
+#[derive(Default)]
+struct Profiler {
+    running: Vec<Running>,
+    functions: HashMap<Symbol, FunctionProfile>,
+
+    ///How many calls of each function are running, so recursive calls are not counted twice in the total
+    active: HashMap<Symbol, usize>,
+}
+
+thread_local! {
+    ///Whether calls on this thread are recorded now
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+
+    ///What the last profiled run on this thread recorded
+    static PROFILER: RefCell<Option<Profiler>> = const { RefCell::new(None) };
+}
+
+///Starts profiling the runs on this thread from scratch, or stops it. Gives whether calls were recorded before
+pub fn set_profiling(enabled: bool) -> bool {
+    PROFILER.with(|profiler| *profiler.borrow_mut() = enabled.then(Profiler::default));
+    ENABLED.with(|cell| cell.replace(enabled))
+}
+
+///Stops or goes on recording calls, keeping what was recorded, like when a profiled run ends. Gives whether they were recorded before
+pub fn set_recording(enabled: bool) -> bool {
+    ENABLED.with(|cell| cell.replace(enabled))
+}
+
+///Records that a function was called
+pub fn enter(name: Symbol) {
+    if !ENABLED.with(|enabled| enabled.get()) {
+        return
+    }
+    PROFILER.with(|profiler| {
+        let mut profiler = profiler.borrow_mut();
+        let Some(profiler) = profiler.as_mut() else {
+            return
+        };
+        profiler.functions.entry(name)
+            .or_insert(FunctionProfile { name, calls: 0, total: Duration::ZERO, own: Duration::ZERO })
+            .calls += 1;
+        *profiler.active.entry(name).or_insert(0) += 1;
+        profiler.running.push(Running { name, start: Instant::now(), children: Duration::ZERO });
+    })
+}
+
+///Records that the last called function returned
+pub fn leave() {
+    if !ENABLED.with(|enabled| enabled.get()) {
+        return
+    }
+    PROFILER.with(|profiler| {
+        if let Some(profiler) = profiler.borrow_mut().as_mut() {
+            profiler.leave()
+        }
+    })
+}
+
+impl Profiler {
+    fn leave(&mut self) {
+        let call = match self.running.pop() {
+            Some(call) => call,
+            None => return
+        };
+        let elapsed = call.start.elapsed();
+        let active = self.active.get_mut(&call.name).unwrap();
+        *active -= 1;
+        let outermost = *active == 0;
+
+        let function = self.functions.get_mut(&call.name).unwrap();
+        function.own += elapsed.saturating_sub(call.children);
+        if outermost {
+            function.total += elapsed;
+        }
+        if let Some(caller) = self.running.last_mut() {
+            caller.children += elapsed;
+        }
+    }
+}
+
+///The profile of the last run on this thread, if it was profiled. Calls that did not return, because of an error, end now
+pub fn take_profile() -> Option<Profile> {
+    let mut profiler = PROFILER.with(|profiler| profiler.borrow_mut().take())?;
+    while !profiler.running.is_empty() {
+        profiler.leave();
+    }
+    let mut functions: Vec<FunctionProfile> = profiler.functions.into_values().collect();
+    functions.sort_by_key(|fun| std::cmp::Reverse(fun.own));
+    Some(Profile { functions })
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.functions.iter().map(|fun| fun.name.as_str().len()).max().unwrap_or(0).max("Function".len());
+        write!(f, "{:width$}  {:>10}  {:>12}  {:>12}", "Function", "Calls", "Total ms", "Own ms")?;
+        for fun in &self.functions {
+            write!(f, "\n{:width$}  {:>10}  {:>12.3}  {:>12.3}", fun.name.as_str(), fun.calls,
+                fun.total.as_secs_f64() * 1_000.0, fun.own.as_secs_f64() * 1_000.0)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use super::*;
+use interner::Symbol;
+use options::ExecutionOptions;
+
+///Reruns a program after its source changed, so it gets the new functions while its top-level variables keep their values
+#[derive(Debug, Default)]
+pub struct Reloader {
+    ///The top-level variables of the last run
+    variables: HashMap<Symbol, Literal>,
+}
+
+impl Reloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///The program with the top-level 'let's of variables from the last run declaring them with their last value.
+    ///It must be type checked, which fails if the program now uses one of them as another type
+    pub fn keep_variables(&self, program: &Exp) -> Exp {
+        match program {
+            Exp::BlockExp(exps, funs, loc) => {
+                let exps = exps.iter().map(|exp| match exp {
+                    Exp::LetExp(id, _, let_loc) if self.variables.contains_key(id) => {
+                        Exp::LetExp(*id, Box::new(Exp::LiteralExp(self.variables[id].clone(), *let_loc)), *let_loc)
+                    },
+                    exp => exp.clone()
+                }).collect();
+                Exp::BlockExp(exps, funs.clone(), *loc)
+            },
+            exp => exp.clone()
+        }
+    }
+
+    ///Runs the program and keeps the values of its top-level variables for the next run.
+    ///Values with functions are not kept, as they would call the old ones
+    pub fn run(&mut self, program: &Exp, options: &ExecutionOptions) -> eval::EvalResult {
+        let (res, variables) = eval::run_with_variables(program, Vec::new(), options);
+        self.variables = variables.into_iter().filter(|(_, value)| !has_function(value)).collect();
+        res
+    }
+}
+
+fn has_function(value: &Literal) -> bool {
+    match value {
+        Literal::Fun(_) => true,
+        Literal::Optional(Some(lit)) => has_function(lit),
+        Literal::Tuple(lits) | Literal::Array(lits) | Literal::Set(lits) => lits.iter().any(has_function),
+        Literal::Struct(value) => value.fields.iter().any(|(_, lit)| has_function(lit)),
+        _ => false
+    }
+}
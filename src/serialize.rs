@@ -0,0 +1,165 @@
+use std::{fmt, rc::Rc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, MapAccess, SeqAccess, Visitor}, ser::{Error, SerializeMap, SerializeSeq}};
+
+use super::*;
+use dump::Node;
+use Literal::*;
+
+///Values serialize as the plain data they hold. A struct is a map of its fields, 'none' and unit are null, and 'some' is the value in it.
+///Functions, tasks and channels cannot be serialized
+impl Serialize for Literal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let seq = |lits: &Vec<Literal>, serializer: S| {
+            let mut seq = serializer.serialize_seq(Some(lits.len()))?;
+            for lit in lits {
+                seq.serialize_element(lit)?;
+            }
+            seq.end()
+        };
+        match self {
+            Int(i) => serializer.serialize_i64(*i),
+            Float(f) => serializer.serialize_f64(*f),
+            Bool(b) => serializer.serialize_bool(*b),
+            Char(c) => serializer.serialize_char(*c),
+            Str(s) => serializer.serialize_str(s),
+            Optional(None) => serializer.serialize_none(),
+            Optional(Some(lit)) => serializer.serialize_some(lit.as_ref()),
+            Tuple(lits) | Array(lits) | Set(lits) => seq(lits, serializer),
+            Struct(value) => {
+                let mut map = serializer.serialize_map(Some(value.fields.len()))?;
+                for (field, lit) in &value.fields {
+                    map.serialize_entry(field, lit)?;
+                }
+                map.end()
+            },
+            Unit => serializer.serialize_unit(),
+            Fun(closure) => Err(S::Error::custom(format!("Function '{}' cannot be serialized", closure.fun.name))),
+            Task(_) => Err(S::Error::custom("Tasks cannot be serialized")),
+            Channel(_) => Err(S::Error::custom("Channels cannot be serialized")),
+        }
+    }
+}
+
+///Reads plain data back into values. Null is unit, and a sequence is an array when all of its elements have the same type, or else a tuple.
+///Maps cannot be read, as a struct needs its name
+impl<'de> Deserialize<'de> for Literal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(LiteralVisitor)
+    }
+}
+
+struct LiteralVisitor;
+
+impl<'de> Visitor<'de> for LiteralVisitor {
+    type Value = Literal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number, bool, char, string, null or sequence")
+    }
+
+    fn visit_i64<E: de::Error>(self, i: i64) -> Result<Literal, E> {
+        Ok(Int(i))
+    }
+
+    fn visit_u64<E: de::Error>(self, u: u64) -> Result<Literal, E> {
+        i64::try_from(u).map(Int).map_err(|_| E::custom(format!("{u} does not fit in an int")))
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<Literal, E> {
+        Ok(Float(f))
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Literal, E> {
+        Ok(Bool(b))
+    }
+
+    fn visit_char<E: de::Error>(self, c: char) -> Result<Literal, E> {
+        Ok(Char(c))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Literal, E> {
+        Ok(Str(Rc::new(s.to_string())))
+    }
+
+    fn visit_string<E: de::Error>(self, s: String) -> Result<Literal, E> {
+        Ok(Str(Rc::new(s)))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Literal, E> {
+        Ok(Unit)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Literal, E> {
+        Ok(Optional(None))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Literal, D::Error> {
+        Ok(Optional(Some(Box::new(Literal::deserialize(deserializer)?))))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Literal, A::Error> {
+        let mut lits = Vec::new();
+        while let Some(lit) = seq.next_element::<Literal>()? {
+            lits.push(lit);
+        }
+        let same_type = lits.windows(2).all(|pair| pair[0].get_type().matches(&pair[1].get_type()));
+        match same_type {
+            true => Ok(Array(Rc::new(lits))),
+            false => Ok(Tuple(Rc::new(lits)))
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, _: A) -> Result<Literal, A::Error> {
+        Err(de::Error::custom("Maps cannot be read as values, as a struct needs its name"))
+    }
+}
+
+///A node of the tree is a map of its kind, its fields and its location, and the other nodes are the plain data they hold
+impl Serialize for Node {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Node::Null => serializer.serialize_unit(),
+            Node::Bool(b) => serializer.serialize_bool(*b),
+            Node::Int(i) => serializer.serialize_i64(*i),
+            Node::Float(f) => serializer.serialize_f64(*f),
+            Node::Str(s) | Node::Name(s) => serializer.serialize_str(s),
+            Node::List(nodes) => {
+                let mut seq = serializer.serialize_seq(Some(nodes.len()))?;
+                for node in nodes {
+                    seq.serialize_element(node)?;
+                }
+                seq.end()
+            },
+            Node::Tree(kind, fields, loc) => {
+                let mut map = serializer.serialize_map(Some(fields.len() + 2))?;
+                map.serialize_entry("kind", kind)?;
+                for (field, node) in fields {
+                    map.serialize_entry(field, node)?;
+                }
+                if let Some(loc) = loc {
+                    map.serialize_entry("location", loc)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl Serialize for Location {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("line", &self.line)?;
+        map.serialize_entry("col", &self.col)?;
+        map.serialize_entry("end_line", &self.end_line)?;
+        map.serialize_entry("end_col", &self.end_col)?;
+        map.end()
+    }
+}
+
+///An expression serializes as the tree Exp::to_node gives, the same as Exp::to_json writes
+impl Serialize for Exp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_node().serialize(serializer)
+    }
+}
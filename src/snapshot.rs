@@ -0,0 +1,205 @@
+use std::{fmt::Display, rc::Rc};
+
+use super::*;
+use interner::Symbol;
+
+///The state of a program between two of its top-level statements. No calls are running there,
+///so it is the top-level variables and where to go on from
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    ///How many of the top-level statements have run
+    pub next: usize,
+
+    ///In the order they were declared
+    pub variables: Vec<(Symbol, Literal)>,
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "next {}", self.next)?;
+        for (id, value) in &self.variables {
+            let mut text = String::new();
+            write_value(value, &mut text);
+            write!(f, "\nvar {id} {text}")?;
+        }
+        Ok(())
+    }
+}
+
+///Writes the value so that Reader::value gives it back. Strings are prefixed with their length, so they need no escapes
+fn write_value(value: &Literal, out: &mut String) {
+    let all = |name: &str, lits: &Vec<Literal>, out: &mut String| {
+        out.push_str(&format!("({name}"));
+        for lit in lits {
+            out.push(' ');
+            write_value(lit, out);
+        }
+        out.push(')');
+    };
+    match value {
+        Literal::Int(i) => out.push_str(&format!("(int {i})")),
+        Literal::Float(f) => out.push_str(&format!("(float {f:?})")),
+        Literal::Bool(b) => out.push_str(&format!("(bool {b})")),
+        Literal::Char(c) => out.push_str(&format!("(char {})", *c as u32)),
+        Literal::Str(s) => out.push_str(&format!("(str {}:{s})", s.chars().count())),
+        Literal::Optional(None) => out.push_str("(none)"),
+        Literal::Optional(Some(lit)) => {
+            out.push_str("(some ");
+            write_value(lit, out);
+            out.push(')');
+        },
+        Literal::Tuple(lits) => all("tuple", lits, out),
+        Literal::Array(lits) => all("array", lits, out),
+        Literal::Set(lits) => all("set", lits, out),
+        Literal::Struct(value) => {
+            out.push_str(&format!("(struct {}", value.name));
+            for (field, lit) in &value.fields {
+                out.push_str(&format!(" ({field} "));
+                write_value(lit, out);
+                out.push(')');
+            }
+            out.push(')');
+        },
+        Literal::Fun(closure) => out.push_str(&format!("(fun {})", closure.fun.name)),
+        Literal::Task(id) => out.push_str(&format!("(task {id})")),
+        Literal::Channel(_) => out.push_str("(channel)"),
+        Literal::Unit => out.push_str("(unit)"),
+    }
+}
+
+///Reads a snapshot written by its Display. Function values are saved by name, and given by the function
+pub fn read(text: &str, function: impl FnMut(Symbol) -> Option<Literal>) -> Result<Snapshot, String> {
+    let mut reader = Reader { chars: text.chars().collect(), pos: 0, function };
+    reader.keyword("next")?;
+    let next = reader.number()?;
+    let mut variables = Vec::new();
+    while !reader.at_end() {
+        reader.keyword("var")?;
+        let id = Symbol::new(&reader.word()?);
+        variables.push((id, reader.value()?));
+    }
+    Ok(Snapshot { next, variables })
+}
+
+struct Reader<F> {
+    chars: Vec<char>,
+    pos: usize,
+    function: F,
+}
+
+impl<F: FnMut(Symbol) -> Option<Literal>> Reader<F> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos >= self.chars.len()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some(c) if *c == expected => {
+                self.pos += 1;
+                Ok(())
+            },
+            Some(c) => Err(format!("Expected '{expected}' in the snapshot, got '{c}'")),
+            None => Err(format!("Expected '{expected}' in the snapshot, but it ended")),
+        }
+    }
+
+    ///Reads until whitespace, a parenthesis or a colon
+    fn word(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| !c.is_whitespace() && !"():".contains(*c)) {
+            self.pos += 1;
+        }
+        match start == self.pos {
+            true => Err(format!("Expected a word in the snapshot at character {start}")),
+            false => Ok(self.chars[start..self.pos].iter().collect())
+        }
+    }
+
+    fn keyword(&mut self, expected: &str) -> Result<(), String> {
+        match self.word()? {
+            word if word == expected => Ok(()),
+            word => Err(format!("Expected '{expected}' in the snapshot, got '{word}'"))
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, String> {
+        let word = self.word()?;
+        word.parse().map_err(|_| format!("Invalid number '{word}' in the snapshot"))
+    }
+
+    ///Reads values until the closing parenthesis
+    fn values(&mut self) -> Result<Vec<Literal>, String> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.chars.get(self.pos) == Some(&')') {
+                self.pos += 1;
+                return Ok(values)
+            }
+            values.push(self.value()?);
+        }
+    }
+
+    fn value(&mut self) -> Result<Literal, String> {
+        self.expect('(')?;
+        let value = match self.word()?.as_str() {
+            "int" => Literal::Int(self.number()?),
+            "float" => Literal::Float(self.number()?),
+            "bool" => Literal::Bool(self.number::<bool>()?),
+            "char" => match char::from_u32(self.number()?) {
+                Some(c) => Literal::Char(c),
+                None => return Err(String::from("Invalid char in the snapshot"))
+            },
+            "str" => {
+                let len: usize = self.number()?;
+                self.expect(':')?;
+                if self.pos + len > self.chars.len() {
+                    return Err(String::from("String in the snapshot is cut off"))
+                }
+                let s: String = self.chars[self.pos..self.pos + len].iter().collect();
+                self.pos += len;
+                Literal::Str(Rc::new(s))
+            },
+            "none" => Literal::Optional(None),
+            "some" => Literal::Optional(Some(Box::new(self.value()?))),
+            "unit" => Literal::Unit,
+            "tuple" => return Ok(Literal::Tuple(Rc::new(self.values()?))),
+            "array" => return Ok(Literal::Array(Rc::new(self.values()?))),
+            "set" => return Ok(Literal::Set(Rc::new(self.values()?))),
+            "struct" => {
+                let name = self.word()?;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                while self.chars.get(self.pos) == Some(&'(') {
+                    self.pos += 1;
+                    let field = self.word()?;
+                    fields.push((field, self.value()?));
+                    self.expect(')')?;
+                    self.skip_whitespace();
+                }
+                Literal::Struct(Rc::new(StructValue { name, fields }))
+            },
+            "fun" => {
+                let name = self.word()?;
+                match (self.function)(Symbol::new(&name)) {
+                    Some(fun) => fun,
+                    None => return Err(format!("Function '{name}' in the snapshot does not exist in the program"))
+                }
+            },
+            "task" => return Err(String::from("Running tasks in the snapshot cannot be resumed")),
+            "channel" => return Err(String::from("Channels in the snapshot cannot be resumed")),
+            kind => return Err(format!("Unknown value '{kind}' in the snapshot"))
+        };
+        self.expect(')')?;
+        Ok(value)
+    }
+}
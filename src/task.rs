@@ -0,0 +1,355 @@
+use std::{cell::{Cell, RefCell}, collections::{HashMap, VecDeque}, rc::Rc, sync::{Arc, Condvar, Mutex, atomic::{AtomicU64, AtomicUsize, Ordering}, mpsc::{self, Receiver, RecvTimeoutError}}, time::Duration};
+
+use lazy_static::lazy_static;
+
+use super::*;
+use interner::Symbol;
+use eval::{Signal, EvalResult};
+
+///A value that owns all of its data, so it can be sent to another thread
+#[derive(Debug, Clone)]
+pub enum Transfer {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Optional(Option<Box<Transfer>>),
+    Tuple(Vec<Transfer>),
+    Struct(String, Vec<(String, Transfer)>),
+    Array(Vec<Transfer>),
+    Set(Vec<Transfer>),
+
+    ///A function is sent by name, and found again where it is received
+    Fun(Symbol),
+
+    ///A task can only be joined on the thread that spawned it
+    Task(u64),
+    Channel(Arc<Channel>),
+    Unit,
+}
+
+impl Transfer {
+    ///A copy of the value that shares nothing with it
+    pub fn new(value: &Literal) -> Result<Transfer, String> {
+        let all = |lits: &Vec<Literal>| lits.iter().map(Transfer::new).collect::<Result<Vec<_>, _>>();
+        Ok(match value {
+            Literal::Int(i) => Transfer::Int(*i),
+            Literal::Float(f) => Transfer::Float(*f),
+            Literal::Bool(b) => Transfer::Bool(*b),
+            Literal::Char(c) => Transfer::Char(*c),
+            Literal::Str(s) => Transfer::Str(s.to_string()),
+            Literal::Optional(lit) => Transfer::Optional(match lit {
+                Some(lit) => Some(Box::new(Transfer::new(lit)?)),
+                None => None
+            }),
+            Literal::Tuple(lits) => Transfer::Tuple(all(lits)?),
+            Literal::Struct(value) => Transfer::Struct(value.name.clone(), value.fields.iter()
+                .map(|(field, lit)| Ok((field.clone(), Transfer::new(lit)?)))
+                .collect::<Result<Vec<_>, String>>()?),
+            Literal::Array(lits) => Transfer::Array(all(lits)?),
+            Literal::Set(lits) => Transfer::Set(all(lits)?),
+            Literal::Fun(closure) => Transfer::Fun(closure.fun.name),
+            Literal::Task(id) => Transfer::Task(*id),
+            Literal::Channel(channel) => Transfer::Channel(channel.clone()),
+            Literal::Unit => Transfer::Unit,
+        })
+    }
+
+    ///The value on this thread. Functions are given by the function, and cannot be received when it gives none
+    pub fn into_literal(self, function: &mut impl FnMut(Symbol) -> Option<Literal>) -> Result<Literal, String> {
+        let mut all = |transfers: Vec<Transfer>| transfers.into_iter().map(|transfer| transfer.into_literal(function)).collect::<Result<Vec<_>, _>>();
+        Ok(match self {
+            Transfer::Int(i) => Literal::Int(i),
+            Transfer::Float(f) => Literal::Float(f),
+            Transfer::Bool(b) => Literal::Bool(b),
+            Transfer::Char(c) => Literal::Char(c),
+            Transfer::Str(s) => Literal::Str(Rc::new(s)),
+            Transfer::Optional(None) => Literal::Optional(None),
+            Transfer::Optional(Some(transfer)) => Literal::Optional(Some(Box::new(transfer.into_literal(function)?))),
+            Transfer::Tuple(transfers) => Literal::Tuple(Rc::new(all(transfers)?)),
+            Transfer::Struct(name, fields) => {
+                let fields = fields.into_iter()
+                    .map(|(field, transfer)| Ok((field, transfer.into_literal(function)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Literal::Struct(Rc::new(StructValue { name, fields }))
+            },
+            Transfer::Array(transfers) => Literal::Array(Rc::new(all(transfers)?)),
+            Transfer::Set(transfers) => Literal::Set(Rc::new(all(transfers)?)),
+            Transfer::Fun(name) => match function(name) {
+                Some(fun) => fun,
+                None => return Err(format!("Function '{name}' cannot be sent between tasks"))
+            },
+            Transfer::Task(id) => Literal::Task(id),
+            Transfer::Channel(channel) => Literal::Channel(channel),
+            Transfer::Unit => Literal::Unit,
+        })
+    }
+}
+
+///A function to run on another thread, with the functions and variables it can see there
+struct Task {
+    fun: Box<Function>,
+    functions: Vec<(Symbol, Box<Function>)>,
+    variables: Vec<(Symbol, Transfer)>,
+}
+
+//The functions of a task are copied without sharing any of the strings in the program, and its variables are transfers,
+//so nothing in it is shared with the thread that made it
+unsafe impl Send for Task {}
+
+///A copy of the function that shares no strings with the program
+fn unshare(fun: &Function) -> Box<Function> {
+    let mut fun = Box::new(fun.clone());
+    let exp = std::mem::replace(&mut fun.exp, Box::new(Exp::LiteralExp(Literal::Unit, fun.loc)));
+    fun.exp = Box::new(unshare_exp(*exp));
+    fun
+}
+
+fn unshare_exp(exp: Exp) -> Exp {
+    match optimize::map_children(exp, unshare_exp) {
+        Exp::LiteralExp(lit, loc) => {
+            let lit = Transfer::new(&lit).and_then(|transfer| transfer.into_literal(&mut |_| None))
+                .expect("Literals in a program are simple values");
+            Exp::LiteralExp(lit, loc)
+        },
+        exp => exp
+    }
+}
+
+impl Task {
+    ///Copies the function value, and what it can see
+    fn new(closure: &Closure<Literal>) -> Result<Task, String> {
+        let functions = closure.envir.functions().iter().map(|(id, fun)| (*id, unshare(fun))).collect();
+        let variables = closure.envir.variables().iter()
+            .map(|(id, value)| Ok((*id, Transfer::new(value)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Task { fun: unshare(&closure.fun), functions, variables })
+    }
+
+    ///Calls the function in an environment like the one it was spawned from
+    fn run(self, loc: Location) -> Result<Transfer, String> {
+        let mut envir = Environment::new();
+        envir.enter_scope();
+        for (id, fun) in self.functions.into_iter().rev() {
+            envir.push_function(&id, fun);
+        }
+        envir.update_fun_envirs();
+        for (id, transfer) in self.variables.into_iter().rev() {
+            let value = transfer.into_literal(&mut |name| envir.lookup_fun(&name).is_ok().then(|| eval::fun_value(&name, &mut envir)))?;
+            envir.push_variable(&id, value);
+        }
+        let closure = Closure { declared: true, fun: self.fun, envir };
+        match eval::call_closure(&closure, Vec::new(), loc) {
+            Ok(value) => Transfer::new(&value),
+            Err(signal) => Err(signal.to_string())
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+///Stack size of the threads that run tasks
+const TASK_STACK_SIZE: usize = 1 << 28;
+
+///How long a wait for a task or channel goes before it checks whether the run is out of time
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+
+///How many tasks run at once, not counting those waiting for another task or a channel. 0 is one for each core
+static POOL_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+///Sets how many tasks run at once, not counting those waiting for another task or a channel. 0 is one for each core
+pub fn set_pool_size(size: usize) {
+    POOL_SIZE.store(size, Ordering::Relaxed);
+}
+
+fn pool_size() -> usize {
+    match POOL_SIZE.load(Ordering::Relaxed) {
+        0 => std::thread::available_parallelism().map_or(4, |threads| threads.get()),
+        size => size
+    }
+}
+
+///The threads that run tasks, and the jobs waiting for one
+#[derive(Default)]
+struct PoolState {
+    jobs: VecDeque<Job>,
+
+    ///Threads running a job or waiting for one
+    threads: usize,
+
+    ///Threads waiting for a job
+    idle: usize,
+
+    ///Threads running a task that waits for another task or a channel. They do not count toward the size of the pool,
+    ///so tasks that wait for each other cannot take all the threads
+    blocked: usize,
+}
+
+#[derive(Default)]
+struct Pool {
+    state: Mutex<PoolState>,
+
+    ///Wakes an idle thread when a job is queued
+    queued: Condvar,
+}
+
+thread_local! {
+    ///Whether this thread is one of the pool
+    static IN_POOL: Cell<bool> = const { Cell::new(false) };
+}
+
+impl Pool {
+    fn submit(&'static self, job: Job) {
+        let mut state = self.state.lock().unwrap();
+        state.jobs.push_back(job);
+        self.grow(&mut state);
+        self.queued.notify_one();
+    }
+
+    ///Starts a thread when there are more jobs than idle threads to take them, and the pool is not full
+    fn grow(&'static self, state: &mut PoolState) {
+        if state.jobs.len() > state.idle && state.threads - state.blocked < pool_size() {
+            state.threads += 1;
+            std::thread::Builder::new()
+                .stack_size(TASK_STACK_SIZE)
+                .spawn(move || self.work())
+                .expect("Should have been able to start a task thread");
+        }
+    }
+
+    ///Runs jobs until there are more threads than the pool needs
+    fn work(&'static self) {
+        IN_POOL.with(|in_pool| in_pool.set(true));
+        loop {
+            let job = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    match state.jobs.pop_front() {
+                        Some(job) => break job,
+                        None => {
+                            state.idle += 1;
+                            state = self.queued.wait(state).unwrap();
+                            state.idle -= 1;
+                        }
+                    }
+                }
+            };
+            job();
+            let mut state = self.state.lock().unwrap();
+            if state.threads - state.blocked > pool_size() {
+                state.threads -= 1;
+                return
+            }
+        }
+    }
+
+    ///Waits on this thread. A thread of the pool is replaced while it waits, so the task it waits for can run
+    fn block<R>(&'static self, wait: impl FnOnce() -> R) -> R {
+        if !IN_POOL.with(|in_pool| in_pool.get()) {
+            return wait()
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            state.blocked += 1;
+            self.grow(&mut state);
+        }
+        let res = wait();
+        self.state.lock().unwrap().blocked -= 1;
+        res
+    }
+}
+
+lazy_static! {
+    static ref POOL: Pool = Pool::default();
+}
+
+///Tasks are numbered across all threads, so a task sent to another thread is not mistaken for one spawned there
+static NEXT_TASK: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    ///Where the results of the tasks spawned on this thread that have not been joined arrive
+    static TASKS: RefCell<HashMap<u64, Receiver<Result<Transfer, String>>>> = RefCell::new(HashMap::new());
+}
+
+///Starts calling the function on the thread pool, and gives the id of the task. The function gets a copy of what it can see
+pub fn spawn(closure: &Closure<Literal>, loc: Location) -> Result<u64, String> {
+    let task = Task::new(closure)?;
+    //The task has the options of the run that spawned it, and uses the same fuel
+    let run = eval::task_run();
+    let (sender, receiver) = mpsc::channel();
+    POOL.submit(Box::new(move || {
+        let _run = eval::enter_run(Rc::new(run));
+        let _ = sender.send(task.run(loc));
+    }));
+    let id = NEXT_TASK.fetch_add(1, Ordering::Relaxed);
+    TASKS.with(|tasks| tasks.borrow_mut().insert(id, receiver));
+    Ok(id)
+}
+
+///Waits for the task to end, and gives what its function returned. The run is stopped at the location if it runs out of time while waiting
+pub fn join(id: u64, loc: Location) -> EvalResult {
+    let receiver = match TASKS.with(|tasks| tasks.borrow_mut().remove(&id)) {
+        Some(receiver) => receiver,
+        None => return Err(Signal::Error(String::from("The task has already been joined, or was spawned on another thread"), loc))
+    };
+    let res = POOL.block(|| loop {
+        match receiver.recv_timeout(WAIT_SLICE) {
+            Ok(res) => return Ok(res),
+            Err(RecvTimeoutError::Timeout) => eval::check_deadline(loc)?,
+            Err(RecvTimeoutError::Disconnected) => return Err(Signal::Error(String::from("The task stopped without a result"), loc))
+        }
+    })?;
+    match res {
+        Ok(transfer) => transfer.into_literal(&mut |_| None).map_err(|msg| Signal::Error(msg, loc)),
+        Err(msg) => Err(Signal::Error(format!("The task failed: {msg}"), loc))
+    }
+}
+
+///Values sent between tasks, received in the order they were sent
+#[derive(Debug, Default)]
+pub struct Channel {
+    queue: Mutex<VecDeque<Transfer>>,
+
+    ///Wakes a receiver when a value is sent
+    sent: Condvar,
+}
+
+///Channels are equal when they are the same channel
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Channel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Sends a copy of the value
+    pub fn send(&self, value: &Literal) -> Result<(), String> {
+        let transfer = Transfer::new(value)?;
+        self.queue.lock().unwrap().push_back(transfer);
+        self.sent.notify_one();
+        Ok(())
+    }
+
+    ///Waits until there is a value, and takes it. Functions cannot be received, as the functions of the sender may not be here.
+    ///The run is stopped at the location if it runs out of time while waiting
+    pub fn recv(&self, loc: Location) -> EvalResult {
+        let transfer = POOL.block(|| {
+            let mut queue = self.queue.lock().unwrap();
+            loop {
+                match queue.pop_front() {
+                    Some(transfer) => return Ok(transfer),
+                    None => {
+                        queue = self.sent.wait_timeout(queue, WAIT_SLICE).unwrap().0;
+                        eval::check_deadline(loc)?;
+                    }
+                }
+            }
+        })?;
+        transfer.into_literal(&mut |_| None).map_err(|msg| Signal::Error(msg, loc))
+    }
+}
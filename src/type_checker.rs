@@ -1,10 +1,130 @@
+use std::cell::RefCell;
+
 use super::*;
 use Type::*;
 use Operator::*;
 use Exp::*;
+use interner::Symbol;
 
 type TypeResult = Result<Type, (String, Location)>;
 
+thread_local! {
+    ///Errors of the statements with errors while a whole program is checked, as checking goes on after them
+    static ERRORS: RefCell<Option<Vec<(String, Location)>>> = const { RefCell::new(None) };
+}
+
+///Type checks the program like Exp::type_check, but goes on after a statement with an error, so the errors of all statements are given
+pub fn check_program(program: &mut Exp, envir: &mut Environment<Type>) -> Result<Type, Vec<(String, Location)>> {
+    let previous = ERRORS.with(|errors| errors.replace(Some(Vec::new())));
+    let res = program.type_check(envir);
+    let mut errors = ERRORS.with(|errors| errors.replace(previous)).unwrap_or_default();
+    match res {
+        Ok(typ) if errors.is_empty() => return Ok(typ),
+        Ok(_) => (),
+        Err(err) => errors.push(err)
+    }
+    //A function called before it is declared is checked at the call
+    errors.sort_by_key(|(_, loc)| *loc);
+    errors.dedup();
+    Err(errors)
+}
+
+///Checks the statements and functions of a block in the scope it is given, so the host can put variables in it first
+///and read the types of the variables the block declares after
+pub fn check_block(exps: &mut [Exp], funs: &mut [(Symbol, Box<Function>)], loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+    //Structs are available in the whole block, like functions
+    for exp in exps.iter() {
+        let (id, decl, loc) = match exp {
+            StructDeclExp(id, fields, loc) => (id, TypeDecl::Struct(fields.clone(), Vec::new()), loc),
+            InterfaceDeclExp(id, methods, loc) => (id, TypeDecl::Interface(methods.clone()), loc),
+            _ => continue
+        };
+        if envir.type_exist_in_scope(id) {
+            return Err((format!("Type '{id}' already exist in this scope"), *loc))
+        }
+        envir.push_type(id, decl);
+    }
+    for exp in exps.iter() {
+        match exp {
+            StructDeclExp(_, fields, loc) => for (_, typ) in fields {
+                check_type_exists(typ, *loc, envir)?;
+            },
+            InterfaceDeclExp(_, methods, loc) => for (_, p_types, ret_type) in methods {
+                for typ in p_types.iter().chain([ret_type]) {
+                    check_type_exists(typ, *loc, envir)?;
+                }
+            },
+            ImplExp(interface, id, methods, loc) => {
+                //Keeps every implementation visible wherever its struct can be used through an interface
+                if envir.scope_depth != 1 {
+                    return Err((String::from("Implementations must be at the top level of the program"), *loc))
+                }
+                check_impl(interface, id, methods, funs, *loc, envir)?;
+            },
+            _ => {}
+        }
+    }
+
+    for (id, fun) in funs.iter() {
+        if envir.fun_exist_in_scope(id) {
+            return Err((format!("Variable '{id}' already exist in this scope"), loc))
+        }
+        envir.push_function(id, fun.clone());
+    }
+
+    envir.update_fun_envirs();
+
+    let mut returned: Type = Unit;
+    //Variables of statements with errors. Statements using them are skipped, as their errors would follow from the first
+    let mut failed: Vec<Symbol> = Vec::new();
+    let last = exps.len().saturating_sub(1);
+    for (i, exp) in exps.iter_mut().enumerate() {
+        if !failed.is_empty() && exp.uses_any(&failed) {
+            if let LetExp(id, _, _) = exp {
+                failed.push(*id);
+            }
+            returned = Any;
+            continue
+        }
+        //The last statement gives the type of the block, so its error is an error of the block
+        if i == last {
+            returned = exp.type_check(envir)?;
+            continue
+        }
+        let checkpoint = envir.checkpoint();
+        match exp.type_check(envir) {
+            Ok(typ) => returned = typ,
+            Err(err) => {
+                recover(err)?;
+                envir.restore(checkpoint);
+                if let LetExp(id, _, _) = exp {
+                    failed.push(*id);
+                }
+            }
+        }
+    }
+
+    //The functions are run as they were checked
+    for (id, fun) in funs.iter_mut() {
+        if let Some(closure) = envir.find_fun(id) {
+            *fun = closure.fun;
+        }
+    }
+
+    Ok(returned)
+}
+
+///Keeps the error of a statement when a whole program is checked, or else gives it back
+fn recover(err: (String, Location)) -> Result<(), (String, Location)> {
+    ERRORS.with(|errors| match errors.borrow_mut().as_mut() {
+        Some(errors) => {
+            errors.push(err);
+            Ok(())
+        },
+        None => Err(err)
+    })
+}
+
 impl<'a> Exp {
     pub fn type_check(&'a mut self, envir: &'a mut Environment<Type>) -> TypeResult {
         match self {
@@ -43,27 +163,36 @@ impl<'a> Exp {
                     (Bool, Bool) => Ok(Bool),
                     (left, right) => Err((format!("Invalid operation '{op}' for '{left}' and '{right}'"), *loc)),
                 },
+                Coalesce => match (check_unread(left, envir)?, right.type_check(envir)?) {
+                    (Optional(inner), right) => match inner.join(&right) {
+                        Some(typ) => {
+                            //A variable holding a 'none' takes its type from the default
+                            if let VarExp(id, loc) = left.as_ref() {
+                                narrow(id, &Optional(inner), &Optional(Box::new(typ.clone())), *loc, envir)?;
+                                check_read(id, envir.lookup_var(id).unwrap(), *loc, envir)?;
+                            }
+                            Ok(typ)
+                        },
+                        None => Err((format!("Right side of '{op}' must be '{inner}', got '{right}'"), *loc))
+                    },
+                    (left, _) => Err((format!("Left side of '{op}' must be an option, got '{left}'"), *loc)),
+                },
+                Range => match (left.type_check(envir)?, right.type_check(envir)?) {
+                    (Int, Int) => Ok(Array(Box::new(Int))),
+                    (left, right) => Err((format!("Invalid operation '{op}' for '{left}' and '{right}'"), *loc)),
+                },
                 And | Or => match (left.type_check(envir)?, right.type_check(envir)?) {
                     (Bool, Bool) => Ok(Bool),
                     (left, right) => Err((format!("Invalid operation '{op}' for '{left}' and '{right}'"), *loc)),
                 },
-                Assign => match (left.as_ref(), right.type_check(envir)?) {
-                    (VarExp(id, loc), value) => {
-                        let typ = match envir.lookup_var(id) {
-                            Ok(typ) => typ,
-                            Err(_) => return Err((format!("Variable '{id}' does not exist here"), *loc))
-                        };
-                        if typ != value {
-                            Err((format!("Cannot assign '{value}' to '{id}' which is '{typ}'"), *loc))
-                        } else {
-                            Ok(Unit)
-                        }
-                    },
-                    _ => Err((format!("Left side of assignment must be a variable name"), *loc))
+                Assign => {
+                    let value = right.type_check(envir)?;
+                    check_assign(left, &value, *loc, envir)?;
+                    Ok(Unit)
                 },
                 PlusAssign | MinusAssign => match left.as_ref() {
                     VarExp(id, loc) => {
-                        let vexp = Box::new(Exp::VarExp(id.clone(), *loc));
+                        let vexp = Box::new(Exp::VarExp(*id, *loc));
                         let op = match op {
                             PlusAssign => Plus,
                             MinusAssign => Minus,
@@ -72,7 +201,7 @@ impl<'a> Exp {
                         Exp::BinOpExp(vexp, op, right.clone(), *loc).type_check(envir)?;
                         Ok(Unit)
                     },
-                    _ => unreachable!("Not a variable id")
+                    _ => Err((format!("Left side of '{op}' must be a variable"), *loc))
                 },
                 Not => unreachable!("Not a binary operator"),
             },
@@ -95,42 +224,35 @@ impl<'a> Exp {
                     Literal::Bool(_) => Ok(Bool),
                     Literal::Char(_) => Ok(Char),
                     Literal::Str(_) => Ok(Str),
-                    Literal::Unit => unreachable!("Unit should not show up as a literal outside of returns"),
+                    Literal::Unit => Ok(Unit),
+                    //Values of the variables kept when a program is reloaded
+                    lit => Ok(lit.get_type()),
                 }
             },
             BlockExp(exps, funs, loc) => {
                 envir.enter_scope();
-
-                for i in 0..funs.len() {
-                    if envir.fun_exist_in_scope(&funs[i].0) {
-                        return Err((format!("Variable '{}' already exist in this scope", funs[i].0), *loc))
-                    }
-                    envir.push_function(funs[i].0.clone(), funs[i].1.clone());
-                }
-
-                envir.update_fun_envirs();
-
-                let mut returned: Type = Unit;
-                for exp in exps {
-                    returned = exp.type_check(envir)?;
-                }
-
+                let returned = check_block(exps, funs, *loc, envir)?;
                 envir.leave_scope();
-
                 Ok(returned)
             },
             VarExp(id, loc) => {
-                match envir.lookup_var(&id) {
-                    Ok(typ) => Ok(typ),
-                    Err(_) => Err((format!("Variable '{id}' does not exist here"), *loc)),
+                match envir.lookup_var(id) {
+                    Ok(typ) => check_read(id, typ, *loc, envir),
+                    //A named function used as a value
+                    Err(_) if envir.lookup_fun(id).is_ok() => {
+                        let fun = resolve_fun(id, *loc, envir)?;
+                        Ok(Fun(fun.param_types, Box::new(fun.ret_type)))
+                    },
+                    Err(_) if lookup_constant(id.as_str()).is_some() => LiteralExp(lookup_constant(id.as_str()).unwrap(), *loc).type_check(envir),
+                    Err(_) => Err((not_found("Variable", id, envir), *loc)),
                 }
             },
             LetExp(id, exp, loc) => {
-                if envir.var_exist_in_scope(&id) {
+                if envir.var_exist_in_scope(id) {
                     return Err((format!("Variable '{id}' already exist in this scope"), *loc))
                 }
                 let value = exp.type_check(envir)?;
-                envir.push_variable(id.clone(), value); 
+                envir.push_variable(id, value); 
                 Ok(Unit)
             },
             IfElseExp(cond, pos, neg, loc) => {
@@ -141,60 +263,168 @@ impl<'a> Exp {
                 let pos_type = pos.type_check(envir)?;
                 if let Some(neg) = neg {
                     let neg_type = neg.type_check(envir)?;
-                    if pos_type != neg_type {
-                        return Err((format!("If and else branch must have same type, got '{pos_type}' and '{neg_type}'"), *loc))
+                    match pos_type.join(&neg_type) {
+                        Some(typ) => Ok(typ),
+                        None => Err((format!("If and else branch must have same type, got '{pos_type}' and '{neg_type}'"), *loc))
                     }
-                    Ok(pos_type)
                 } else {
                     Ok(Unit)
                 }
             },
-            WhileExp(cond, _, loc) => {
+            WhileExp(cond, body, label, loc) => {
                 if cond.type_check(envir)? != Bool {
                     return Err((format!("Condition for while must be boolean, got '{cond}'"), *loc))
                 }
+                check_label(label, *loc, envir)?;
+                envir.enter_loop(label.clone());
+                body.type_check(envir)?;
+                envir.leave_loop();
                 Ok(Unit)
-            }
+            },
+            BreakExp(label, loc) => check_jump("break", label, *loc, envir),
+            ContinueExp(label, loc) => check_jump("continue", label, *loc, envir),
             FunCallExp(id, args, loc) => {
-                let mut closure = match envir.lookup_fun(id) {
-                    Ok(clo) => clo,
-                    Err(_) => return Err((format!("Function '{id}' does not exist here"), *loc))
+                let arg_types = match known_args(check_args(args, fills(id, envir), envir)?) {
+                    Ok(arg_types) => arg_types,
+                    Err(arg_types) => return check_spread_call(id, arg_types, *loc, envir)
                 };
-
-                if closure.fun.ret_type == Any {
-                    return Err((format!("Cannot call '{id}' here. '{id}' needs a type annotation as the call is prior to its definition"), *loc))
-                } else if !closure.declared {
-                    //Enables recursive calls to fun before it is declared
-                    let mut renv = envir.get_scope(closure.decl_scope());
-                    renv.declare_fun(id);
-                    closure.fun.ret_type = closure.fun.type_check(id, *loc, &mut renv)?;
+                let typ = check_call(id, arg_types.clone(), *loc, envir)?;
+                check_mutated(id, args.first(), &arg_types, *loc, envir)?;
+                Ok(typ)
+            },
+            MethodCallExp(exp, method, args, loc) => {
+                let receiver = match fills(method, envir) {
+                    true => check_unread(exp, envir)?,
+                    false => exp.type_check(envir)?
+                };
+                let mut arg_types = vec![receiver];
+                match known_args(check_args(args, false, envir)?) {
+                    Ok(types) => arg_types.extend(types),
+                    Err(_) => return Err((String::from("Arrays can only be spread into calls of named functions"), *loc))
+                }
+                check_method(exp, method, arg_types, *loc, envir)
+            },
+            InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _) | OperatorDeclExp(_, _) => Ok(Unit),
+            TupleExp(exps, _) => {
+                let mut types = Vec::new();
+                for exp in exps {
+                    types.push(exp.type_check(envir)?);
                 }
-                
-                if args.len() != closure.fun.param_types.len() {
-                    panic!("Incorrect argument count")
+                Ok(Tuple(types))
+            },
+            ArrayExp(exps, loc) => {
+                let mut elem = Any;
+                for exp in exps {
+                    let typ = match exp {
+                        SpreadExp(exp, loc) => match exp.type_check(envir)? {
+                            Array(typ) => *typ,
+                            typ => return Err((format!("Only arrays can be spread into an array, got '{typ}'"), *loc))
+                        },
+                        exp => exp.type_check(envir)?
+                    };
+                    elem = match elem.join(&typ) {
+                        Some(elem) => elem,
+                        None => return Err((format!("Elements of an array must have the same type, got '{elem}' and '{typ}'"), *loc))
+                    };
                 }
+                Ok(Array(Box::new(elem)))
+            },
+            IndexExp(exp, index, loc) => match (exp.type_check(envir)?, index.type_check(envir)?) {
+                (Array(elem), Int) => Ok(*elem),
+                //The char at the index, counted in chars
+                (Str, Int) => Ok(Char),
+                (Array(_) | Str, index) => Err((format!("Index must be 'int', got '{index}'"), *loc)),
+                (typ, _) => Err((format!("Cannot index into '{typ}'"), *loc)),
+            },
+            SpreadExp(_, loc) => Err((String::from("'...' can only be used in array literals and the arguments of calls"), *loc)),
+            StructDeclExp(_, _, _) => Ok(Unit),
+            StructExp(id, fields, loc) => {
+                let decl = match envir.lookup_struct(id) {
+                    Ok(decl) => decl,
+                    Err(_) => return Err((format!("Struct '{id}' does not exist here"), *loc))
+                };
 
-                for i in 0..args.len() {
-                    if args[i].type_check(envir)? != closure.fun.param_types[i] {
-                        panic!("Incorrect argument type")
+                for (field, _) in fields.iter() {
+                    if !decl.iter().any(|(id, _)| id == field) {
+                        return Err((format!("Struct '{id}' has no field '{field}'"), *loc))
+                    }
+                    if fields.iter().filter(|(id, _)| id == field).count() > 1 {
+                        return Err((format!("Field '{field}' is given more than once"), *loc))
                     }
                 }
 
-                Ok(closure.fun.ret_type)
+                //Put the fields in declaration order, so equal structs are stored equally
+                let mut ordered = Vec::new();
+                for (field, typ) in decl.iter() {
+                    let index = match fields.iter().position(|(id, _)| id == field) {
+                        Some(index) => index,
+                        None => return Err((format!("Missing field '{field}' for struct '{id}'"), *loc))
+                    };
+                    let (field, mut exp) = fields.remove(index);
+                    let value = exp.type_check(envir)?;
+                    if !fits(&value, typ, envir) {
+                        return Err((format!("Field '{field}' of '{id}' is '{typ}', got '{value}'"), *loc))
+                    }
+                    ordered.push((field, exp));
+                }
+                *fields = ordered;
+
+                Ok(Struct(id.clone()))
+            },
+            FieldExp(exp, field, loc) => match exp.type_check(envir)? {
+                Struct(id) => {
+                    let decl = match envir.lookup_struct(&id) {
+                        Ok(decl) => decl,
+                        Err(_) => return Err((format!("Struct '{id}' does not exist here"), *loc))
+                    };
+                    match decl.into_iter().find(|(name, _)| name == field) {
+                        Some((_, typ)) => Ok(typ),
+                        None => Err((format!("Struct '{id}' has no field '{field}'"), *loc))
+                    }
+                },
+                typ => Err((format!("Cannot access field '{field}' on '{typ}'"), *loc))
+            },
+            DestructExp(pattern, exp, loc) => {
+                let value = exp.type_check(envir)?;
+                bind_pattern(pattern, &value, *loc, envir)?;
+                Ok(Unit)
+            },
+            OptionExp(exp, _) => match exp {
+                Some(exp) => Ok(Optional(Box::new(exp.type_check(envir)?))),
+                None => Ok(Optional(Box::new(Any))),
             },
             FunDeclExp(id, loc) => {
-                envir.declare_fun(&id);
-                let mut clo = envir.lookup_fun(&id).unwrap();
-                let res = clo.fun.type_check(&id, *loc, &mut clo.envir)?;
-                
-                Ok(res)
+                envir.declare_fun(id);
+                let mut clo = envir.lookup_fun(id).unwrap();
+                let ret_type = clo.fun.type_check(id, *loc, &mut clo.envir)?;
+                if clo.fun.ret_type == Any {
+                    clo.fun.ret_type = ret_type;
+                }
+                envir.update_fun(id, clo.fun);
+
+                //A declaration has no value, like when it is evaluated
+                Ok(Unit)
             },
-            ForExp(let_exp, cond, increment, body, _) => {
+            ForInExp(id, exp, body, label, loc) => {
+                let typ = exp.type_check(envir)?;
+                let elem = check_iterator(exp, typ, *loc, envir)?;
+                check_label(label, *loc, envir)?;
+                envir.enter_scope();
+                envir.push_variable(id, elem);
+                envir.enter_loop(label.clone());
+                body.type_check(envir)?;
+                envir.leave_loop();
+                envir.leave_scope();
+                Ok(Unit)
+            },
+            ForExp(let_exp, cond, increment, body, label, loc) => {
+                check_label(label, *loc, envir)?;
                 envir.enter_scope();
                 let_exp.type_check(envir)?;
-                cond.type_check(envir)?;
-                increment.type_check(envir)?;
+                check_for(cond, increment, envir)?;
+                envir.enter_loop(label.clone());
                 body.type_check(envir)?;
+                envir.leave_loop();
                 envir.leave_scope();
                 Ok(Unit)
             },
@@ -202,21 +432,541 @@ impl<'a> Exp {
     }
 }
 
+///Gives the element type of a value that can be looped over. It must have a 'next' method
+///or function giving the next element and the rest of the iteration, or none when it is done
+fn check_iterator(exp: &Exp, typ: Type, loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+    let next = check_method(exp, &Symbol::new("next"), vec![typ.clone()], loc, envir)
+        .map_err(|_| (format!("Cannot iterate over '{typ}', it needs a 'next' method giving 'option<(T, {typ})>'"), loc))?;
+    match next {
+        Optional(inner) => match *inner {
+            Tuple(types) if types.len() == 2 && fits(&types[1], &typ, envir) => Ok(types[0].clone()),
+            //Empty arrays and sets
+            Any => Ok(Any),
+            _ => Err((format!("'next' of '{typ}' must give 'option<(T, {typ})>'"), loc))
+        },
+        _ => Err((format!("'next' of '{typ}' must give 'option<(T, {typ})>'"), loc))
+    }
+}
+
+///Checks a call of a method on the first of the argument types. Without such a method, 'x.f(a)' is 'f(x, a)'
+fn check_method(receiver_exp: &Exp, method: &Symbol, arg_types: Vec<Type>, loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+    let receiver = arg_types[0].clone();
+    let name = match &receiver {
+        Struct(name) => name.clone(),
+        _ => String::new()
+    };
+    match envir.lookup_type(&name) {
+        //Dispatched at runtime to the implementation of the struct
+        Ok(TypeDecl::Interface(methods)) => match methods.into_iter().find(|(id, _, _)| id == method.as_str()) {
+            Some((_, p_types, ret_type)) => {
+                if p_types.len() != arg_types.len() - 1 {
+                    return Err((format!("'{method}' takes {} arguments, got {}", p_types.len(), arg_types.len() - 1), loc))
+                }
+                for (arg, typ) in arg_types[1..].iter().zip(&p_types) {
+                    if !fits(arg, typ, envir) {
+                        return Err((format!("'{method}' expects '{typ}', got '{arg}'"), loc))
+                    }
+                }
+                Ok(ret_type)
+            },
+            None => Err((format!("Interface '{name}' has no method '{method}'"), loc))
+        },
+        Ok(TypeDecl::Struct(_, _)) if envir.lookup_fun(&Symbol::new(&format!("{name}.{method}"))).is_ok() => {
+            check_call(&Symbol::new(&format!("{name}.{method}")), arg_types, loc, envir)
+        },
+        //Uniform function call syntax: 'x.f(a)' is 'f(x, a)'
+        _ => if envir.lookup_fun(method).is_ok() || called_builtin(method, envir).is_some()
+            || matches!(envir.lookup_var(method), Ok(Fun(_, _))) {
+            let typ = check_call(method, arg_types.clone(), loc, envir)?;
+            check_mutated(method, Some(receiver_exp), &arg_types, loc, envir)?;
+            Ok(typ)
+        } else {
+            Err((format!("'{receiver}' has no method '{method}', and no function '{method}' exists here"), loc))
+        }
+    }
+}
+
+///Type of an argument of a call. A spread array gives any number of arguments
+enum ArgType {
+    One(Type),
+    Many(Type)
+}
+
+///Types of the arguments of a call. A spread tuple gives one argument per element.
+///The first is checked with check_unread if the call fills it
+fn check_args(args: &mut [Exp], fills_first: bool, envir: &mut Environment<Type>) -> Result<Vec<ArgType>, (String, Location)> {
+    let mut types = Vec::new();
+    for (i, arg) in args.iter_mut().enumerate() {
+        match arg {
+            SpreadExp(exp, loc) => match exp.type_check(envir)? {
+                Tuple(elems) => types.extend(elems.into_iter().map(ArgType::One)),
+                Array(elem) => types.push(ArgType::Many(*elem)),
+                typ => return Err((format!("Only arrays and tuples can be spread, got '{typ}'"), *loc))
+            },
+            arg if i == 0 && fills_first => types.push(ArgType::One(check_unread(arg, envir)?)),
+            arg => types.push(ArgType::One(arg.type_check(envir)?))
+        }
+    }
+    Ok(types)
+}
+
+///The argument types if no array is spread, so the number of arguments is known
+fn known_args(types: Vec<ArgType>) -> Result<Vec<Type>, Vec<ArgType>> {
+    if types.iter().any(|typ| matches!(typ, ArgType::Many(_))) {
+        return Err(types)
+    }
+    Ok(types.into_iter().map(|typ| match typ {
+        ArgType::One(typ) | ArgType::Many(typ) => typ
+    }).collect())
+}
+
+///Checks a call with spread arrays. Each argument must fit all the parameters it can end up at.
+///The number of arguments is checked when it runs
+fn check_spread_call(id: &Symbol, arg_types: Vec<ArgType>, loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+    let (p_types, ret_type) = if envir.lookup_fun(id).is_ok() {
+        let fun = resolve_fun(id, loc, envir)?;
+        (fun.param_types, fun.ret_type)
+    } else {
+        match envir.lookup_var(id) {
+            Ok(Fun(p_types, ret_type)) => (p_types, *ret_type),
+            _ if called_builtin(id, envir).is_some() => return Err((format!("Arrays cannot be spread into the builtin '{id}'"), loc)),
+            _ => return Err((not_found("Function", id, envir), loc))
+        }
+    };
+
+    let is_one = |typ: &&ArgType| matches!(typ, ArgType::One(_));
+    let is_many = |typ: &ArgType| matches!(typ, ArgType::Many(_));
+    let count = arg_types.iter().filter(is_one).count();
+    if count > p_types.len() {
+        return Err((format!("'{id}' takes {} arguments, got at least {count}", p_types.len()), loc))
+    }
+
+    for (i, arg) in arg_types.iter().enumerate() {
+        let before = arg_types[..i].iter().filter(is_one).count();
+        let after = arg_types[i + 1..].iter().filter(is_one).count();
+        let last = p_types.len() - 1 - after;
+        let (typ, first, last) = match arg {
+            ArgType::One(typ) if !arg_types[..i].iter().any(is_many) => (typ, before, before),
+            ArgType::One(typ) if !arg_types[i + 1..].iter().any(is_many) => (typ, last, last),
+            ArgType::One(typ) | ArgType::Many(typ) => (typ, before, last)
+        };
+        for p_type in p_types.iter().take(last + 1).skip(first) {
+            if !fits(typ, p_type, envir) {
+                return Err((format!("'{id}' expects '{p_type}', got '{typ}'"), loc))
+            }
+        }
+    }
+
+    Ok(ret_type)
+}
+
+///Checks the arguments of a call to a named function, a function value or a builtin, and gives its return type
+fn check_call(id: &Symbol, arg_types: Vec<Type>, loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+    if envir.lookup_fun(id).is_err() {
+        let signature = match envir.lookup_var(id) {
+            Ok(Fun(p_types, ret_type)) => Some((p_types, *ret_type)),
+            _ => host::lookup_host_function(id).map(|host| (host.params, host.ret_type))
+        };
+        return match signature {
+            Some((p_types, ret_type)) => {
+                if p_types.len() != arg_types.len() {
+                    return Err((format!("'{id}' takes {} arguments, got {}", p_types.len(), arg_types.len()), loc))
+                }
+                for (arg, typ) in arg_types.iter().zip(&p_types) {
+                    if !fits(arg, typ, envir) {
+                        return Err((format!("'{id}' expects '{typ}', got '{arg}'"), loc))
+                    }
+                }
+                Ok(ret_type)
+            },
+            None => match called_builtin(id, envir) {
+                Some(builtin) => (builtin.check)(&arg_types).map_err(|msg| (format!("'{id}' {msg}"), loc)),
+                None => Err((not_found("Function", id, envir), loc))
+            }
+        }
+    }
+    let fun = resolve_fun(id, loc, envir)?;
+    
+    if arg_types.len() != fun.param_types.len() {
+        return Err((format!("'{id}' takes {} arguments, got {}", fun.param_types.len(), arg_types.len()), loc))
+    }
+
+    for (arg, typ) in arg_types.iter().zip(&fun.param_types) {
+        if !fits(arg, typ, envir) {
+            return Err((format!("'{id}' expects '{typ}', got '{arg}'"), loc))
+        }
+    }
+
+    Ok(fun.ret_type)
+}
+
+///That the variable or function does not exist, with the name in scope that is closest to it when it looks misspelled
+fn not_found(kind: &str, id: &Symbol, envir: &Environment<Type>) -> String {
+    let id = id.as_str();
+    let names = envir.variables().into_iter().map(|(name, _)| name)
+        .chain(envir.functions().into_iter().map(|(name, _)| name))
+        .map(|name| name.to_string())
+        .chain(BUILTINS.iter().map(|builtin| builtin.name.to_string()));
+    //Methods are named 'Struct.method', and names starting with '.' are made by the parser
+    let closest = names.filter(|name| !name.contains('.'))
+        .map(|name| (edit_distance(id, &name), name))
+        .filter(|(distance, _)| *distance <= (id.chars().count() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance);
+    match closest {
+        Some((_, name)) => format!("{kind} '{id}' does not exist here, did you mean '{name}'?"),
+        None => format!("{kind} '{id}' does not exist here")
+    }
+}
+
+///How many chars must be inserted, removed or replaced to turn one text into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    //Distances from the first chars of a to each start of b
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let replaced = diagonal + usize::from(a_char != b[j]);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+///Looks up a named function. Its body is checked first if the return type is not known yet
+fn resolve_fun(id: &Symbol, loc: Location, envir: &mut Environment<Type>) -> Result<Box<Function>, (String, Location)> {
+    let mut closure = match envir.lookup_fun(id) {
+        Ok(clo) => clo,
+        Err(_) => return Err((not_found("Function", id, envir), loc))
+    };
+
+    if closure.fun.ret_type == Any {
+        return Err((format!("Cannot call '{id}' here. '{id}' needs a type annotation as the call is prior to its definition"), loc))
+    } else if !closure.declared {
+        //Enables recursive calls to fun before it is declared
+        let mut renv = envir.get_scope(closure.decl_scope());
+        renv.declare_fun(id);
+        closure.fun.ret_type = closure.fun.type_check(id, loc, &mut renv)?;
+    }
+
+    Ok(closure.fun)
+}
+
+///The builtin a call refers to, unless a function, a function value or a host function has the name
+fn called_builtin(id: &Symbol, envir: &Environment<Type>) -> Option<&'static Builtin> {
+    if envir.lookup_fun(id).is_ok() || matches!(envir.lookup_var(id), Ok(Fun(_, _))) || host::lookup_host_function(id).is_some() {
+        None
+    } else {
+        lookup_builtin_symbol(id)
+    }
+}
+
+///Builtins that change their first argument store the result in it, so it must be a variable.
+///If it holds an empty array or set, or a new channel, it takes the element type of the first value put into it
+fn check_mutated(id: &Symbol, first: Option<&Exp>, arg_types: &[Type], loc: Location, envir: &mut Environment<Type>) -> Result<(), (String, Location)> {
+    match (called_builtin(id, envir), first) {
+        (Some(builtin), Some(first)) if builtin.mutates && !matches!(first, VarExp(_, _)) => {
+            Err((format!("'{id}' changes its first argument, so it must be a variable"), loc))
+        },
+        (Some(_), Some(VarExp(var, _))) if fills(id, envir) && id.as_str() != "len" => {
+            let value = Box::new(arg_types[arg_types.len() - 1].clone());
+            let filled = match &arg_types[0] {
+                Array(_) => Array(value),
+                Set(_) => Set(value),
+                Channel(_) => Channel(value),
+                _ => return Ok(())
+            };
+            narrow(var, &arg_types[0], &filled, loc, envir)
+        },
+        _ => Ok(())
+    }
+}
+
+///Builtins whose first argument is a container they put a value into, or only count. Reading a variable there does not
+///depend on its element type, so it can still get one from what is put into it
+fn fills(id: &Symbol, envir: &Environment<Type>) -> bool {
+    matches!(id.as_str(), "push" | "insert" | "add" | "send" | "len") && called_builtin(id, envir).is_some()
+}
+
+///Type of the expression, without marking a variable as read. For the first argument of a builtin that fills it,
+///and a variable that takes its type from where it is used
+fn check_unread(exp: &mut Exp, envir: &mut Environment<Type>) -> TypeResult {
+    match exp {
+        VarExp(id, _) => match envir.lookup_var(id) {
+            Ok(typ) => Ok(typ),
+            Err(_) => exp.type_check(envir)
+        },
+        exp => exp.type_check(envir)
+    }
+}
+
+///Reading a variable whose type still holds 'any' keeps it from getting a type later, as what was read may rely on the 'any'.
+///A new channel is shared by its copies, so it cannot be copied before its element type is known
+fn check_read(id: &Symbol, typ: Type, loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+    if has_open_channel(&typ) {
+        return Err((format!("'{id}' holds a channel with no element type yet. Send a value to it before using it elsewhere"), loc))
+    }
+    if typ.is_open() {
+        envir.mark_read(id);
+    }
+    Ok(typ)
+}
+
+fn has_open_channel(typ: &Type) -> bool {
+    match typ {
+        Channel(elem) => elem.is_open(),
+        Optional(typ) | Array(typ) | Set(typ) | Task(typ) => has_open_channel(typ),
+        Tuple(types) => types.iter().any(has_open_channel),
+        Fun(params, ret_type) => params.iter().any(has_open_channel) || has_open_channel(ret_type),
+        _ => false
+    }
+}
+
+///Gives an open variable the type of the first value put into it. After it was read with the open type, it cannot change
+fn narrow(id: &Symbol, typ: &Type, value: &Type, loc: Location, envir: &mut Environment<Type>) -> Result<(), (String, Location)> {
+    let narrowed = filled(typ, value);
+    if narrowed == *typ {
+        return Ok(())
+    }
+    if envir.was_read(id) {
+        return Err((format!("'{id}' is used while it is '{typ}', so it cannot become '{narrowed}' later"), loc))
+    }
+    envir.mutate(id, narrowed);
+    Ok(())
+}
+
+///The type with each 'any' replaced by the part of the value type at its place.
+///The other parts are kept, so a struct put where an interface is expected stays the interface
+fn filled(typ: &Type, value: &Type) -> Type {
+    match (typ, value) {
+        (Any, value) => value.clone(),
+        (Optional(typ), Optional(value)) => Optional(Box::new(filled(typ, value))),
+        (Array(typ), Array(value)) => Array(Box::new(filled(typ, value))),
+        (Set(typ), Set(value)) => Set(Box::new(filled(typ, value))),
+        (Task(typ), Task(value)) => Task(Box::new(filled(typ, value))),
+        (Channel(typ), Channel(value)) => Channel(Box::new(filled(typ, value))),
+        (Tuple(types), Tuple(values)) if types.len() == values.len() => {
+            Tuple(types.iter().zip(values).map(|(typ, value)| filled(typ, value)).collect())
+        },
+        (Fun(params, ret_type), Fun(values, ret_value)) if params.len() == values.len() => {
+            Fun(params.iter().zip(values).map(|(typ, value)| filled(typ, value)).collect(), Box::new(filled(ret_type, ret_value)))
+        },
+        (typ, _) => typ.clone()
+    }
+}
+
+///Like Type::matches, but a struct also fits the interfaces it implements
+fn fits(value: &Type, expected: &Type, envir: &Environment<Type>) -> bool {
+    match (value, expected) {
+        (Struct(id), Struct(interface)) if id != interface => match envir.lookup_type(id) {
+            Ok(TypeDecl::Struct(_, interfaces)) => interfaces.contains(interface),
+            _ => false
+        },
+        (Optional(value), Optional(expected))
+        | (Array(value), Array(expected))
+        | (Set(value), Set(expected))
+        | (Channel(value), Channel(expected)) => fits(value, expected, envir),
+        (Tuple(values), Tuple(expected)) => values.len() == expected.len()
+            && values.iter().zip(expected).all(|(value, expected)| fits(value, expected, envir)),
+        (value, expected) => value.matches(expected)
+    }
+}
+
+///Checks that the struct has all the methods of the interface with the right types, and records that it implements it
+fn check_impl(interface: &String, id: &String, methods: &Vec<String>, funs: &mut [(Symbol, Box<Function>)], loc: Location, envir: &mut Environment<Type>) -> Result<(), (String, Location)> {
+    let signatures = match envir.lookup_interface(interface) {
+        Ok(signatures) => signatures,
+        Err(_) => return Err((format!("Interface '{interface}' does not exist here"), loc))
+    };
+    let (fields, mut interfaces) = match envir.lookup_type(id) {
+        Ok(TypeDecl::Struct(fields, interfaces)) => (fields, interfaces),
+        _ => return Err((format!("Struct '{id}' does not exist here"), loc))
+    };
+    if interfaces.contains(interface) {
+        return Err((format!("'{id}' already implements '{interface}'"), loc))
+    }
+
+    for method in methods {
+        if !signatures.iter().any(|(name, _, _)| name == method) {
+            return Err((format!("'{method}' is not a method of '{interface}'"), loc))
+        }
+    }
+
+    for (method, p_types, ret_type) in signatures {
+        let fun = match funs.iter_mut().find(|(name, _)| name.as_str() == format!("{id}.{method}")) {
+            Some((_, fun)) if methods.contains(&method) => fun,
+            _ => return Err((format!("Missing method '{method}' of '{interface}' for '{id}'"), loc))
+        };
+        if fun.param_types[1..] != p_types {
+            return Err((format!("Parameters of '{method}' do not match the interface '{interface}'"), fun.loc))
+        }
+        if fun.ret_type == Any {
+            fun.ret_type = ret_type;
+        } else if fun.ret_type != ret_type {
+            return Err((format!("'{method}' must return '{ret_type}' as declared in '{interface}', got '{}'", fun.ret_type), fun.loc))
+        }
+    }
+
+    interfaces.push(interface.clone());
+    envir.mutate_type(id, TypeDecl::Struct(fields, interfaces));
+    Ok(())
+}
+
+///The left side of an assignment is a variable or a tuple of them, which takes apart a tuple value
+fn check_assign(target: &Exp, value: &Type, loc: Location, envir: &mut Environment<Type>) -> Result<(), (String, Location)> {
+    match (target, value) {
+        (VarExp(id, loc), value) => {
+            let typ = match envir.lookup_var(id) {
+                Ok(typ) => typ,
+                Err(_) => return Err((not_found("Variable", id, envir), *loc))
+            };
+            if !fits(value, &typ, envir) {
+                Err((format!("Cannot assign '{value}' to '{id}' which is '{typ}'"), *loc))
+            } else {
+                narrow(id, &typ, value, *loc, envir)
+            }
+        },
+        (TupleExp(targets, loc), Tuple(types)) => {
+            if targets.len() != types.len() {
+                return Err((format!("Cannot assign '{value}' to {} variables", targets.len()), *loc))
+            }
+            for (target, typ) in targets.iter().zip(types) {
+                check_assign(target, typ, *loc, envir)?;
+            }
+            Ok(())
+        },
+        (TupleExp(_, loc), value) => Err((format!("Cannot destructure '{value}' in assignment"), *loc)),
+        _ => Err((String::from("Left side of assignment must be a variable name"), loc))
+    }
+}
+
+///Checks that the pattern fits the type, and declares the variables it binds
+fn bind_pattern(pattern: &Pattern, typ: &Type, loc: Location, envir: &mut Environment<Type>) -> Result<(), (String, Location)> {
+    match (pattern, typ) {
+        (Pattern::Id(id), typ) => {
+            if envir.var_exist_in_scope(id) {
+                return Err((format!("Variable '{id}' already exist in this scope"), loc))
+            }
+            envir.push_variable(id, typ.clone());
+            Ok(())
+        },
+        (Pattern::Tuple(patterns), Tuple(types)) => {
+            if patterns.len() != types.len() {
+                return Err((format!("Pattern '{pattern}' has {} elements, but '{typ}' has {}", patterns.len(), types.len()), loc))
+            }
+            for (pattern, typ) in patterns.iter().zip(types) {
+                bind_pattern(pattern, typ, loc, envir)?;
+            }
+            Ok(())
+        },
+        (Pattern::Struct(id, fields), Struct(name)) if id == name => {
+            let decl = match envir.lookup_struct(id) {
+                Ok(decl) => decl,
+                Err(_) => return Err((format!("Struct '{id}' does not exist here"), loc))
+            };
+            for (field, pattern) in fields {
+                match decl.iter().find(|(name, _)| name == field) {
+                    Some((_, typ)) => bind_pattern(pattern, typ, loc, envir)?,
+                    None => return Err((format!("Struct '{id}' has no field '{field}'"), loc))
+                }
+            }
+            Ok(())
+        },
+        (pattern, typ) => Err((format!("Cannot destructure '{typ}' with pattern '{pattern}'"), loc))
+    }
+}
+
+///Names in annotations must refer to declared structs or interfaces
+fn check_type_exists(typ: &Type, loc: Location, envir: &Environment<Type>) -> Result<(), (String, Location)> {
+    match typ {
+        Struct(id) => match envir.lookup_type(id) {
+            Ok(_) => Ok(()),
+            Err(_) => Err((format!("Type '{id}' does not exist here"), loc))
+        },
+        Optional(typ) | Array(typ) | Set(typ) => check_type_exists(typ, loc, envir),
+        Fun(types, ret_type) => {
+            for typ in types.iter().chain([ret_type.as_ref()]) {
+                check_type_exists(typ, loc, envir)?;
+            }
+            Ok(())
+        },
+        Tuple(types) => {
+            for typ in types {
+                check_type_exists(typ, loc, envir)?;
+            }
+            Ok(())
+        },
+        _ => Ok(())
+    }
+}
+
+///Checks the comparison and increment the parser makes from 'for (i, from, to, step)' or 'for (n)'. What the user wrote,
+///the count and the step, is checked first, so an error is about the for instead of an operator it was turned into
+fn check_for(cond: &mut Exp, increment: &mut Exp, envir: &mut Environment<Type>) -> Result<(), (String, Location)> {
+    match cond {
+        BinOpExp(var, _, count, _) => {
+            var.type_check(envir)?;
+            match count.type_check(envir)? {
+                Int | Float => (),
+                typ => return Err((format!("Count in for must be int or float, got '{typ}'"), count.loc()))
+            }
+        },
+        cond => { cond.type_check(envir)?; }
+    }
+    match increment {
+        //The variable keeps the type of where it starts from
+        BinOpExp(var, _, step, _) => match (var.type_check(envir)?, step.type_check(envir)?) {
+            (Int, Int) | (Float, Int | Float) => (),
+            (Int, Float) => return Err((String::from("Step in for must be int when it counts from an int, got 'float'"), step.loc())),
+            (_, typ) => return Err((format!("Step in for must be int or float, got '{typ}'"), step.loc()))
+        },
+        increment => { increment.type_check(envir)?; }
+    }
+    Ok(())
+}
+
+///Nested loops must not reuse the label of an enclosing loop
+fn check_label(label: &Option<String>, loc: Location, envir: &Environment<Type>) -> Result<(), (String, Location)> {
+    match label {
+        Some(id) if envir.loop_exist(label) => Err((format!("Label '{id}' is already used by an enclosing loop"), loc)),
+        _ => Ok(())
+    }
+}
+
+///Break and continue must target an enclosing loop
+fn check_jump(kind: &str, label: &Option<String>, loc: Location, envir: &Environment<Type>) -> TypeResult {
+    if envir.loop_exist(label) {
+        Ok(Unit)
+    } else if let Some(label) = label {
+        Err((format!("Cannot {kind} '{label}', no enclosing loop has that label"), loc))
+    } else {
+        Err((format!("Cannot {kind} outside of a loop"), loc))
+    }
+}
+
 impl Function {
-    pub fn type_check(&mut self, id: &String, loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+    pub fn type_check(&mut self, id: &Symbol, loc: Location, envir: &mut Environment<Type>) -> TypeResult {
+        for typ in self.param_types.iter().chain([&self.ret_type]) {
+            check_type_exists(typ, loc, envir)?;
+        }
+
+        let loops = envir.take_loops();
         envir.enter_scope();
 
         for i in 0..self.param_types.len() {
-            envir.push_variable(self.params[i].clone(), self.param_types[i].clone());
+            envir.push_variable(&self.params[i], self.param_types[i].clone());
         }
 
         let res = self.exp.type_check(envir)?;
         
         envir.leave_scope();
+        envir.restore_loops(loops);
 
         if self.ret_type == Any {
-            envir.update_return_type(id, res)
-        } else if self.ret_type != res {
+            envir.update_return_type(id, res.clone())
+        } else if !fits(&res, &self.ret_type, envir) {
             return Err((format!("Return type does not match annotation, got '{res}' but '{}' was annotated", self.ret_type), loc))
         }
 
@@ -0,0 +1,97 @@
+use super::*;
+use interner::Symbol;
+use Exp::*;
+
+///Looks at every node of a tree. By default each method goes on into the children, so an implementation only overrides
+///the nodes it is about, and calls walk_exp in them to go on to their children
+pub trait Visitor {
+    fn visit_exp(&mut self, exp: &Exp) {
+        walk_exp(self, exp)
+    }
+
+    ///A function declared in a block, after the statements of the block are visited
+    fn visit_function(&mut self, _id: &Symbol, fun: &Function) {
+        walk_function(self, fun)
+    }
+}
+
+///Visits the direct subexpressions of the expression, and the functions declared in it if it is a block
+pub fn walk_exp<V: Visitor + ?Sized>(visitor: &mut V, exp: &Exp) {
+    match exp {
+        BlockExp(exps, funs, _) => {
+            for exp in exps {
+                visitor.visit_exp(exp);
+            }
+            for (id, fun) in funs {
+                visitor.visit_function(id, fun);
+            }
+        },
+        _ => for child in exp.children() {
+            visitor.visit_exp(child);
+        }
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, fun: &Function) {
+    visitor.visit_exp(&fun.exp);
+}
+
+///Rebuilds a tree node by node. By default each method rebuilds the node from its folded children, so an implementation
+///only overrides the nodes it changes, and calls fold_children in them to fold their children as well
+pub trait Folder {
+    fn fold_exp(&mut self, exp: Exp) -> Exp {
+        fold_children(self, exp)
+    }
+
+    ///A function declared in a block
+    fn fold_function(&mut self, _id: &Symbol, fun: Box<Function>) -> Box<Function> {
+        fold_function_body(self, fun)
+    }
+}
+
+///Rebuilds the expression with each of its direct subexpressions folded, and the functions declared in it if it is a block
+pub fn fold_children<F: Folder + ?Sized>(folder: &mut F, exp: Exp) -> Exp {
+    let mut boxed = |exp: Box<Exp>| Box::new(folder.fold_exp(*exp));
+    match exp {
+        BinOpExp(left, op, right, loc) => BinOpExp(boxed(left), op, boxed(right), loc),
+        UnOpExp(op, exp, loc) => UnOpExp(op, boxed(exp), loc),
+        WhileExp(cond, body, label, loc) => WhileExp(boxed(cond), boxed(body), label, loc),
+        ForExp(init, cond, incr, body, label, loc) => ForExp(boxed(init), boxed(cond), boxed(incr), boxed(body), label, loc),
+        ForInExp(id, exp, body, label, loc) => ForInExp(id, boxed(exp), boxed(body), label, loc),
+        LetExp(id, exp, loc) => LetExp(id, boxed(exp), loc),
+        IfElseExp(cond, pos, neg, loc) => IfElseExp(boxed(cond), boxed(pos), neg.map(boxed), loc),
+        FieldExp(exp, field, loc) => FieldExp(boxed(exp), field, loc),
+        DestructExp(pattern, exp, loc) => DestructExp(pattern, boxed(exp), loc),
+        SpreadExp(exp, loc) => SpreadExp(boxed(exp), loc),
+        IndexExp(exp, index, loc) => IndexExp(boxed(exp), boxed(index), loc),
+        OptionExp(exp, loc) => OptionExp(exp.map(boxed), loc),
+        MethodCallExp(receiver, method, args, loc) => {
+            let receiver = boxed(receiver);
+            MethodCallExp(receiver, method, fold_all(folder, args), loc)
+        },
+        BlockExp(exps, funs, loc) => {
+            let exps = fold_all(folder, exps);
+            let funs = funs.into_iter().map(|(id, fun)| {
+                let fun = folder.fold_function(&id, fun);
+                (id, fun)
+            }).collect();
+            BlockExp(exps, funs, loc)
+        },
+        FunCallExp(id, args, loc) => FunCallExp(id, fold_all(folder, args), loc),
+        TupleExp(exps, loc) => TupleExp(fold_all(folder, exps), loc),
+        ArrayExp(exps, loc) => ArrayExp(fold_all(folder, exps), loc),
+        StructExp(name, fields, loc) => StructExp(name, fields.into_iter().map(|(field, exp)| (field, folder.fold_exp(exp))).collect(), loc),
+        exp @ (LiteralExp(_, _) | VarExp(_, _) | BreakExp(_, _) | ContinueExp(_, _) | FunDeclExp(_, _)
+            | StructDeclExp(_, _, _) | InterfaceDeclExp(_, _, _) | ImplExp(_, _, _, _) | OperatorDeclExp(_, _)) => exp
+    }
+}
+
+pub fn fold_function_body<F: Folder + ?Sized>(folder: &mut F, mut fun: Box<Function>) -> Box<Function> {
+    let body = std::mem::replace(&mut fun.exp, Box::new(LiteralExp(Literal::Unit, fun.loc)));
+    fun.exp = Box::new(folder.fold_exp(*body));
+    fun
+}
+
+fn fold_all<F: Folder + ?Sized>(folder: &mut F, exps: Vec<Exp>) -> Vec<Exp> {
+    exps.into_iter().map(|exp| folder.fold_exp(exp)).collect()
+}
@@ -0,0 +1,504 @@
+use std::fmt::Display;
+
+use super::*;
+use eval::{Signal, EvalResult, binary_op, unary_op, index_op, max_call_depth, step, type_error};
+use options::ExecutionOptions;
+use ir::{Ir, Var};
+use interner::Symbol;
+use Literal::*;
+
+///A bytecode instruction. Jumps go to an index in the code of the same function
+#[derive(Clone)]
+pub enum Op {
+    Push(Literal),
+    Load(Var),
+    Store(Var),
+    Pop,
+    Binary(Operator, Location),
+    Unary(Operator, Location),
+    Jump(usize),
+
+    ///Jumps back to the start of a loop, which uses fuel
+    Loop(usize, Location),
+
+    ///Pops the condition
+    JumpIfFalse(usize),
+
+    ///Pops an option. If it has a value, the value is pushed and it jumps
+    JumpIfSome(usize),
+
+    ///Pops two values and compares them with the operator, jumping if the comparison is false.
+    ///Only made by the peephole pass, from a comparison followed by a JumpIfFalse
+    CompareJump(Operator, usize, Location),
+
+    ///Function, number of arguments
+    Call(usize, usize, Location),
+
+    ///A call in tail position, which replaces the frame of the caller
+    TailCall(usize, usize, Location),
+
+    ///Builtin, number of arguments, where a changed first argument is stored
+    CallBuiltin(&'static Builtin, usize, Option<Var>, Location),
+    Return,
+
+    ///Makes a tuple or array of the given number of values on top of the stack
+    Tuple(usize),
+    Array(usize),
+    Some,
+    Index(Location),
+}
+
+///A compiled function
+pub struct Chunk {
+    name: Symbol,
+    code: Vec<Op>,
+
+    ///Parameters and local variables
+    slots: usize,
+}
+
+///A compiled program. The first chunk is the top level of the program
+pub struct Bytecode {
+    chunks: Vec<Chunk>,
+    globals: usize,
+}
+
+struct Loop {
+    ///Stack depth outside the loop
+    depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+///The function being compiled
+struct Compiler {
+    code: Vec<Op>,
+
+    ///Values on the stack above the local variables
+    depth: usize,
+    loops: Vec<Loop>,
+
+    ///Whether calls in tail position can replace the frame. Not in the top level
+    tail_calls: bool,
+}
+
+///Compiles a type checked program. Gives the name of the first feature the VM does not support, if any.
+///Those programs can still run with the tree-walking interpreter
+pub fn compile(program: &Exp) -> Result<Bytecode, String> {
+    let program = ir::lower(program)?;
+    let chunks = program.functions.iter().enumerate().map(|(i, fun)| {
+        let mut compiler = Compiler { code: Vec::new(), depth: 0, loops: Vec::new(), tail_calls: i > 0 };
+        compiler.ir(&fun.body, true);
+        compiler.emit(Op::Return);
+        Chunk { name: fun.name, code: compiler.code, slots: fun.slots }
+    }).collect();
+
+    Ok(Bytecode { chunks, globals: program.globals })
+}
+
+impl Compiler {
+    ///Adds an instruction, keeping track of how many values it leaves on the stack
+    fn emit(&mut self, op: Op) -> usize {
+        match &op {
+            Op::Push(_) | Op::Load(_) => self.depth += 1,
+            Op::Store(_) | Op::Pop | Op::Binary(_, _) | Op::JumpIfFalse(_) | Op::JumpIfSome(_)
+            | Op::Index(_) | Op::Return => self.depth -= 1,
+            Op::Call(_, args, _) | Op::TailCall(_, args, _) | Op::CallBuiltin(_, args, _, _)
+            | Op::Tuple(args) | Op::Array(args) => self.depth = self.depth + 1 - args,
+            Op::CompareJump(_, _, _) => self.depth -= 2,
+            Op::Unary(_, _) | Op::Jump(_) | Op::Loop(_, _) | Op::Some => {}
+        }
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    ///Where the next instruction goes
+    fn next(&self) -> usize {
+        self.code.len()
+    }
+
+    ///Makes the jump at 'at' go to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.next();
+        if let Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfSome(to) = &mut self.code[at] {
+            *to = target
+        }
+    }
+
+    ///Compiles code that leaves the value of the expression on the stack
+    fn ir(&mut self, ir: &Ir, tail: bool) {
+        match ir {
+            Ir::Const(lit) => {
+                self.emit(Op::Push(lit.clone()));
+            },
+            Ir::Load(var) => {
+                self.emit(Op::Load(*var));
+            },
+            Ir::Store(var, value) => {
+                self.ir(value, false);
+                self.emit(Op::Store(*var));
+                self.emit(Op::Push(Unit));
+            },
+            Ir::Binary(op, left, right, loc) => {
+                self.ir(left, false);
+                self.ir(right, false);
+                self.emit(Op::Binary(*op, *loc));
+            },
+            Ir::Unary(op, value, loc) => {
+                self.ir(value, false);
+                self.emit(Op::Unary(*op, *loc));
+            },
+            Ir::Coalesce(left, right) => {
+                self.ir(left, false);
+                let jump = self.emit(Op::JumpIfSome(0));
+                self.ir(right, false);
+                self.patch(jump);
+            },
+            Ir::If(cond, pos, neg) => {
+                self.ir(cond, false);
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                let depth = self.depth;
+                self.ir(pos, tail);
+                let to_end = self.emit(Op::Jump(0));
+                self.depth = depth;
+                self.patch(to_else);
+                self.ir(neg, tail);
+                self.patch(to_end);
+            },
+            Ir::Loop(cond, body, increment, loc) => {
+                let start = self.next();
+                self.loops.push(Loop { depth: self.depth, breaks: Vec::new(), continues: Vec::new() });
+                self.ir(cond, false);
+                let to_end = self.emit(Op::JumpIfFalse(0));
+                self.ir(body, false);
+                self.emit(Op::Pop);
+                let continue_target = self.next();
+                if let Some(increment) = increment {
+                    self.ir(increment, false);
+                    self.emit(Op::Pop);
+                }
+                self.emit(Op::Loop(start, *loc));
+                self.patch(to_end);
+
+                let lp = self.loops.pop().unwrap();
+                for jump in lp.breaks {
+                    self.patch(jump);
+                }
+                for jump in lp.continues {
+                    if let Op::Jump(to) = &mut self.code[jump] {
+                        *to = continue_target
+                    }
+                }
+                self.emit(Op::Push(Unit));
+            },
+            Ir::Break(out) | Ir::Continue(out) => {
+                let depth = self.depth;
+                let target = self.loops.len() - 1 - out;
+                //Values of the expressions it is inside are left behind
+                for _ in self.loops[target].depth..depth {
+                    self.emit(Op::Pop);
+                }
+                let jump = self.emit(Op::Jump(0));
+                let lp = &mut self.loops[target];
+                if matches!(ir, Ir::Break(_)) {
+                    lp.breaks.push(jump);
+                } else {
+                    lp.continues.push(jump);
+                }
+                //Code after the jump is not reached, but it is compiled as if the jump gave unit
+                self.depth = depth + 1;
+            },
+            Ir::Seq(irs) => {
+                if irs.is_empty() {
+                    self.emit(Op::Push(Unit));
+                }
+                for (i, ir) in irs.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(Op::Pop);
+                    }
+                    self.ir(ir, tail && i == irs.len() - 1);
+                }
+            },
+            Ir::Call(index, args, loc) => {
+                self.irs(args);
+                if tail && self.tail_calls {
+                    self.emit(Op::TailCall(*index, args.len(), *loc));
+                } else {
+                    self.emit(Op::Call(*index, args.len(), *loc));
+                }
+            },
+            Ir::CallBuiltin(builtin, args, store, loc) => {
+                self.irs(args);
+                self.emit(Op::CallBuiltin(builtin, args.len(), *store, *loc));
+            },
+            Ir::Tuple(irs) => {
+                self.irs(irs);
+                self.emit(Op::Tuple(irs.len()));
+            },
+            Ir::Array(irs) => {
+                self.irs(irs);
+                self.emit(Op::Array(irs.len()));
+            },
+            Ir::Some(value) => {
+                self.ir(value, false);
+                self.emit(Op::Some);
+            },
+            Ir::Index(array, index, loc) => {
+                self.ir(array, false);
+                self.ir(index, false);
+                self.emit(Op::Index(*loc));
+            },
+        }
+    }
+
+    fn irs(&mut self, irs: &[Ir]) {
+        for ir in irs {
+            self.ir(ir, false);
+        }
+    }
+}
+
+impl Bytecode {
+    ///Instructions in all of the functions
+    pub fn instruction_count(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.code.len()).sum()
+    }
+}
+
+///How many instructions a program had before and after the peephole pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeepholeStats {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Display for PeepholeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bytecode: {} instructions, {} after the peephole pass", self.before, self.after)
+    }
+}
+
+///Rewrites short sequences of instructions into fewer that do the same. Conditional jumps on a constant become a jump or nothing,
+///values that are pushed only to be popped are not pushed, and a comparison followed by a conditional jump becomes one instruction
+pub fn peephole(bytecode: &mut Bytecode) -> PeepholeStats {
+    let before = bytecode.instruction_count();
+    for chunk in &mut bytecode.chunks {
+        //A rewrite can make another possible, as when a store is followed by a unit that is pushed and popped
+        while peephole_pass(&mut chunk.code) {}
+    }
+    PeepholeStats { before, after: bytecode.instruction_count() }
+}
+
+///Rewrites each pair of instructions that can be rewritten, and gives whether any were
+fn peephole_pass(code: &mut Vec<Op>) -> bool {
+    //Another path joins at an instruction that is jumped to, so it cannot be merged with the one before it
+    let mut targets = vec![false; code.len() + 1];
+    for op in code.iter_mut() {
+        if let Some(to) = jump_target(op) {
+            targets[*to] = true;
+        }
+    }
+
+    let mut rewritten = Vec::with_capacity(code.len());
+    //Where each instruction is in the rewritten code. One that was removed is where the code after it is
+    let mut moved = Vec::with_capacity(code.len() + 1);
+    let mut i = 0;
+    while i < code.len() {
+        moved.push(rewritten.len());
+        let next = code.get(i + 1).filter(|_| !targets[i + 1]);
+        let pair = match (&code[i], next) {
+            (Op::Push(_) | Op::Load(_), Some(Op::Pop)) => Some(None),
+            (Op::Push(Bool(true)), Some(Op::JumpIfFalse(_))) => Some(None),
+            (Op::Push(Bool(false)), Some(Op::JumpIfFalse(to))) => Some(Some(Op::Jump(*to))),
+            (Op::Binary(op, loc), Some(Op::JumpIfFalse(to))) if is_comparison(op) => Some(Some(Op::CompareJump(*op, *to, *loc))),
+            _ => None
+        };
+        match pair {
+            Some(op) => {
+                moved.push(rewritten.len());
+                rewritten.extend(op);
+                i += 2;
+            },
+            None => {
+                rewritten.push(code[i].clone());
+                i += 1;
+            }
+        }
+    }
+    moved.push(rewritten.len());
+
+    if rewritten.len() == code.len() {
+        return false
+    }
+    for op in rewritten.iter_mut() {
+        if let Some(to) = jump_target(op) {
+            *to = moved[*to];
+        }
+    }
+    *code = rewritten;
+    true
+}
+
+fn jump_target(op: &mut Op) -> Option<&mut usize> {
+    match op {
+        Op::Jump(to) | Op::Loop(to, _) | Op::JumpIfFalse(to) | Op::JumpIfSome(to) | Op::CompareJump(_, to, _) => Some(to),
+        _ => None
+    }
+}
+
+fn is_comparison(op: &Operator) -> bool {
+    matches!(op, Operator::LessThan | Operator::GreaterThan | Operator::LessOrEquals | Operator::GreaterOrEquals | Operator::Equals | Operator::NotEquals)
+}
+
+///A function being run
+#[derive(Clone, Copy)]
+struct CallFrame {
+    chunk: usize,
+    ip: usize,
+
+    ///Where its local variables start on the stack
+    base: usize,
+
+    ///Where it was called. Not used for the top level
+    loc: Location,
+}
+
+///Runs a compiled program
+pub fn run(bytecode: &Bytecode, options: &ExecutionOptions) -> EvalResult {
+    let _run = eval::start_run(options);
+    let mut callers: Vec<CallFrame> = Vec::new();
+    let mut frame = CallFrame { chunk: 0, ip: 0, base: 0, loc: Location::point(0, 0) };
+    execute(bytecode, &mut frame, &mut callers).map_err(|signal| {
+        //The running function is the innermost call
+        std::iter::once(frame).chain(callers.into_iter().rev())
+            .filter(|frame| frame.chunk != 0)
+            .fold(signal, |signal, frame| signal.traced(bytecode.chunks[frame.chunk].name, frame.loc))
+    })
+}
+
+///Runs the program until it returns or fails. The frame and callers are left as they were when it failed
+fn execute(bytecode: &Bytecode, frame: &mut CallFrame, callers: &mut Vec<CallFrame>) -> EvalResult {
+    let mut stack: Vec<Literal> = vec![Unit; bytecode.chunks[0].slots];
+    let mut globals = vec![Unit; bytecode.globals];
+    let max_depth = max_call_depth();
+
+    loop {
+        let op = &bytecode.chunks[frame.chunk].code[frame.ip];
+        frame.ip += 1;
+        match op {
+            Op::Push(lit) => stack.push(lit.clone()),
+            Op::Load(Var::Local(slot)) => stack.push(stack[frame.base + slot].clone()),
+            Op::Load(Var::Global(slot)) => stack.push(globals[*slot].clone()),
+            Op::Store(var) => {
+                let value = stack.pop().unwrap();
+                store(*var, value, frame.base, &mut stack, &mut globals);
+            },
+            Op::Pop => {
+                stack.pop();
+            },
+            Op::Binary(op, loc) => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                stack.push(binary_op(op, left, right, *loc)?);
+            },
+            Op::Unary(op, loc) => {
+                let value = stack.pop().unwrap();
+                stack.push(unary_op(op, value, *loc)?);
+            },
+            Op::Jump(to) => frame.ip = *to,
+            Op::Loop(to, loc) => {
+                step(*loc)?;
+                frame.ip = *to;
+            },
+            Op::JumpIfFalse(to) => match stack.pop().unwrap() {
+                Bool(false) => frame.ip = *to,
+                Bool(true) => {},
+                //Jumps have no location, so the error is at the call
+                _ => return Err(type_error(frame.loc))
+            },
+            Op::CompareJump(op, to, loc) => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                if let Bool(false) = binary_op(op, left, right, *loc)? {
+                    frame.ip = *to;
+                }
+            },
+            Op::JumpIfSome(to) => match stack.pop().unwrap() {
+                Optional(Some(lit)) => {
+                    stack.push(*lit);
+                    frame.ip = *to;
+                },
+                Optional(None) => {},
+                _ => return Err(type_error(frame.loc))
+            },
+            Op::Call(chunk, args, loc) => {
+                step(*loc)?;
+                if callers.len() >= max_depth {
+                    return Err(Signal::Error(format!("Maximum recursion depth exceeded, calls can only go {max_depth} deep"), *loc))
+                }
+                callers.push(*frame);
+                *frame = CallFrame { chunk: *chunk, ip: 0, base: stack.len() - args, loc: *loc };
+                profiler::enter(bytecode.chunks[*chunk].name);
+                stack.resize(frame.base + bytecode.chunks[*chunk].slots, Unit);
+            },
+            Op::TailCall(chunk, args, loc) => {
+                step(*loc)?;
+                //Moves the arguments to where the frame starts
+                let args_start = stack.len() - args;
+                stack.drain(frame.base..args_start);
+                *frame = CallFrame { chunk: *chunk, ip: 0, base: frame.base, loc: *loc };
+                profiler::leave();
+                profiler::enter(bytecode.chunks[*chunk].name);
+                stack.resize(frame.base + bytecode.chunks[*chunk].slots, Unit);
+            },
+            Op::CallBuiltin(builtin, args, target, loc) => {
+                let mut args = stack.split_off(stack.len() - args);
+                //The variable lets go of its value during the call, so the builtin can change it without copying it
+                if let Some(var) = target {
+                    store(*var, Unit, frame.base, &mut stack, &mut globals);
+                }
+                let value = (builtin.eval)(&mut args, *loc);
+                if let Some(var) = target {
+                    store(*var, args.swap_remove(0), frame.base, &mut stack, &mut globals);
+                }
+                stack.push(value?);
+            },
+            Op::Return => {
+                let value = stack.pop().unwrap();
+                stack.truncate(frame.base);
+                match callers.pop() {
+                    Some(caller) => {
+                        profiler::leave();
+                        *frame = caller;
+                    },
+                    None => return Ok(value)
+                }
+                stack.push(value);
+            },
+            Op::Tuple(len) => {
+                let lits = stack.split_off(stack.len() - len);
+                stack.push(Tuple(lits.into()));
+            },
+            Op::Array(len) => {
+                let lits = stack.split_off(stack.len() - len);
+                stack.push(Array(lits.into()));
+            },
+            Op::Some => {
+                let value = stack.pop().unwrap();
+                stack.push(Optional(Some(Box::new(value))));
+            },
+            Op::Index(loc) => {
+                let index = stack.pop().unwrap();
+                let value = stack.pop().unwrap();
+                stack.push(index_op(value, index, *loc)?);
+            },
+        }
+    }
+}
+
+fn store(var: Var, value: Literal, base: usize, stack: &mut [Literal], globals: &mut [Literal]) {
+    match var {
+        Var::Local(slot) => stack[base + slot] = value,
+        Var::Global(slot) => globals[slot] = value,
+    }
+}
@@ -0,0 +1,224 @@
+use std::{io::Cursor, sync::{Arc, Mutex}, thread};
+
+use nebulang::{Interpreter, Value, Error, options::{ExecutionOptions, Hooks}};
+
+fn eval(source: &str) -> Value {
+    match Interpreter::new().eval(source) {
+        Ok(value) => value,
+        Err(err) => panic!("'{source}' failed: {err}")
+    }
+}
+
+fn runtime_error(source: &str) -> String {
+    match Interpreter::new().eval(source) {
+        Err(Error::Runtime(signal)) => signal.message(),
+        res => panic!("Expected a runtime error for '{source}', got {res:?}")
+    }
+}
+
+#[test]
+fn string_functions() {
+    assert_eq!(eval("substring(\"héllo\", 1, 4)"), Value::from("éll"));
+    assert_eq!(eval("to_string(split(\"a,b,,c\", \",\"))"), Value::from("[\"a\", \"b\", \"\", \"c\"]"));
+    assert_eq!(eval("trim(\"  x y   \")"), Value::from("x y"));
+    assert_eq!(eval("to_upper(\"abc\") + to_lower(\"DEF\")"), Value::from("ABCdef"));
+    assert_eq!(eval("replace(\"a-b-c\", \"-\", \"+\")"), Value::from("a+b+c"));
+    assert_eq!(eval("starts_with(\"nebulang\", \"neb\") && contains(\"nebulang\", \"bul\") && contains(\"nebulang\", 'g')"), Value::from(true));
+    assert_eq!(runtime_error("split(\"abc\", \"\")"), "Cannot split on an empty string");
+    assert!(runtime_error("substring(\"abc\", 2, 5)").contains("5"));
+}
+
+#[test]
+fn array_functions() {
+    assert_eq!(eval("let a = [1, 2]; a.push(3); a.insert(0, 0); let last = a.pop(); let first = a.remove(0); to_string((a, last, first))"),
+        Value::from("([1, 2], some(3), 0)"));
+    assert_eq!(eval("fun square(x: int) = x * x; fun odd(x: int) = x % 2 == 1; fun add(a: int, b: int) = a + b;
+        [1, 2, 3, 4].filter(odd).map(square).reduce(0, add)"), Value::from(10));
+    assert_eq!(eval("let a = [1]; a.pop(); to_string(a.pop())"), Value::from("none"));
+    assert!(runtime_error("let a = [1, 2]; a.remove(2)").contains("2"));
+}
+
+#[test]
+fn sets_keep_unique_values_in_order() {
+    assert_eq!(eval("let s = to_set([3, 1, 3]); let added = s.add(2); let again = s.add(1); to_string((s, added, again))"),
+        Value::from("({3, 1, 2}, true, false)"));
+    assert_eq!(eval("let s = to_set([1, 2]); (s.remove(1), s.remove(5), len(s), contains(s, 2))"),
+        Value::Tuple(vec![true.into(), false.into(), 1.into(), true.into()].into()));
+    assert_eq!(eval("to_string((union(to_set([1, 2]), to_set([2, 3])), intersect(to_set([1, 2]), to_set([2, 3]))))"),
+        Value::from("({1, 2, 3}, {2})"));
+    assert_eq!(eval("typeof(to_set([\"a\"]))"), Value::from("set<string>"));
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn regular_expressions() {
+    assert_eq!(eval("regex_match(\"order 66\", \"[0-9]+\")"), Value::from(true));
+    assert_eq!(eval("to_string((regex_find(\"order 66\", \"[0-9]+\"), regex_find(\"order\", \"[0-9]+\")))"), Value::from("(some(\"66\"), none)"));
+    assert_eq!(eval("regex_replace(\"2024-01-31\", \"([0-9]+)-([0-9]+)-([0-9]+)\", \"$3.$2.$1\")"), Value::from("31.01.2024"));
+    assert!(runtime_error("regex_match(\"a\", \"(\")").contains("regex"));
+}
+
+#[test]
+fn math_functions() {
+    assert_eq!(eval("sqrt(pow(3, 2) + pow(4, 2))"), Value::from(5.0));
+    assert_eq!(eval("(pow(2, 10), abs(-3), floor(2.7), ceil(2.1), round(-2.5), round(2.5))"),
+        Value::Tuple(vec![1024.into(), 3.into(), 2.into(), 3.into(), (-3).into(), 3.into()].into()));
+    assert_eq!(eval("(min(2, 3), max(2, 3.5), clamp(15, 0, 10))"), Value::Tuple(vec![2.into(), 3.5.into(), 10.into()].into()));
+    assert_eq!(eval("sin(0) + cos(0) + log(E) + exp(0) + tan(0)"), Value::from(3.0));
+    assert_eq!(eval("floor(PI * 100)"), Value::from(314));
+    assert!(runtime_error("pow(2, -1)").contains("-1"));
+    assert_eq!(runtime_error("pow(2, 64)"), "Overflow in pow(2, 64)");
+    assert!(runtime_error("floor(10000000000.0 * 10000000000.0)").contains("does not fit in an int"));
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn random_numbers_stay_in_their_range() {
+    assert_eq!(eval("let ok = true; for(i, 0, 200) { let r = random(); let n = random_int(-2, 3); ok = ok && r >= 0.0 && r < 1.0 && n >= -2 && n < 3 }; ok"),
+        Value::from(true));
+    assert_eq!(eval("to_string(sort(shuffle([5, 1, 4, 2, 3])))"), Value::from("[1, 2, 3, 4, 5]"));
+    assert!(runtime_error("random_int(3, 3)").contains("3"));
+    let options = ExecutionOptions { random: false, ..ExecutionOptions::default() };
+    assert!(matches!(Interpreter::with_options(options).eval("random()"), Err(Error::Runtime(signal)) if signal.message().contains("is not allowed")));
+}
+
+#[test]
+fn time_builtins() {
+    assert_eq!(eval("let start = clock(); let ms = now_ms(); sleep_ms(20); let took = clock() - start;
+        took >= 0.02 && now_ms() - ms >= 20 && now() > 1600000000.0"), Value::from(true));
+    let options = ExecutionOptions { time: false, ..ExecutionOptions::default() };
+    assert!(matches!(Interpreter::with_options(options.clone()).eval("now()"), Err(Error::Runtime(signal)) if signal.message().contains("is not allowed")));
+    let timer = Interpreter::with_options(options).eval("fun never(): unit = (); after(10, never)");
+    assert!(matches!(timer, Err(Error::Runtime(signal)) if signal.message() == "Setting timers is not allowed"));
+}
+
+#[test]
+fn files_are_written_and_read() {
+    let path = std::env::temp_dir().join(format!("nebulang-builtins-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    assert_eq!(eval(&format!("let path = \"{path}\"; write_file(path, \"a\"); append_file(path, \"b\"); (file_exists(path), read_file(path))")),
+        Value::Tuple(vec![true.into(), "ab".into()].into()));
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(eval(&format!("file_exists(\"{path}\")")), Value::from(false));
+    assert!(runtime_error(&format!("read_file(\"{path}\")")).contains(path));
+    let sandboxed = Interpreter::with_options(ExecutionOptions::default().sandboxed()).eval(&format!("write_file(\"{path}\", \"a\")"));
+    assert!(matches!(sandboxed, Err(Error::Runtime(signal)) if signal.message().contains("is not allowed")));
+    assert!(!std::path::Path::new(path).exists());
+}
+
+#[test]
+fn env_gives_none_for_variables_that_are_not_set() {
+    let path = std::env::var("PATH").unwrap();
+    assert_eq!(eval("env(\"PATH\") ?? \"\""), Value::from(path));
+    assert_eq!(eval("to_string(env(\"NEBULANG_NOT_SET_ANYWHERE\"))"), Value::from("none"));
+}
+
+#[test]
+fn typeof_names_the_type() {
+    assert_eq!(eval("typeof(1) + \" \" + typeof(1.5) + \" \" + typeof(\"s\") + \" \" + typeof('c') + \" \" + typeof(true)"),
+        Value::from("int float string char bool"));
+    assert_eq!(eval("struct P { x: int } fun f(x: int) = [x]; (typeof([\"a\"]), typeof((1, some(P { x: 1 }))), typeof(f))"),
+        Value::Tuple(vec!["[string]".into(), "(int, option<P>)".into(), "fun(int): [int]".into()].into()));
+}
+
+#[test]
+fn format_builds_strings() {
+    assert_eq!(eval("format(\"{} + {} = {}\", 1, 2, 3)"), Value::from("1 + 2 = 3"));
+    assert_eq!(eval("format(\"{{{}}} {}\", \"a\", [1.5])"), Value::from("{a} [1.5]"));
+    assert_eq!(runtime_error("format(\"{} {}\", 1)"), "Format string has more '{}' than the 1 values given");
+}
+
+#[test]
+fn sleep_waits_for_the_time() {
+    let before = std::time::Instant::now();
+    assert_eq!(eval("sleep_ms(50)"), Value::Unit);
+    assert!(before.elapsed() >= std::time::Duration::from_millis(50));
+    assert_eq!(runtime_error("sleep_ms(-1)"), "Cannot sleep for a negative time, got -1");
+}
+
+#[test]
+fn each_run_prints_and_reads_through_its_own_hooks() {
+    //The runs are at the same time, and none of them sees what another prints or reads
+    let runs: Vec<_> = (0..4).map(|i| thread::spawn(move || {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let slept = Arc::new(Mutex::new(Vec::new()));
+        let recorded = slept.clone();
+        let mut hooks = Hooks {
+            output: output.clone(),
+            input: Arc::new(Mutex::new(Cursor::new(format!("line {i}\n")))),
+            args: vec![i.to_string()],
+            sleep: Arc::new(Mutex::new(Box::new(move |duration| {
+                recorded.lock().unwrap().push(duration.as_millis());
+                Ok(())
+            }))),
+            ..Hooks::default()
+        };
+        let program = "print(\"{} {};\", read_line(), args()[0]); sleep_ms(1000)";
+        for _ in 0..20 {
+            Interpreter::with_options(ExecutionOptions { hooks: hooks.clone(), ..ExecutionOptions::default() }).eval(program).unwrap();
+            hooks.input = Arc::new(Mutex::new(Cursor::new(format!("line {i}\n"))));
+        }
+        let printed = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let sleeps = slept.lock().unwrap().clone();
+        (i, printed, sleeps)
+    })).collect();
+    for run in runs {
+        let (i, printed, sleeps) = run.join().unwrap();
+        assert_eq!(printed, format!("line {i} {i};").repeat(20));
+        assert_eq!(sleeps, vec![1000; 20]);
+    }
+}
+
+#[test]
+fn min_max_and_clamp() {
+    assert_eq!(eval("(min(-1, 4), max(-1, 4), min(1, 0.5), max(2.5, 1), clamp(-3, 0, 10), clamp(0.5, 1, 2))"),
+        Value::Tuple(vec![(-1).into(), 4.into(), 0.5.into(), 2.5.into(), 0.into(), 1.0.into()].into()));
+    assert_eq!(eval("typeof(min(1, 2)) + typeof(min(1, 2.0))"), Value::from("intfloat"));
+    assert_eq!(runtime_error("clamp(1, 5, 2)"), "Cannot clamp with 5 above 2");
+    assert!(matches!(Interpreter::new().eval("min(1, \"2\")"), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn char_classification() {
+    assert_eq!(eval("(is_digit('7'), is_digit('a'), is_alpha('é'), is_alpha('1'), is_whitespace(' '), is_whitespace('x'))"),
+        Value::Tuple(vec![true.into(), false.into(), true.into(), false.into(), true.into(), false.into()].into()));
+    assert_eq!(eval("to_string((to_upper('a'), to_lower('Ä')))"), Value::from("('A', 'ä')"));
+}
+
+#[test]
+fn to_string_works_on_every_value() {
+    assert_eq!(eval("struct P { x: int, s: string } fun f(x: int) = x;
+        to_string((1, 2.5, \"s\", 'c', true, some(none), [1], to_set([1]), P { x: 1, s: \"a\" }, f, ()))"),
+        Value::from("(1, 2.5, \"s\", 'c', true, some(none), [1], {1}, P { x: 1, s: \"a\" }, fun, Unit)"));
+    assert_eq!(eval("to_string(\"top\") + to_string('c')"), Value::from("topc"));
+    assert_eq!(eval("fun one(): int = 1; to_string(spawn(one)).starts_with(\"task\")"), Value::from(true));
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http_get_gives_errors_as_runtime_errors() {
+    assert!(runtime_error("http_get(\"not a url\")").starts_with("Request failed"));
+    let sandboxed = Interpreter::with_options(ExecutionOptions::default().sandboxed()).eval("http_get(\"http://localhost/\")");
+    assert!(matches!(sandboxed, Err(Error::Runtime(signal)) if signal.message().contains("is not allowed")));
+}
+
+#[cfg(not(feature = "http"))]
+#[test]
+fn http_get_is_left_out_without_the_feature() {
+    assert!(matches!(Interpreter::new().eval("http_get(\"http://localhost/\")"), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn assertions_fail_with_both_values() {
+    assert_eq!(eval("fun double(x: int) = x * 2; assert_eq([1, 2].map(double), [2, 4]); assert_ne(1, 2)"), Value::Unit);
+    assert_eq!(runtime_error("assert_eq(1, 2)"), "Assertion failed, 1 is not equal to 2");
+    assert_eq!(runtime_error("assert_ne(\"a\", \"a\")"), "Assertion failed, both values are \"a\"");
+    assert!(matches!(Interpreter::new().eval("assert_eq(1, \"1\")"), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn equal_values_hash_the_same() {
+    assert_eq!(eval("struct P { x: int } hash((1, \"a\", [P { x: 2 }])) == hash((1, \"a\", [P { x: 2 }]))"), Value::from(true));
+    assert_eq!(eval("hash(to_set([1, 2])) == hash(to_set([1, 2])) && hash(1) != hash(2) && hash(\"ab\") != hash(\"ba\")"), Value::from(true));
+    assert_eq!(eval("hash(\"stable\")"), eval("hash(\"stable\")"));
+}
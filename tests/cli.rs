@@ -0,0 +1,92 @@
+use std::{fs, io::Write, process::{Command, Output, Stdio}, sync::atomic::{AtomicUsize, Ordering}};
+
+static SCRIPTS: AtomicUsize = AtomicUsize::new(0);
+
+///Runs the binary on the source with the options and arguments, and gives what it wrote
+fn run_with_args(options: &[&str], source: &str, arguments: &[&str], input: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("nebulang-cli-{}-{}.nbl", std::process::id(), SCRIPTS.fetch_add(1, Ordering::Relaxed)));
+    fs::write(&path, source).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nebulang"))
+        .args(options).arg(&path).args(arguments)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+fn run(options: &[&str], source: &str, input: &str) -> Output {
+    run_with_args(options, source, &[], input)
+}
+
+///What the program printed, between the lines the binary prints before and after it
+fn printed(output: &Output) -> String {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let start = stdout.find("Running\n").map_or(0, |start| start + "Running\n".len());
+    let end = stdout.rfind("Returned: ").unwrap_or(stdout.len());
+    stdout[start..end].to_string()
+}
+
+#[test]
+fn println_replaces_each_placeholder() {
+    let output = run(&[], "let a = [1, 2]; println(\"{} has {} elements, {{}}\", a, len(a)); print('c'); println(\"s\")", "");
+    assert_eq!(printed(&output), "[1, 2] has 2 elements, {}\ncs\n");
+}
+
+#[test]
+fn read_line_reads_until_the_input_ends() {
+    let output = run(&[], "let name = read_line(); let age = read_int(); println(\"{} is {}\", name, age + 1); read_line()", "Ada\n36\n");
+    assert!(printed(&output).starts_with("Ada is 37\nRuntime Error[E0310]: No more input to read"));
+}
+
+#[test]
+fn args_are_those_after_the_path() {
+    let output = run_with_args(&["--sandbox"], "println(args()); len(args())", &["a", "b c", "--vm"], "");
+    assert_eq!(printed(&output), "[\"a\", \"b c\", \"--vm\"]\n");
+    assert_eq!(printed(&run(&[], "print(len(args()))", "")), "0");
+}
+
+#[test]
+fn exit_ends_the_program_with_the_status() {
+    let output = run(&[], "print(1); exit(3); print(2)", "");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("Running\n1"));
+    assert_eq!(run(&["--vm"], "fun f(): unit = exit(4); f()", "").status.code(), Some(4));
+    assert_eq!(run(&[], "1", "").status.code(), Some(0));
+}
+
+#[test]
+fn eprint_writes_to_standard_error() {
+    let output = run(&[], "eprint(1); eprintln(\"{} and {}\", 'a', [2]); println(\"out\")", "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "1a and [2]\n");
+    assert_eq!(printed(&output), "out\n");
+}
+
+#[test]
+fn events_call_their_callbacks_after_the_program() {
+    let source = "fun greet(name: string): unit = println(\"Hello \" + name);
+        fun later(): unit = emit(\"greet\", \"later\");
+        on(\"greet\", greet);
+        on(\"line\", greet);
+        after(200, later);
+        emit(\"greet\", \"now\");
+        println(\"first\")";
+    let output = run(&["--events"], source, "a\nb\n");
+    assert_eq!(printed(&output), "first\nHello now\nHello a\nHello b\nHello later\n");
+    let output = run(&[], "fun never(): unit = (); after(-1, never)", "");
+    assert!(printed(&output).contains("Cannot wait for a negative time, got -1"));
+}
+
+#[test]
+fn diagnostics_can_be_printed_as_json() {
+    let output = run(&["--diagnostics=json"], "let x = \"s\";\nx * true", "");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(r#"{"code":"E0201","kind":"Type Error","message":"Invalid operation '*' for 'string' and 'bool'","location":{"line":2,"col":3"#), "{stderr}");
+}
+
+#[test]
+fn scripts_can_start_with_a_shebang() {
+    let output = run(&[], "\u{FEFF}#!/usr/bin/env nebulang\nprintln(\"ran\")", "");
+    assert_eq!(printed(&output), "ran\n");
+}
@@ -0,0 +1,46 @@
+use nebulang::{Interpreter, Error};
+
+///The diagnostic of the error the source gives, rendered with the source
+fn rendered(source: &str) -> String {
+    match Interpreter::new().eval(source) {
+        Err(err) => err.diagnostic().render(source),
+        Ok(value) => panic!("Expected an error for '{source}', got {value:?}")
+    }
+}
+
+fn trace(source: &str) -> Vec<String> {
+    match Interpreter::new().eval(source) {
+        Err(Error::Runtime(signal)) => signal.trace(),
+        res => panic!("Expected a runtime error for '{source}', got {res:?}")
+    }
+}
+
+#[test]
+fn runtime_errors_show_the_calls_they_went_through() {
+    assert_eq!(trace("fun inner(a: [int]) = a[3];\nfun outer(): int = 1 + inner([1]);\nouter()"),
+        ["in 'inner', called at line 2:24", "in 'outer', called at line 3:1"]);
+    let deep = trace("fun down(n: int): int = if (n == 0) 1 / n else 1 + down(n - 1);\ndown(3)");
+    assert_eq!(deep, ["in 'down', called at line 1:52", "... the same call 2 more times", "in 'down', called at line 2:1"]);
+    assert!(rendered("fun f(): int = 1 / 0;\nf()").contains("= in 'f', called at line 2:1"));
+}
+
+#[test]
+fn errors_show_their_line_with_the_span_underlined() {
+    let shown = rendered("let x = 1;\n\tlet y = x + true");
+    assert!(shown.starts_with("Type Error[E0201]: "));
+    assert!(shown.ends_with("\n --> line 2:12\n  |\n2 | \tlet y = x + true\n  | \t          ^"), "{shown}");
+    let long = rendered("let s = \"a\";\n\n\n\n\n\n\n\n\n\nlet u = s && 2;");
+    assert!(long.ends_with("\n  --> line 11:11\n   |\n11 | let u = s && 2;\n   |           ^^"), "{long}");
+}
+
+#[test]
+fn errors_have_stable_codes() {
+    let code = |source: &str| match Interpreter::new().eval(source) {
+        Err(err) => err.diagnostic().code,
+        Ok(value) => panic!("Expected an error for '{source}', got {value:?}")
+    };
+    assert_eq!([code("let a = §;"), code("let = 1;"), code("missing"), code("1 + true"), code("let z = 0; 1 / z"), code("[1][4]")],
+        ["E0001", "E0108", "E0202", "E0201", "E0301", "E0303"]);
+    let json = Interpreter::new().eval("fun f(): int = 1 / 0;\nf()").unwrap_err().diagnostic().to_json();
+    assert_eq!(json, r#"{"code":"E0301","kind":"Runtime Error","message":"Division by zero","location":{"line":1,"col":18,"end_line":1,"end_col":19},"notes":["in 'f', called at line 2:1"]}"#);
+}
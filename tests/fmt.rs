@@ -0,0 +1,31 @@
+use nebulang::{ast::Exp, fmt::{format, program_source}, lexer::lex, parser::parse};
+
+#[test]
+fn programs_are_formatted_canonically() {
+    let source = "let a=( 1+2 )*3 ;// three\n\n\n/* next */\nfun f(x:int,y:int):int={if(x>y){x}else{y}}\nwhile(a>0){a-=1}\nf(a,2)";
+    let formatted = format(source).unwrap();
+    assert_eq!(formatted, "let a = (1 + 2) * 3; // three\n\n/* next */\nfun f(x: int, y: int): int = {\n    if (x > y) {\n        x\n    } else {\n        y\n    }\n}\nwhile (a > 0) {\n    a -= 1\n}\nf(a, 2)\n");
+    assert_eq!(format(&formatted).unwrap(), formatted);
+    assert!(format("let a = (1 +;").is_err());
+}
+
+#[test]
+fn programs_are_written_back_as_source_that_parses_the_same() {
+    let mut sources: Vec<String> = std::fs::read_dir("src/test_programs").unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap()).collect();
+    sources.push(String::from("let x = -(1 - 2) - -3; let y = !(x > 1 && (x < 2 || false)); let s = \"a\\\"b\";
+        struct P { x: int } interface Get { fun get(self): int; } impl Get for P { fun get(self) = self.x; } fun mul(a: int, b: int): int = a * b; operator +++ left 2 = mul;
+        let o = some(P { x: 1 }) ?? P { x: 2 }; o.get() + (1 +++ 2) + [i * 2 for i in [1, 2] if i > 1][0]"));
+    for source in &sources {
+        let program = parse(&mut lex(source).unwrap().iter()).unwrap();
+        let written = program_source(&program);
+        let reparsed = parse(&mut lex(&written).unwrap().iter()).unwrap_or_else(|errors| panic!("{written}\ndoes not parse: {errors:?}"));
+        assert_eq!(reparsed.to_sexp(), program.to_sexp(), "{written}");
+        if let Exp::BlockExp(exps, funs, _) = &program {
+            assert_eq!(exps[0].to_string(), exps[0].to_source());
+            if let Some((_, fun)) = funs.first() {
+                assert!(fun.to_string().starts_with("fun "));
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use nebulang::{incremental::Document, lexer::{lex, Location}, parser::parse};
+
+///The document after the edit must be what parsing its whole source gives, locations included
+fn check_edit(source: &str, range: Location, text: &str) -> Document {
+    let mut document = Document::new(source);
+    document.edit(range, text);
+    let whole = lex(document.source()).map_err(|err| vec![err]).and_then(|lexed| parse(&mut lexed.iter()));
+    assert_eq!(format!("{:?}", document.program()), format!("{whole:?}"), "after replacing {range:?} with '{text}'");
+    document
+}
+
+fn range(line: u32, col: usize, end_line: u32, end_col: usize) -> Location {
+    Location { line, col, end_line, end_col }
+}
+
+#[test]
+fn edits_give_what_parsing_everything_gives() {
+    let source = "let a = 1;\nfun f(x: int): int = x * 2;\nlet b = f(a);\nb + 1";
+    let document = check_edit(source, range(1, 9, 1, 10), "20");
+    assert_eq!(document.source(), "let a = 20;\nfun f(x: int): int = x * 2;\nlet b = f(a);\nb + 1");
+    check_edit(source, range(2, 25, 2, 26), "3\n\n");
+    check_edit(source, range(3, 1, 3, 1), "let c = 2;\n");
+    check_edit(source, range(2, 1, 3, 14), "");
+    //Joining two statements, and opening a block that takes in the rest
+    check_edit(source, range(1, 10, 1, 11), "");
+    check_edit(source, range(2, 28, 2, 28), "{");
+    check_edit(source, range(1, 1, 1, 1), "\"");
+}
+
+#[test]
+fn an_edit_can_fix_a_lexer_error() {
+    let mut document = check_edit("let a = \"open;\na", range(2, 1, 2, 1), "");
+    assert!(document.lex_error().is_some());
+    document.edit(range(1, 14, 1, 14), "\"");
+    assert!(document.lex_error().is_none());
+    assert!(document.program().is_ok());
+}
@@ -0,0 +1,129 @@
+use std::rc::Rc;
+
+use nebulang::{Interpreter, Value, Error, Type, StructValue, HostStruct, interner::{self, Symbol}, parser::parse_expression};
+
+#[test]
+fn calling_a_missing_function_does_not_make_a_symbol() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("fun twice(x: int) = x * 2;").unwrap();
+    assert_eq!(interpreter.call("twice", &[Value::from(4)]).unwrap(), Value::from(8));
+    assert_eq!(Interpreter::new().call("len", &[Value::from("four")]).unwrap(), Value::from(4));
+    assert!(matches!(interpreter.call("never_declared_anywhere", &[]), Err(Error::Type(_, _))));
+    assert_eq!(Symbol::find("never_declared_anywhere"), None);
+}
+
+#[test]
+fn string_literals_of_dropped_programs_are_released() {
+    for i in 0..100 {
+        Interpreter::new().eval(&format!("\"literal {i}\"")).unwrap();
+    }
+    Interpreter::new().eval("1").unwrap();
+    assert!(interner::with_interner(|interner| interner.len()) < 10);
+}
+
+#[test]
+fn variables_keep_their_types_between_pieces() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("let a = some(1); a = none;").unwrap();
+    assert!(matches!(interpreter.eval("a = some(\"s\")"), Err(Error::Type(_, _))));
+    assert_eq!(interpreter.eval("(a ?? 4) + 1").unwrap(), Value::from(5));
+    interpreter.eval("a = some(2);").unwrap();
+    assert_eq!(interpreter.eval("a ?? 0").unwrap(), Value::from(2));
+}
+
+fn point(fields: &[(&str, Value)]) -> Value {
+    let fields = fields.iter().map(|(id, value)| (id.to_string(), value.clone())).collect();
+    Value::Struct(Rc::new(StructValue { name: String::from("Point"), fields }))
+}
+
+#[test]
+fn structs_from_the_host_are_checked_against_their_declaration() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("struct Point { x: int, y: int }; fun sum(p: Point) = p.x + p.y;").unwrap();
+    assert_eq!(interpreter.call("sum", &[point(&[("x", Value::from(1)), ("y", Value::from(2))])]).unwrap(), Value::from(3));
+    assert!(matches!(interpreter.call("sum", &[point(&[("x", Value::from(1))])]), Err(Error::Type(_, _))));
+    assert!(matches!(interpreter.call("sum", &[point(&[("y", Value::from(2)), ("x", Value::from(1))])]), Err(Error::Type(_, _))));
+    assert!(matches!(interpreter.call("sum", &[point(&[("x", Value::from(1)), ("y", Value::from("s1"))])]), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn host_functions_cannot_return_structs_with_wrong_fields() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register("bad_point", &[], Type::Struct(String::from("Point")), |_| Ok(point(&[("x", Value::from(1)), ("y", Value::from("s1"))])));
+    interpreter.register("good_point", &[], Type::Struct(String::from("Point")), |_| Ok(point(&[("x", Value::from(1)), ("y", Value::from(2))])));
+    interpreter.eval("struct Point { x: int, y: int };").unwrap();
+    assert_eq!(interpreter.eval("good_point().y").unwrap(), Value::from(2));
+    let res = interpreter.eval("bad_point().y + 1");
+    assert!(matches!(res, Err(Error::Runtime(signal)) if signal.message().contains("Field 'y' of 'Point' must be 'int', got 'string'")));
+}
+
+#[test]
+fn functions_are_called_by_name_with_the_state_so_far() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("let total = 0; fun add(n: int): int = { total += n; total }").unwrap();
+    assert_eq!(interpreter.call("add", &[Value::from(2)]).unwrap(), Value::from(2));
+    assert_eq!(interpreter.call("add", &[Value::from(3)]).unwrap(), Value::from(5));
+    assert_eq!(interpreter.eval("total").unwrap(), Value::from(5));
+    assert!(matches!(interpreter.call("add", &[Value::from("s")]), Err(Error::Type(_, _))));
+    assert!(matches!(interpreter.call("add", &[]), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn registered_functions_are_called_like_builtins() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register("halve", &[Type::Int], Type::Int, |args| match args {
+        [Value::Int(n)] if n % 2 == 0 => Ok(Value::from(n / 2)),
+        _ => Err(String::from("Cannot halve an odd number"))
+    });
+    interpreter.register("lying", &[], Type::Int, |_| Ok(Value::from("s")));
+    assert_eq!(interpreter.eval("halve(halve(12)) + 1").unwrap(), Value::from(4));
+    assert!(matches!(interpreter.eval("halve(\"s\")"), Err(Error::Type(_, _))));
+    assert!(matches!(interpreter.eval("halve(3)"), Err(Error::Runtime(signal)) if signal.message() == "Cannot halve an odd number"));
+    let res = interpreter.eval("lying()");
+    assert!(matches!(res, Err(Error::Runtime(signal)) if signal.message() == "'lying' returned 'string', but is declared to return 'int'"));
+}
+
+#[derive(Debug, PartialEq)]
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+impl HostStruct for Point {
+    const NAME: &'static str = "Point";
+
+    fn to_fields(&self) -> Vec<(String, Value)> {
+        vec![(String::from("x"), self.x.into()), (String::from("y"), self.y.into())]
+    }
+
+    fn from_fields(value: &StructValue) -> Result<Self, String> {
+        Ok(Point { x: value.field("x")?, y: value.field("y")? })
+    }
+}
+
+#[test]
+fn rust_values_convert_to_and_from_values() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("struct Point { x: int, y: int }; fun flip(p: Point) = Point { x: p.y, y: p.x };").unwrap();
+    let flipped = interpreter.call("flip", &[Point { x: 1, y: 2 }.to_value()]).unwrap();
+    assert_eq!(Point::from_value(&flipped), Ok(Point { x: 2, y: 1 }));
+    let pairs = interpreter.eval("[(\"a\", some(1.5)), (\"b\", none)]").unwrap();
+    assert_eq!(Vec::<(String, Option<f64>)>::try_from(pairs), Ok(vec![(String::from("a"), Some(1.5)), (String::from("b"), None)]));
+    assert_eq!(i64::try_from(Value::from("s")), Err(String::from("Expected 'int', got 'string'")));
+    assert_eq!(Point::from_value(&point(&[("x", Value::from(1))])), Err(String::from("'Point' has no field 'y'")));
+    assert!(matches!(interpreter.call("flip", &[Value::from(vec![1, 2])]), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn expressions_are_parsed_alone_and_run_again() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("let total = 15; fun double(n: int) = n * 2;").unwrap();
+    let formula = parse_expression("double(total) + 1").unwrap();
+    assert_eq!(interpreter.eval_expression(&formula).unwrap(), Value::from(31));
+    interpreter.eval("total = 1;").unwrap();
+    assert_eq!(interpreter.eval_expression(&formula).unwrap(), Value::from(3));
+    assert_eq!(interpreter.eval_expression(&parse_expression("{ let a = 2; a * a }").unwrap()).unwrap(), Value::from(4));
+    assert!(matches!(parse_expression("1 + 2; 3"), Err(Error::Parse(msg, _)) if msg == "Expected the end of the expression, got ';'"));
+    assert!(matches!(parse_expression("\"open"), Err(Error::Lex(_, _))));
+    assert!(matches!(parse_expression("{ let = 1; 2 }"), Err(Error::Parse(_, _))));
+}
@@ -0,0 +1,94 @@
+use std::io::{BufReader, Read};
+
+use nebulang::lexer::{lex, lex_reader, tokenize, LexToken, Location, TokenKind};
+
+///Gives the text a few bytes at a time, like a pipe
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.0.len()).min(3);
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn reading_gives_the_tokens_of_the_whole_text() {
+    let source = "let s = \"two\nlines ü\";\n/* a\ncomment */ let c = 'ö';\nlet f = 1.25 + 3; // end\ns";
+    let from_reader = lex_reader(BufReader::with_capacity(4, Trickle(source.as_bytes()))).unwrap();
+    let from_str = lex(source).unwrap();
+    assert_eq!(from_reader.tokens(), from_str.tokens());
+    assert_eq!(from_reader.comments(), from_str.comments());
+    assert!(lex_reader(BufReader::new(Trickle("let s = \"open\n".as_bytes()))).is_err());
+}
+
+#[test]
+fn tokens_have_a_kind_for_highlighting() {
+    use TokenKind::*;
+    let lexed = lex("fun f(x: int): bool = x >= 2.5 && true; 'c' + \"s\"").unwrap();
+    let kinds: Vec<TokenKind> = lexed.tokens().iter().map(|(token, _)| token.kind()).collect();
+    assert_eq!(kinds, [Keyword, Identifier, Punctuation, Identifier, Punctuation, Type, Punctuation, Punctuation, Type, Operator,
+        Identifier, Operator, Number, Operator, Bool, Punctuation, Char, Operator, Str, EndOfInput]);
+}
+
+#[test]
+fn comments_are_kept_beside_the_tokens() {
+    let lexed = lex("let a = 1; // one\n/* two\nlines */ a").unwrap();
+    let comments: Vec<(&str, Location)> = lexed.comments().iter().map(|(text, loc)| (text.as_str(), *loc)).collect();
+    let one = Location { line: 1, col: 12, end_line: 1, end_col: 18 };
+    let two = Location { line: 2, col: 1, end_line: 3, end_col: 9 };
+    assert_eq!(comments, [("// one", one), ("/* two\nlines */", two)]);
+    assert_eq!(lexed.tokens().len(), 7);
+}
+
+///The message and line of the lexer error in the source
+fn lex_error(source: &str) -> (String, u32) {
+    match lex(source) {
+        Err((msg, loc)) => (msg, loc.line),
+        Ok(_) => panic!("Expected a lexer error for '{source}'")
+    }
+}
+
+#[test]
+fn unterminated_strings_and_comments_are_reported_where_they_start() {
+    assert_eq!(lex_error("let a = 1;\nlet s = \"never\nclosed;\na"), (String::from("Unterminated string literal starting at line 2:9"), 2));
+    assert_eq!(lex_error("let a = 1; /* never\n\nclosed"), (String::from("Unterminated comment starting at line 1:12"), 1));
+    assert_eq!(lex_error("/*/"), (String::from("Unterminated comment starting at line 1:1"), 1));
+}
+
+#[test]
+fn invalid_chars_are_named_with_a_hint() {
+    assert_eq!(lex_error("let s = “quoted”;").0, "Invalid char '“' (U+201C), did you mean '\"'?");
+    assert_eq!(lex_error("let a = 1;\n# not a comment").0, "Invalid char '#' (U+0023), comments start with '//'");
+    assert_eq!(lex_error("let a\u{200B} = 1;").0, "Invalid char '\\u{200b}' (U+200B), it is invisible and can be deleted");
+    assert_eq!(lex_error("a ≤ b"), (String::from("Invalid char '≤' (U+2264), did you mean '<='?"), 1));
+    assert_eq!(lex_error("§").0, "Invalid char '§' (U+00A7)");
+}
+
+#[test]
+fn tokenize_goes_on_after_errors() {
+    let lexed = tokenize("let c = § + ab;\nlet ok = 1;\nlet s = \"open");
+    let tokens: Vec<(TokenKind, u32)> = lexed.tokens().iter().map(|(token, loc)| (token.kind(), loc.line)).collect();
+    assert_eq!(tokens[3..7], [(TokenKind::Error, 1), (TokenKind::Operator, 1), (TokenKind::Identifier, 1), (TokenKind::Punctuation, 1)]);
+    assert_eq!(tokens[7..12], [(TokenKind::Keyword, 2), (TokenKind::Identifier, 2), (TokenKind::Operator, 2), (TokenKind::Number, 2), (TokenKind::Punctuation, 2)]);
+    assert_eq!(tokens[15..], [(TokenKind::Error, 3), (TokenKind::EndOfInput, 3)]);
+    let messages: Vec<&String> = lexed.tokens().iter().filter_map(|(token, _)| match token {
+        LexToken::Error(msg) => Some(msg),
+        _ => None
+    }).collect();
+    assert_eq!(messages, ["Invalid char '§' (U+00A7)", "Unterminated string literal starting at line 3:9"]);
+    assert_eq!(tokenize("let a = 1;").tokens(), lex("let a = 1;").unwrap().tokens());
+}
+
+#[test]
+fn a_script_can_start_with_a_shebang_and_a_byte_order_mark() {
+    let lexed = lex("\u{FEFF}#!/usr/bin/env nebulang\nlet a = 1;").unwrap();
+    let comments: Vec<(&str, (u32, usize, usize))> = lexed.comments().iter().map(|(text, loc)| (text.as_str(), (loc.line, loc.col, loc.end_col))).collect();
+    assert_eq!(comments, [("#!/usr/bin/env nebulang", (1, 1, 24))]);
+    assert_eq!((lexed.tokens()[0].1.line, lexed.tokens()[0].1.col), (2, 1));
+    assert_eq!(lex("#!nebulang").unwrap().tokens().len(), 1);
+    assert!(lex("let a = 1;\n#!/usr/bin/env nebulang").is_err());
+    assert!(lex("let a = 1;\u{FEFF}").is_err());
+}
@@ -0,0 +1,27 @@
+use nebulang::{environment::Environment, lexer::lex, parser::parse, type_checker, lint::lint};
+
+///The warnings for the source, with the lines they are on
+fn warnings(source: &str) -> Vec<(String, u32)> {
+    let mut program = parse(&mut lex(source).unwrap().iter()).unwrap();
+    type_checker::check_program(&mut program, &mut Environment::new()).unwrap();
+    lint(&program).into_iter().map(|(msg, loc)| (msg, loc.line)).collect()
+}
+
+#[test]
+fn unused_names_are_warned_about() {
+    let found = warnings("let a = 1;\nlet _b = 2;\nfun never(): int = a;\nfun used(): int = 3;\nused()");
+    assert_eq!(found.len(), 1, "{found:?}");
+    assert!(found[0].0.starts_with("Function 'never' is never called"), "{found:?}");
+    let found = warnings("let a = 1;\na = 2;\n3");
+    assert!(found.iter().any(|(msg, line)| msg.starts_with("Variable 'a' is never read") && *line == 1), "{found:?}");
+}
+
+#[test]
+fn unreachable_code_and_constant_conditions_are_warned_about() {
+    let found = warnings("let n = 0;\nwhile (n < 3) {\n    n += 1;\n    break;\n    n += 2\n};\nif (true) n else 0");
+    let lines: Vec<u32> = found.iter().map(|(_, line)| *line).collect();
+    assert_eq!(lines, [5, 7], "{found:?}");
+    assert!(found[0].0.starts_with("Unreachable statement"));
+    assert!(found[1].0.starts_with("Condition is always true"));
+    assert!(warnings("let n = 1;\nif (n > 0) n else 0").is_empty());
+}
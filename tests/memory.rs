@@ -0,0 +1,22 @@
+use nebulang::{Interpreter, Error, memory::{self, CountingAllocator}, options::ExecutionOptions};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn programs_stop_when_they_use_too_much_memory() {
+    let max = memory::allocated() + 1_048_576;
+    let options = ExecutionOptions { max_memory: Some(max), ..ExecutionOptions::default() };
+    let res = Interpreter::with_options(options.clone()).eval("let a = []; while (true) { a.push(\"grows and grows\") }");
+    assert!(matches!(res, Err(Error::Runtime(signal)) if signal.message().starts_with("Out of memory")));
+    assert!(Interpreter::with_options(options).eval("let a = []; for(i, 0, 100) { a.push(i) }; len(a)").is_ok());
+}
+
+#[test]
+fn ranges_too_big_to_allocate_are_an_error() {
+    let res = Interpreter::new().eval("len(0..9223372036854775807)");
+    assert!(matches!(res, Err(Error::Runtime(signal)) if signal.message() == "Out of memory, the range 0..9223372036854775807 is too big"));
+    let options = ExecutionOptions { max_memory: Some(memory::allocated() + 1_048_576), ..ExecutionOptions::default() };
+    let res = Interpreter::with_options(options).eval("len(0..10000000)");
+    assert!(matches!(res, Err(Error::Runtime(signal)) if signal.message().starts_with("Out of memory, the program used more than")));
+}
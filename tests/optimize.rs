@@ -0,0 +1,43 @@
+use nebulang::{ast::Exp, environment::Environment, lexer::lex, optimize, parser::parse, type_checker};
+
+fn checked(source: &str) -> Exp {
+    let mut program = parse(&mut lex(source).unwrap().iter()).unwrap();
+    type_checker::check_program(&mut program, &mut Environment::new()).unwrap();
+    program
+}
+
+///The statements of a block, as S-expressions
+fn statements(program: Exp) -> Vec<String> {
+    match program {
+        Exp::BlockExp(exps, _, _) => exps.iter().map(Exp::to_sexp).collect(),
+        exp => vec![exp.to_sexp()]
+    }
+}
+
+#[test]
+fn constants_are_folded() {
+    assert_eq!(statements(optimize::fold_constants(checked("let x = 4; 2 * 3 + x"))), ["(Let x (Literal int 4))", "(BinOp + (Literal int 6) (Var x))"]);
+    assert_eq!(statements(optimize::fold_constants(checked("1.5 < 2 && !false"))), ["(Literal bool true)"]);
+    assert_eq!(statements(optimize::fold_constants(checked("-(3 - 5)"))), ["(Literal int 2)"]);
+}
+
+#[test]
+fn failing_operations_are_left_to_fail() {
+    assert_eq!(statements(optimize::fold_constants(checked("1 / 0"))), ["(BinOp / (Literal int 1) (Literal int 0))"]);
+    assert_eq!(statements(optimize::fold_constants(checked("9223372036854775807 + 1"))), ["(BinOp + (Literal int 9223372036854775807) (Literal int 1))"]);
+}
+
+#[test]
+fn dead_code_is_removed() {
+    assert_eq!(statements(optimize::eliminate_dead_code(checked("let x = 1; x; 1 < 2; x + 1"))), ["(Let x (Literal int 1))", "(BinOp + (Var x) (Literal int 1))"]);
+    assert_eq!(statements(optimize::optimize(checked("let x = 1; if (1 > 2) x = 2 else x = 3; x"))),
+        ["(Let x (Literal int 1))", "(BinOp = (Var x) (Literal int 3))", "(Var x)"]);
+    let looped = statements(optimize::eliminate_dead_code(checked("while (true) { break; println(1) }")));
+    assert!(!looped[0].contains("println"));
+}
+
+#[test]
+fn effects_and_declarations_are_kept() {
+    let kept = statements(optimize::optimize(checked("let a = [1]; a.push(2); fun f() = 1; 1 / 0; f()")));
+    assert_eq!(kept.len(), 5);
+}
@@ -0,0 +1,86 @@
+use std::{thread, time::{Duration, Instant}};
+
+use nebulang::{Interpreter, Value, Error, Type, eval::Signal, options::{ExecutionOptions, Overflow}};
+
+const READ_ENV: &str = "env(\"PATH\"); 1";
+
+fn is_denied(res: Result<Value, Error>) -> bool {
+    matches!(res, Err(Error::Runtime(signal)) if signal.message().contains("is not allowed"))
+}
+
+#[test]
+fn sandbox_is_kept_while_another_interpreter_runs() {
+    let open = thread::spawn(|| {
+        for _ in 0..200 {
+            assert!(Interpreter::new().eval(READ_ENV).is_ok());
+        }
+    });
+    for _ in 0..200 {
+        assert!(is_denied(Interpreter::with_options(ExecutionOptions::default().sandboxed()).eval(READ_ENV)));
+    }
+    open.join().unwrap();
+}
+
+#[test]
+fn sandbox_is_kept_after_a_nested_run() {
+    let mut sandboxed = Interpreter::with_options(ExecutionOptions::default().sandboxed());
+    sandboxed.register("other", &[], Type::Int, |_| Interpreter::new().eval("1").map_err(|err| err.to_string()));
+    assert!(is_denied(sandboxed.eval(&format!("other(); {READ_ENV}"))));
+}
+
+#[test]
+fn fuel_is_counted_for_each_run() {
+    let options = ExecutionOptions { fuel: Some(100), ..ExecutionOptions::default() };
+    let mut limited = Interpreter::with_options(options);
+    assert!(matches!(limited.eval("while (true) { }"), Err(Error::Runtime(Signal::BudgetExceeded(_)))));
+    assert_eq!(Interpreter::new().eval("let i = 0; while (i < 1000) { i += 1 }; i").unwrap(), Value::from(1000));
+    assert_eq!(limited.eval("let i = 0; while (i < 50) { i += 1 }; i").unwrap(), Value::from(50));
+}
+
+#[test]
+fn each_element_of_a_range_uses_fuel() {
+    let options = ExecutionOptions { fuel: Some(100), ..ExecutionOptions::default() };
+    assert!(matches!(Interpreter::with_options(options.clone()).eval("len(0..1000)"), Err(Error::Runtime(Signal::BudgetExceeded(_)))));
+    assert_eq!(Interpreter::with_options(options).eval("len(0..50)").unwrap(), Value::from(50));
+}
+
+#[test]
+fn calls_deeper_than_the_limit_are_an_error() {
+    let options = ExecutionOptions { max_call_depth: 10, ..ExecutionOptions::default() };
+    let mut limited = Interpreter::with_options(options);
+    limited.eval("fun deep(n: int): int = if (n == 0) 0 else 1 + deep(n - 1);").unwrap();
+    assert_eq!(limited.eval("deep(9)").unwrap(), Value::from(9));
+    let res = limited.eval("deep(10)");
+    assert!(matches!(res, Err(Error::Runtime(signal)) if signal.message() == "Maximum recursion depth exceeded, calls can only go 10 deep"));
+    assert_eq!(limited.eval("deep(5)").unwrap(), Value::from(5));
+}
+
+#[test]
+fn deep_recursion_stops_before_the_stack_runs_out() {
+    //Runs on the test thread, with its default stack
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("fun f(n: int): int = if (n == 0) 0 else f(n - 1) + 1;").unwrap();
+    let res = interpreter.eval("f(1000000)");
+    assert!(matches!(res, Err(Error::Runtime(signal)) if signal.message().starts_with("Maximum recursion depth exceeded, the stack ran out")));
+    assert_eq!(interpreter.eval("f(20)").unwrap(), Value::from(20));
+}
+
+#[test]
+fn timeout_stops_loops() {
+    let options = ExecutionOptions { max_duration: Some(Duration::from_millis(50)), ..ExecutionOptions::default() };
+    let before = Instant::now();
+    assert!(matches!(Interpreter::with_options(options.clone()).eval("while (true) { }"), Err(Error::Runtime(Signal::Timeout(_)))));
+    //A sleep goes over the deadline, and the next step stops the program
+    assert!(matches!(Interpreter::with_options(options.clone()).eval("sleep_ms(100); for(i, 0, 10) { }"), Err(Error::Runtime(Signal::Timeout(_)))));
+    assert!(before.elapsed() < Duration::from_millis(2000));
+    assert_eq!(Interpreter::with_options(options).eval("1 + 1").unwrap(), Value::from(2));
+}
+
+#[test]
+fn overflow_follows_the_option() {
+    let with = |overflow: Overflow| Interpreter::with_options(ExecutionOptions { overflow, ..ExecutionOptions::default() });
+    let source = "let big = 9223372036854775807; (big + 1, -big - 2, big * 2)";
+    assert!(matches!(with(Overflow::Error).eval(source), Err(Error::Runtime(signal)) if signal.message().starts_with("Overflow")));
+    assert_eq!(with(Overflow::Wrap).eval(source).unwrap(), Value::Tuple(vec![i64::MIN.into(), i64::MAX.into(), (-2).into()].into()));
+    assert_eq!(with(Overflow::Saturate).eval(source).unwrap(), Value::Tuple(vec![i64::MAX.into(), i64::MIN.into(), i64::MAX.into()].into()));
+}
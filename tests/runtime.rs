@@ -0,0 +1,178 @@
+use nebulang::{Interpreter, Value, Error, options::ExecutionOptions, ast::Exp, lexer::lex, parser::parse};
+
+fn eval(source: &str) -> Value {
+    match Interpreter::new().eval(source) {
+        Ok(value) => value,
+        Err(err) => panic!("'{source}' failed: {err}")
+    }
+}
+
+#[test]
+fn struct_fields_are_kept_in_declaration_order() {
+    assert_eq!(eval("struct P { x: int, y: int } fun f(): P = P { y: 1, x: 2 }; to_set([f(), P { x: 2, y: 1 }]).len()"), Value::from(1));
+    assert_eq!(eval("struct P { x: int, y: int } fun f(): P = P { y: 1, x: 2 }; to_string(f())"), Value::from("P { x: 2, y: 1 }"));
+}
+
+#[test]
+fn sort_puts_nan_last() {
+    assert_eq!(eval("to_string(sort([2.0, 0.0 / 0.0, 1.0, -1.0]))"), Value::from("[-1, 1, 2, NaN]"));
+    assert_eq!(eval("to_string(sort([0.0 / 0.0, 3.0, 0.0 / 0.0, 1.0]))"), Value::from("[1, 3, NaN, NaN]"));
+}
+
+#[test]
+fn methods_are_found_for_each_struct() {
+    assert_eq!(eval("interface Shape { fun area(self): int } struct A { x: int } struct B { x: int }
+        impl Shape for A { fun area(self) = self.x } impl Shape for B { fun area(self) = self.x * 10 }
+        fun area_of(shape: Shape) = shape.area();
+        let sum = 0; for(i, 0, 3) { sum += area_of(A { x: 1 }) + area_of(B { x: 2 }) }; sum"), Value::from(63));
+}
+
+#[test]
+fn method_declared_later_replaces_the_function() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("struct P { x: int } fun size(p: P) = 1; interface Sized { fun size(self): int }").unwrap();
+    assert_eq!(interpreter.eval("P { x: 1 }.size()").unwrap(), Value::from(1));
+    interpreter.eval("impl Sized for P { fun size(self) = 2 }").unwrap();
+    assert_eq!(interpreter.eval("P { x: 1 }.size()").unwrap(), Value::from(2));
+}
+
+#[test]
+fn labeled_break_and_continue_leave_the_outer_loop() {
+    assert_eq!(eval("let found = (0, 0); outer: for(i, 1, 10) { for(j, 1, 10) { if (i * j == 42) { found = (i, j); break outer } } }; to_string(found)"),
+        Value::from("(6, 7)"));
+    assert_eq!(eval("let count = 0; outer: for(i, 0, 5) { for(j, 0, 5) { if (j > i) continue outer; count += 1 } }; count"), Value::from(15));
+}
+
+#[test]
+fn labels_cannot_be_reused_or_reached_from_a_function() {
+    assert!(matches!(Interpreter::new().eval("a: while (true) { a: while (true) { break a } }"), Err(Error::Type(_, _))));
+    assert!(matches!(Interpreter::new().eval("a: while (true) { fun f(): unit = { break a }; f() }"), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn functions_return_several_values_as_a_tuple() {
+    assert_eq!(eval("fun divmod(a: int, b: int): (int, int) = (a / b, a % b); let (q, r) = divmod(17, 5); q * 10 + r"), Value::from(32));
+    assert_eq!(eval("fun swap(a: int, b: string) = (b, a); let (s, i) = swap(1, \"one\"); s + to_string(i)"), Value::from("one1"));
+}
+
+#[test]
+fn tail_calls_do_not_count_towards_the_call_depth() {
+    assert_eq!(eval("fun count(n: int, acc: int): int = if (n == 0) acc else count(n - 1, acc + 1); count(100000, 0)"), Value::from(100000));
+    assert_eq!(eval("fun even(n: int): bool = if (n == 0) true else { odd(n - 1) }
+        fun odd(n: int): bool = if (n == 0) false else { even(n - 1) }
+        even(100001)"), Value::from(false));
+    let options = ExecutionOptions { max_call_depth: 20, ..ExecutionOptions::default() };
+    let mut limited = Interpreter::with_options(options);
+    assert_eq!(limited.eval("fun count(n: int, acc: int): int = if (n == 0) acc else count(n - 1, acc + 1); count(1000, 0)").unwrap(), Value::from(1000));
+    assert!(matches!(limited.eval("fun deep(n: int): int = if (n == 0) 0 else 1 + deep(n - 1); deep(1000)"), Err(Error::Runtime(_))));
+}
+
+#[test]
+fn unit_can_be_written_as_a_value() {
+    assert_eq!(eval("()"), Value::Unit);
+    assert_eq!(eval("fun ignore(x: unit) = 1; ignore(unit) + ignore(())"), Value::from(2));
+    assert_eq!(eval("let u = (); typeof(u)"), Value::from("unit"));
+    assert_eq!(eval("let a = 1; if (a > 0) () else a = 2"), Value::Unit);
+}
+
+#[test]
+fn structs_are_used_through_their_interfaces() {
+    assert_eq!(eval("interface Shape { fun area(self): float; fun scaled(self, by: float): Shape }
+        struct Circle { r: float } struct Square { side: float }
+        impl Shape for Circle { fun area(self) = 3.0 * self.r * self.r; fun scaled(self, by: float) = Circle { r: self.r * by } }
+        impl Shape for Square { fun area(self) = self.side * self.side; fun scaled(self, by: float) = Square { side: self.side * by } }
+        fun total(a: Shape, b: Shape) = a.area() + b.area();
+        total(Circle { r: 1.0 }.scaled(2.0), Square { side: 3.0 })"), Value::from(21.0));
+    assert!(matches!(Interpreter::new().eval("interface Shape { fun area(self): float } struct Dot { x: int } impl Shape for Dot { }"),
+        Err(Error::Type(_, _))));
+    assert!(matches!(Interpreter::new().eval("interface Shape { fun area(self): float } struct Dot { x: int } fun f(s: Shape) = s.area(); f(Dot { x: 1 })"),
+        Err(Error::Type(_, _))));
+}
+
+#[test]
+fn method_calls_fall_back_to_functions() {
+    assert_eq!(eval("fun double(x: int) = x * 2; fun add(a: int, b: int) = a + b; 5.double().add(3)"), Value::from(13));
+    assert_eq!(eval("\"a, b, c\".split(\", \")[1].to_upper()"), Value::from("B"));
+    assert_eq!(eval("fun len(s: string) = 0; \"four\".len()"), Value::from(0));
+}
+
+#[test]
+fn for_in_follows_the_next_protocol() {
+    assert_eq!(eval("struct Countdown { n: int }
+        fun next(c: Countdown): option<(int, Countdown)> = if (c.n > 0) some((c.n, Countdown { n: c.n - 1 })) else none;
+        let seen = []; for(n in Countdown { n: 3 }) { seen.push(n) }; to_string(seen)"), Value::from("[3, 2, 1]"));
+    assert_eq!(eval("let sum = 0; for(x in to_set([1, 2, 2, 3])) { sum += x }; sum"), Value::from(6));
+    assert_eq!(eval("to_string(next([1, 2, 3]))"), Value::from("some((1, [2, 3]))"));
+    assert!(matches!(Interpreter::new().eval("for(x in 5) { }"), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn spread_into_calls_and_arrays() {
+    assert_eq!(eval("let middle = [2, 3]; to_string([1, ...middle, 4])"), Value::from("[1, 2, 3, 4]"));
+    assert_eq!(eval("fun add3(a: int, b: int, c: int) = a + b + c; let pair = (2, 3); add3(1, ...pair)"), Value::from(6));
+    assert_eq!(eval("fun add3(a: int, b: int, c: int) = a + b + c; add3(...[1, 2, 3])"), Value::from(6));
+    assert!(matches!(Interpreter::new().eval("fun add3(a: int, b: int, c: int) = a + b + c; add3(...[1, 2])"), Err(Error::Runtime(_))));
+    assert!(matches!(Interpreter::new().eval("fun add3(a: int, b: int, c: int) = a + b + c; add3(1, ...(2, 3, 4))"), Err(Error::Type(_, _))));
+}
+
+#[test]
+fn shared_values_are_copied_when_changed() {
+    assert_eq!(eval("let a = [1]; let b = a; a.push(2); (len(a), len(b))"), Value::Tuple(vec![2.into(), 1.into()].into()));
+    assert_eq!(eval("let s = to_set([1]); let t = s; t.add(2); (len(s), len(t))"), Value::Tuple(vec![1.into(), 2.into()].into()));
+    assert_eq!(eval("fun grow(a: [int]) = { a.push(0); len(a) }; let a = [1, 2]; let grown = grow(a); (grown, len(a))"),
+        Value::Tuple(vec![3.into(), 2.into()].into()));
+}
+
+#[test]
+fn values_keep_every_bit() {
+    assert_eq!(std::mem::size_of::<Value>(), 16);
+    assert_eq!(eval("9223372036854775807"), Value::from(i64::MAX));
+    assert_eq!(eval("-9223372036854775807 - 1"), Value::from(i64::MIN));
+    assert_eq!(eval("0.1 + 0.2"), Value::from(0.1 + 0.2));
+    assert_eq!(eval("let big = (1, 2.5, \"s\", [1], some(some(3))); to_string(big)"), Value::from("(1, 2.5, \"s\", [1], some(some(3)))"));
+}
+
+#[test]
+fn dividing_an_int_by_zero_is_an_error() {
+    let error = |source: &str| match Interpreter::new().eval(source) {
+        Err(Error::Runtime(signal)) => signal.message(),
+        res => panic!("Expected a runtime error for '{source}', got {res:?}")
+    };
+    assert_eq!(error("let zero = 0; 1 / zero"), "Division by zero");
+    assert_eq!(error("let zero = 0; 1 % zero"), "Modulo by zero");
+    assert_eq!(error("1 / 0"), "Division by zero");
+    assert_eq!(eval("to_string((1.0 / 0.0, 0.0 / 0.0))"), Value::from("(inf, NaN)"));
+}
+
+#[test]
+fn closures_share_the_variables_they_use() {
+    assert_eq!(eval("let count = 0; fun bump(): int = { count += 1; count }; bump(); count += 10; let f = bump; f(); (count, bump())"),
+        Value::Tuple(vec![12.into(), 13.into()].into()));
+    let funs = match parse(&mut lex("fun f(a: int): int = { let b = a + c; g(b) }").unwrap().iter()).unwrap() {
+        Exp::BlockExp(_, funs, _) => funs,
+        exp => panic!("Expected a block, got {exp}")
+    };
+    let mut names: Vec<String> = funs[0].1.used_names().iter().map(|name| name.to_string()).collect();
+    names.sort();
+    assert_eq!(names, ["a", "b", "c", "g"]);
+}
+
+#[test]
+fn declared_operators_call_their_function() {
+    assert_eq!(eval("fun double(x: int) = x * 2; fun pipe(x: int, f: fun(int): int): int = f(x); operator |> left 8 = pipe; 5 |> double |> double"),
+        Value::from(20));
+    assert_eq!(eval("fun pow2(a: int, b: int): int = a * 10 + b; operator ^^ right 2 = pow2; 1 ^^ 2 ^^ 3"), Value::from(33));
+    assert_eq!(eval("fun cat(a: int, b: int): int = a * 10 + b; operator @@ left 3 = cat; 1 + 1 @@ 2 * 3"), Value::from(26));
+    assert!(matches!(Interpreter::new().eval("fun f(a: int, b: int) = a; operator + left 3 = f; 1"), Err(Error::Parse(_, _))));
+    assert!(matches!(Interpreter::new().eval("1 @@ 2"), Err(Error::Parse(_, _))));
+}
+
+#[test]
+fn strings_are_counted_and_indexed_in_chars() {
+    let program = std::fs::read_to_string("src/test_programs/unicode.nbl").unwrap();
+    assert_eq!(eval(&program), Value::Unit);
+    assert_eq!(eval("let s = \"héllo 🌍\"; (len(s), s[6], substring(s, 1, 3))"), Value::Tuple(vec![7.into(), '🌍'.into(), "él".into()].into()));
+    assert!(matches!(Interpreter::new().eval("\"é\"[2]"), Err(Error::Runtime(signal)) if signal.message() == "Index out of bounds"));
+    let several = Interpreter::new().eval("'e\u{301}'");
+    assert!(matches!(several, Err(Error::Lex(msg, _)) if msg.starts_with("Char literal 'e\u{301}' has 2 chars")));
+}
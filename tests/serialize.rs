@@ -0,0 +1,26 @@
+#![cfg(feature = "serde")]
+
+use nebulang::{Interpreter, Value, lexer::lex, parser::parse};
+
+#[test]
+fn values_serialize_as_plain_data() {
+    let value = Interpreter::new().eval("struct P { name: string, tags: [int] }; (P { name: \"a\", tags: [1, 2] }, some(1.5), none, ())").unwrap();
+    assert_eq!(serde_json::to_string(&value).unwrap(), r#"[{"name":"a","tags":[1,2]},1.5,null,null]"#);
+    let function = Interpreter::new().eval("fun f() = 1; f").unwrap();
+    assert_eq!(serde_json::to_string(&function).unwrap_err().to_string(), "Function 'f' cannot be serialized");
+}
+
+#[test]
+fn plain_data_deserializes_to_values() {
+    let value: Value = serde_json::from_str(r#"[[1, 2], [1, "a"], null, true]"#).unwrap();
+    let expected = Interpreter::new().eval("([1, 2], (1, \"a\"), (), true)").unwrap();
+    assert_eq!(value, expected);
+    assert!(serde_json::from_str::<Value>(r#"{"x": 1}"#).is_err());
+    assert!(serde_json::from_str::<Value>("18446744073709551615").is_err());
+}
+
+#[test]
+fn trees_serialize_as_they_are_dumped() {
+    let program = parse(&mut lex("fun f(x: int) = [x, 2];\nlet s = \"a\\\"b\"; f(1)").unwrap().iter()).unwrap();
+    assert_eq!(serde_json::to_string(&program).unwrap(), program.to_json());
+}
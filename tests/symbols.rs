@@ -0,0 +1,50 @@
+use std::thread;
+
+use nebulang::{Interpreter, Value, interner::{Interner, Symbol}, builtins::{BUILTINS, lookup_builtin_symbol}};
+
+#[test]
+fn symbols_keep_their_names_past_the_first_chunk() {
+    let symbols: Vec<Symbol> = (0..5_000).map(|i| Symbol::new(&format!("chunked_{i}"))).collect();
+    for (i, symbol) in symbols.iter().enumerate() {
+        assert_eq!(symbol.as_str(), format!("chunked_{i}"));
+        assert_eq!(Symbol::new(&format!("chunked_{i}")), *symbol);
+    }
+}
+
+#[test]
+fn interned_strings_are_shared_until_released() {
+    let mut interner = Interner::new();
+    assert!(interner.is_empty());
+    let first = interner.intern(&String::from("shared"));
+    assert!(std::rc::Rc::ptr_eq(&first, &interner.intern(&String::from("shared"))));
+    interner.intern(&String::from("dropped"));
+    assert_eq!(interner.len(), 2);
+    interner.release_unused();
+    assert_eq!(interner.len(), 1);
+}
+
+#[test]
+fn symbols_are_the_same_on_every_thread() {
+    let threads: Vec<_> = (0..4).map(|_| thread::spawn(|| (0..1_000).map(|i| Symbol::new(&format!("shared_{i}"))).collect::<Vec<_>>())).collect();
+    let symbols: Vec<_> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+    for other in &symbols[1..] {
+        assert_eq!(&symbols[0], other);
+    }
+    assert_eq!(symbols[0][999].as_str(), "shared_999");
+}
+
+#[test]
+fn builtins_are_found_by_their_symbol() {
+    for builtin in BUILTINS.iter() {
+        assert_eq!(lookup_builtin_symbol(&Symbol::new(builtin.name)).map(|found| found.name), Some(builtin.name));
+    }
+    assert!(lookup_builtin_symbol(&Symbol::new("not_a_builtin")).is_none());
+}
+
+#[test]
+fn calls_find_functions_before_builtins_and_function_values() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.eval("let m = 0; for(i, 0, 100) { m = max(m, i) }; m").unwrap(), Value::from(99));
+    assert_eq!(interpreter.eval("fun max(a: int, b: int): int = a + b; max(1, 2)").unwrap(), Value::from(3));
+    assert_eq!(interpreter.eval("fun one(): int = 1; let call = one; call()").unwrap(), Value::from(1));
+}
@@ -0,0 +1,163 @@
+use nebulang::{Interpreter, Value, Error, ast::{Exp, Literal}, visit::{Visitor, Folder, walk_exp, fold_children}, environment::Environment, lexer::{lex, Location}, parser::{parse, parse_with_options}, options::ParseOptions, type_checker};
+
+///The tree of the first statement of the source, as an S-expression
+fn tree(source: &str) -> String {
+    match parse(&mut lex(source).unwrap().iter()).unwrap() {
+        Exp::BlockExp(exps, _, _) => exps[0].to_sexp(),
+        exp => exp.to_sexp()
+    }
+}
+
+#[test]
+fn operators_group_from_the_left() {
+    assert_eq!(tree("10 - 3 - 2"), "(BinOp - (BinOp - (Literal int 10) (Literal int 3)) (Literal int 2))");
+    assert_eq!(tree("a * b + c"), "(BinOp + (BinOp * (Var a) (Var b)) (Var c))");
+}
+
+#[test]
+fn assignments_and_defaults_group_from_the_right() {
+    assert_eq!(tree("x ?? y ?? 1"), "(BinOp ?? (Var x) (BinOp ?? (Var y) (Literal int 1)))");
+    assert_eq!(tree("a = b = 3"), "(BinOp = (Var a) (BinOp = (Var b) (Literal int 3)))");
+}
+
+#[test]
+fn operators_bind_by_their_precedence() {
+    assert_eq!(tree("2 * -x"), "(BinOp * (Literal int 2) (UnOp - (Var x)))");
+    assert_eq!(tree("!a || b && c"), "(BinOp || (UnOp ! (Var a)) (BinOp && (Var b) (Var c)))");
+    assert_eq!(tree("a + 1 < b * 2 == c"), "(BinOp == (BinOp < (BinOp + (Var a) (Literal int 1)) (BinOp * (Var b) (Literal int 2))) (Var c))");
+    assert_eq!(tree("a += 1 + 2"), "(BinOp += (Var a) (BinOp + (Literal int 1) (Literal int 2)))");
+}
+
+#[test]
+fn long_expressions_parse_in_one_pass() {
+    let source = vec!["1"; 5000].join(" + ");
+    let mut exp = match parse(&mut lex(&source).unwrap().iter()).unwrap() {
+        Exp::BlockExp(mut exps, _, _) => exps.remove(0),
+        exp => exp
+    };
+    let mut operators = 0;
+    while let Exp::BinOpExp(left, _, _, _) = exp {
+        exp = *left;
+        operators += 1;
+    }
+    assert_eq!(operators, 4999);
+}
+
+#[test]
+fn tokens_span_their_text() {
+    let lexed = lex("let name = \"two\nlines\";\n  name").unwrap();
+    let spans: Vec<(u32, usize, u32, usize)> = lexed.tokens().iter().map(|(_, loc)| (loc.line, loc.col, loc.end_line, loc.end_col)).collect();
+    assert_eq!(spans[..5], [(1, 1, 1, 4), (1, 5, 1, 9), (1, 10, 1, 11), (1, 12, 2, 7), (2, 7, 2, 8)]);
+    assert_eq!(spans[5], (3, 3, 3, 7));
+    let span = |res: Result<_, Error>| match res {
+        Err(Error::Type(_, loc)) => loc,
+        res => panic!("Expected a type error, got {res:?}")
+    };
+    assert_eq!(span(Interpreter::new().eval("let x = 1;\nx + true")), Location { line: 2, col: 3, end_line: 2, end_col: 4 });
+}
+
+#[test]
+fn every_parse_and_type_error_is_reported() {
+    let errors = parse(&mut lex("let a = ;\nlet b = 1;\nlet c = (2 + ;\nb").unwrap().iter()).unwrap_err();
+    assert_eq!(errors.iter().map(|(_, loc)| loc.line).collect::<Vec<u32>>(), [1, 3]);
+    let mut program = parse(&mut lex("let a = 1 + true;\nlet b = !2;\nlet c = 3;\nmissing").unwrap().iter()).unwrap();
+    let errors = type_checker::check_program(&mut program, &mut Environment::new()).unwrap_err();
+    assert_eq!(errors.iter().map(|(_, loc)| loc.line).collect::<Vec<u32>>(), [1, 2, 4]);
+}
+
+#[test]
+fn malformed_input_is_an_error_and_not_a_panic() {
+    let source = "struct P { x: int } fun f(p: P, n: int): int = { let (a, b) = (n, 2); for(i, 0, 3) { if (i > b) break }; p.x + a }
+        let s = [x * 2 for x in [1, 2] if x > 1]; let o = some(1) ?? 2; 'c'; \"s\"; -f(P { x: 1 }, 3) /* c */";
+    parse(&mut lex(source).unwrap().iter()).unwrap();
+    for end in source.char_indices().map(|(i, _)| i) {
+        if let Ok(lexed) = lex(&source[..end]) {
+            let _ = parse(&mut lexed.iter());
+        }
+    }
+    for garbage in [")", "}", "]", "let", "fun", "fun f(", "(,)", "a..", "if", "else 1", "struct", "::", "= 1", "[1,,2]", "f(1 2)"] {
+        assert!(parse(&mut lex(garbage).unwrap().iter()).is_err(), "'{garbage}' parsed");
+    }
+}
+
+#[test]
+fn lists_can_end_with_a_comma() {
+    assert_eq!(tree("add(5, 10,)"), tree("add(5, 10)"));
+    assert_eq!(Interpreter::new().eval("fun add(\n    a: int,\n    b: int,\n): int = a + b;\nadd(\n    1,\n    2,\n)").unwrap(), Value::from(3));
+    assert!(parse(&mut lex("add(5,, 10)").unwrap().iter()).is_err());
+    assert!(parse(&mut lex("add(,)").unwrap().iter()).is_err());
+}
+
+///The message of the first parse error in the source
+fn parse_error(source: &str) -> String {
+    parse(&mut lex(source).unwrap().iter()).unwrap_err().remove(0).0
+}
+
+#[test]
+fn parse_errors_say_what_could_be_there() {
+    assert_eq!(parse_error("let a = ;"),
+        "Expected an expression, got ';'. Expected one of: identifier, literal, '(', '[', '{', 'if', 'some', 'none', 'unit', '-', '!'");
+    assert_eq!(parse_error("f(1 2)"),
+        "Expected an operator or the end of the expression, got '2'. Expected one of: operator, ';', ')', '}', ']', 'else', ',', end of input");
+    assert!(parse_error("if (true) 1 else").starts_with("Unexpected end of input, expected an expression. Expected one of: "));
+    assert_eq!(parse_error("let = 1;"), "Expected an identifier, got '='");
+    assert_eq!(parse_error("1 $ 2"), "Unknown operator: '$'");
+}
+
+#[test]
+fn newlines_can_end_statements() {
+    let with_newlines = |source: &str| {
+        let options = ParseOptions { newline_ends_statement: true };
+        parse_with_options(&mut lex(source).unwrap().iter(), options).map(|program| program.to_sexp())
+    };
+    let source = "let a = [\n    1,\n    2\n]\nlet b = a[0]\n    + 2\nif (b > 2)\n    b\nelse\n    0";
+    let with_semicolons = "let a = [1, 2]; let b = a[0] + 2; if (b > 2) b else 0";
+    assert_eq!(with_newlines(source).unwrap(), parse(&mut lex(with_semicolons).unwrap().iter()).unwrap().to_sexp());
+    assert!(with_newlines("let a = 1 let b = 2").is_err());
+    assert!(parse(&mut lex("let a = 1\nlet b = 2\nb").unwrap().iter()).is_err());
+}
+
+#[test]
+fn trees_dump_as_json_and_s_expressions() {
+    let program = parse(&mut lex("let a = -1;\nf(a)").unwrap().iter()).unwrap();
+    assert_eq!(program.to_sexp(), "(Block ((Let a (UnOp - (Literal int 1))) (FunCall f ((Var a)))) ())");
+    assert_eq!(program.to_json(), concat!(r#"{"kind":"Block","statements":[{"kind":"Let","name":"a","value":{"kind":"UnOp","op":"-","exp":"#,
+        r#"{"kind":"Literal","type":"int","value":1,"location":{"line":1,"col":10,"end_line":1,"end_col":11}},"#,
+        r#""location":{"line":1,"col":9,"end_line":1,"end_col":10}},"location":{"line":1,"col":1,"end_line":1,"end_col":4}},"#,
+        r#"{"kind":"FunCall","name":"f","args":[{"kind":"Var","name":"a","location":{"line":2,"col":3,"end_line":2,"end_col":4}}],"#,
+        r#""location":{"line":2,"col":1,"end_line":2,"end_col":2}}],"functions":[],"location":{"line":1,"col":1,"end_line":1,"end_col":4}}"#));
+}
+
+///Counts the variables read, in functions too
+struct VarCounter(usize);
+
+impl Visitor for VarCounter {
+    fn visit_exp(&mut self, exp: &Exp) {
+        if let Exp::VarExp(_, _) = exp {
+            self.0 += 1;
+        }
+        walk_exp(self, exp)
+    }
+}
+
+///Replaces every int literal with its double
+struct Doubler;
+
+impl Folder for Doubler {
+    fn fold_exp(&mut self, exp: Exp) -> Exp {
+        match exp {
+            Exp::LiteralExp(Literal::Int(i), loc) => Exp::LiteralExp(Literal::Int(i * 2), loc),
+            exp => fold_children(self, exp)
+        }
+    }
+}
+
+#[test]
+fn visitors_and_folders_reach_every_node() {
+    let program = parse(&mut lex("let a = 1; fun f(x: int) = x + a + 1; while (a < 3) { a += f(a) }; a").unwrap().iter()).unwrap();
+    let mut counter = VarCounter(0);
+    counter.visit_exp(&program);
+    assert_eq!(counter.0, 6);
+    let doubled = Doubler.fold_exp(program);
+    assert_eq!(doubled.to_string(), parse(&mut lex("let a = 2; fun f(x: int) = x + a + 2; while (a < 6) { a += f(a) }; a").unwrap().iter()).unwrap().to_string());
+}
@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+
+use nebulang::{Interpreter, Value, Error, eval::Signal, options::ExecutionOptions, task};
+
+fn eval(source: &str) -> Value {
+    task::set_pool_size(1);
+    match Interpreter::new().eval(source) {
+        Ok(value) => value,
+        Err(err) => panic!("'{source}' failed: {err}")
+    }
+}
+
+#[test]
+fn nested_joins_run_on_a_pool_of_one() {
+    assert_eq!(eval("fun leaf(): int = 1;
+        fun middle(): int = join(spawn(leaf)) + 1;
+        fun top(): int = join(spawn(middle)) + 1;
+        join(spawn(top))"), Value::from(3));
+}
+
+#[test]
+fn join_stops_when_the_run_is_out_of_time() {
+    task::set_pool_size(1);
+    let options = ExecutionOptions { max_duration: Some(Duration::from_millis(100)), ..ExecutionOptions::default() };
+    let before = Instant::now();
+    let res = Interpreter::with_options(options).eval("fun slow(): int = { sleep_ms(1000); 1 }; join(spawn(slow))");
+    assert!(matches!(res, Err(Error::Runtime(Signal::Timeout(_)))));
+    assert!(before.elapsed() < Duration::from_millis(800));
+}
+
+#[test]
+fn recv_waits_for_a_task_queued_after_it() {
+    assert_eq!(eval("let results = channel(); send(results, 0); recv(results);
+        fun send_later(): unit = send(results, 2);
+        fun receive(): int = { spawn(send_later); recv(results) };
+        join(spawn(receive))"), Value::from(2));
+}
+
+#[test]
+fn recv_stops_when_the_run_is_out_of_time() {
+    task::set_pool_size(1);
+    let options = ExecutionOptions { max_duration: Some(Duration::from_millis(100)), ..ExecutionOptions::default() };
+    let res = Interpreter::with_options(options).eval("let never = channel(); send(never, 0); recv(never); recv(never)");
+    assert!(matches!(res, Err(Error::Runtime(Signal::Timeout(_)))));
+}
@@ -0,0 +1,165 @@
+use std::{cell::RefCell, rc::Rc};
+
+use nebulang::{Interpreter, Value, interner::Symbol, ast::{Exp, Literal}, debugger::{self, Change, Debugger, Pause, Resume}, coverage, profiler, options::ExecutionOptions,
+    environment::Environment, lexer::{lex, Location}, parser::parse, type_checker, reload::Reloader, eval, snapshot};
+
+fn checked(program: &Exp) -> Exp {
+    let mut program = program.clone();
+    type_checker::check_program(&mut program, &mut Environment::new()).unwrap();
+    program
+}
+
+fn parsed(source: &str) -> Exp {
+    parse(&mut lex(source).unwrap().iter()).unwrap()
+}
+
+thread_local! {
+    static TRACED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+fn collect_trace(exp: &Exp, value: &Literal) {
+    TRACED.with(|traced| traced.borrow_mut().push(format!("{exp} => {value}")));
+}
+
+#[test]
+fn profile_counts_the_calls_of_each_function() {
+    let options = ExecutionOptions { profile: true, ..ExecutionOptions::default() };
+    Interpreter::with_options(options).eval("fun leaf(): int = { sleep_ms(5); 1 }
+        fun twice(): int = leaf() + leaf();
+        twice() + twice() + leaf()").unwrap();
+    let profile = profiler::take_profile().unwrap();
+    let function = |name: &str| profile.functions.iter().find(|fun| fun.name.as_str() == name).unwrap();
+    assert_eq!((function("twice").calls, function("leaf").calls), (2, 5));
+    assert!(function("twice").total >= function("twice").own);
+    assert!(function("leaf").total.as_millis() >= 25);
+    assert!(profiler::take_profile().is_none());
+    Interpreter::new().eval("fun one() = 1; one()").unwrap();
+    assert!(profiler::take_profile().is_none());
+}
+
+#[test]
+fn profiling_and_coverage_stop_when_the_run_ends() {
+    let options = ExecutionOptions { profile: true, coverage: true, ..ExecutionOptions::default() };
+    Interpreter::with_options(options).eval("fun one() = 1; one()").unwrap();
+    profiler::enter(Symbol::new("after"));
+    profiler::leave();
+    coverage::statement(Location { line: 10, col: 1, end_line: 10, end_col: 2 });
+    let profile = profiler::take_profile().unwrap();
+    assert_eq!(profile.functions.iter().map(|fun| fun.name.as_str()).collect::<Vec<_>>(), ["one"]);
+    assert!(coverage::take_coverage().unwrap().statements.keys().all(|loc| loc.line == 1));
+}
+
+#[test]
+fn trace_gets_each_statement_and_its_value() {
+    let options = ExecutionOptions { trace: Some(collect_trace), ..ExecutionOptions::default() };
+    Interpreter::with_options(options).eval("let x = 2; fun double(n: int) = { let d = n * 2; d }; double(x) + 1").unwrap();
+    let traced = TRACED.with(|traced| traced.take());
+    assert_eq!(traced, ["let x = 2 => Unit", "fun double => Unit", "let d = n * 2 => Unit", "d => 4", "double(x) + 1 => 5"]);
+}
+
+///The line and the value of 'x' at each pause
+type Pauses = Vec<(u32, Option<Value>)>;
+
+///Keeps where it paused, and resumes as it is told
+struct Recorder {
+    pauses: Rc<RefCell<Pauses>>,
+    resume: Resume,
+}
+
+impl Debugger for Recorder {
+    fn paused(&mut self, pause: &Pause) -> Resume {
+        self.pauses.borrow_mut().push((pause.loc().line, pause.variable("x")));
+        self.resume
+    }
+}
+
+///Runs the source with a recorder attached, and gives where it paused
+fn debug(source: &str, resume: Resume, setup: impl FnOnce()) -> Pauses {
+    let pauses = Rc::new(RefCell::new(Vec::new()));
+    debugger::attach(Box::new(Recorder { pauses: pauses.clone(), resume }));
+    setup();
+    let res = Interpreter::new().eval(source);
+    debugger::detach();
+    res.unwrap();
+    pauses.take()
+}
+
+#[test]
+fn debugger_pauses_at_breakpoints_and_steps() {
+    let source = "let x = 1;\nfun bump(): unit = {\n    x += 10\n};\nbump();\nx += 100;\nx";
+    let at_breakpoint = debug(source, Resume::Continue, || debugger::set_breakpoint(3));
+    debugger::remove_breakpoint(3);
+    assert_eq!(at_breakpoint, [(3, Some(Value::from(1)))]);
+    let stepped_into: Vec<u32> = debug(source, Resume::StepInto, debugger::pause).into_iter().map(|(line, _)| line).collect();
+    assert_eq!(stepped_into, [1, 2, 5, 3, 6, 7]);
+    let stepped_over = debug(source, Resume::StepOver, debugger::pause);
+    assert_eq!(stepped_over, [(1, None), (2, Some(Value::from(1))), (5, Some(Value::from(1))), (6, Some(Value::from(11))), (7, Some(Value::from(111)))]);
+}
+
+///Keeps each change of a watched variable
+struct Watcher(Rc<RefCell<Vec<Change>>>);
+
+impl Debugger for Watcher {
+    fn paused(&mut self, _pause: &Pause) -> Resume {
+        Resume::Continue
+    }
+
+    fn changed(&mut self, change: &Change, _pause: &Pause) -> Resume {
+        self.0.borrow_mut().push(change.clone());
+        Resume::Continue
+    }
+}
+
+#[test]
+fn watched_variables_report_each_change() {
+    let changes = Rc::new(RefCell::new(Vec::new()));
+    debugger::attach(Box::new(Watcher(changes.clone())));
+    debugger::watch(Symbol::new("watched"));
+    let res = Interpreter::new().eval("let watched = 1; let other = 2; watched += 1; other += 1; fun reset(): unit = watched = 10; reset(); watched");
+    debugger::unwatch(Symbol::new("watched"));
+    debugger::detach();
+    assert_eq!(res.unwrap(), Value::from(10));
+    let changes: Vec<(Option<Value>, Value)> = changes.take().into_iter().map(|change| (change.old, change.new)).collect();
+    assert_eq!(changes, [(None, Value::from(1)), (Some(Value::from(1)), Value::from(2)), (Some(Value::from(2)), Value::from(10))]);
+}
+
+#[test]
+fn coverage_counts_statements_and_branches() {
+    let options = ExecutionOptions { coverage: true, ..ExecutionOptions::default() };
+    Interpreter::with_options(options).eval("let n = 0;\nfor(i, 0, 3) {\n    if (i > 5) n += 100;\n    n += 1\n};\nfun never(): int = {\n    1\n};\nn").unwrap();
+    let coverage = coverage::take_coverage().unwrap();
+    let count = |line: u32| coverage.statements.iter().filter(|(loc, _)| loc.line == line).map(|(_, count)| *count).max();
+    assert_eq!((count(1), count(3), count(4), count(9)), (Some(1), Some(3), Some(3), Some(1)));
+    assert_eq!(count(7), Some(0));
+    let branch = coverage.branches.values().next().unwrap();
+    assert_eq!((branch.when_true, branch.when_false), (0, 3));
+    assert_eq!(coverage.branches_taken(), 1);
+}
+
+#[test]
+fn reloading_keeps_the_top_level_variables() {
+    let options = ExecutionOptions::default();
+    let mut reloader = Reloader::new();
+    let first = checked(&parsed("fun step(): int = 1; let count = 0; count += step(); count"));
+    assert_eq!(reloader.run(&first, &options).unwrap(), Literal::Int(1));
+    let changed = parsed("fun step(): int = 10; let count = 0; count += step(); count");
+    assert_eq!(reloader.run(&checked(&reloader.keep_variables(&changed)), &options).unwrap(), Literal::Int(11));
+    let retyped = reloader.keep_variables(&parsed("let count = false; if (count) 1 else 0"));
+    assert!(type_checker::check_program(&mut retyped.clone(), &mut Environment::new()).is_err());
+}
+
+#[test]
+fn snapshots_resume_where_they_were_saved() {
+    let options = ExecutionOptions::default();
+    let program = checked(&parsed("fun add(n: int): int = n + 1; let total = 1; let f = add; total = f(total); total = total * 10; total"));
+    let mut saved = Vec::new();
+    assert_eq!(eval::run_resumable(&program, None, &options, |snapshot| saved.push(snapshot.to_string())).unwrap(), Literal::Int(20));
+    let after_call = &saved[3];
+    //Functions are saved by name, and must be given back when read
+    assert!(snapshot::read(after_call, |_| None).is_err());
+    let snapshot = snapshot::read(after_call, |id| (id.as_str() == "add").then_some(Literal::Unit)).unwrap();
+    assert_eq!(snapshot.next, 4);
+    assert_eq!(snapshot.variables.iter().map(|(id, value)| format!("{id} = {value}")).collect::<Vec<_>>(), ["total = 2", "f = Unit"]);
+    assert_eq!(eval::run_resumable(&program, Some(after_call), &options, |_| ()).unwrap(), Literal::Int(20));
+    assert!(snapshot::read("next x", |_| None).is_err());
+}
@@ -0,0 +1,88 @@
+use nebulang::{Interpreter, Error, Value};
+
+fn eval(source: &str) -> Value {
+    match Interpreter::new().eval(source) {
+        Ok(value) => value,
+        Err(err) => panic!("'{source}' failed: {err}")
+    }
+}
+
+fn type_error(source: &str) -> String {
+    match Interpreter::new().eval(source) {
+        Err(Error::Type(msg, _)) => msg,
+        res => panic!("Expected a type error for '{source}', got {res:?}")
+    }
+}
+
+#[test]
+fn none_takes_the_type_of_the_first_value_assigned() {
+    assert_eq!(eval("fun first_or_zero(x: option<int>) = x ?? 0; let a = none; a = some(12); first_or_zero(a)"), Value::from(12));
+    assert!(type_error("let x = none; x = some(5); if (x ?? true) 1 else 2").starts_with("Right side of '??' must be 'int'"));
+    assert!(type_error("let x = none; x = some(\"hi\"); (x ?? 1) * 2").starts_with("Right side of '??' must be 'string'"));
+    assert!(type_error("let x = none; x = some(1); x = some(\"a\"); x").starts_with("Cannot assign"));
+}
+
+#[test]
+fn none_takes_the_type_of_its_default() {
+    assert_eq!(eval("let a = none; let b = a ?? 7; a = some(2); b + (a ?? 0)"), Value::from(9));
+    assert!(type_error("let x = none; let r = 0; for (i, 0, 2) { if (x ?? true) r += 1; x = some(5); }; r").starts_with("Cannot assign"));
+}
+
+#[test]
+fn none_read_before_it_gets_a_type() {
+    assert!(type_error("fun f() = none; let x = f(); let y = x; x = some(true); y").contains("cannot become 'option<bool>' later"));
+}
+
+#[test]
+fn none_joins_with_the_other_branch_or_element() {
+    assert!(type_error("[none, some(5)][1] ?? true").starts_with("Right side of '??' must be 'int'"));
+    assert!(type_error("(if (true) none else some(5)) ?? true").starts_with("Right side of '??' must be 'int'"));
+    assert_eq!(eval("fun last(acc: option<int>, x: int) = some(x); reduce([1, 2], none, last) ?? 0"), Value::from(2));
+}
+
+#[test]
+fn empty_array_takes_the_type_of_the_first_value() {
+    assert_eq!(eval("let a = []; push(a, 1); a.push(2); len(a)"), Value::from(2));
+    assert_eq!(eval("let a = []; while (len(a) < 3) push(a, 'x'); len(a)"), Value::from(3));
+    assert!(type_error("let a = []; a = [\"x\"]; push(a, 1); a[0] * 2").starts_with("'push' expects 'string'"));
+    assert!(type_error("let s = to_set([]); add(s, 1); add(s, true); s").starts_with("'add' expects 'int'"));
+}
+
+#[test]
+fn empty_array_read_before_it_gets_a_type() {
+    assert!(type_error("let a = []; let b = a; push(a, 1); push(b, \"x\"); b[0] * 2").contains("cannot become '[int]' later"));
+    assert!(type_error("let e = []; let (x, y) = (e, 1); push(x, \"a\"); push(e, 1); e[0] * y").contains("cannot become '[int]' later"));
+    assert!(type_error("let a = []; for (i, 0, 2) { let n = len(pop(a) ?? []); push(a, [\"x\"]); }; a").contains("cannot become"));
+}
+
+#[test]
+fn new_channel_is_not_copied_before_it_gets_a_type() {
+    assert_eq!(eval("let c = channel(); send(c, 2); let d = c; send(d, 3); recv(c) * recv(d)"), Value::from(6));
+    assert!(type_error("let c = channel(); let d = c; send(d, \"x\"); send(c, 1); recv(c) * 2").contains("holds a channel with no element type yet"));
+}
+
+#[test]
+fn assignments_give_unit_and_cannot_be_chained() {
+    assert_eq!(eval("let a = 1; a = 2"), Value::Unit);
+    assert_eq!(type_error("let a = 1; let b = 2; a = b = 3"), "Cannot assign 'unit' to 'a' which is 'int'");
+}
+
+#[test]
+fn missing_names_suggest_the_closest_one() {
+    assert_eq!(type_error("let length = 3; lenght + 1"), "Variable 'lenght' does not exist here, did you mean 'length'?");
+    assert_eq!(type_error("fun double(n: int) = n * 2; doubel(2)"), "Function 'doubel' does not exist here, did you mean 'double'?");
+    assert_eq!(type_error("to_strin(1)"), "Function 'to_strin' does not exist here, did you mean 'to_string'?");
+    assert_eq!(type_error("let length = 3; width"), "Variable 'width' does not exist here");
+}
+
+#[test]
+fn errors_in_for_loops_point_at_what_was_written() {
+    let error = |source: &str| match Interpreter::new().eval(source) {
+        Err(Error::Type(msg, loc)) => (msg, loc.col),
+        res => panic!("Expected a type error for '{source}', got {res:?}")
+    };
+    assert_eq!(error("for(i, 0, 10, \"s\") { }"), (String::from("Step in for must be int or float, got 'string'"), 15));
+    assert_eq!(error("for(i, 0, 10, 0.5) { }"), (String::from("Step in for must be int when it counts from an int, got 'float'"), 15));
+    assert_eq!(error("for(\"s\") { }"), (String::from("Count in for must be int or float, got 'string'"), 5));
+    assert_eq!(eval("let s = \"\"; for(i, 10, 0, 3) { s = s + to_string(i) }; s"), Value::from("10741"));
+}
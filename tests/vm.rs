@@ -0,0 +1,72 @@
+use nebulang::{Value, ast::Exp, environment::Environment, eval::{self, Signal}, lexer::lex, options::ExecutionOptions, parser::parse, type_checker, vm, ir};
+
+fn checked(source: &str) -> Exp {
+    let mut program = parse(&mut lex(source).unwrap().iter()).unwrap();
+    type_checker::check_program(&mut program, &mut Environment::new()).unwrap();
+    program
+}
+
+///Runs the source on the VM, and checks it gives what the tree-walking interpreter gives
+fn run_vm(source: &str) -> Result<Value, Signal> {
+    let program = checked(source);
+    let bytecode = vm::compile(&program).unwrap_or_else(|feature| panic!("'{source}' does not compile: {feature}"));
+    let res = vm::run(&bytecode, &ExecutionOptions::default());
+    assert_eq!(res.as_ref().ok(), eval::run(&program, &ExecutionOptions::default()).as_ref().ok());
+    res
+}
+
+#[test]
+fn vm_runs_loops_and_recursion() {
+    assert_eq!(run_vm("fun fib(n: int): int = if (n < 2) n else fib(n - 1) + fib(n - 2); fib(15)").unwrap(), Value::from(610));
+    assert_eq!(run_vm("let primes = 0; for(i, 2, 200) { let p = true; let d = 2; while (d < i && p) { if (i % d == 0) p = false; d += 1 }; if (p) primes += 1 }; primes").unwrap(),
+        Value::from(46));
+    assert_eq!(run_vm("let s = \"\"; let i = 0; while (true) { i += 1; if (i % 2 == 0) continue; if (i > 7) break; s = s + to_string(i) }; s").unwrap(),
+        Value::from("1357"));
+    assert_eq!(run_vm("fun count(n: int, acc: int): int = if (n == 0) acc else count(n - 1, acc + 1); count(100000, 0)").unwrap(), Value::from(100000));
+}
+
+#[test]
+fn vm_gives_runtime_errors() {
+    assert!(matches!(run_vm("let a = [1, 2]; a[5]"), Err(Signal::Error(msg, _)) if msg == "Index out of bounds"));
+    assert!(matches!(run_vm("let zero = 0; 1 / zero"), Err(Signal::Error(msg, _)) if msg == "Division by zero"));
+}
+
+#[test]
+fn vm_leaves_out_what_it_does_not_support() {
+    assert_eq!(vm::compile(&checked("struct P { x: int } P { x: 1 }.x")).err(), Some(String::from("structs")));
+    assert_eq!(vm::compile(&checked("let s = 0; for(x in [1]) { s += x }; s")).err(), Some(String::from("for-in loops")));
+}
+
+#[test]
+fn fuel_stops_the_vm() {
+    let bytecode = vm::compile(&checked("let i = 0; while (true) { i += 1 }; i")).unwrap();
+    let options = ExecutionOptions { fuel: Some(1000), ..ExecutionOptions::default() };
+    assert!(matches!(vm::run(&bytecode, &options), Err(Signal::BudgetExceeded(_))));
+    let bytecode = vm::compile(&checked("let i = 0; while (i < 10) { i += 1 }; i")).unwrap();
+    assert_eq!(vm::run(&bytecode, &options).unwrap(), Value::from(10));
+}
+
+#[test]
+fn lowering_resolves_functions_and_variables() {
+    let program = ir::lower(&checked("let total = 0; fun add(n: int): unit = total += n; fun twice(n: int): int = { let d = n * 2; d }; add(twice(3)); total")).unwrap();
+    assert_eq!(program.functions.len(), 3);
+    assert_eq!(program.globals, 1);
+    let twice = program.functions.iter().find(|fun| fun.name.as_str() == "twice").unwrap();
+    assert_eq!(twice.slots, 2);
+    assert!(matches!(&program.functions[0].body, ir::Ir::Seq(_)));
+    assert!(ir::lower(&checked("fun one() = 1; let f = one; f()")).is_err());
+}
+
+#[test]
+fn peephole_pass_merges_instructions_without_changing_results() {
+    for source in ["let i = 0; let s = 0; while (i < 100) { if (i % 3 == 0) s += i; i += 1 }; s",
+        "fun fib(n: int): int = if (n < 2) n else fib(n - 1) + fib(n - 2); let x = 1; x; fib(12)",
+        "let s = 0; for(i, 0, 10) { if (i >= 5) break; s += i }; s"] {
+        let program = checked(source);
+        let mut bytecode = vm::compile(&program).unwrap();
+        let stats = vm::peephole(&mut bytecode);
+        assert!(stats.after < stats.before, "'{source}' was not rewritten");
+        assert_eq!(stats.after, bytecode.instruction_count());
+        assert_eq!(vm::run(&bytecode, &ExecutionOptions::default()).unwrap(), eval::run(&program, &ExecutionOptions::default()).unwrap());
+    }
+}